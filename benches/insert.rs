@@ -0,0 +1,88 @@
+//! Measures the throughput of adding transactions to the graph and keys to the key store, which are written to the
+//! database one by one during a sync. Run using `cargo bench --bench insert`
+use criterion::{criterion_group, criterion_main, BatchSize, Criterion, Throughput};
+use nuts_rs::network::{Graph, Hash, Transaction, TransactionBuilder};
+use nuts_rs::pki::{public_jwk, KeyStorage, KeyStore};
+use p256::ecdsa::SigningKey;
+use rand::rngs::OsRng;
+
+const TRANSACTIONS: usize = 1_000;
+
+const KEYS: usize = 1_000;
+
+/// Signs a chain of transactions once, so that only inserting them is measured
+fn transactions() -> Vec<Transaction> {
+    let key = SigningKey::random(&mut OsRng);
+    let mut transactions: Vec<Transaction> = vec![];
+    let mut prev: Option<Hash> = None;
+
+    for i in 0..TRANSACTIONS {
+        let tx = TransactionBuilder::new("application/did+json", format!("payload-{}", i))
+            .unwrap()
+            .prevs(prev.into_iter().collect())
+            .lamport_clock(i as u32)
+            .embed_key(i == 0)
+            .sign("did:nuts:bench#key-1", &key)
+            .unwrap();
+
+        prev = Some(tx.id.clone());
+        transactions.push(tx);
+    }
+
+    transactions
+}
+
+fn temporary_db() -> sled::Db {
+    sled::Config::new().temporary(true).open().unwrap()
+}
+
+fn graph_insert(c: &mut Criterion) {
+    let transactions = transactions();
+    let mut group = c.benchmark_group("graph");
+
+    group.throughput(Throughput::Elements(TRANSACTIONS as u64));
+    group.sample_size(10);
+    group.bench_function("add", |b| {
+        b.iter_batched(
+            || (Graph::open(temporary_db()).unwrap(), transactions.clone()),
+            |(mut graph, transactions)| {
+                for tx in transactions {
+                    graph.add(tx).unwrap();
+                }
+            },
+            BatchSize::PerIteration,
+        )
+    });
+    group.finish();
+}
+
+fn key_store_insert(c: &mut Criterion) {
+    let keys = (0..KEYS)
+        .map(|i| {
+            let kid = format!("did:nuts:bench#key-{}", i);
+            let key = public_jwk(&kid, &SigningKey::random(&mut OsRng));
+
+            (kid, key)
+        })
+        .collect::<Vec<_>>();
+    let mut group = c.benchmark_group("key_store");
+
+    group.throughput(Throughput::Elements(KEYS as u64));
+    group.sample_size(10);
+    group.bench_function("add_and_get", |b| {
+        b.iter_batched(
+            || (KeyStore::open(temporary_db()).unwrap(), keys.clone()),
+            |(mut store, keys)| {
+                for (kid, key) in keys {
+                    store.add(kid.clone(), key).unwrap();
+                    store.get(&kid).unwrap().unwrap();
+                }
+            },
+            BatchSize::PerIteration,
+        )
+    });
+    group.finish();
+}
+
+criterion_group!(benches, graph_insert, key_store_insert);
+criterion_main!(benches);
@@ -0,0 +1,75 @@
+//! Measures how long it takes to verify and schedule a synced transaction list of 10k transactions, once using a
+//! single worker and once using a worker per core. Run using `cargo bench --bench sync`
+use std::thread;
+use std::time::{Duration, Instant};
+
+use anyhow::Result;
+use nuts_rs::network::{Admission, Graph, Hash, TransactionBuilder};
+use nuts_rs::pki::{KeyStore, TrustPolicy};
+use p256::ecdsa::SigningKey;
+use rand::rngs::OsRng;
+use tokio::runtime::Runtime;
+
+const TRANSACTIONS: usize = 10_000;
+
+const KEY_ID: &str = "did:nuts:bench#key-1";
+
+/// Signs a chain of transactions of which only the root embeds the key, like the list a new node receives
+fn transactions() -> Result<Vec<Vec<u8>>> {
+    let key = SigningKey::random(&mut OsRng);
+    let mut encoded = vec![];
+    let mut prev: Option<Hash> = None;
+
+    for i in 0..TRANSACTIONS {
+        let tx = TransactionBuilder::new("application/did+json", format!("payload-{}", i))?
+            .prevs(prev.into_iter().collect())
+            .lamport_clock(i as u32)
+            .embed_key(i == 0)
+            .sign(KEY_ID, &key)?;
+
+        prev = Some(tx.id.clone());
+        encoded.push(tx.data);
+    }
+
+    Ok(encoded)
+}
+
+fn admit(runtime: &Runtime, workers: usize, encoded: Vec<Vec<u8>>) -> Result<Duration> {
+    let db = sled::Config::new().temporary(true).open()?;
+    let mut key_store = KeyStore::open(db.clone())?;
+    let graph = Graph::open(db.clone())?;
+    let admission = Admission::new(
+        workers,
+        false,
+        Duration::from_secs(5),
+        TrustPolicy::open(db)?,
+    );
+    let started_at = Instant::now();
+    let verified = runtime.block_on(admission.verify(&mut key_store, encoded))?;
+    let scheduled = Admission::schedule(&graph, verified);
+    let elapsed = started_at.elapsed();
+
+    assert_eq!(scheduled.len(), TRANSACTIONS);
+
+    Ok(elapsed)
+}
+
+fn main() -> Result<()> {
+    let runtime = Runtime::new()?;
+    let encoded = transactions()?;
+    let cores = thread::available_parallelism()?.get();
+
+    for workers in [1, cores] {
+        let elapsed = admit(&runtime, workers, encoded.clone())?;
+
+        println!(
+            "{} transactions, {} worker(s): {}ms ({:.0} transactions/s)",
+            TRANSACTIONS,
+            workers,
+            elapsed.as_millis(),
+            TRANSACTIONS as f64 / elapsed.as_secs_f64()
+        );
+    }
+
+    Ok(())
+}
@@ -1,7 +1,19 @@
 use std::{env, fs};
 
 fn main() -> Result<(), Box<dyn std::error::Error>> {
-    tonic_build::compile_protos("proto/network.proto")?;
+    let mut config = prost_build::Config::new();
+
+    // Payloads are shared with the database instead of copied into every message which serves them
+    config.bytes(&[
+        ".transport.TransactionPayload.data",
+        ".nutsrs.v2.Transaction.payload",
+    ]);
+
+    tonic_build::configure().compile_with_config(
+        config,
+        &["proto/network.proto", "proto/v2.proto"],
+        &["proto"],
+    )?;
 
     // Fix for `connect` gRPC method conflict
     let output_file = format!("{}/transport.rs", env::var("OUT_DIR")?);
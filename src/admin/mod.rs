@@ -0,0 +1,345 @@
+use std::convert::Infallible;
+use std::io::{BufWriter, Seek, SeekFrom};
+use std::net::{SocketAddr, TcpListener};
+use std::sync::Arc;
+use std::time::Duration;
+
+use anyhow::{anyhow, Result};
+use futures::stream;
+use hyper::body::Bytes;
+use hyper::header::{AUTHORIZATION, CONTENT_LENGTH, CONTENT_TYPE};
+use hyper::service::{make_service_fn, service_fn};
+use hyper::{Body, Method, Request, Response, StatusCode};
+use nuts_rs::network::{
+    parse_period, AddressBook, Graph, Hash, Health, PayloadStore, PendingWrites, Stats,
+};
+use serde::Serialize;
+use sled::Db;
+use tokio::fs::File;
+use tokio::io::AsyncReadExt;
+use tokio::sync::watch;
+use tokio::task;
+
+use crate::annotations::{Annotations, Subject};
+use crate::{backup, status};
+
+pub use tokens::{Role, TokenStore};
+
+mod tokens;
+
+/// Size of the chunks in which payloads and backups are streamed
+const PAYLOAD_CHUNK_SIZE: usize = 64 * 1024;
+
+/// Routes of the admin API
+enum Route {
+    Peers,
+    Stats(Duration),
+    AddLabel(Hash, String),
+    RemoveLabel(Hash, String),
+    Backup,
+    Payload(Hash),
+}
+
+impl Route {
+    fn parse(method: &Method, path: &str, query: Option<&str>) -> Result<Option<Self>> {
+        let segments = path.trim_matches('/').split('/').collect::<Vec<_>>();
+
+        Ok(Some(match (method, segments.as_slice()) {
+            (&Method::GET, ["network", "peers"]) => Route::Peers,
+            (&Method::GET, ["graph", "stats"]) => {
+                let history = query
+                    .into_iter()
+                    .flat_map(|query| query.split('&'))
+                    .find_map(|param| param.strip_prefix("history="))
+                    .unwrap_or("24h");
+
+                Route::Stats(parse_period(history)?)
+            }
+            (&Method::PUT, ["graph", "transactions", id, "labels", label]) => {
+                Route::AddLabel(Hash::parse_encoded(id)?, label.to_string())
+            }
+            (&Method::DELETE, ["graph", "transactions", id, "labels", label]) => {
+                Route::RemoveLabel(Hash::parse_encoded(id)?, label.to_string())
+            }
+            (&Method::POST, ["backup"]) => Route::Backup,
+            (&Method::GET, ["payloads", hash]) => Route::Payload(Hash::parse_encoded(hash)?),
+            _ => return Ok(None),
+        }))
+    }
+
+    fn required_role(&self) -> Role {
+        match self {
+            Route::Peers | Route::Stats(_) | Route::Payload(_) => Role::Read,
+            // The backup contains the (encrypted) private keys of the node
+            Route::AddLabel(..) | Route::RemoveLabel(..) | Route::Backup => Role::Write,
+        }
+    }
+}
+
+fn json(status: StatusCode, value: &impl Serialize) -> Response<Body> {
+    match serde_json::to_vec(value) {
+        Ok(body) => Response::builder()
+            .status(status)
+            .header(CONTENT_TYPE, "application/json")
+            .body(Body::from(body))
+            .unwrap(),
+        Err(e) => error(StatusCode::INTERNAL_SERVER_ERROR, e.to_string()),
+    }
+}
+
+fn error(status: StatusCode, message: impl Into<String>) -> Response<Body> {
+    json(
+        status,
+        &serde_json::json!({
+            "error": message.into(),
+        }),
+    )
+}
+
+/// HTTP API used by operators to manage the node, every request must be authenticated using a bearer token except
+/// for the health check which is used by probes
+struct AdminApi {
+    db: Db,
+    tokens: TokenStore,
+    health: watch::Receiver<Health>,
+    /// Transactions are written to the database in the background, which is waited for before taking a backup
+    writes: PendingWrites,
+}
+
+impl AdminApi {
+    async fn handle(&self, request: &Request<Body>) -> (Response<Body>, Option<String>) {
+        if request.method() == Method::GET && request.uri().path() == "/health" {
+            let health = self.health.borrow();
+            let status = if health.is_healthy() {
+                StatusCode::OK
+            } else {
+                StatusCode::SERVICE_UNAVAILABLE
+            };
+
+            return (json(status, &*health), None);
+        }
+
+        let token = match request
+            .headers()
+            .get(AUTHORIZATION)
+            .and_then(|value| value.to_str().ok())
+            .and_then(|value| value.strip_prefix("Bearer "))
+            .map(|secret| self.tokens.authenticate(secret))
+        {
+            Some(Ok(Some(token))) => token,
+            Some(Err(e)) => {
+                return (
+                    error(StatusCode::INTERNAL_SERVER_ERROR, e.to_string()),
+                    None,
+                )
+            }
+            _ => {
+                return (
+                    error(StatusCode::UNAUTHORIZED, "missing or invalid token"),
+                    None,
+                )
+            }
+        };
+
+        let response = match Route::parse(
+            request.method(),
+            request.uri().path(),
+            request.uri().query(),
+        ) {
+            Ok(Some(route)) if !token.role.allows(route.required_role()) => error(
+                StatusCode::FORBIDDEN,
+                format!("route requires the {} role", route.required_role()),
+            ),
+            Ok(Some(route)) => self
+                .dispatch(route)
+                .await
+                .unwrap_or_else(|e| error(StatusCode::INTERNAL_SERVER_ERROR, e.to_string())),
+            Ok(None) => error(StatusCode::NOT_FOUND, "not found"),
+            Err(e) => error(StatusCode::BAD_REQUEST, e.to_string()),
+        };
+
+        (response, Some(token.id))
+    }
+
+    async fn dispatch(&self, route: Route) -> Result<Response<Body>> {
+        Ok(match route {
+            Route::Peers => json(StatusCode::OK, &AddressBook::open(self.db.clone())?.list()?),
+            Route::Stats(period) => json(
+                StatusCode::OK,
+                &Stats::open(self.db.clone())?.history(period)?,
+            ),
+            Route::AddLabel(id, label) => self.label(id, label, false)?,
+            Route::RemoveLabel(id, label) => self.label(id, label, true)?,
+            Route::Backup => self.backup().await?,
+            Route::Payload(hash) => self.payload(hash)?,
+        })
+    }
+
+    /// Writes the backup to a temporary file which is streamed once it's complete, so that a failure is reported
+    /// using the status code and the backup isn't held in memory
+    async fn backup(&self) -> Result<Response<Body>> {
+        self.writes.drain().await?;
+
+        let db = self.db.clone();
+        let (manifest, mut archive, len) = task::spawn_blocking(move || -> Result<_> {
+            let mut archive = tempfile::tempfile()?;
+            let manifest = backup::create(&db, BufWriter::new(&mut archive))?;
+            let len = archive.seek(SeekFrom::Current(0))?;
+
+            archive.seek(SeekFrom::Start(0))?;
+
+            Ok((manifest, File::from_std(archive), len))
+        })
+        .await??;
+
+        tracing::info!(target: "nuts::admin", "created backup of {} trees ({} records)", manifest.trees.len(), manifest.records());
+
+        let chunks = async_stream::try_stream! {
+            let mut buf = vec![0; PAYLOAD_CHUNK_SIZE];
+
+            loop {
+                let n = archive.read(&mut buf).await?;
+
+                if n == 0 {
+                    break;
+                }
+
+                yield Bytes::copy_from_slice(&buf[..n]);
+            }
+        };
+
+        Ok(Response::builder()
+            .status(StatusCode::OK)
+            .header(CONTENT_TYPE, "application/zstd")
+            .header(CONTENT_LENGTH, len)
+            .body(Body::wrap_stream::<_, _, std::io::Error>(chunks))?)
+    }
+
+    /// Streams the payload in chunks which share the buffer read from the database, so that serving a large payload
+    /// to several clients at once doesn't copy it for every client
+    fn payload(&self, hash: Hash) -> Result<Response<Body>> {
+        let data = match PayloadStore::open(self.db.clone())?.get_shared(&hash)? {
+            Some(data) => data,
+            None => {
+                return Ok(error(
+                    StatusCode::NOT_FOUND,
+                    format!("payload not found with hash: {}", hash),
+                ))
+            }
+        };
+        let len = data.len();
+        let chunks = (0..len).step_by(PAYLOAD_CHUNK_SIZE).map(move |start| {
+            Ok::<_, Infallible>(data.slice(start..len.min(start + PAYLOAD_CHUNK_SIZE)))
+        });
+
+        Ok(Response::builder()
+            .status(StatusCode::OK)
+            .header(CONTENT_TYPE, "application/octet-stream")
+            .header(CONTENT_LENGTH, len)
+            .body(Body::wrap_stream(stream::iter(chunks)))?)
+    }
+
+    fn label(&self, id: Hash, label: String, remove: bool) -> Result<Response<Body>> {
+        if !Graph::is_stored(&self.db, &id)? {
+            return Ok(error(
+                StatusCode::NOT_FOUND,
+                format!("transaction not found with id: {}", id),
+            ));
+        }
+
+        let annotations = Annotations::open(self.db.clone())?;
+        let subject = Subject::Transaction(id);
+
+        if remove {
+            annotations.remove(&subject, &label)?;
+        } else {
+            annotations.add(&subject, label)?;
+        }
+
+        Ok(Response::builder()
+            .status(StatusCode::NO_CONTENT)
+            .body(Body::empty())?)
+    }
+}
+
+/// Bearer tokens and backups (which contain the encrypted private keys) are sent in plain text, so the admin API is
+/// only served on the loopback interface
+fn check_loopback(addr: SocketAddr) -> Result<()> {
+    if !addr.ip().is_loopback() {
+        return Err(anyhow!(
+            "refusing to serve the admin API on {} without TLS, use a loopback address (e.g. 127.0.0.1:1323)",
+            addr
+        ));
+    }
+
+    Ok(())
+}
+
+/// Starts serving the admin API on the given loopback address in the background
+pub fn listen(
+    db: Db,
+    addr: SocketAddr,
+    health: watch::Receiver<Health>,
+    writes: PendingWrites,
+) -> Result<()> {
+    check_loopback(addr)?;
+
+    let listener =
+        TcpListener::bind(addr).map_err(|e| anyhow!("unable to listen on {}: {}", addr, e))?;
+
+    listen_on(db, listener, health, writes)
+}
+
+/// Starts serving the admin API on an already bound loopback socket (e.g. passed by systemd) in the background
+pub fn listen_on(
+    db: Db,
+    listener: TcpListener,
+    health: watch::Receiver<Health>,
+    writes: PendingWrites,
+) -> Result<()> {
+    let addr = listener.local_addr()?;
+
+    check_loopback(addr)?;
+
+    let mut ready = health.clone();
+    let api = Arc::new(AdminApi {
+        tokens: TokenStore::open(db.clone())?,
+        db,
+        health,
+        writes,
+    });
+    let make_service = make_service_fn(move |_| {
+        let api = api.clone();
+
+        async move {
+            Ok::<_, Infallible>(service_fn(move |request: Request<Body>| {
+                let api = api.clone();
+
+                async move {
+                    let (response, token_id) = api.handle(&request).await;
+
+                    tracing::info!(target: "nuts::admin", "{} {} {} (token: {})", request.method(), request.uri().path(), response.status().as_u16(), token_id.as_deref().unwrap_or("none"));
+
+                    Ok::<_, Infallible>(response)
+                }
+            }))
+        }
+    });
+    let builder = hyper::Server::from_tcp(listener)?;
+
+    // The address is bound right away so that a conflict fails fast, but requests are only served once the initial
+    // sync completed or timed out
+    tokio::spawn(async move {
+        if !status::ready(&mut ready).await {
+            return;
+        }
+
+        tracing::info!(target: "nuts::admin", "admin API listening on {}", addr);
+
+        if let Err(e) = builder.serve(make_service).await {
+            tracing::error!(target: "nuts::admin", "failed to serve admin API: {}", e);
+        }
+    });
+
+    Ok(())
+}
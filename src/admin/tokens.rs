@@ -0,0 +1,127 @@
+use std::fmt::{Display, Formatter};
+use std::str::FromStr;
+
+use anyhow::{anyhow, Result};
+use chrono::Utc;
+use nuts_rs::network::Hash;
+use rand::RngCore;
+use rmp_serde::{decode, encode};
+use serde::{Deserialize, Serialize};
+use sled::Db;
+
+/// Role of an API token, tokens with the write role can also use all read routes
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub enum Role {
+    Read,
+    Write,
+}
+
+impl Role {
+    /// Whether a token with this role is allowed to use a route which requires the given role
+    pub fn allows(&self, required: Role) -> bool {
+        *self == Role::Write || required == Role::Read
+    }
+}
+
+impl FromStr for Role {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "read" => Ok(Role::Read),
+            "write" => Ok(Role::Write),
+            _ => Err(anyhow!("invalid role '{}' (expected read or write)", s)),
+        }
+    }
+}
+
+impl Display for Role {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Role::Read => write!(f, "read"),
+            Role::Write => write!(f, "write"),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Token {
+    /// Short identifier of the token which can be logged and used to revoke it
+    pub id: String,
+    pub role: Role,
+    pub created_at: i64,
+}
+
+/// Stores the tokens which grant access to the admin API, only the hash of each token is stored
+pub struct TokenStore {
+    db: Db,
+}
+
+impl TokenStore {
+    pub fn open(db: Db) -> Result<Self> {
+        Ok(Self { db })
+    }
+
+    /// Creates a new token and returns it, the token can't be retrieved afterwards
+    pub fn create(&self, role: Role) -> Result<(Token, String)> {
+        let mut secret = [0; 32];
+
+        rand::thread_rng().fill_bytes(&mut secret);
+
+        let secret = hex::encode(secret);
+        let hash = Hash::new(&secret)?;
+        let token = Token {
+            id: hash.to_string()[..16].to_string(),
+            role,
+            created_at: Utc::now().timestamp(),
+        };
+
+        self.db
+            .open_tree("nuts/admin-tokens")?
+            .insert(hash, encode::to_vec(&token)?)?;
+
+        Ok((token, secret))
+    }
+
+    /// Returns the token matching the secret from a request
+    pub fn authenticate(&self, secret: &str) -> Result<Option<Token>> {
+        match self
+            .db
+            .open_tree("nuts/admin-tokens")?
+            .get(Hash::new(secret)?)?
+        {
+            Some(value) => Ok(Some(decode::from_read(value.as_ref())?)),
+            None => Ok(None),
+        }
+    }
+
+    pub fn list(&self) -> Result<Vec<Token>> {
+        let mut tokens = vec![];
+
+        for record in self.db.open_tree("nuts/admin-tokens")?.iter() {
+            let (_, value) = record?;
+
+            tokens.push(decode::from_read(value.as_ref())?);
+        }
+
+        Ok(tokens)
+    }
+
+    /// Revokes the token with the given ID and returns whether it existed
+    pub fn revoke(&self, id: &str) -> Result<bool> {
+        let tree = self.db.open_tree("nuts/admin-tokens")?;
+
+        for record in tree.iter() {
+            let (key, value) = record?;
+            let token: Token = decode::from_read(value.as_ref())?;
+
+            if token.id == id {
+                tree.remove(key)?;
+
+                return Ok(true);
+            }
+        }
+
+        Ok(false)
+    }
+}
@@ -0,0 +1,109 @@
+use std::fmt::{Display, Formatter};
+
+use anyhow::{anyhow, Result};
+use nuts_rs::network::Hash;
+use rmp_serde::{decode, encode};
+use sled::Db;
+
+/// Something an operator can attach labels to
+#[derive(Debug, Clone, PartialEq)]
+pub enum Subject {
+    Transaction(Hash),
+    Key(String),
+}
+
+impl Subject {
+    fn to_key(&self) -> String {
+        match self {
+            Subject::Transaction(id) => format!("tx/{}", id),
+            Subject::Key(id) => format!("key/{}", id),
+        }
+    }
+
+    fn from_key(key: &[u8]) -> Result<Self> {
+        let key = std::str::from_utf8(key)?;
+
+        if let Some(id) = key.strip_prefix("tx/") {
+            Ok(Subject::Transaction(Hash::parse_hex(id.as_bytes())?))
+        } else if let Some(id) = key.strip_prefix("key/") {
+            Ok(Subject::Key(id.to_string()))
+        } else {
+            Err(anyhow!("invalid annotation subject: {}", key))
+        }
+    }
+}
+
+impl Display for Subject {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Subject::Transaction(id) => write!(f, "transaction: {}", id),
+            Subject::Key(id) => write!(f, "key: {}", id),
+        }
+    }
+}
+
+/// Local-only labels which operators attach to transactions and keys, these are never sent to peers
+pub struct Annotations {
+    db: Db,
+}
+
+impl Annotations {
+    pub fn open(db: Db) -> Result<Self> {
+        Ok(Self { db })
+    }
+
+    pub fn get(&self, subject: &Subject) -> Result<Vec<String>> {
+        let tree = self.db.open_tree("nuts/annotations")?;
+
+        match tree.get(subject.to_key())? {
+            Some(value) => Ok(decode::from_read(value.as_ref())?),
+            None => Ok(vec![]),
+        }
+    }
+
+    pub fn add(&self, subject: &Subject, label: String) -> Result<()> {
+        let mut labels = self.get(subject)?;
+
+        if !labels.contains(&label) {
+            labels.push(label);
+        }
+
+        self.set(subject, labels)
+    }
+
+    pub fn remove(&self, subject: &Subject, label: &str) -> Result<()> {
+        let mut labels = self.get(subject)?;
+
+        labels.retain(|l| l != label);
+
+        self.set(subject, labels)
+    }
+
+    fn set(&self, subject: &Subject, labels: Vec<String>) -> Result<()> {
+        let tree = self.db.open_tree("nuts/annotations")?;
+
+        if labels.is_empty() {
+            tree.remove(subject.to_key())?;
+        } else {
+            tree.insert(subject.to_key(), encode::to_vec(&labels)?)?;
+        }
+
+        Ok(())
+    }
+
+    /// Returns all subjects which have the given label
+    pub fn search(&self, label: &str) -> Result<Vec<Subject>> {
+        let mut subjects = vec![];
+
+        for record in self.db.open_tree("nuts/annotations")?.iter() {
+            let (key, value) = record?;
+            let labels: Vec<String> = decode::from_read(value.as_ref())?;
+
+            if labels.iter().any(|l| l == label) {
+                subjects.push(Subject::from_key(&key)?);
+            }
+        }
+
+        Ok(subjects)
+    }
+}
@@ -0,0 +1,317 @@
+use std::fmt::{self, Display, Formatter};
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::sync::Arc;
+
+use anyhow::{anyhow, Error, Result};
+use bytes::Bytes;
+use serde::{Deserialize, Serialize};
+use sled::Db;
+
+use crate::schema::META_TREE;
+
+#[cfg(feature = "redb")]
+pub use self::redb::RedbStorage;
+
+#[cfg(feature = "redb")]
+mod redb;
+
+const BACKEND_KEY: &str = "storage_backend";
+
+/// Path of the file of the backend, relative to the data directory
+const PATH_KEY: &str = "storage_path";
+
+/// Data directory the database was opened from, which is recorded on every open as sled doesn't expose it
+const DIR_KEY: &str = "storage_dir";
+
+const REDB_FILE: &str = "trees.redb";
+
+/// Records of a tree in the order of their keys
+pub type Records<'a> = Box<dyn Iterator<Item = Result<(Vec<u8>, Vec<u8>)>> + 'a>;
+
+/// Ordered key-value tree of a storage backend
+pub trait Tree: Send + Sync {
+    fn get(&self, key: &[u8]) -> Result<Option<Vec<u8>>>;
+
+    /// Same as [`Tree::get`] but shares the value with the cache of the backend instead of copying it, when the
+    /// backend supports it
+    fn get_shared(&self, key: &[u8]) -> Result<Option<Bytes>> {
+        Ok(self.get(key)?.map(Bytes::from))
+    }
+
+    fn contains_key(&self, key: &[u8]) -> Result<bool> {
+        Ok(self.get(key)?.is_some())
+    }
+
+    fn insert(&self, key: &[u8], value: &[u8]) -> Result<()>;
+
+    /// Inserts all records at once, either all or none of them are written
+    fn insert_all(&self, records: Vec<(Vec<u8>, Vec<u8>)>) -> Result<()>;
+
+    /// Removes the record, returns false when there was no record with the key
+    fn remove(&self, key: &[u8]) -> Result<bool>;
+
+    fn len(&self) -> Result<usize>;
+
+    fn is_empty(&self) -> Result<bool> {
+        Ok(self.len()? == 0)
+    }
+
+    /// Iterates over the records of which the key starts with the prefix
+    fn scan_prefix(&self, prefix: &[u8]) -> Records<'_>;
+
+    fn iter(&self) -> Records<'_> {
+        self.scan_prefix(&[])
+    }
+
+    /// Blocks until all writes are persisted
+    fn flush(&self) -> Result<()>;
+}
+
+/// Storage of the trees which grow with the size of the network: the DAG, keys, payloads and peers. Other trees are
+/// always stored in sled
+pub trait Storage: Send + Sync {
+    fn open_tree(&self, name: &str) -> Result<Arc<dyn Tree>>;
+
+    /// Returns the names of all trees which were opened at least once
+    fn tree_names(&self) -> Result<Vec<String>>;
+}
+
+/// Storage backends which can be used for the large trees
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Backend {
+    #[default]
+    Sled,
+    /// Stored in a single redb file in the data directory, which keeps the memory usage down for large DAGs
+    Redb,
+}
+
+impl Display for Backend {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            Backend::Sled => write!(f, "sled"),
+            Backend::Redb => write!(f, "redb"),
+        }
+    }
+}
+
+impl FromStr for Backend {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "sled" => Ok(Backend::Sled),
+            "redb" => Ok(Backend::Redb),
+            _ => Err(anyhow!(
+                "invalid storage backend '{}' (expected sled or redb)",
+                s
+            )),
+        }
+    }
+}
+
+struct SledTree(sled::Tree);
+
+impl Tree for SledTree {
+    fn get(&self, key: &[u8]) -> Result<Option<Vec<u8>>> {
+        Ok(self.0.get(key)?.map(|value| value.to_vec()))
+    }
+
+    fn get_shared(&self, key: &[u8]) -> Result<Option<Bytes>> {
+        Ok(self.0.get(key)?.map(Bytes::from_owner))
+    }
+
+    fn contains_key(&self, key: &[u8]) -> Result<bool> {
+        Ok(self.0.contains_key(key)?)
+    }
+
+    fn insert(&self, key: &[u8], value: &[u8]) -> Result<()> {
+        self.0.insert(key, value)?;
+
+        Ok(())
+    }
+
+    fn insert_all(&self, records: Vec<(Vec<u8>, Vec<u8>)>) -> Result<()> {
+        let mut batch = sled::Batch::default();
+
+        for (key, value) in records {
+            batch.insert(key, value);
+        }
+
+        Ok(self.0.apply_batch(batch)?)
+    }
+
+    fn remove(&self, key: &[u8]) -> Result<bool> {
+        Ok(self.0.remove(key)?.is_some())
+    }
+
+    fn len(&self) -> Result<usize> {
+        Ok(self.0.len())
+    }
+
+    fn is_empty(&self) -> Result<bool> {
+        Ok(self.0.is_empty())
+    }
+
+    fn scan_prefix(&self, prefix: &[u8]) -> Records<'_> {
+        Box::new(self.0.scan_prefix(prefix).map(|record| {
+            let (key, value) = record?;
+
+            Ok((key.to_vec(), value.to_vec()))
+        }))
+    }
+
+    fn flush(&self) -> Result<()> {
+        self.0.flush()?;
+
+        Ok(())
+    }
+}
+
+impl Storage for Db {
+    fn open_tree(&self, name: &str) -> Result<Arc<dyn Tree>> {
+        Ok(Arc::new(SledTree(sled::Db::open_tree(self, name)?)))
+    }
+
+    fn tree_names(&self) -> Result<Vec<String>> {
+        sled::Db::tree_names(self)
+            .into_iter()
+            .map(|name| Ok(String::from_utf8(name.to_vec())?))
+            .collect()
+    }
+}
+
+/// Returns the backend in which the large trees of the database are stored
+pub fn backend(db: &Db) -> Result<Backend> {
+    match db.open_tree(META_TREE)?.get(BACKEND_KEY)? {
+        Some(value) => std::str::from_utf8(&value)?.parse(),
+        None => Ok(Backend::Sled),
+    }
+}
+
+/// Records the data directory the database was opened from, the files of other backends are stored relative to it
+/// so that the data directory can be moved or restored elsewhere
+pub fn attach(db: &Db, data_dir: &Path) -> Result<()> {
+    let meta = db.open_tree(META_TREE)?;
+    let data_dir = data_dir.canonicalize()?;
+    let data_dir = data_dir.to_string_lossy();
+
+    if meta.get(DIR_KEY)?.as_deref() != Some(data_dir.as_bytes()) {
+        meta.insert(DIR_KEY, data_dir.as_bytes())?;
+    }
+
+    // Older versions stored the absolute path of the file, which was always in the data directory
+    if let Some(path) = meta.get(PATH_KEY)? {
+        if Path::new(std::str::from_utf8(&path)?).is_absolute() {
+            meta.insert(PATH_KEY, REDB_FILE.as_bytes())?;
+        }
+    }
+
+    Ok(())
+}
+
+/// Whether the record of the tree only applies to the host the database is stored on, which isn't included in
+/// backups
+pub fn is_local(tree: &[u8], key: &[u8]) -> bool {
+    tree == META_TREE.as_bytes() && key == DIR_KEY.as_bytes()
+}
+
+/// Chooses the backend of a new database, the backend of a database can't be changed once it's used
+pub fn select(db: &Db, backend: Backend, data_dir: Option<&Path>) -> Result<()> {
+    let meta = db.open_tree(META_TREE)?;
+
+    if meta.contains_key(BACKEND_KEY)? || !crate::schema::is_empty(db)? {
+        let current = self::backend(db)?;
+
+        if current != backend {
+            return Err(anyhow!(
+                "database uses the {} storage backend and can't be switched to {}",
+                current,
+                backend
+            ));
+        }
+
+        return Ok(());
+    }
+
+    if backend == Backend::Redb {
+        if data_dir.is_none() {
+            return Err(anyhow!(
+                "the redb storage backend requires the database to be stored on disk"
+            ));
+        }
+
+        meta.insert(PATH_KEY, REDB_FILE.as_bytes())?;
+    }
+
+    meta.insert(BACKEND_KEY, backend.to_string().as_bytes())?;
+    db.flush()?;
+
+    Ok(())
+}
+
+/// Opens the storage of the large trees of the database
+pub fn open(db: &Db) -> Result<Arc<dyn Storage>> {
+    match backend(db)? {
+        Backend::Sled => Ok(Arc::new(db.clone())),
+        Backend::Redb => open_redb(db),
+    }
+}
+
+fn data_dir(db: &Db) -> Result<PathBuf> {
+    let data_dir = db
+        .open_tree(META_TREE)?
+        .get(DIR_KEY)?
+        .ok_or_else(|| anyhow!("data directory of the database is unknown"))?;
+
+    Ok(PathBuf::from(std::str::from_utf8(&data_dir)?))
+}
+
+/// Moves a tree which older versions always stored in sled into the storage backend, which is a no-op for databases
+/// which use sled as their backend
+pub(crate) fn move_tree(db: &Db, name: &str) -> Result<()> {
+    if backend(db)? == Backend::Sled {
+        return Ok(());
+    }
+
+    let records = db
+        .open_tree(name)?
+        .iter()
+        .map(|record| {
+            let (key, value) = record?;
+
+            Ok((key.to_vec(), value.to_vec()))
+        })
+        .collect::<Result<Vec<_>>>()?;
+    let tree = open(db)?.open_tree(name)?;
+
+    tree.insert_all(records)?;
+    tree.flush()?;
+    db.drop_tree(name)?;
+
+    Ok(())
+}
+
+/// Returns the path of the file in which the redb backend stores the trees of a new database
+pub fn redb_path(db: &Db) -> Result<PathBuf> {
+    Ok(data_dir(db)?.join(REDB_FILE))
+}
+
+#[cfg(feature = "redb")]
+fn open_redb(db: &Db) -> Result<Arc<dyn Storage>> {
+    let path = db
+        .open_tree(META_TREE)?
+        .get(PATH_KEY)?
+        .ok_or_else(|| anyhow!("path of the redb storage backend is missing"))?;
+    let path = data_dir(db)?.join(std::str::from_utf8(&path)?);
+
+    Ok(Arc::new(RedbStorage::open(&path)?))
+}
+
+#[cfg(not(feature = "redb"))]
+fn open_redb(_db: &Db) -> Result<Arc<dyn Storage>> {
+    Err(anyhow!(
+        "database uses the redb storage backend, which requires the redb feature"
+    ))
+}
@@ -0,0 +1,210 @@
+use std::collections::VecDeque;
+use std::ops::Bound;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex, Weak};
+
+use ::redb::{Database, Durability, ReadableTable, TableDefinition, TableHandle};
+use anyhow::Result;
+
+use crate::backend::{Records, Storage, Tree};
+
+/// Number of records which are read at once when iterating over a tree
+const SCAN_BATCH_SIZE: usize = 1024;
+
+/// Databases which are open in this process, a redb file can only be opened once while every store opens the storage
+/// of the database separately
+static OPEN: Mutex<Vec<(PathBuf, Weak<Database>)>> = Mutex::new(Vec::new());
+
+/// Stores every tree as a table in a single redb file. Writes are committed right away but only persisted to disk
+/// when a tree is flushed, which is comparable to sled which persists writes on an interval
+pub struct RedbStorage {
+    db: Arc<Database>,
+}
+
+impl RedbStorage {
+    pub fn open(path: &Path) -> Result<Self> {
+        let mut open = OPEN.lock().unwrap();
+
+        open.retain(|(_, db)| db.strong_count() > 0);
+
+        if let Some(db) = open
+            .iter()
+            .find(|(other, _)| other == path)
+            .and_then(|(_, db)| db.upgrade())
+        {
+            return Ok(Self { db });
+        }
+
+        let db = Arc::new(Database::create(path)?);
+
+        open.push((path.to_path_buf(), Arc::downgrade(&db)));
+
+        Ok(Self { db })
+    }
+}
+
+impl Storage for RedbStorage {
+    fn open_tree(&self, name: &str) -> Result<Arc<dyn Tree>> {
+        let tree = RedbTree {
+            db: self.db.clone(),
+            name: name.to_string(),
+        };
+
+        // Tables only exist after they're opened in a write transaction, which makes reading an empty tree possible
+        tree.write(|_| Ok(()))?;
+
+        Ok(Arc::new(tree))
+    }
+
+    fn tree_names(&self) -> Result<Vec<String>> {
+        let txn = self.db.begin_read()?;
+        let names = txn
+            .list_tables()?
+            .map(|table| table.name().to_string())
+            .collect();
+
+        Ok(names)
+    }
+}
+
+struct RedbTree {
+    db: Arc<Database>,
+    name: String,
+}
+
+impl RedbTree {
+    fn table(&self) -> TableDefinition<'_, &'static [u8], &'static [u8]> {
+        TableDefinition::new(&self.name)
+    }
+
+    fn write<T>(
+        &self,
+        f: impl FnOnce(&mut ::redb::Table<'_, '_, &'static [u8], &'static [u8]>) -> Result<T>,
+    ) -> Result<T> {
+        let mut txn = self.db.begin_write()?;
+
+        txn.set_durability(Durability::Eventual);
+
+        let result = f(&mut txn.open_table(self.table())?)?;
+
+        txn.commit()?;
+
+        Ok(result)
+    }
+
+    /// Reads the next batch of records starting at the given bound
+    fn scan(&self, from: Bound<&[u8]>, prefix: &[u8]) -> Result<Vec<(Vec<u8>, Vec<u8>)>> {
+        let txn = self.db.begin_read()?;
+        let table = txn.open_table(self.table())?;
+        let mut records = vec![];
+
+        for record in table.range::<&[u8]>((from, Bound::Unbounded))? {
+            let (key, value) = record?;
+
+            if !key.value().starts_with(prefix) || records.len() == SCAN_BATCH_SIZE {
+                break;
+            }
+
+            records.push((key.value().to_vec(), value.value().to_vec()));
+        }
+
+        Ok(records)
+    }
+}
+
+impl Tree for RedbTree {
+    fn get(&self, key: &[u8]) -> Result<Option<Vec<u8>>> {
+        let txn = self.db.begin_read()?;
+        let table = txn.open_table(self.table())?;
+        let value = table.get(key)?.map(|value| value.value().to_vec());
+
+        Ok(value)
+    }
+
+    fn insert(&self, key: &[u8], value: &[u8]) -> Result<()> {
+        self.write(|table| {
+            table.insert(key, value)?;
+
+            Ok(())
+        })
+    }
+
+    fn insert_all(&self, records: Vec<(Vec<u8>, Vec<u8>)>) -> Result<()> {
+        self.write(|table| {
+            for (key, value) in records.iter() {
+                table.insert(key.as_slice(), value.as_slice())?;
+            }
+
+            Ok(())
+        })
+    }
+
+    fn remove(&self, key: &[u8]) -> Result<bool> {
+        self.write(|table| Ok(table.remove(key)?.is_some()))
+    }
+
+    fn len(&self) -> Result<usize> {
+        let txn = self.db.begin_read()?;
+        let len = txn.open_table(self.table())?.len()?;
+
+        Ok(len as usize)
+    }
+
+    fn scan_prefix(&self, prefix: &[u8]) -> Records<'_> {
+        Box::new(Scan {
+            tree: self,
+            prefix: prefix.to_vec(),
+            last: None,
+            buffer: VecDeque::new(),
+            done: false,
+        })
+    }
+
+    fn flush(&self) -> Result<()> {
+        // Committing with immediate durability persists the commits before it as well
+        let mut txn = self.db.begin_write()?;
+
+        txn.set_durability(Durability::Immediate);
+        txn.commit()?;
+
+        Ok(())
+    }
+}
+
+/// Iterates over a tree in batches, so that a read transaction isn't kept open while the records are handled
+struct Scan<'a> {
+    tree: &'a RedbTree,
+    prefix: Vec<u8>,
+    /// Key of the last record which was read
+    last: Option<Vec<u8>>,
+    buffer: VecDeque<(Vec<u8>, Vec<u8>)>,
+    done: bool,
+}
+
+impl Iterator for Scan<'_> {
+    type Item = Result<(Vec<u8>, Vec<u8>)>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.buffer.is_empty() && !self.done {
+            let from = match &self.last {
+                Some(key) => Bound::Excluded(key.as_slice()),
+                None => Bound::Included(self.prefix.as_slice()),
+            };
+
+            match self.tree.scan(from, &self.prefix) {
+                Ok(records) => {
+                    self.done = records.len() < SCAN_BATCH_SIZE;
+                    self.last = records.last().map(|(key, _)| key.clone());
+                    self.buffer.extend(records);
+                }
+                Err(e) => {
+                    self.done = true;
+
+                    return Some(Err(e));
+                }
+            }
+        }
+
+        self.buffer.pop_front().map(Ok)
+    }
+}
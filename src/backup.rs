@@ -0,0 +1,365 @@
+use std::convert::TryInto;
+use std::fs::File;
+use std::io::{self, BufWriter, Read, Seek, SeekFrom, Write};
+
+use anyhow::{anyhow, Result};
+use chrono::Utc;
+use nuts_rs::backend::{self, Backend};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use sled::Db;
+
+/// Version of the backup format, backups with another version are rejected when restoring
+const FORMAT_VERSION: u32 = 1;
+
+const MANIFEST: &str = "manifest.json";
+
+/// Collection type of the trees of the redb backend, which are stored in the same way as the trees of sled
+const REDB_KIND: &[u8] = b"tree";
+
+/// Tree of the database as it's stored in a backup
+#[derive(Debug, Serialize, Deserialize)]
+pub struct TreeEntry {
+    pub name: String,
+    /// Hex encoded collection type as used by sled
+    pub kind: String,
+    /// Backend the tree is stored in, the trees of the redb backend are restored into a new redb file
+    #[serde(default)]
+    pub backend: Backend,
+    pub records: u64,
+    /// Hex encoded SHA-256 checksum of the file in which the records are stored
+    pub sha256: String,
+}
+
+/// First file in a backup which lists the trees and their checksums
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Manifest {
+    pub version: u32,
+    pub created_at: i64,
+    pub trees: Vec<TreeEntry>,
+}
+
+impl Manifest {
+    pub fn records(&self) -> u64 {
+        self.trees.iter().map(|tree| tree.records).sum()
+    }
+}
+
+fn tree_path(i: usize) -> String {
+    format!("trees/{}.bin", i)
+}
+
+/// Computes the checksum of everything that's written to the inner writer
+struct Checksum<W> {
+    inner: W,
+    hasher: Sha256,
+}
+
+impl<W: Write> Write for Checksum<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let n = self.inner.write(buf)?;
+
+        self.hasher.update(&buf[..n]);
+
+        Ok(n)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+/// Encodes the records of a tree as length-prefixed fields and returns the number of records
+fn encode(
+    records: impl Iterator<Item = Result<Vec<Vec<u8>>>>,
+    out: &mut impl Write,
+) -> Result<u64> {
+    let mut count = 0;
+
+    for fields in records {
+        let fields = fields?;
+
+        out.write_all(&(fields.len() as u32).to_be_bytes())?;
+
+        for field in fields {
+            out.write_all(&(field.len() as u32).to_be_bytes())?;
+            out.write_all(&field)?;
+        }
+
+        count += 1;
+    }
+
+    Ok(count)
+}
+
+fn decode(mut data: &[u8]) -> Result<Vec<Vec<Vec<u8>>>> {
+    fn take<'a>(data: &mut &'a [u8], len: usize) -> Result<&'a [u8]> {
+        if data.len() < len {
+            return Err(anyhow!("unexpected end of tree data"));
+        }
+
+        let (head, tail) = data.split_at(len);
+
+        *data = tail;
+
+        Ok(head)
+    }
+
+    fn take_len(data: &mut &[u8]) -> Result<usize> {
+        Ok(u32::from_be_bytes(take(data, 4)?.try_into()?) as usize)
+    }
+
+    let mut records = vec![];
+
+    while !data.is_empty() {
+        let count = take_len(&mut data)?;
+        let mut fields = Vec::with_capacity(count);
+
+        for _ in 0..count {
+            let len = take_len(&mut data)?;
+
+            fields.push(take(&mut data, len)?.to_vec());
+        }
+
+        records.push(fields);
+    }
+
+    Ok(records)
+}
+
+fn append(
+    archive: &mut tar::Builder<impl Write>,
+    path: &str,
+    size: u64,
+    data: impl Read,
+) -> Result<()> {
+    let mut header = tar::Header::new_gnu();
+
+    header.set_size(size);
+    header.set_mode(0o600);
+    header.set_mtime(Utc::now().timestamp() as u64);
+    header.set_cksum();
+
+    archive.append_data(&mut header, path, data)?;
+
+    Ok(())
+}
+
+/// Writes the records of a tree to a temporary file
+fn spool(
+    name: String,
+    kind: &[u8],
+    backend: Backend,
+    records: impl Iterator<Item = Result<Vec<Vec<u8>>>>,
+) -> Result<(TreeEntry, File)> {
+    let mut file = tempfile::tempfile()?;
+    let (records, sha256) = {
+        let mut spool = Checksum {
+            inner: BufWriter::new(&mut file),
+            hasher: Sha256::new(),
+        };
+        let records = encode(records, &mut spool)?;
+
+        spool.flush()?;
+
+        (records, spool.hasher.finalize())
+    };
+
+    let entry = TreeEntry {
+        name,
+        kind: hex::encode(kind),
+        backend,
+        records,
+        sha256: hex::encode(sha256),
+    };
+
+    Ok((entry, file))
+}
+
+/// Writes all trees of the database to a zstd compressed tar archive, including the trees of the redb backend. The
+/// trees are spooled to temporary files first as the manifest with their checksums comes first. sled doesn't support
+/// snapshots, so the trees are exported one after another and a backup of a running node isn't a point in time copy
+/// (e.g. the payload of a transaction which was added during the backup might be missing)
+pub fn create(db: &Db, out: impl Write) -> Result<Manifest> {
+    db.flush()?;
+
+    let mut manifest = Manifest {
+        version: FORMAT_VERSION,
+        created_at: Utc::now().timestamp(),
+        trees: vec![],
+    };
+    let mut files = vec![];
+
+    for (kind, name, records) in db.export() {
+        let tree = name.clone();
+        let records = records
+            .filter(|fields| !backend::is_local(&tree, &fields[0]))
+            .map(Ok);
+        let (entry, file) = spool(String::from_utf8(name)?, &kind, Backend::Sled, records)?;
+
+        manifest.trees.push(entry);
+        files.push(file);
+    }
+
+    if backend::backend(db)? == Backend::Redb {
+        let storage = backend::open(db)?;
+
+        for name in storage.tree_names()? {
+            let tree = storage.open_tree(&name)?;
+            let records = tree
+                .iter()
+                .map(|record| record.map(|(key, value)| vec![key, value]));
+            let (entry, file) = spool(name, REDB_KIND, Backend::Redb, records)?;
+
+            manifest.trees.push(entry);
+            files.push(file);
+        }
+    }
+
+    let mut archive = tar::Builder::new(zstd::Encoder::new(out, 0)?);
+    let listing = serde_json::to_vec_pretty(&manifest)?;
+
+    // The manifest comes first so that the checksums are known before the trees are read
+    append(
+        &mut archive,
+        MANIFEST,
+        listing.len() as u64,
+        listing.as_slice(),
+    )?;
+
+    for (i, mut file) in files.into_iter().enumerate() {
+        let size = file.seek(SeekFrom::End(0))?;
+
+        file.seek(SeekFrom::Start(0))?;
+        append(&mut archive, &tree_path(i), size, file)?;
+    }
+
+    archive.into_inner()?.finish()?.flush()?;
+
+    Ok(manifest)
+}
+
+/// Restores a backup into an empty database, nothing is written unless the checksums of all trees match
+pub fn restore(db: &Db, input: impl Read) -> Result<Manifest> {
+    for name in db.tree_names() {
+        for key in db.open_tree(&name)?.iter().keys() {
+            if !backend::is_local(&name, &key?) {
+                return Err(anyhow!(
+                    "unable to restore into a database which isn't empty (tree '{}' has records)",
+                    String::from_utf8_lossy(&name)
+                ));
+            }
+        }
+    }
+
+    let mut archive = tar::Archive::new(zstd::Decoder::new(input)?);
+    let mut entries = archive.entries()?;
+    let mut next = || -> Result<Option<(String, Vec<u8>)>> {
+        let mut entry = match entries.next() {
+            Some(entry) => entry?,
+            None => return Ok(None),
+        };
+        let path = entry.path()?.to_string_lossy().to_string();
+        let mut data = vec![];
+
+        entry.read_to_end(&mut data)?;
+
+        Ok(Some((path, data)))
+    };
+    let manifest: Manifest = match next()? {
+        Some((path, data)) if path == MANIFEST => serde_json::from_slice(&data)?,
+        _ => return Err(anyhow!("backup doesn't start with a manifest")),
+    };
+
+    if manifest.version != FORMAT_VERSION {
+        return Err(anyhow!(
+            "unsupported backup version {} (expected {})",
+            manifest.version,
+            FORMAT_VERSION
+        ));
+    }
+
+    let mut collections = vec![];
+    let mut redb_trees = vec![];
+
+    for (i, tree) in manifest.trees.iter().enumerate() {
+        let data = match next()? {
+            Some((path, data)) if path == tree_path(i) => data,
+            Some((path, _)) => return Err(anyhow!("unexpected file in backup: {}", path)),
+            None => {
+                return Err(anyhow!(
+                    "backup is incomplete, {} of {} trees are present",
+                    i,
+                    manifest.trees.len()
+                ))
+            }
+        };
+
+        if hex::encode(Sha256::digest(&data)) != tree.sha256 {
+            return Err(anyhow!("checksum of tree '{}' doesn't match", tree.name));
+        }
+
+        let records = decode(&data)?;
+
+        if records.len() as u64 != tree.records {
+            return Err(anyhow!(
+                "tree '{}' has {} records while the manifest lists {}",
+                tree.name,
+                records.len(),
+                tree.records
+            ));
+        }
+
+        match tree.backend {
+            Backend::Sled => collections.push((
+                hex::decode(&tree.kind)?,
+                tree.name.as_bytes().to_vec(),
+                records.into_iter(),
+            )),
+            Backend::Redb => redb_trees.push((tree.name.as_str(), records)),
+        }
+    }
+
+    if !redb_trees.is_empty() {
+        if !cfg!(feature = "redb") {
+            return Err(anyhow!(
+                "backup contains trees of the redb storage backend, which requires the redb feature"
+            ));
+        }
+
+        let path = backend::redb_path(db)?;
+
+        if path.exists() {
+            return Err(anyhow!(
+                "unable to restore the trees of the redb storage backend, '{}' already exists",
+                path.display()
+            ));
+        }
+    }
+
+    db.import(collections);
+    db.flush()?;
+
+    if !redb_trees.is_empty() {
+        let storage = backend::open(db)?;
+
+        for (name, records) in redb_trees {
+            let tree = storage.open_tree(name)?;
+            let records = records
+                .into_iter()
+                .map(|fields| {
+                    let [key, value]: [Vec<u8>; 2] = fields
+                        .try_into()
+                        .map_err(|_| anyhow!("invalid record in tree '{}'", name))?;
+
+                    Ok((key, value))
+                })
+                .collect::<Result<Vec<_>>>()?;
+
+            tree.insert_all(records)?;
+            tree.flush()?;
+        }
+    }
+
+    Ok(manifest)
+}
@@ -0,0 +1,156 @@
+use anyhow::{anyhow, Result};
+use chrono::Utc;
+use nuts_rs::pki::{
+    sign_es256, thumbprint, verify_es256, Decision, Key, KeyStorage, KeyStore, PrivateKeyStore,
+    TrustPolicy, TrustRecord,
+};
+use serde::{Deserialize, Serialize};
+use sled::Db;
+
+/// Keys, trust decisions and revocations which are shared between the nodes of an organization
+#[derive(Debug, Serialize, Deserialize)]
+struct Contents {
+    created_at: i64,
+    keys: Vec<Key>,
+    trust: Vec<TrustRecord>,
+}
+
+/// Signed bundle as it's written to a file
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Bundle {
+    /// ID of the key which signed the bundle
+    signer: String,
+    /// Base64url encoded JSON contents
+    payload: String,
+    /// Base64url encoded ES256 signature over the payload
+    signature: String,
+}
+
+/// Summary of the changes made by importing a bundle
+#[derive(Debug, Default)]
+pub struct ImportSummary {
+    pub keys: usize,
+    pub decisions: usize,
+    pub revocations: usize,
+    /// Keys which are already known with a different public key
+    pub conflicts: Vec<String>,
+}
+
+impl Bundle {
+    /// Exports all keys, trust decisions and revocations of the node signed by one of it's private keys
+    pub fn export(db: Db, private_keys: &PrivateKeyStore, signer: &str) -> Result<Self> {
+        let signing_key = private_keys
+            .get(signer)?
+            .ok_or_else(|| anyhow!("private key not found with ID: {}", signer))?;
+        let contents = Contents {
+            created_at: Utc::now().timestamp(),
+            keys: KeyStore::open(db.clone())?.list()?,
+            trust: TrustPolicy::open(db)?.list()?,
+        };
+        let payload = serde_json::to_vec(&contents)?;
+
+        Ok(Self {
+            signer: signer.to_string(),
+            signature: base64::encode_config(
+                sign_es256(&signing_key, &payload),
+                base64::URL_SAFE_NO_PAD,
+            ),
+            payload: base64::encode_config(payload, base64::URL_SAFE_NO_PAD),
+        })
+    }
+
+    /// Verifies the signature using the key of the signer, which must be explicitly trusted by this node or pinned
+    /// by the operator
+    fn verify(
+        &self,
+        key_store: &KeyStore,
+        trust: &TrustPolicy,
+        pinned_signer: Option<&str>,
+    ) -> Result<Contents> {
+        let key = key_store
+            .get(&self.signer)?
+            .ok_or_else(|| anyhow!("bundle is signed by an unknown key: {}", self.signer))?;
+
+        trust.check(&self.signer, Utc::now().timestamp())?;
+
+        match pinned_signer {
+            Some(pinned) if pinned != self.signer => {
+                return Err(anyhow!(
+                    "bundle is signed by '{}' instead of the pinned signer '{}'",
+                    self.signer,
+                    pinned
+                ))
+            }
+            Some(_) => {}
+            None => {
+                let decision = trust.get(&self.signer)?.and_then(|record| record.decision);
+
+                if decision != Some(Decision::Trusted) {
+                    return Err(anyhow!(
+                        "bundle is signed by key '{}' which isn't trusted (trust it first or pin it using --signer)",
+                        self.signer
+                    ));
+                }
+            }
+        }
+
+        let payload = base64::decode_config(&self.payload, base64::URL_SAFE_NO_PAD)?;
+        let signature = base64::decode_config(&self.signature, base64::URL_SAFE_NO_PAD)?;
+
+        verify_es256(&key, &payload, &signature)
+            .map_err(|e| anyhow!("failed to verify bundle: {}", e))?;
+
+        Ok(serde_json::from_slice(&payload)?)
+    }
+
+    /// Merges the bundle into the local key store and trust policy, local trust decisions take precedence over
+    /// the ones in the bundle while revocations are always applied
+    pub fn import(&self, db: Db, pinned_signer: Option<&str>) -> Result<ImportSummary> {
+        let mut key_store = KeyStore::open(db.clone())?;
+        let trust = TrustPolicy::open(db)?;
+        let contents = self.verify(&key_store, &trust, pinned_signer)?;
+        let mut summary = ImportSummary::default();
+
+        for key in contents.keys {
+            let key_id = key
+                .common
+                .key_id
+                .clone()
+                .ok_or_else(|| anyhow!("bundle contains a key without an ID"))?;
+
+            match key_store.get(&key_id)? {
+                Some(existing) if thumbprint(&existing)? != thumbprint(&key)? => {
+                    summary.conflicts.push(key_id)
+                }
+                Some(_) => {}
+                None => {
+                    key_store.add(key_id, key)?;
+                    summary.keys += 1;
+                }
+            }
+        }
+
+        for record in contents.trust {
+            let local = trust.get(&record.key_id)?;
+
+            if let Some(decision) = record.decision {
+                if local.as_ref().and_then(|local| local.decision).is_none() {
+                    trust.decide(&record.key_id, decision)?;
+                    summary.decisions += 1;
+                }
+            }
+
+            if let Some(revoked_at) = record.revoked_at {
+                match local.and_then(|local| local.revoked_at) {
+                    Some(current) if current <= revoked_at => {}
+                    _ => {
+                        trust.revoke(&record.key_id, revoked_at)?;
+                        summary.revocations += 1;
+                    }
+                }
+            }
+        }
+
+        Ok(summary)
+    }
+}
@@ -0,0 +1,81 @@
+use anyhow::{anyhow, Result};
+use chrono::NaiveDateTime;
+use clap::Clap;
+use sled::Db;
+
+use crate::admin::{Role, TokenStore};
+
+#[derive(Clap)]
+pub struct Opts {
+    #[clap(subcommand)]
+    cmd: Cmd,
+}
+
+#[derive(Clap)]
+pub struct CreateTokenOpts {
+    /// Role of the token (read or write)
+    #[clap(long)]
+    role: Role,
+}
+
+#[derive(Clap)]
+pub struct RevokeTokenOpts {
+    id: String,
+}
+
+#[derive(Clap)]
+pub enum TokenCmd {
+    /// Creates a token which grants access to the admin API
+    Create(CreateTokenOpts),
+
+    /// Lists all tokens
+    List,
+
+    /// Revokes a token by it's ID
+    Revoke(RevokeTokenOpts),
+}
+
+#[derive(Clap)]
+pub enum Cmd {
+    /// Manages the tokens which grant access to the admin API
+    #[clap(subcommand)]
+    Token(TokenCmd),
+}
+
+async fn create_token(db: Db, opts: CreateTokenOpts) -> Result<()> {
+    let (token, secret) = TokenStore::open(db)?.create(opts.role)?;
+
+    eprintln!("created {} token: {}", token.role, token.id);
+    println!("{}", secret);
+
+    Ok(())
+}
+
+async fn list_tokens(db: Db) -> Result<()> {
+    for token in TokenStore::open(db)?.list()? {
+        println!(
+            "{} (role: {}, created at: {})",
+            token.id,
+            token.role,
+            NaiveDateTime::from_timestamp(token.created_at, 0)
+        );
+    }
+
+    Ok(())
+}
+
+async fn revoke_token(db: Db, opts: RevokeTokenOpts) -> Result<()> {
+    if !TokenStore::open(db)?.revoke(&opts.id)? {
+        return Err(anyhow!("token not found with id: {}", opts.id));
+    }
+
+    Ok(())
+}
+
+pub async fn cmd(db: Db, opts: Opts) -> Result<()> {
+    match opts.cmd {
+        Cmd::Token(TokenCmd::Create(opts)) => create_token(db, opts).await,
+        Cmd::Token(TokenCmd::List) => list_tokens(db).await,
+        Cmd::Token(TokenCmd::Revoke(opts)) => revoke_token(db, opts).await,
+    }
+}
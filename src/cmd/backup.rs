@@ -0,0 +1,118 @@
+use std::fs::File;
+use std::io::{BufReader, BufWriter};
+use std::net::SocketAddr;
+use std::path::PathBuf;
+
+use anyhow::{anyhow, Result};
+use chrono::NaiveDateTime;
+use clap::Clap;
+use hyper::body::{self, HttpBody};
+use hyper::header::AUTHORIZATION;
+use hyper::{Body, Client, Method, Request};
+use sled::Db;
+use tokio::io::AsyncWriteExt;
+use tokio::{fs, task};
+
+use crate::backup;
+
+#[derive(Clap)]
+pub struct Opts {
+    /// File to write the backup to (a zstd compressed tar archive)
+    #[clap(long)]
+    out: PathBuf,
+
+    /// Address of the admin API of a running node to take the backup from, as the database is locked while the node
+    /// is running (e.g. 127.0.0.1:1323)
+    #[clap(long)]
+    admin_addr: Option<SocketAddr>,
+
+    /// Admin API token with the write role
+    #[clap(long, env = "NUTS_ADMIN_TOKEN")]
+    token: Option<String>,
+}
+
+#[derive(Clap)]
+pub struct RestoreOpts {
+    /// Backup to restore, the data directory must be empty
+    path: PathBuf,
+}
+
+/// Downloads a backup from the admin API of a running node, which is written to the file as it's received
+async fn fetch(addr: SocketAddr, opts: &Opts) -> Result<()> {
+    let token = opts.token.as_deref().ok_or_else(|| {
+        anyhow!("an admin API token is required, use --token or NUTS_ADMIN_TOKEN")
+    })?;
+    let request = Request::builder()
+        .method(Method::POST)
+        .uri(format!("http://{}/backup", addr))
+        .header(AUTHORIZATION, format!("Bearer {}", token))
+        .body(Body::empty())?;
+    let response = Client::new()
+        .request(request)
+        .await
+        .map_err(|e| anyhow!("unable to reach admin API on {}: {}", addr, e))?;
+    let status = response.status();
+    let mut body = response.into_body();
+
+    if !status.is_success() {
+        return Err(anyhow!(
+            "failed to create backup ({}): {}",
+            status,
+            String::from_utf8_lossy(&body::to_bytes(body).await?)
+        ));
+    }
+
+    let mut file = fs::File::create(&opts.out).await?;
+    let mut len = 0;
+
+    while let Some(chunk) = body.data().await {
+        let chunk = chunk?;
+
+        file.write_all(&chunk).await?;
+        len += chunk.len();
+    }
+
+    file.sync_all().await?;
+
+    println!("wrote backup of {} bytes to: {}", len, opts.out.display());
+
+    Ok(())
+}
+
+/// Takes the backup from a running node when the address of it's admin API is given, which doesn't need the database
+pub async fn online(opts: &Opts) -> Option<Result<()>> {
+    let addr = opts.admin_addr?;
+
+    Some(fetch(addr, opts).await)
+}
+
+pub async fn cmd(db: Db, opts: Opts) -> Result<()> {
+    let out = opts.out.clone();
+    let manifest =
+        task::spawn_blocking(move || backup::create(&db, BufWriter::new(File::create(out)?)))
+            .await??;
+
+    println!(
+        "wrote backup of {} trees ({} records) to: {}",
+        manifest.trees.len(),
+        manifest.records(),
+        opts.out.display()
+    );
+
+    Ok(())
+}
+
+pub async fn restore(db: Db, opts: RestoreOpts) -> Result<()> {
+    let manifest =
+        task::spawn_blocking(move || backup::restore(&db, BufReader::new(File::open(opts.path)?)))
+            .await??;
+
+    println!(
+        "restored {} trees ({} records) from backup created at {}",
+        manifest.trees.len(),
+        manifest.records(),
+        NaiveDateTime::from_timestamp(manifest.created_at, 0)
+    );
+
+    Ok(())
+}
@@ -0,0 +1,102 @@
+use std::path::PathBuf;
+use std::time::Duration;
+
+use anyhow::{anyhow, Result};
+use clap::Clap;
+use nuts_rs::network::Conformance;
+use sha2::{Digest, Sha256};
+use tonic::transport::{Certificate, Identity};
+use uuid::Uuid;
+
+use crate::config::Config;
+use crate::output::{print_json, Output};
+use crate::tls::TlsFiles;
+
+#[derive(Clap)]
+pub struct Opts {
+    /// Address of the peer to test (e.g. https://nuts.example.com:5555)
+    addr: String,
+
+    /// ID of the network the peer is part of (defaults to the network of this node)
+    #[clap(long)]
+    network_id: Option<String>,
+
+    /// Peer ID to connect with, defaults to an ID derived from the certificate so that it stays the same between
+    /// runs (peers bind the peer ID to the certificate)
+    #[clap(long)]
+    peer_id: Option<Uuid>,
+
+    /// Maximum time in seconds to wait for each response of the peer
+    #[clap(long, default_value = "10")]
+    timeout: u64,
+
+    /// Path to the PEM encoded CA certificates which are trusted (defaults to tls/truststore.pem)
+    #[clap(long)]
+    tls_truststore: Option<PathBuf>,
+
+    /// Path to the PEM encoded certificate to connect with (defaults to tls/localhost.pem)
+    #[clap(long)]
+    tls_certificate: Option<PathBuf>,
+
+    /// Path to the PEM encoded private key to connect with (defaults to tls/localhost.key)
+    #[clap(long)]
+    tls_key: Option<PathBuf>,
+
+    /// Prints the report as JSON
+    #[clap(long)]
+    json: bool,
+}
+
+/// Runs the conformance checks against a peer and prints a report, fails when any of the checks failed
+pub async fn cmd(config: &Config, opts: &Opts, output: Output) -> Result<()> {
+    let files = TlsFiles::new(
+        opts.tls_truststore.as_ref(),
+        opts.tls_certificate.as_ref(),
+        opts.tls_key.as_ref(),
+        &config.tls,
+    );
+    let material = files.read().await?;
+    let peer_id = match opts.peer_id {
+        Some(peer_id) => peer_id,
+        None => Uuid::from_slice(&Sha256::digest(&material.certificate)[..16])?,
+    };
+    let network_id = opts
+        .network_id
+        .clone()
+        .or_else(|| config.network.network_id.clone())
+        .unwrap_or_else(|| "default".to_string());
+    let conformance = Conformance::new(
+        Certificate::from_pem(&material.truststore),
+        Identity::from_pem(&material.certificate, &material.key),
+        peer_id,
+        network_id,
+        Duration::from_secs(opts.timeout),
+    );
+    let results = conformance.run(&opts.addr).await;
+
+    if opts.json || output.is_json() {
+        print_json(&results)?;
+    } else {
+        for result in results.iter() {
+            println!(
+                "{} {:<24} {}",
+                if result.passed { "PASS" } else { "FAIL" },
+                result.name,
+                result.detail
+            );
+        }
+    }
+
+    let failed = results.iter().filter(|result| !result.passed).count();
+
+    if failed > 0 {
+        return Err(anyhow!(
+            "{} of {} checks failed against '{}'",
+            failed,
+            results.len(),
+            opts.addr
+        ));
+    }
+
+    Ok(())
+}
@@ -0,0 +1,213 @@
+use std::io::{self, BufRead, Write};
+
+use anyhow::{anyhow, Result};
+use clap::Clap;
+use sled::{Db, IVec};
+
+use nuts_rs::network::Graph;
+use nuts_rs::schema;
+
+#[derive(Clap)]
+pub struct Opts {
+    #[clap(subcommand)]
+    cmd: Cmd,
+}
+
+#[derive(Clap)]
+pub struct InspectOpts {
+    /// Name of the tree to inspect, lists all trees when omitted
+    tree: Option<String>,
+
+    /// Key of the record to dump (binary keys are prefixed with `0x`)
+    key: Option<String>,
+
+    /// Maximum number of records to list
+    #[clap(long, default_value = "100")]
+    limit: usize,
+
+    /// Delete the record instead of dumping it
+    #[clap(long)]
+    delete: bool,
+
+    /// Don't ask for confirmation when deleting a record
+    #[clap(long)]
+    yes: bool,
+}
+
+#[derive(Clap)]
+pub struct MigrateOpts {
+    /// Only list the pending migrations without applying them
+    #[clap(long)]
+    dry_run: bool,
+}
+
+#[derive(Clap)]
+pub enum Cmd {
+    /// Inspect the raw database trees and records
+    Inspect(InspectOpts),
+    /// Rebuild the edges of the DAG from the previous transactions of each transaction
+    RepairEdges,
+    /// Upgrade the database to the layout used by this version of the node
+    Migrate(MigrateOpts),
+}
+
+/// Formats raw bytes as text when printable or as prefixed hex otherwise
+fn format_bytes(bytes: &[u8]) -> String {
+    match std::str::from_utf8(bytes) {
+        Ok(text) if !text.is_empty() && text.chars().all(|c| !c.is_control()) => text.to_string(),
+        _ => format!("0x{}", hex::encode(bytes)),
+    }
+}
+
+fn parse_key(key: &str) -> Result<Vec<u8>> {
+    match key.strip_prefix("0x") {
+        Some(key) => Ok(hex::decode(key)?),
+        None => Ok(key.as_bytes().to_vec()),
+    }
+}
+
+fn confirm(question: &str) -> Result<bool> {
+    print!("{} [y/N] ", question);
+    io::stdout().flush()?;
+
+    let mut answer = String::new();
+
+    io::stdin().lock().read_line(&mut answer)?;
+
+    Ok(matches!(answer.trim(), "y" | "Y" | "yes"))
+}
+
+fn list_trees(db: &Db) -> Result<()> {
+    for name in db.tree_names() {
+        let tree = db.open_tree(&name)?;
+        let mut size = 0;
+
+        for record in tree.iter() {
+            let (key, value) = record?;
+
+            size += key.len() + value.len();
+        }
+
+        println!(
+            "{} (keys: {}, size: {} bytes)",
+            format_bytes(&name),
+            tree.len(),
+            size
+        );
+    }
+
+    Ok(())
+}
+
+fn list_records(db: &Db, name: &str, limit: usize) -> Result<()> {
+    let tree = open_existing_tree(db, name)?;
+
+    for record in tree.iter().take(limit) {
+        let (key, value) = record?;
+
+        println!("{} ({} bytes)", format_bytes(&key), value.len());
+    }
+
+    if tree.len() > limit {
+        println!("... {} more records", tree.len() - limit);
+    }
+
+    Ok(())
+}
+
+fn open_existing_tree(db: &Db, name: &str) -> Result<sled::Tree> {
+    if !db.tree_names().contains(&IVec::from(name)) {
+        return Err(anyhow!("tree not found: {}", name));
+    }
+
+    Ok(db.open_tree(name)?)
+}
+
+fn inspect_record(db: &Db, name: &str, key: &str, opts: &InspectOpts) -> Result<()> {
+    let tree = open_existing_tree(db, name)?;
+    let key = parse_key(key)?;
+    let value = tree
+        .get(&key)?
+        .ok_or_else(|| anyhow!("record not found: {}", format_bytes(&key)))?;
+
+    if !opts.delete {
+        println!("{}", format_bytes(&value));
+
+        return Ok(());
+    }
+
+    if !opts.yes
+        && !confirm(&format!(
+            "delete record '{}' from tree '{}'?",
+            format_bytes(&key),
+            name
+        ))?
+    {
+        return Ok(());
+    }
+
+    tree.remove(&key)?;
+    tree.flush()?;
+
+    println!("deleted record: {}", format_bytes(&key));
+
+    Ok(())
+}
+
+async fn inspect(db: Db, opts: InspectOpts) -> Result<()> {
+    match (&opts.tree, &opts.key) {
+        (None, _) => list_trees(&db),
+        (Some(tree), None) => list_records(&db, tree, opts.limit),
+        (Some(tree), Some(key)) => inspect_record(&db, tree, key, &opts),
+    }
+}
+
+async fn repair_edges(db: Db) -> Result<()> {
+    let repair = Graph::repair_edges(&db)?;
+
+    // Loading the graph adds every transaction again which validates the rebuilt DAG
+    let graph = Graph::open(db)?;
+
+    graph.flush().await?;
+
+    println!("transactions: {}", repair.transactions);
+    println!("corrected links: {}", repair.corrected);
+    println!("reindexed transactions: {}", repair.reindexed);
+    println!("heads: {}", graph.heads().len());
+
+    Ok(())
+}
+
+async fn migrate(db: Db, opts: MigrateOpts) -> Result<()> {
+    println!("schema version: {}", schema::version(&db)?);
+    println!("supported version: {}", schema::SCHEMA_VERSION);
+
+    let migrations = if opts.dry_run {
+        schema::pending(&db)?
+    } else {
+        schema::migrate(&db)?
+    };
+
+    if migrations.is_empty() {
+        println!("no pending migrations");
+    }
+
+    for migration in migrations {
+        println!(
+            "{} {}: {}",
+            if opts.dry_run { "pending" } else { "applied" },
+            migration.version,
+            migration.description
+        );
+    }
+
+    Ok(())
+}
+
+pub async fn cmd(db: Db, opts: Opts) -> Result<()> {
+    match opts.cmd {
+        Cmd::Inspect(opts) => inspect(db, opts).await,
+        Cmd::RepairEdges => repair_edges(db).await,
+        Cmd::Migrate(opts) => migrate(db, opts).await,
+    }
+}
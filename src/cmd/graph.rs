@@ -1,8 +1,22 @@
-use anyhow::Result;
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::path::PathBuf;
+use std::time::Duration;
+
+use anyhow::{anyhow, Result};
+use chrono::{NaiveDateTime, Utc};
 use clap::Clap;
+use nuts_rs::network::{
+    export, parse_period, Admission, ExportFormat, ExportedTransaction, Graph, Stats, Transaction,
+    Verification, DEFAULT_MAX_CLOCK_SKEW,
+};
+use nuts_rs::pki::{Key, KeyStore, TrustPolicy};
+use serde::Serialize;
+use serde_json::{Map, Value};
 use sled::Db;
+use tokio::fs;
 
-use crate::network::{Graph, Hash};
+use crate::annotations::{Annotations, Subject};
+use crate::output::{print_json, Output};
 
 #[derive(Clap)]
 pub struct Opts {
@@ -12,7 +26,66 @@ pub struct Opts {
 
 #[derive(Clap)]
 pub struct GetOpts {
+    /// ID of the transaction, which can be abbreviated to a unique hex prefix
     id: String,
+
+    /// Show which key and rules were used to verify the transaction when it was admitted
+    #[clap(long)]
+    verification: bool,
+}
+
+#[derive(Clap)]
+pub struct ExportOpts {
+    /// Format of the export (graphml, gexf, dot or ndjson), defaults to the extension of the output file or graphml
+    #[clap(long)]
+    format: Option<ExportFormat>,
+
+    /// File to write the export to, defaults to stdout
+    #[clap(long)]
+    out: Option<PathBuf>,
+}
+
+#[derive(Clap)]
+pub struct ImportOpts {
+    /// File with a JSON object with the compact JWS (`{"jws": "..."}`) of a transaction on every line, as written by
+    /// `graph export --format ndjson`
+    path: PathBuf,
+}
+
+#[derive(Clap)]
+pub struct LabelOpts {
+    /// ID of the transaction, which can be abbreviated to a unique hex prefix
+    id: String,
+    label: String,
+
+    /// Remove the label instead of adding it
+    #[clap(long)]
+    remove: bool,
+}
+
+#[derive(Clap)]
+pub struct SearchOpts {
+    /// Label to search transactions and keys for
+    #[clap(long)]
+    label: String,
+}
+
+#[derive(Clap)]
+pub struct StatsOpts {
+    /// Show the statistics recorded by the node within the given period (e.g. 30m, 24h or 7d)
+    #[clap(long, parse(try_from_str = parse_period))]
+    history: Option<Duration>,
+}
+
+#[derive(Clap)]
+pub struct OrphansOpts {
+    /// Remove the orphan from the pool, it's parked again when a peer sends it another time
+    #[clap(long = "drop")]
+    drop: Vec<String>,
+
+    /// Query the missing previous transactions of the orphan again on the next sync
+    #[clap(long)]
+    retry: Vec<String>,
 }
 
 #[derive(Clap)]
@@ -22,11 +95,63 @@ pub enum Cmd {
 
     /// Get, and decode a transaction by it's hash
     Get(GetOpts),
+
+    /// Exports the DAG to a standard graph format or a portable archive of signed transactions
+    Export(ExportOpts),
+
+    /// Verifies the transactions in an archive and adds them to the DAG
+    Import(ImportOpts),
+
+    /// Verifies the signature and previous transactions of every transaction in the DAG
+    Verify,
+
+    /// Attaches a local-only label to a transaction
+    Label(LabelOpts),
+
+    /// Searches for transactions and keys by label
+    Search(SearchOpts),
+
+    /// Shows the size of the DAG or how it changed over time
+    Stats(StatsOpts),
+
+    /// Lists the transactions which are waiting for their previous transactions to arrive
+    Orphans(OrphansOpts),
+
+    /// Lists the transactions which aren't referenced by any other transaction yet
+    Heads,
 }
 
-async fn list_transactions(db: Db) -> Result<()> {
+/// Transaction as shown by `graph get`, hashes are hex encoded
+#[derive(Serialize)]
+struct TransactionInfo<'a> {
+    id: String,
+    key: Option<&'a Key>,
+    key_id: &'a str,
+    version: usize,
+    sign_algorithm: String,
+    sign_at: String,
+    payload: String,
+    payload_type: &'a str,
+    lamport_clock: u32,
+    extra_headers: &'a Map<String, Value>,
+    /// Number of encrypted participants, only present for private transactions
+    participants: Option<usize>,
+    previous: Vec<String>,
+    labels: Vec<String>,
+    verification: Option<&'a Verification>,
+}
+
+async fn list_transactions(db: Db, output: Output) -> Result<()> {
     let store = Graph::open(db)?;
 
+    if output.is_json() {
+        let mut ids = vec![];
+
+        store.walk(|tx| ids.push(tx.id.to_string()));
+
+        return print_json(&ids);
+    }
+
     store.walk(|tx| {
         println!("{}", tx.id);
     });
@@ -34,11 +159,51 @@ async fn list_transactions(db: Db) -> Result<()> {
     Ok(())
 }
 
-async fn get_transaction(db: Db, opts: GetOpts) -> Result<()> {
-    let store = Graph::open(db)?;
-    let hash = Hash::parse_hex(opts.id.as_bytes())?;
+async fn list_heads(db: Db) -> Result<()> {
+    for id in Graph::open(db)?.heads() {
+        println!("{}", id);
+    }
+
+    Ok(())
+}
+
+async fn get_transaction(db: Db, opts: GetOpts, output: Output) -> Result<()> {
+    let store = Graph::open(db.clone())?;
+    let annotations = Annotations::open(db)?;
+    let hash = store.resolve_id(&opts.id).await?;
 
     match store.get(&hash) {
+        Some(tx) if output.is_json() => {
+            print_json(&TransactionInfo {
+                id: tx.id.to_string(),
+                key: tx.key.as_ref(),
+                key_id: &tx.key_id,
+                version: tx.version,
+                sign_algorithm: format!("{:?}", tx.sign_algo),
+                sign_at: tx.sign_at.to_string(),
+                payload: tx.payload.to_string(),
+                payload_type: &tx.payload_type,
+                lamport_clock: store.clock(&tx.id).unwrap_or_default(),
+                extra_headers: &tx.extra_headers,
+                participants: if tx.is_private() {
+                    Some(tx.pal.len())
+                } else {
+                    None
+                },
+                previous: store
+                    .parents(&tx.id)
+                    .unwrap_or_default()
+                    .iter()
+                    .map(|parent| parent.id.to_string())
+                    .collect(),
+                labels: annotations.get(&Subject::Transaction(tx.id.clone()))?,
+                verification: if opts.verification {
+                    tx.verification.as_ref()
+                } else {
+                    None
+                },
+            })?;
+        }
         Some(tx) => {
             println!("id: {}", tx.id);
             println!("key: {:?}", tx.key);
@@ -46,15 +211,50 @@ async fn get_transaction(db: Db, opts: GetOpts) -> Result<()> {
             println!("version: {}", tx.version);
             println!("sign_algorithm: {:?}", tx.sign_algo);
             println!("sign_at: {}", tx.sign_at);
+            println!("payload: {}", tx.payload);
             println!("payload_type: {}", tx.payload_type);
+            println!("lamport_clock: {}", store.clock(&tx.id).unwrap_or_default());
+
+            for (name, value) in tx.extra_headers.iter() {
+                println!("header {}: {}", name, value);
+            }
+
+            if tx.is_private() {
+                println!("participants: {} (encrypted)", tx.pal.len());
+            }
+
             println!(
                 "previous: {}",
-                tx.prevs
+                store
+                    .parents(&tx.id)
+                    .unwrap_or_default()
                     .iter()
-                    .map(|id| format!("{}", id))
+                    .map(|parent| format!("{}", parent.id))
                     .collect::<Vec<_>>()
                     .join(", ")
             );
+
+            let labels = annotations.get(&Subject::Transaction(tx.id.clone()))?;
+
+            if !labels.is_empty() {
+                println!("labels: {}", labels.join(", "));
+            }
+
+            if opts.verification {
+                match &tx.verification {
+                    Some(verification) => {
+                        println!("verification:");
+                        println!("  key_thumbprint: {}", verification.key_thumbprint);
+                        println!("  algorithm: {}", verification.algorithm);
+                        println!("  policy_version: {}", verification.policy_version);
+                        println!(
+                            "  verified_at: {}",
+                            NaiveDateTime::from_timestamp(verification.verified_at, 0)
+                        );
+                    }
+                    None => println!("verification: not recorded"),
+                }
+            }
         }
         None => eprintln!("transaction not found with id: {}", hash),
     };
@@ -62,9 +262,269 @@ async fn get_transaction(db: Db, opts: GetOpts) -> Result<()> {
     Ok(())
 }
 
-pub async fn cmd(db: Db, opts: Opts) -> Result<()> {
+async fn export_graph(db: Db, opts: ExportOpts) -> Result<()> {
+    let store = Graph::open(db)?;
+    let format = match (opts.format, &opts.out) {
+        (Some(format), _) => format,
+        (None, Some(path)) if path.extension().is_some_and(|ext| ext == "ndjson") => {
+            ExportFormat::Ndjson
+        }
+        (None, _) => ExportFormat::GraphML,
+    };
+    let output = export(&store, format)?;
+
+    match opts.out {
+        Some(path) => fs::write(path, output).await?,
+        None => print!("{}", output),
+    };
+
+    Ok(())
+}
+
+async fn import_graph(db: Db, opts: ImportOpts) -> Result<()> {
+    let mut store = Graph::open(db.clone())?;
+    let mut key_store = KeyStore::open(db.clone())?;
+    let encoded = fs::read_to_string(opts.path)
+        .await?
+        .lines()
+        .enumerate()
+        .filter(|(_, line)| !line.trim().is_empty())
+        .map(|(i, line)| {
+            let line: ExportedTransaction = serde_json::from_str(line)
+                .map_err(|e| anyhow!("invalid transaction on line {}: {}", i + 1, e))?;
+
+            Ok(line.jws.into_bytes())
+        })
+        .collect::<Result<Vec<_>>>()?;
+    let total = encoded.len();
+    let admission = Admission::new(
+        std::thread::available_parallelism()?.get(),
+        false,
+        DEFAULT_MAX_CLOCK_SKEW,
+        TrustPolicy::open(db)?,
+    );
+    let verified = admission.verify(&mut key_store, encoded).await?;
+    let rejected = total - verified.len();
+    let transactions = Admission::schedule(&store, verified);
+    let imported = transactions.len();
+
+    for tx in transactions {
+        store.add(tx)?;
+    }
+
+    store.flush().await?;
+
+    println!(
+        "imported {} transactions ({} already present, {} rejected)",
+        imported,
+        total - rejected - imported,
+        rejected
+    );
+
+    if rejected > 0 {
+        return Err(anyhow!("{} transactions failed verification", rejected));
+    }
+
+    Ok(())
+}
+
+async fn verify_graph(db: Db) -> Result<()> {
+    let store = Graph::open(db.clone())?;
+    let key_store = KeyStore::open(db)?;
+    let transactions = store
+        .iter()
+        .map(|tx| (&tx.id, tx))
+        .collect::<HashMap<_, _>>();
+    let mut problems = 0;
+
+    for tx in store.iter() {
+        if let Err(e) = Transaction::parse(&key_store, String::from_utf8(tx.data.clone())?) {
+            println!("{}: invalid signature: {}", tx.id, e);
+            problems += 1;
+        }
+
+        for id in tx.prevs.iter() {
+            match transactions.get(id) {
+                Some(prev) if prev.sign_at > tx.sign_at => {
+                    println!(
+                        "{}: signed at {} before previous transaction '{}' which is signed at {}",
+                        tx.id, tx.sign_at, id, prev.sign_at
+                    );
+                    problems += 1;
+                }
+                Some(_) => {}
+                None => {
+                    println!("{}: previous transaction '{}' is missing", tx.id, id);
+                    problems += 1;
+                }
+            }
+        }
+    }
+
+    for tx in store.orphans() {
+        println!(
+            "{}: dangling transaction with missing previous transactions",
+            tx.id
+        );
+        problems += 1;
+    }
+
+    println!(
+        "verified {} transactions ({} orphans), found {} problems",
+        transactions.len(),
+        store.orphans().len(),
+        problems
+    );
+
+    if problems > 0 {
+        return Err(anyhow!("graph verification failed"));
+    }
+
+    Ok(())
+}
+
+async fn label_transaction(db: Db, opts: LabelOpts) -> Result<()> {
+    let store = Graph::open(db.clone())?;
+    let annotations = Annotations::open(db)?;
+    let hash = store.resolve_id(&opts.id).await?;
+
+    if store.get(&hash).is_none() {
+        return Err(anyhow!("transaction not found with id: {}", hash));
+    }
+
+    let subject = Subject::Transaction(hash);
+
+    if opts.remove {
+        annotations.remove(&subject, &opts.label)
+    } else {
+        annotations.add(&subject, opts.label)
+    }
+}
+
+async fn search(db: Db, opts: SearchOpts) -> Result<()> {
+    let annotations = Annotations::open(db)?;
+
+    for subject in annotations.search(&opts.label)? {
+        println!("{}", subject);
+    }
+
+    Ok(())
+}
+
+async fn show_stats(db: Db, opts: StatsOpts) -> Result<()> {
+    let period = match opts.history {
+        Some(period) => period,
+        None => {
+            let store = Graph::open(db.clone())?;
+            let mut payload_types: BTreeMap<&str, usize> = BTreeMap::new();
+            let mut signers = HashSet::new();
+
+            for tx in store.iter() {
+                *payload_types.entry(&tx.payload_type).or_default() += 1;
+                signers.insert(&tx.key_id);
+            }
+
+            println!("transactions: {}", store.len());
+            println!("heads: {}", store.heads().len());
+            println!("orphans: {}", store.orphans().len());
+            println!("depth: {}", store.lamport_clock());
+            println!("signers: {}", signers.len());
+
+            if let Some(earliest) = store.iter().map(|tx| tx.sign_at).min() {
+                println!("earliest sign_at: {}", earliest);
+            }
+
+            if let Some(latest) = store.iter().map(|tx| tx.sign_at).max() {
+                println!("latest sign_at: {}", latest);
+            }
+
+            println!("stored size: {} bytes", Graph::stored_size(&db)?);
+            println!("payload types:");
+
+            for (payload_type, count) in payload_types {
+                println!("  {}: {}", payload_type, count);
+            }
+
+            return Ok(());
+        }
+    };
+
+    println!("time\ttransactions\theads\tadmitted\tpeers");
+
+    for sample in Stats::open(db)?.history(period)? {
+        println!(
+            "{}\t{}\t{}\t{}\t{}",
+            NaiveDateTime::from_timestamp(sample.timestamp, 0),
+            sample.transactions,
+            sample.heads,
+            sample.admitted,
+            sample.peers
+        );
+    }
+
+    Ok(())
+}
+
+async fn orphans(db: Db, opts: OrphansOpts) -> Result<()> {
+    let mut store = Graph::open(db)?;
+
+    for id in opts.drop.iter() {
+        let hash = store.resolve_orphan(id).await?;
+
+        if !store.drop_orphan(&hash)? {
+            return Err(anyhow!("orphan not found with id: {}", id));
+        }
+    }
+
+    for id in opts.retry.iter() {
+        let hash = store.resolve_orphan(id).await?;
+
+        if !store.retry_orphan(&hash)? {
+            return Err(anyhow!("orphan not found with id: {}", id));
+        }
+    }
+
+    if !opts.drop.is_empty() || !opts.retry.is_empty() {
+        return store.flush().await;
+    }
+
+    let now = Utc::now().timestamp();
+
+    println!("id\tmissing\twaiting\tpeer");
+
+    for orphan in store.pending_orphans()? {
+        println!(
+            "{}\t{}\t{}s{}\t{}",
+            orphan.id,
+            orphan
+                .missing
+                .iter()
+                .map(|id| id.to_string())
+                .collect::<Vec<_>>()
+                .join(","),
+            now - orphan.received_at,
+            if orphan.retry { " (retrying)" } else { "" },
+            orphan
+                .peer_id
+                .map(|peer_id| peer_id.to_string())
+                .unwrap_or_else(|| "unknown".to_string())
+        );
+    }
+
+    Ok(())
+}
+
+pub async fn cmd(db: Db, opts: Opts, output: Output) -> Result<()> {
     match opts.cmd {
-        Cmd::List => list_transactions(db).await,
-        Cmd::Get(opts) => get_transaction(db, opts).await,
+        Cmd::List => list_transactions(db, output).await,
+        Cmd::Get(opts) => get_transaction(db, opts, output).await,
+        Cmd::Export(opts) => export_graph(db, opts).await,
+        Cmd::Import(opts) => import_graph(db, opts).await,
+        Cmd::Verify => verify_graph(db).await,
+        Cmd::Label(opts) => label_transaction(db, opts).await,
+        Cmd::Search(opts) => search(db, opts).await,
+        Cmd::Stats(opts) => show_stats(db, opts).await,
+        Cmd::Orphans(opts) => orphans(db, opts).await,
+        Cmd::Heads => list_heads(db).await,
     }
 }
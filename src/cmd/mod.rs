@@ -1,3 +1,13 @@
+pub mod admin;
+pub mod backup;
+pub mod conformance;
+pub mod db;
 pub mod graph;
+pub mod network;
+pub mod payload;
 pub mod pki;
 pub mod run;
+pub mod supervise;
+pub mod support;
+pub mod top;
+pub mod tx;
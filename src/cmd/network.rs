@@ -0,0 +1,338 @@
+use std::net::SocketAddr;
+use std::path::Path;
+
+use anyhow::{anyhow, Result};
+use chrono::{NaiveDateTime, Utc};
+use clap::Clap;
+use nuts_rs::network::{AddressBook, Blocklist, ConnectionLog, Health, PeerIdentities, PeerRule};
+use sled::Db;
+
+use crate::output::{print_json, Output};
+use crate::status;
+
+#[derive(Clap)]
+pub struct Opts {
+    #[clap(subcommand)]
+    cmd: Cmd,
+}
+
+#[derive(Clap)]
+pub enum Cmd {
+    /// Prints the peer ID of this node, which is generated when the node starts for the first time
+    Id,
+    /// Lists all peers in the address book
+    Peers(PeersOpts),
+    /// Shows the health of the running node
+    Status(StatusOpts),
+    /// Shows what the connected peers of the running node reported about themselves
+    Diagnostics(DiagnosticsOpts),
+    /// Blocks peers by address, CIDR or certificate fingerprint, lists the blocked peers when none is given
+    Block(BlockOpts),
+    /// Removes a peer from the blocklist
+    Unblock(UnblockOpts),
+}
+
+#[derive(Clap)]
+pub struct PeersOpts {
+    #[clap(subcommand)]
+    cmd: Option<PeersCmd>,
+}
+
+#[derive(Clap)]
+pub enum PeersCmd {
+    /// Shows a timeline of the connection events of a peer
+    History(HistoryOpts),
+    /// Lists the certificates the peer IDs of peers are bound to
+    Identities,
+    /// Removes the certificate binding of a peer so that it can connect using another peer ID or certificate
+    Unbind(UnbindOpts),
+}
+
+#[derive(Clap)]
+pub struct HistoryOpts {
+    /// Peer ID or address of the peer
+    peer: String,
+}
+
+#[derive(Clap)]
+pub struct UnbindOpts {
+    /// Peer ID of the peer or the subject of it's certificate
+    peer: String,
+}
+
+#[derive(Clap)]
+pub struct StatusOpts {
+    /// Address of the admin API to query instead of reading the status file (e.g. 127.0.0.1:1323)
+    #[clap(long)]
+    admin_addr: Option<SocketAddr>,
+
+    /// Prints the status as JSON
+    #[clap(long)]
+    json: bool,
+}
+
+#[derive(Clap)]
+pub struct BlockOpts {
+    /// DNS name, IP address, CIDR or SHA-256 certificate fingerprint of the peer
+    rule: Option<PeerRule>,
+}
+
+#[derive(Clap)]
+pub struct UnblockOpts {
+    /// Rule the peer was blocked by
+    rule: PeerRule,
+}
+
+#[derive(Clap)]
+pub struct DiagnosticsOpts {
+    /// Address of the admin API to query instead of reading the status file (e.g. 127.0.0.1:1323)
+    #[clap(long)]
+    admin_addr: Option<SocketAddr>,
+}
+
+async fn show_id(db: Db) -> Result<()> {
+    println!("{}", PeerIdentities::open(db)?.local_peer_id()?);
+
+    Ok(())
+}
+
+async fn list_peers(db: Db, output: Output) -> Result<()> {
+    let address_book = AddressBook::open(db)?;
+    let peers = address_book.list()?;
+
+    if output.is_json() {
+        return print_json(&peers);
+    }
+
+    for peer in peers {
+        println!(
+            "{} (peer ID: {}, last seen: {})",
+            peer.addr,
+            peer.peer_id,
+            NaiveDateTime::from_timestamp(peer.last_seen, 0)
+        );
+    }
+
+    Ok(())
+}
+
+async fn peer_history(db: Db, opts: HistoryOpts) -> Result<()> {
+    let connection_log = ConnectionLog::open(db.clone())?;
+    let mut events = connection_log.history(&opts.peer)?;
+
+    // Outgoing connections are recorded by address until the peer ID is known
+    for peer in AddressBook::open(db)?.list()? {
+        if peer.peer_id == opts.peer {
+            events.extend(connection_log.history(&peer.addr)?);
+        }
+    }
+
+    events.sort_by_key(|event| event.at);
+
+    if events.is_empty() {
+        println!("no connection events recorded for peer: {}", opts.peer);
+    }
+
+    for event in events {
+        println!(
+            "{} {:<16} {}{}",
+            NaiveDateTime::from_timestamp(
+                event.at.div_euclid(1000),
+                (event.at.rem_euclid(1000) * 1_000_000) as u32
+            ),
+            event.kind.to_string(),
+            event
+                .addr
+                .map(|addr| format!("[{}] ", addr))
+                .unwrap_or_default(),
+            event.detail
+        );
+    }
+
+    Ok(())
+}
+
+async fn list_identities(db: Db) -> Result<()> {
+    for identity in PeerIdentities::open(db)?.list()? {
+        println!(
+            "{} (peer ID: {}, DNS names: {}, bound at: {})",
+            identity.subject,
+            identity.peer_id,
+            if identity.dns_names.is_empty() {
+                "none".to_string()
+            } else {
+                identity.dns_names.join(",")
+            },
+            NaiveDateTime::from_timestamp(identity.bound_at, 0)
+        );
+    }
+
+    Ok(())
+}
+
+async fn unbind(db: Db, opts: UnbindOpts) -> Result<()> {
+    match PeerIdentities::open(db)?.unbind(&opts.peer)? {
+        0 => Err(anyhow!("no certificate bound to peer: {}", opts.peer)),
+        count => {
+            println!("removed {} binding(s) of peer: {}", count, opts.peer);
+
+            Ok(())
+        }
+    }
+}
+
+async fn block(db: Db, opts: BlockOpts, output: Output) -> Result<()> {
+    let blocklist = Blocklist::open(db)?;
+    let rule = match opts.rule {
+        Some(rule) => rule,
+        None => {
+            let blocked = blocklist.list()?;
+
+            if output.is_json() {
+                return print_json(&blocked);
+            }
+
+            for peer in blocked {
+                println!(
+                    "{} (blocked at: {})",
+                    peer.rule,
+                    NaiveDateTime::from_timestamp(peer.blocked_at, 0)
+                );
+            }
+
+            return Ok(());
+        }
+    };
+
+    if blocklist.block(&rule)? {
+        println!("blocked peer: {}", rule);
+    } else {
+        println!("peer was already blocked: {}", rule);
+    }
+
+    Ok(())
+}
+
+async fn unblock(db: Db, opts: UnblockOpts) -> Result<()> {
+    if !Blocklist::open(db)?.unblock(&opts.rule)? {
+        return Err(anyhow!("peer isn't blocked: {}", opts.rule));
+    }
+
+    println!("unblocked peer: {}", opts.rule);
+
+    Ok(())
+}
+
+fn print_status(health: &Health) {
+    let ago = |timestamp: i64| format!("{}s ago", Utc::now().timestamp() - timestamp);
+
+    println!(
+        "status:       {}",
+        if health.is_healthy() {
+            "healthy"
+        } else {
+            "unhealthy"
+        }
+    );
+    println!("sync:         {}", health.sync);
+    println!("updated:      {}", ago(health.updated_at));
+    println!("height:       {}", health.height);
+    println!("transactions: {}", health.transactions);
+    println!("root:         {}", health.root.as_deref().unwrap_or("-"));
+    println!("peers:        {}", health.peers);
+    println!(
+        "last sync:    {}",
+        health.last_sync.map(ago).as_deref().unwrap_or("never")
+    );
+    println!(
+        "storage:      {}",
+        match &health.storage.error {
+            Some(e) => format!("error: {}", e),
+            None => format!("ok ({} bytes)", health.storage.size_on_disk),
+        }
+    );
+    println!(
+        "payloads:     {} retained, {} expired, {} pending",
+        health.payloads.retained, health.payloads.expired, health.payloads.pending
+    );
+
+    for failures in health.payloads.failures.iter() {
+        println!(
+            "  failed to serve payloads: {} ({} missing, {} invalid, {} timeouts, last {})",
+            failures.peer_id,
+            failures.missing,
+            failures.invalid,
+            failures.timeouts,
+            ago(failures.last_failure_at)
+        );
+    }
+}
+
+async fn show_status(data_dir: &Path, opts: &StatusOpts, output: Output) -> Result<()> {
+    let health = status::load(data_dir, opts.admin_addr).await?;
+
+    if opts.json || output.is_json() {
+        print_json(&health)?;
+    } else {
+        print_status(&health);
+    }
+
+    Ok(())
+}
+
+async fn show_diagnostics(data_dir: &Path, opts: &DiagnosticsOpts, output: Output) -> Result<()> {
+    let diagnostics = status::load(data_dir, opts.admin_addr).await?.diagnostics;
+
+    if output.is_json() {
+        return print_json(&diagnostics);
+    }
+
+    if diagnostics.is_empty() {
+        println!("no diagnostics received from peers");
+    }
+
+    for peer in diagnostics {
+        println!(
+            "{} (version: {}, software: {}, uptime: {}s, transactions: {}, peers: {}, received: {}s ago)",
+            peer.peer_id,
+            peer.software_version.as_deref().unwrap_or("-"),
+            peer.software_id.as_deref().unwrap_or("-"),
+            peer.uptime,
+            peer.transactions,
+            peer.peers.len(),
+            Utc::now().timestamp() - peer.received_at
+        );
+    }
+
+    Ok(())
+}
+
+/// Runs the commands which don't need the database, returns `None` for all other commands
+pub async fn status(data_dir: &Path, opts: &Opts, output: Output) -> Option<Result<()>> {
+    match &opts.cmd {
+        Cmd::Status(opts) => Some(show_status(data_dir, opts, output).await),
+        Cmd::Diagnostics(opts) => Some(show_diagnostics(data_dir, opts, output).await),
+        _ => None,
+    }
+}
+
+pub async fn cmd(db: Db, opts: Opts, output: Output) -> Result<()> {
+    match opts.cmd {
+        Cmd::Id => show_id(db).await,
+        Cmd::Peers(PeersOpts { cmd: None }) => list_peers(db, output).await,
+        Cmd::Peers(PeersOpts {
+            cmd: Some(PeersCmd::History(opts)),
+        }) => peer_history(db, opts).await,
+        Cmd::Peers(PeersOpts {
+            cmd: Some(PeersCmd::Identities),
+        }) => list_identities(db).await,
+        Cmd::Peers(PeersOpts {
+            cmd: Some(PeersCmd::Unbind(opts)),
+        }) => unbind(db, opts).await,
+        Cmd::Block(opts) => block(db, opts, output).await,
+        Cmd::Unblock(opts) => unblock(db, opts).await,
+        Cmd::Status(_) | Cmd::Diagnostics(_) => {
+            unreachable!("the status is shown before the database is opened")
+        }
+    }
+}
@@ -0,0 +1,58 @@
+use anyhow::Result;
+use clap::Clap;
+use nuts_rs::network::{Graph, PayloadStore};
+use sled::Db;
+
+#[derive(Clap)]
+pub struct Opts {
+    #[clap(subcommand)]
+    cmd: Cmd,
+}
+
+#[derive(Clap)]
+pub struct GetOpts {
+    /// Hex or base64url encoded hash of the payload, which can be abbreviated to a unique hex prefix
+    hash: String,
+}
+
+#[derive(Clap)]
+pub enum Cmd {
+    /// Get a payload by it's hash and show which transactions reference it
+    Get(GetOpts),
+}
+
+async fn get_payload(db: Db, opts: GetOpts) -> Result<()> {
+    let graph = Graph::open(db.clone())?;
+    let store = PayloadStore::open(db)?;
+    let hash = graph.resolve_payload(&opts.hash).await?;
+    let refs = graph.payload_refs(&hash)?;
+
+    if refs.is_empty() {
+        eprintln!("payload not referenced by any transaction: {}", hash);
+
+        return Ok(());
+    }
+
+    println!("payload: {}", hash);
+    println!("base64url: {}", hash.to_base64url());
+    println!(
+        "transactions: {}",
+        refs.iter()
+            .map(|id| format!("{}", id))
+            .collect::<Vec<_>>()
+            .join(", ")
+    );
+
+    match store.get(&hash)? {
+        Some(data) => println!("stored: yes ({} bytes)", data.len()),
+        None => println!("stored: no"),
+    }
+
+    Ok(())
+}
+
+pub async fn cmd(db: Db, opts: Opts) -> Result<()> {
+    match opts.cmd {
+        Cmd::Get(opts) => get_payload(db, opts).await,
+    }
+}
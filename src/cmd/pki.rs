@@ -1,8 +1,24 @@
-use anyhow::Result;
+use std::path::PathBuf;
+use std::str::FromStr;
+
+use anyhow::{anyhow, Result};
+use chrono::{DateTime, Utc};
 use clap::Clap;
+use nuts_rs::network::{KeyUsage, KeyUsagePolicy};
+use nuts_rs::pki::{
+    public_jwk, thumbprint, Decision, KeyStorage, KeyStore, PrivateKeyStore, TrustPolicy,
+};
+use p256::ecdsa::SigningKey;
+use rand::rngs::OsRng;
+use serde::Serialize;
 use sled::Db;
+use tokio::fs;
 
-use crate::pki::KeyStore;
+use crate::annotations::{Annotations, Subject};
+use crate::bundle::Bundle;
+use crate::keyfile::{self, KeyFile, KeyFormat, KeyMaterial};
+use crate::output::{print_json, Output};
+use crate::passphrase;
 
 #[derive(Clap)]
 pub struct Opts {
@@ -10,26 +26,431 @@ pub struct Opts {
     cmd: Cmd,
 }
 
+pub enum Algorithm {
+    ES256,
+}
+
+impl FromStr for Algorithm {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "es256" => Ok(Algorithm::ES256),
+            _ => Err(anyhow!("unsupported key algorithm: {}", s)),
+        }
+    }
+}
+
+#[derive(Clap)]
+pub struct GenerateOpts {
+    /// Algorithm of the key (only es256 is supported)
+    #[clap(long, default_value = "es256")]
+    algo: Algorithm,
+
+    /// ID of the key (e.g. did:nuts:123#key-1)
+    #[clap(long)]
+    kid: String,
+}
+
+#[derive(Clap)]
+pub struct LabelOpts {
+    kid: String,
+    label: String,
+
+    /// Remove the label instead of adding it
+    #[clap(long)]
+    remove: bool,
+}
+
+#[derive(Clap)]
+pub struct TrustOpts {
+    kid: String,
+
+    /// Distrust the key instead, transactions signed by it are rejected
+    #[clap(long)]
+    distrust: bool,
+}
+
+#[derive(Clap)]
+pub struct RevokeOpts {
+    kid: String,
+
+    /// Moment from which transactions signed by the key are rejected (RFC 3339, e.g. 2021-10-01T12:00:00Z),
+    /// defaults to now
+    #[clap(long)]
+    at: Option<DateTime<Utc>>,
+}
+
+#[derive(Clap)]
+pub struct DeleteOpts {
+    kid: String,
+}
+
+#[derive(Clap)]
+pub struct ExportBundleOpts {
+    /// ID of the private key used to sign the bundle
+    #[clap(long)]
+    signer: String,
+
+    /// File to write the bundle to, defaults to stdout
+    #[clap(long)]
+    out: Option<PathBuf>,
+}
+
+#[derive(Clap)]
+pub struct ImportOpts {
+    /// PEM (SEC1 or PKCS#8) or JWK file which contains a P-256 private or public key
+    #[clap(long)]
+    file: PathBuf,
+
+    /// ID of the key (e.g. did:nuts:123#key-1), defaults to the key ID of the JWK
+    #[clap(long)]
+    kid: Option<String>,
+}
+
+#[derive(Clap)]
+pub struct ExportOpts {
+    kid: String,
+
+    /// Format of the export (pem or jwk)
+    #[clap(long, default_value = "pem")]
+    format: KeyFormat,
+
+    /// Only export the public key, the private key is exported when this node holds it
+    #[clap(long)]
+    public: bool,
+
+    /// File to write the key to, defaults to stdout
+    #[clap(long)]
+    out: Option<PathBuf>,
+}
+
+#[derive(Clap)]
+pub struct ImportBundleOpts {
+    file: PathBuf,
+
+    /// ID of the key which must have signed the bundle, required when the key isn't marked as trusted
+    #[clap(long)]
+    signer: Option<String>,
+}
+
 #[derive(Clap)]
 pub enum Cmd {
     /// Lists all keys in the key-store
     ListKeys,
+
+    /// Generates a new keypair and adds the public key to the key-store
+    Generate(GenerateOpts),
+
+    /// Imports a key from a PEM or JWK file, private keys are encrypted just like generated keys
+    Import(ImportOpts),
+
+    /// Exports a key as PEM or JWK (e.g. to back up the private keys of this node)
+    Export(ExportOpts),
+
+    /// Attaches a local-only label to a key
+    Label(LabelOpts),
+
+    /// Marks a key as trusted or distrusted
+    Trust(TrustOpts),
+
+    /// Revokes a key, transactions signed by it from now on are rejected
+    Revoke(RevokeOpts),
+
+    /// Deletes a key and it's private key, the key stays revoked when it was revoked before
+    Delete(DeleteOpts),
+
+    /// Exports the keys, trust decisions and revocations as a signed bundle for other nodes of the organization
+    ExportBundle(ExportBundleOpts),
+
+    /// Imports a signed bundle exported by another node of the organization
+    ImportBundle(ImportBundleOpts),
+
+    /// Verifies the passphrase of the private keys, private keys which aren't encrypted yet are encrypted using a
+    /// new passphrase
+    Unlock,
+
+    /// Shows how often each key signed transactions compared to it's baseline
+    Usage,
+}
+
+#[derive(Serialize)]
+struct KeyInfo {
+    key_id: String,
+    labels: Vec<String>,
+}
+
+async fn list_keys(db: Db, output: Output) -> Result<()> {
+    let store = KeyStore::open(db.clone())?;
+    let annotations = Annotations::open(db)?;
+    let mut keys = vec![];
+
+    for record in store.iter() {
+        let (key_id, _) = record?;
+
+        keys.push(KeyInfo {
+            labels: annotations.get(&Subject::Key(key_id.clone()))?,
+            key_id,
+        });
+    }
+
+    if output.is_json() {
+        return print_json(&keys);
+    }
+
+    for key in keys {
+        if key.labels.is_empty() {
+            println!("{}", key.key_id);
+        } else {
+            println!("{} (labels: {})", key.key_id, key.labels.join(", "));
+        }
+    }
+
+    Ok(())
+}
+
+async fn key_usage(db: Db) -> Result<()> {
+    let hour = Utc::now().timestamp().div_euclid(60 * 60);
+
+    for (kid, record) in KeyUsage::open(db, KeyUsagePolicy::default())?.list()? {
+        let payload_types = record
+            .payload_types
+            .iter()
+            .map(|(payload_type, count)| format!("{}={}", payload_type, count))
+            .collect::<Vec<_>>();
+
+        println!(
+            "{}\t{} signatures\t{} last hour\t{:.1}/h baseline\t{}",
+            kid,
+            record.total,
+            record.hours.get(&hour).copied().unwrap_or(0),
+            record.baseline(hour),
+            payload_types.join(", ")
+        );
+    }
+
+    Ok(())
+}
+
+/// Opens the private keys to add a key to, private keys are never stored unencrypted so a passphrase is created when
+/// there is none yet
+fn open_private_keys(db: Db) -> Result<PrivateKeyStore> {
+    let mut private_keys = PrivateKeyStore::open(db.clone())?;
+
+    if private_keys.is_encrypted()? {
+        private_keys = passphrase::unlock(db)?;
+    } else {
+        private_keys.encrypt(&passphrase::read_new()?)?;
+    }
+
+    Ok(private_keys)
+}
+
+async fn generate_key(db: Db, opts: GenerateOpts) -> Result<()> {
+    if opts.kid.is_empty() {
+        return Err(anyhow!("key ID can't be empty"));
+    }
+
+    let mut store = KeyStore::open(db.clone())?;
+
+    if store.contains(&opts.kid)? {
+        return Err(anyhow!("key with ID '{}' already exists", opts.kid));
+    }
+
+    let private_keys = open_private_keys(db)?;
+    let key = match opts.algo {
+        Algorithm::ES256 => SigningKey::random(&mut OsRng),
+    };
+    let jwk = public_jwk(&opts.kid, &key);
+    let thumbprint = thumbprint(&jwk)?;
+
+    private_keys.add(&opts.kid, &key)?;
+    store.add(opts.kid, jwk)?;
+
+    println!("{}", thumbprint);
+
+    Ok(())
+}
+
+async fn import_key(db: Db, opts: ImportOpts) -> Result<()> {
+    let data = fs::read_to_string(&opts.file).await?;
+    let file = KeyFile::decode(&data)
+        .map_err(|e| anyhow!("invalid key file '{}': {}", opts.file.display(), e))?;
+    let kid = opts
+        .kid
+        .or(file.key_id)
+        .ok_or_else(|| anyhow!("the key file doesn't contain a key ID, use --kid to set it"))?;
+
+    if kid.is_empty() {
+        return Err(anyhow!("key ID can't be empty"));
+    }
+
+    let mut store = KeyStore::open(db.clone())?;
+
+    if store.contains(&kid)? {
+        return Err(anyhow!("key with ID '{}' already exists", kid));
+    }
+
+    let jwk = match file.material {
+        KeyMaterial::Private(key) => {
+            open_private_keys(db)?.add(&kid, &key)?;
+
+            public_jwk(&kid, &key)
+        }
+        KeyMaterial::Public(mut jwk) => {
+            jwk.common.key_id = Some(kid.clone());
+            jwk
+        }
+    };
+    let thumbprint = thumbprint(&jwk)?;
+
+    store.add(kid, jwk)?;
+
+    println!("{}", thumbprint);
+
+    Ok(())
+}
+
+async fn export_key(db: Db, opts: ExportOpts) -> Result<()> {
+    let jwk = KeyStore::open(db.clone())?
+        .get(&opts.kid)?
+        .ok_or_else(|| anyhow!("key not found with ID: {}", opts.kid))?;
+    let holds_private_key = PrivateKeyStore::open(db.clone())?
+        .ids()?
+        .contains(&opts.kid);
+    let private_key = if holds_private_key && !opts.public {
+        passphrase::unlock(db)?.get(&opts.kid)?
+    } else {
+        None
+    };
+    let encoded = match &private_key {
+        Some(key) => keyfile::encode_private(&opts.kid, key, opts.format)?,
+        None => keyfile::encode_public(&jwk, opts.format)?,
+    };
+
+    match opts.out {
+        Some(path) if private_key.is_some() => keyfile::write_private(&path, &encoded).await?,
+        Some(path) => fs::write(path, encoded).await?,
+        None => print!("{}", encoded),
+    }
+
+    Ok(())
+}
+
+async fn label_key(db: Db, opts: LabelOpts) -> Result<()> {
+    let store = KeyStore::open(db.clone())?;
+    let annotations = Annotations::open(db)?;
+
+    if !store.contains(&opts.kid)? {
+        return Err(anyhow!("key not found with ID: {}", opts.kid));
+    }
+
+    let subject = Subject::Key(opts.kid);
+
+    if opts.remove {
+        annotations.remove(&subject, &opts.label)
+    } else {
+        annotations.add(&subject, opts.label)
+    }
+}
+
+async fn trust_key(db: Db, opts: TrustOpts) -> Result<()> {
+    if !KeyStore::open(db.clone())?.contains(&opts.kid)? {
+        return Err(anyhow!("key not found with ID: {}", opts.kid));
+    }
+
+    TrustPolicy::open(db)?.decide(
+        &opts.kid,
+        if opts.distrust {
+            Decision::Distrusted
+        } else {
+            Decision::Trusted
+        },
+    )
 }
 
-async fn list_keys(db: Db) -> Result<()> {
+async fn revoke_key(db: Db, opts: RevokeOpts) -> Result<()> {
     let store = KeyStore::open(db)?;
-    let jwk_set = store.as_ref();
 
-    for key in jwk_set.keys.iter() {
-        println!("{}", key.common.key_id.as_ref().unwrap());
+    if !store.contains(&opts.kid)? {
+        return Err(anyhow!("key not found with ID: {}", opts.kid));
+    }
+
+    Ok(store.revoke(&opts.kid, opts.at.unwrap_or_else(Utc::now))?)
+}
+
+async fn delete_key(db: Db, opts: DeleteOpts) -> Result<()> {
+    if !KeyStore::open(db.clone())?.remove(&opts.kid)? {
+        return Err(anyhow!("key not found with ID: {}", opts.kid));
+    }
+
+    if PrivateKeyStore::open(db)?.remove(&opts.kid)? {
+        println!("deleted key and private key: {}", opts.kid);
+    } else {
+        println!("deleted key: {}", opts.kid);
+    }
+
+    Ok(())
+}
+
+async fn export_bundle(db: Db, opts: ExportBundleOpts) -> Result<()> {
+    let private_keys = passphrase::unlock(db.clone())?;
+    let bundle = serde_json::to_string_pretty(&Bundle::export(db, &private_keys, &opts.signer)?)?;
+
+    match opts.out {
+        Some(path) => fs::write(path, bundle).await?,
+        None => println!("{}", bundle),
+    }
+
+    Ok(())
+}
+
+async fn import_bundle(db: Db, opts: ImportBundleOpts) -> Result<()> {
+    let bundle: Bundle = serde_json::from_slice(&fs::read(&opts.file).await?)
+        .map_err(|e| anyhow!("invalid bundle '{}': {}", opts.file.display(), e))?;
+    let summary = bundle.import(db, opts.signer.as_deref())?;
+
+    for key_id in summary.conflicts.iter() {
+        eprintln!("skipped key with a different public key: {}", key_id);
+    }
+
+    println!(
+        "imported {} keys, {} trust decisions and {} revocations",
+        summary.keys, summary.decisions, summary.revocations
+    );
+
+    Ok(())
+}
+
+async fn unlock(db: Db) -> Result<()> {
+    let mut private_keys = PrivateKeyStore::open(db.clone())?;
+
+    if private_keys.is_encrypted()? {
+        passphrase::unlock(db)?;
+
+        println!("passphrase is valid");
+    } else {
+        let count = private_keys.encrypt(&passphrase::read_new()?)?;
+
+        println!("encrypted {} private keys", count);
     }
 
     Ok(())
 }
 
-pub async fn cmd(db: Db, opts: Opts) -> Result<()> {
+pub async fn cmd(db: Db, opts: Opts, output: Output) -> Result<()> {
     match opts.cmd {
-        Cmd::ListKeys => list_keys(db),
+        Cmd::ListKeys => list_keys(db, output).await,
+        Cmd::Generate(opts) => generate_key(db, opts).await,
+        Cmd::Import(opts) => import_key(db, opts).await,
+        Cmd::Export(opts) => export_key(db, opts).await,
+        Cmd::Label(opts) => label_key(db, opts).await,
+        Cmd::Trust(opts) => trust_key(db, opts).await,
+        Cmd::Revoke(opts) => revoke_key(db, opts).await,
+        Cmd::Delete(opts) => delete_key(db, opts).await,
+        Cmd::ExportBundle(opts) => export_bundle(db, opts).await,
+        Cmd::ImportBundle(opts) => import_bundle(db, opts).await,
+        Cmd::Unlock => unlock(db).await,
+        Cmd::Usage => key_usage(db).await,
     }
-    .await
 }
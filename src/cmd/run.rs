@@ -1,33 +1,544 @@
-use anyhow::Result;
+use std::collections::HashMap;
+use std::net::{SocketAddr, TcpListener};
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::time::Duration;
+
+use anyhow::{anyhow, Result};
 use clap::Clap;
+use nuts_rs::metrics::Metrics;
+use nuts_rs::network::{
+    parse_period, AccessPolicy, AnomalyHandler, DiscoveryPolicy, OverflowPolicy, PeerGroups,
+    PeerIdentities, PeerRule, RetentionPolicy, Server, ServerOptions, SyncPolicy, TlsPolicy,
+    TlsVersion,
+};
+use nuts_rs::retry::RetryPolicy;
 use sled::Db;
-use tokio::fs;
-use tonic::transport::{Certificate, Identity};
 
-use crate::network::Server;
+use crate::config::{Config, NetworkConfig};
+use crate::profile::Tuning;
+use crate::systemd::ListenFds;
+use crate::tls::{self, TlsFiles};
+use crate::webhook::Webhook;
+use crate::{admin, passphrase, self_test, shutdown, status, systemd};
+
+/// Names of the sockets passed using socket activation (`FileDescriptorName=` in the socket unit)
+const NETWORK_SOCKET: &str = "network";
+const ADMIN_SOCKET: &str = "admin";
+
+/// Sockets of a node which were passed using socket activation, they're used instead of the configured addresses
+#[derive(Default)]
+pub struct Sockets {
+    network: Option<TcpListener>,
+    admin: Option<TcpListener>,
+}
+
+impl Sockets {
+    /// Takes the `network` and `admin` sockets, a single socket with another name is used for the peer listener
+    pub fn take(fds: &mut ListenFds) -> Self {
+        let admin = fds.take(ADMIN_SOCKET);
+        let network = fds.take(NETWORK_SOCKET).or_else(|| fds.take_single());
+
+        Self { network, admin }
+    }
+
+    /// Takes the sockets of a node hosted by the supervisor, which are prefixed with the name of the node (e.g.
+    /// `care-x-network`) as all nodes share the sockets passed to the process
+    pub fn take_node(fds: &mut ListenFds, node: &str) -> Self {
+        Self {
+            network: fds.take(&format!("{}-{}", node, NETWORK_SOCKET)),
+            admin: fds.take(&format!("{}-{}", node, ADMIN_SOCKET)),
+        }
+    }
+}
 
 #[derive(Clap)]
 pub struct Opts {
+    /// Number of workers used to verify transaction signatures in parallel, overrides the profile
+    #[clap(long)]
+    admission_workers: Option<usize>,
+
+    /// Number of messages buffered per connection before the overflow policy applies, overrides the profile
+    #[clap(long)]
+    channel_capacity: Option<usize>,
+
+    /// What happens when a peer sends messages faster than they're handled: drop-oldest (default) or disconnect
+    #[clap(long)]
+    overflow_policy: Option<OverflowPolicy>,
+
+    /// Maximum number of transactions queried from a peer at once while syncing, overrides the profile
+    #[clap(long)]
+    sync_batch_size: Option<usize>,
+
+    /// Milliseconds to wait for more transactions to query from the same peer, so that they're queried at once
+    #[clap(long, default_value = "50")]
+    query_delay: u64,
+
+    /// Address to accept incoming connections from other peers on (e.g. 0.0.0.0:5555)
+    #[clap(long)]
+    listen_addr: Option<SocketAddr>,
+
+    /// DID of the node operator which is sent to peers when connecting
+    #[clap(long)]
+    node_did: Option<String>,
+
+    /// ID of the network this node is part of, peers from other networks are rejected (defaults to default)
+    #[clap(long)]
+    network_id: Option<String>,
+
+    /// Store which key and rules were used to verify each admitted transaction for auditing
+    #[clap(long)]
+    record_verification: bool,
+
+    /// URL to which unusual usage of signing keys (e.g. a sudden spike in signatures) is posted as JSON
+    #[clap(long)]
+    anomaly_webhook: Option<hyper::Uri>,
+
+    /// Loopback address on which the admin API listens (e.g. 127.0.0.1:1323), the API is disabled when not set
+    #[clap(long)]
+    admin_addr: Option<SocketAddr>,
+
+    /// Reject peers and transactions which don't strictly follow the specification (e.g. unknown JWS headers or keys
+    /// which are embedded under another ID than their thumbprint)
+    #[clap(long)]
+    strict: bool,
+
+    /// Maximum number of seconds the signing time of a transaction may be ahead of the clock of this node
+    #[clap(long, default_value = "600")]
+    max_clock_skew: u64,
+
+    /// Admit transactions from peers which fail verification instead of rejecting them (unsafe, allows peers to
+    /// inject forged transactions)
+    #[clap(long)]
+    allow_unverified: bool,
+
+    /// Generates a new peer ID for this node before starting, peers rebind the certificate of this node to the new
+    /// peer ID when it connects
+    #[clap(long)]
+    reset_peer_id: bool,
+
+    /// Initial delay in seconds before reconnecting to a peer, doubled after every failed attempt
+    #[clap(long, default_value = "1")]
+    reconnect_interval: u64,
+
+    /// Maximum delay in seconds between attempts to reconnect to a peer
+    #[clap(long, default_value = "60")]
+    reconnect_max_interval: u64,
+
+    /// Maximum number of consecutive failed attempts before giving up on a peer
+    #[clap(long)]
+    max_retries: Option<u32>,
+
+    /// Minimum interval in seconds between queries to a peer which is producing new transactions (defaults to 5)
+    #[clap(long)]
+    sync_min_interval: Option<u64>,
+
+    /// Maximum interval in seconds between queries to a peer which is idle (defaults to 300)
+    #[clap(long)]
+    sync_max_interval: Option<u64>,
+
+    /// Maximum time in seconds to wait for the initial sync before the admin API is served anyway and the node is
+    /// flagged as degraded (defaults to 60)
+    #[clap(long)]
+    initial_sync_timeout: Option<u64>,
+
+    /// DNS name which is resolved to discover peers, SRV records are queried for names starting with an underscore
+    /// (e.g. _nuts._tcp.example.com)
+    #[clap(long)]
+    discovery_name: Option<String>,
+
+    /// Interval in seconds at which peers are discovered (defaults to 300)
+    #[clap(long)]
+    discovery_interval: Option<u64>,
+
+    /// Number of connected peers at which no more discovered peers are connected to (defaults to 8)
+    #[clap(long)]
+    target_peers: Option<usize>,
+
+    /// Path to the PEM encoded CA certificates which are trusted (defaults to tls/truststore.pem)
+    #[clap(long)]
+    tls_truststore: Option<PathBuf>,
+
+    /// Path to the PEM encoded certificate of this node (defaults to tls/localhost.pem)
+    #[clap(long)]
+    tls_certificate: Option<PathBuf>,
+
+    /// Path to the PEM encoded private key of this node (defaults to tls/localhost.key)
+    #[clap(long)]
+    tls_key: Option<PathBuf>,
+
+    /// Interval in seconds at which the TLS files are checked for changes, changed certificates are used for new
+    /// connections without a restart (defaults to 60, 0 disables reloading)
+    #[clap(long)]
+    tls_reload_interval: Option<u64>,
+
+    /// Lowest TLS version which is accepted for connections with peers (1.2 or 1.3, defaults to 1.2)
+    #[clap(long)]
+    tls_min_version: Option<TlsVersion>,
+
+    /// Cipher suite which is allowed (e.g. TLS13_AES_256_GCM_SHA384), can be specified multiple times and all
+    /// suites supported by rustls are allowed when not set
+    #[clap(
+        long = "tls-cipher-suite",
+        multiple_occurrences = true,
+        number_of_values = 1
+    )]
+    tls_cipher_suites: Vec<String>,
+
+    /// ALPN protocol which is offered, can be specified multiple times and must include h2 (defaults to h2)
+    #[clap(
+        long = "tls-alpn-protocol",
+        multiple_occurrences = true,
+        number_of_values = 1
+    )]
+    tls_alpn_protocols: Vec<String>,
+
+    /// ID of a peer group this node is a member of, can be specified multiple times
+    #[clap(long = "group", multiple_occurrences = true, number_of_values = 1)]
+    groups: Vec<String>,
+
+    /// Restricts the payloads of a payload type to a peer group (e.g. application/vc+json=care-x)
+    #[clap(
+        long = "restrict",
+        multiple_occurrences = true,
+        number_of_values = 1,
+        parse(try_from_str = parse_restriction)
+    )]
+    restrictions: Vec<(String, String)>,
+
+    /// Period for which the payloads of a payload type are kept (e.g. application/vc+json=90d), payloads of other
+    /// payload types are kept forever
+    #[clap(
+        long = "retention",
+        multiple_occurrences = true,
+        number_of_values = 1,
+        parse(try_from_str = parse_retention)
+    )]
+    retention: Vec<(String, Duration)>,
+
+    /// Performs an end-to-end check of the node after initialization and exits
+    #[clap(long)]
+    self_test: bool,
+
     bootstrap_node: Vec<String>,
 }
 
-pub async fn cmd(db: Db, opts: Opts) -> Result<()> {
-    let ca_pem = fs::read("tls/truststore.pem").await?;
-    let ca = Certificate::from_pem(ca_pem);
-    let (cert, key) = (
-        fs::read("tls/localhost.pem").await?,
-        fs::read("tls/localhost.key").await?,
-    );
-    let identity = Identity::from_pem(cert, key);
-    let mut server = Server::new(db, ca, identity)?;
+fn parse_restriction(value: &str) -> Result<(String, String)> {
+    match value.split_once('=') {
+        Some((payload_type, group)) if !payload_type.is_empty() && !group.is_empty() => {
+            Ok((payload_type.to_string(), group.to_string()))
+        }
+        _ => Err(anyhow!(
+            "invalid restriction '{}' (expected <payload-type>=<group>)",
+            value
+        )),
+    }
+}
+
+fn parse_retention(value: &str) -> Result<(String, Duration)> {
+    match value.split_once('=') {
+        Some((payload_type, period)) if !payload_type.is_empty() => {
+            Ok((payload_type.to_string(), parse_period(period)?))
+        }
+        _ => Err(anyhow!(
+            "invalid retention '{}' (expected <payload-type>=<period>)",
+            value
+        )),
+    }
+}
+
+/// Combines the TLS policy of the command-line with the configuration file, settings on the command-line take
+/// precedence
+fn tls_policy(opts: &Opts, config: &Config) -> Result<TlsPolicy> {
+    let defaults = TlsPolicy::default();
+    let min_version = match (opts.tls_min_version, &config.tls.min_version) {
+        (Some(version), _) => version,
+        (None, Some(version)) => version.parse()?,
+        (None, None) => defaults.min_version,
+    };
+    let policy = TlsPolicy {
+        min_version,
+        cipher_suites: if opts.tls_cipher_suites.is_empty() {
+            config.tls.cipher_suites.clone()
+        } else {
+            opts.tls_cipher_suites.clone()
+        },
+        alpn_protocols: if !opts.tls_alpn_protocols.is_empty() {
+            opts.tls_alpn_protocols.clone()
+        } else if !config.tls.alpn_protocols.is_empty() {
+            config.tls.alpn_protocols.clone()
+        } else {
+            defaults.alpn_protocols
+        },
+    };
 
-    for addr in opts.bootstrap_node {
-        server.connect_to_peer(addr).await?;
+    policy
+        .validate()
+        .map_err(|e| e.context("invalid TLS policy"))?;
+
+    Ok(policy)
+}
+
+/// Parses the peer rules of the configuration file
+fn access_policy(config: &NetworkConfig) -> Result<AccessPolicy> {
+    let parse = |rules: &[String]| {
+        rules
+            .iter()
+            .map(|rule| rule.parse())
+            .collect::<Result<Vec<PeerRule>>>()
+    };
+
+    Ok(AccessPolicy {
+        allow: parse(&config.allow_peers)?,
+        deny: parse(&config.deny_peers)?,
+    })
+}
+
+/// Parses the member rules of the peer groups, which are matched against the certificate of a peer only
+fn group_members(config: &NetworkConfig) -> Result<HashMap<String, Vec<PeerRule>>> {
+    let mut members = HashMap::new();
+
+    for (group, rules) in config.group_members.iter() {
+        let rules = rules
+            .iter()
+            .map(|rule| match rule.parse()? {
+                PeerRule::Cidr(_) => Err(anyhow!(
+                    "invalid member rule '{}' of group '{}' (expected a certificate fingerprint or DNS name)",
+                    rule,
+                    group
+                )),
+                rule => Ok(rule),
+            })
+            .collect::<Result<Vec<PeerRule>>>()?;
+
+        members.insert(group.clone(), rules);
+    }
+
+    Ok(members)
+}
+
+fn tls_files(opts: &Opts, config: &Config) -> TlsFiles {
+    TlsFiles::new(
+        opts.tls_truststore.as_ref(),
+        opts.tls_certificate.as_ref(),
+        opts.tls_key.as_ref(),
+        &config.tls,
+    )
+}
+
+/// Creates the server of a node, starts listening and connects to it's peers, the returned server still needs to
+/// be run
+pub async fn start(
+    db: Db,
+    data_dir: Option<PathBuf>,
+    config: Config,
+    tuning: Tuning,
+    opts: Opts,
+    sockets: Sockets,
+) -> Result<Server> {
+    // Verify the passphrase of encrypted private keys before the node starts so a wrong passphrase fails fast
+    passphrase::unlock(db.clone())?;
+
+    if opts.reset_peer_id {
+        let peer_id = PeerIdentities::open(db.clone())?.reset_local_peer_id()?;
+
+        tracing::warn!(
+            "rotated the peer ID of this node to: {} (peers rebind it to the certificate of this node)",
+            peer_id
+        );
+    }
+
+    let tls_files = tls_files(&opts, &config);
+    let tls_material = tls_files.read().await?;
+    let tls_policy = tls_policy(&opts, &config)?;
+    let tls_reload_interval = opts
+        .tls_reload_interval
+        .or(config.tls.reload_interval)
+        .unwrap_or(60);
+    let sync_min_interval = opts
+        .sync_min_interval
+        .or(config.network.sync_min_interval)
+        .unwrap_or(5);
+    let sync_max_interval = opts
+        .sync_max_interval
+        .or(config.network.sync_max_interval)
+        .unwrap_or(300);
+    let initial_sync_timeout = opts
+        .initial_sync_timeout
+        .or(config.network.initial_sync_timeout)
+        .unwrap_or(60);
+    let access = access_policy(&config.network)?;
+    let group_members = group_members(&config.network)?;
+    let overflow_policy = match (opts.overflow_policy, &config.network.overflow_policy) {
+        (Some(policy), _) => policy,
+        (None, Some(policy)) => policy.parse()?,
+        (None, None) => OverflowPolicy::default(),
+    };
+    let discovery = opts
+        .discovery_name
+        .clone()
+        .or_else(|| config.network.discovery_name.clone())
+        .map(|name| DiscoveryPolicy {
+            name,
+            interval: Duration::from_secs(
+                opts.discovery_interval
+                    .or(config.network.discovery_interval)
+                    .unwrap_or(300),
+            ),
+            target_peers: opts
+                .target_peers
+                .or(config.network.target_peers)
+                .unwrap_or(8),
+            nameserver: config.network.discovery_nameserver,
+        });
+
+    let mut retention = HashMap::new();
+
+    for (payload_type, period) in config.retention {
+        retention.insert(payload_type, parse_period(&period)?);
     }
 
-    server.run().await;
+    retention.extend(opts.retention);
+
+    let metrics = Metrics::default();
+    let mut server = Server::new(
+        db.clone(),
+        &tls_material,
+        ServerOptions {
+            admission_workers: opts.admission_workers.unwrap_or(tuning.admission_workers),
+            channel_capacity: opts.channel_capacity.unwrap_or(tuning.channel_capacity),
+            overflow_policy,
+            sync_batch_size: opts.sync_batch_size.unwrap_or(tuning.sync_batch_size),
+            query_delay: Duration::from_millis(opts.query_delay),
+            node_did: opts.node_did.or(config.network.node_did),
+            network_id: opts
+                .network_id
+                .or(config.network.network_id)
+                .unwrap_or_else(|| "default".to_string()),
+            record_verification: opts.record_verification,
+            strict: opts.strict,
+            max_clock_skew: Duration::from_secs(opts.max_clock_skew),
+            allow_unverified: opts.allow_unverified,
+            reconnect: RetryPolicy::exponential(Duration::from_secs(opts.reconnect_interval))
+                .max_interval(Duration::from_secs(opts.reconnect_max_interval))
+                .max_attempts(opts.max_retries),
+            sync: SyncPolicy {
+                min_interval: Duration::from_secs(sync_min_interval),
+                max_interval: Duration::from_secs(sync_max_interval),
+            },
+            groups: PeerGroups {
+                memberships: opts.groups,
+                restricted: opts.restrictions.into_iter().collect(),
+                members: group_members,
+            },
+            retention: RetentionPolicy { max_age: retention },
+            anomaly_handler: opts
+                .anomaly_webhook
+                .map(|url| Arc::new(Webhook::new(url, metrics.clone())) as Arc<dyn AnomalyHandler>),
+            initial_sync_timeout: Some(Duration::from_secs(initial_sync_timeout)),
+            tls_policy,
+            access,
+            metrics,
+            ..ServerOptions::default()
+        },
+    )?;
+
+    server.verify_checkpoint()?;
+
+    // Prefer the sockets passed by systemd (socket activation) over the configured listen addresses
+    if let Some(listener) = sockets.network {
+        server.listen_on(listener)?;
+    } else if let Some(addr) = opts.listen_addr.or(config.network.listen_addr) {
+        server.listen(addr)?;
+    }
+
+    if tls_reload_interval > 0 {
+        tls::spawn_reload(
+            tls_files,
+            server.tls_reloader(),
+            Duration::from_secs(tls_reload_interval),
+        );
+    }
+
+    if let Some(listener) = sockets.admin {
+        admin::listen_on(
+            db,
+            listener,
+            server.subscribe_health(),
+            server.pending_writes(),
+        )?;
+    } else if let Some(addr) = opts.admin_addr.or(config.admin.listen_addr) {
+        admin::listen(db, addr, server.subscribe_health(), server.pending_writes())?;
+    }
+
+    if let Some(data_dir) = data_dir {
+        status::spawn_writer(status::path(&data_dir), server.subscribe_health());
+    }
+
+    // Reconnect to the peers from the address book as well as the bootstrap nodes
+    let mut peers = server.known_peers()?;
+
+    for addr in config
+        .network
+        .bootstrap_nodes
+        .into_iter()
+        .chain(opts.bootstrap_node)
+    {
+        if !peers.contains(&addr) {
+            peers.push(addr);
+        }
+    }
+
+    // There is nothing to sync with for the first node of a network
+    if peers.is_empty() && discovery.is_none() {
+        server.skip_initial_sync();
+    }
+
+    for addr in peers {
+        server.connect_to_peer(addr);
+    }
+
+    if let Some(discovery) = discovery {
+        server.discover(discovery);
+    }
+
+    Ok(server)
+}
+
+pub async fn cmd(
+    db: Db,
+    data_dir: Option<PathBuf>,
+    config: Config,
+    tuning: Tuning,
+    opts: Opts,
+) -> Result<()> {
+    if opts.self_test {
+        passphrase::unlock(db.clone())?;
+
+        let tls_material = tls_files(&opts, &config).read().await?;
+
+        return self_test::run(&db, &tls_material, tls_policy(&opts, &config)?).await;
+    }
+
+    let sockets = Sockets::take(&mut systemd::listen_fds()?);
+    let mut server = start(db, data_dir, config, tuning, opts, sockets).await?;
+
+    systemd::spawn_watchdog()?;
+
+    let mut health = server.subscribe_health();
+
+    tokio::spawn(async move {
+        if status::ready(&mut health).await {
+            systemd::notify_ready();
+        }
+    });
+
+    server.run(shutdown::signal()).await;
+
+    tracing::info!("shutting down");
 
-    log::info!("shutting down");
+    systemd::notify_stopping();
+    server.shutdown().await?;
 
     Ok(())
 }
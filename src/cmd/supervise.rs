@@ -0,0 +1,353 @@
+use std::collections::BTreeMap;
+use std::convert::Infallible;
+use std::fmt::Write;
+use std::net::SocketAddr;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+use anyhow::{anyhow, Result};
+use clap::Clap;
+use futures::future;
+use hyper::header::CONTENT_TYPE;
+use hyper::service::{make_service_fn, service_fn};
+use hyper::{Body, Method, Request, Response, StatusCode};
+use nuts_rs::backend;
+use nuts_rs::metrics::Metrics;
+use nuts_rs::network::{Health, Server, SyncState};
+use tokio::sync::watch;
+
+use crate::cmd::run;
+use crate::config::{Config, SupervisorConfig};
+use crate::profile::Profile;
+use crate::storage::Storage;
+use crate::{shutdown, status, systemd};
+
+#[derive(Clap)]
+pub struct Opts {
+    /// Path to the configuration file with a table per node (e.g. [nodes.care-x])
+    config: PathBuf,
+}
+
+/// Node which is hosted by the supervisor
+struct Node {
+    name: String,
+    server: Server,
+}
+
+/// Health and counters of a node which are exported on the metrics endpoint
+type Exported = (String, watch::Receiver<Health>, Metrics);
+
+/// Opens the database of a node and starts it, the data directory defaults to a directory named after the node
+async fn start(
+    name: &str,
+    data_dir: &Path,
+    config: Config,
+    sockets: run::Sockets,
+) -> Result<Server> {
+    let profile: Profile = config.profile.as_deref().unwrap_or("default").parse()?;
+    let tuning = profile.tuning();
+    let storage: Storage = config.storage.as_deref().unwrap_or("disk").parse()?;
+    let data_dir = config
+        .data_dir
+        .clone()
+        .unwrap_or_else(|| data_dir.join(name));
+    let db = storage.open(
+        &data_dir,
+        config.cache_capacity.unwrap_or(tuning.cache_capacity),
+    )?;
+    let data_dir = match storage {
+        Storage::Disk => Some(data_dir),
+        Storage::Memory => None,
+    };
+
+    if let Some(storage_backend) = &config.storage_backend {
+        backend::select(&db, storage_backend.parse()?, data_dir.as_deref())?;
+    }
+
+    // Settings which can't be set in the configuration file use the defaults of `run`
+    let opts = run::Opts::try_parse_from(&["run"])?;
+
+    run::start(db, data_dir, config, tuning, opts, sockets).await
+}
+
+fn gauge(
+    output: &mut String,
+    name: &str,
+    help: &str,
+    nodes: &[(String, Health)],
+    value: impl Fn(&Health) -> u64,
+) {
+    writeln!(output, "# HELP {} {}", name, help).unwrap();
+    writeln!(output, "# TYPE {} gauge", name).unwrap();
+
+    for (node, health) in nodes {
+        writeln!(
+            output,
+            "{}{{node=\"{}\"}} {}",
+            name,
+            label(node),
+            value(health)
+        )
+        .unwrap();
+    }
+}
+
+fn label(node: &str) -> String {
+    node.replace('\\', "\\\\").replace('"', "\\\"")
+}
+
+/// Renders the counters of all nodes, which are named after the counter (e.g. `peers.blocked` becomes
+/// `nuts_peers_blocked_total`). A node only reports the counters which were incremented at least once
+fn counters(output: &mut String, nodes: &[Exported]) {
+    let mut counters = BTreeMap::<String, Vec<(&str, u64)>>::new();
+
+    for (node, _, metrics) in nodes {
+        for (name, value) in metrics.counters() {
+            counters.entry(name).or_default().push((node, value));
+        }
+    }
+
+    for (name, values) in counters {
+        let name = format!(
+            "nuts_{}_total",
+            name.replace(|c: char| !c.is_ascii_alphanumeric(), "_")
+        );
+
+        writeln!(output, "# TYPE {} counter", name).unwrap();
+
+        for (node, value) in values {
+            writeln!(output, "{}{{node=\"{}\"}} {}", name, label(node), value).unwrap();
+        }
+    }
+}
+
+/// Renders the health and counters of all nodes in the Prometheus text format, labelled by the name of the node
+fn render(exported: &[Exported]) -> String {
+    let nodes = exported
+        .iter()
+        .map(|(name, health, _)| (name.clone(), health.borrow().clone()))
+        .collect::<Vec<_>>();
+    let mut output = String::new();
+
+    gauge(
+        &mut output,
+        "nuts_height",
+        "Highest lamport clock in the DAG",
+        &nodes,
+        |health| health.height as u64,
+    );
+    gauge(
+        &mut output,
+        "nuts_transactions",
+        "Number of transactions in the DAG",
+        &nodes,
+        |health| health.transactions as u64,
+    );
+    gauge(
+        &mut output,
+        "nuts_peers",
+        "Number of peers the node syncs with",
+        &nodes,
+        |health| health.peers as u64,
+    );
+    gauge(
+        &mut output,
+        "nuts_synced",
+        "Whether the initial sync completed",
+        &nodes,
+        |health| (health.sync == SyncState::Synced) as u64,
+    );
+    gauge(
+        &mut output,
+        "nuts_storage_ok",
+        "Whether the database is accessible",
+        &nodes,
+        |health| health.storage.ok as u64,
+    );
+    gauge(
+        &mut output,
+        "nuts_storage_size_bytes",
+        "Size of the database on disk",
+        &nodes,
+        |health| health.storage.size_on_disk,
+    );
+    gauge(
+        &mut output,
+        "nuts_payloads_retained",
+        "Number of payloads held by the node",
+        &nodes,
+        |health| health.payloads.retained as u64,
+    );
+    gauge(
+        &mut output,
+        "nuts_payloads_expired",
+        "Number of payloads dropped by the retention policy",
+        &nodes,
+        |health| health.payloads.expired as u64,
+    );
+    gauge(
+        &mut output,
+        "nuts_payloads_pending",
+        "Number of payloads being retrieved from peers",
+        &nodes,
+        |health| health.payloads.pending as u64,
+    );
+    counters(&mut output, exported);
+
+    output
+}
+
+/// Starts serving the combined metrics of all nodes on the given address in the background
+fn serve_metrics(addr: SocketAddr, nodes: Vec<Exported>) -> Result<()> {
+    let nodes = Arc::new(nodes);
+    let make_service = make_service_fn(move |_| {
+        let nodes = nodes.clone();
+
+        async move {
+            Ok::<_, Infallible>(service_fn(move |request: Request<Body>| {
+                let response = if request.method() == Method::GET && request.uri().path() == "/metrics"
+                {
+                    Response::builder()
+                        .status(StatusCode::OK)
+                        .header(CONTENT_TYPE, "text/plain; version=0.0.4")
+                        .body(Body::from(render(&nodes)))
+                } else {
+                    Response::builder()
+                        .status(StatusCode::NOT_FOUND)
+                        .body(Body::empty())
+                };
+
+                future::ready(response)
+            }))
+        }
+    });
+    let builder = hyper::Server::try_bind(&addr)
+        .map_err(|e| anyhow!("unable to listen on {}: {}", addr, e))?;
+
+    tracing::info!(target: "nuts::supervisor", "metrics listening on {}", addr);
+
+    tokio::spawn(async move {
+        if let Err(e) = builder.serve(make_service).await {
+            tracing::error!(target: "nuts::supervisor", "failed to serve metrics: {}", e);
+        }
+    });
+
+    Ok(())
+}
+
+/// Waits until the supervisor is stopping
+async fn stopped(mut stopping: watch::Receiver<bool>) -> Result<()> {
+    while !*stopping.borrow() {
+        if stopping.changed().await.is_err() {
+            break;
+        }
+    }
+
+    Ok(())
+}
+
+/// Runs all nodes from the configuration file in this process until it receives a signal, the nodes share the
+/// runtime but each has it's own database, identity and network
+pub async fn cmd(data_dir: &Path, opts: &Opts) -> Result<()> {
+    let config = SupervisorConfig::load(&opts.config)?;
+
+    if config.nodes.is_empty() {
+        return Err(anyhow!(
+            "no nodes configured in '{}'",
+            opts.config.display()
+        ));
+    }
+
+    let mut nodes = vec![];
+    let mut sockets = systemd::listen_fds()?;
+
+    for (name, node_config) in config.nodes {
+        tracing::info!(target: "nuts::supervisor", "starting node: {}", name);
+
+        // The sockets are shared by all nodes, so they're named after the node they belong to
+        let node_sockets = run::Sockets::take_node(&mut sockets, &name);
+        let server = start(&name, data_dir, node_config, node_sockets)
+            .await
+            .map_err(|e| e.context(format!("unable to start node '{}'", name)))?;
+
+        nodes.push(Node { name, server });
+    }
+
+    for name in sockets.names() {
+        tracing::warn!(target: "nuts::supervisor", "socket '{}' doesn't belong to any node (expected <node>-network or <node>-admin)", name);
+    }
+
+    let health = nodes
+        .iter()
+        .map(|node| (node.name.clone(), node.server.subscribe_health()))
+        .collect::<Vec<_>>();
+
+    if let Some(addr) = config.metrics_addr {
+        let exported = nodes
+            .iter()
+            .map(|node| {
+                (
+                    node.name.clone(),
+                    node.server.subscribe_health(),
+                    node.server.metrics().clone(),
+                )
+            })
+            .collect();
+
+        serve_metrics(addr, exported)?;
+    }
+
+    systemd::spawn_watchdog()?;
+
+    // The supervisor is ready as soon as all of it's nodes are
+    tokio::spawn(async move {
+        for (_, mut health) in health {
+            if !status::ready(&mut health).await {
+                return;
+            }
+        }
+
+        systemd::notify_ready();
+    });
+
+    // The signal handler can only be installed once so the shutdown is passed on to every node
+    let (stop, stopping) = watch::channel(false);
+
+    tokio::spawn(async move {
+        if let Err(e) = shutdown::signal().await {
+            tracing::error!(target: "nuts::supervisor", "failed to wait for a signal: {}", e);
+        }
+
+        let _ = stop.send(true);
+    });
+
+    future::join_all(
+        nodes
+            .iter_mut()
+            .map(|node| node.server.run(stopped(stopping.clone()))),
+    )
+    .await;
+
+    tracing::info!("shutting down");
+
+    systemd::notify_stopping();
+
+    let mut failed = vec![];
+
+    for node in nodes.iter_mut() {
+        if let Err(e) = node.server.shutdown().await {
+            tracing::error!(target: "nuts::supervisor", "failed to shut down node '{}': {}", node.name, e);
+
+            failed.push(node.name.clone());
+        }
+    }
+
+    if !failed.is_empty() {
+        return Err(anyhow!(
+            "failed to shut down nodes: {}",
+            failed.join(", ")
+        ));
+    }
+
+    Ok(())
+}
@@ -0,0 +1,78 @@
+use std::path::{Path, PathBuf};
+
+use anyhow::{anyhow, Result};
+use chrono::Utc;
+use clap::Clap;
+use sled::Db;
+use tokio::fs;
+
+use crate::passphrase;
+use crate::support::SupportBundle;
+
+#[derive(Clap)]
+pub struct Opts {
+    /// ID of the private key which signs the bundle, required when there are multiple private keys
+    #[clap(long)]
+    key_id: Option<String>,
+
+    /// Log file of the node of which the last lines are included (redacted)
+    #[clap(long)]
+    log_file: Option<PathBuf>,
+
+    /// Number of log lines which are included
+    #[clap(long, default_value = "1000")]
+    log_lines: usize,
+
+    /// Path to write the bundle to (defaults to nuts-support-<timestamp>.json)
+    #[clap(long, short)]
+    output: Option<PathBuf>,
+}
+
+pub async fn cmd(
+    db: Db,
+    data_dir: Option<&Path>,
+    config: Option<&Path>,
+    opts: Opts,
+) -> Result<()> {
+    let private_keys = passphrase::unlock(db.clone())?;
+    let key_id = match opts.key_id {
+        Some(key_id) => key_id,
+        None => {
+            let mut ids = private_keys.ids()?;
+
+            match ids.len() {
+                1 => ids.remove(0),
+                0 => return Err(anyhow!("no private keys found, generate one first")),
+                _ => {
+                    return Err(anyhow!(
+                        "multiple private keys found, specify one using --key-id ({})",
+                        ids.join(", ")
+                    ))
+                }
+            }
+        }
+    };
+    let bundle = SupportBundle::create(
+        db,
+        data_dir,
+        config,
+        opts.log_file.as_deref(),
+        opts.log_lines,
+        &private_keys,
+        &key_id,
+    )
+    .await?;
+    let output = opts.output.unwrap_or_else(|| {
+        format!("nuts-support-{}.json", Utc::now().format("%Y%m%d%H%M%S")).into()
+    });
+
+    fs::write(&output, serde_json::to_vec_pretty(&bundle)?).await?;
+
+    println!(
+        "wrote support bundle signed by '{}' to: {}",
+        key_id,
+        output.display()
+    );
+
+    Ok(())
+}
@@ -0,0 +1,307 @@
+use std::collections::HashMap;
+use std::io::{self, Stdout};
+use std::net::SocketAddr;
+use std::path::Path;
+use std::time::Duration;
+
+use anyhow::Result;
+use chrono::Utc;
+use clap::Clap;
+use crossterm::event::{Event, EventStream, KeyCode, KeyEvent, KeyModifiers};
+use crossterm::execute;
+use crossterm::terminal::{self, EnterAlternateScreen, LeaveAlternateScreen};
+use futures::StreamExt;
+use nuts_rs::network::Health;
+use ratatui::backend::CrosstermBackend;
+use ratatui::layout::{Constraint, Direction, Layout, Rect};
+use ratatui::style::{Color, Modifier, Style};
+use ratatui::text::{Line, Span};
+use ratatui::widgets::{Block, Borders, List, ListItem, Paragraph, Row, Table};
+use ratatui::{Frame, Terminal};
+use tokio::time;
+
+use crate::status;
+
+#[derive(Clap)]
+pub struct Opts {
+    /// Address of the admin API to query instead of reading the status file (e.g. 127.0.0.1:1323)
+    #[clap(long)]
+    admin_addr: Option<SocketAddr>,
+
+    /// Interval in seconds at which the status of the node is read
+    #[clap(long, default_value = "1")]
+    interval: u64,
+}
+
+/// Restores the terminal when the dashboard is closed, also when it failed
+struct Screen {
+    terminal: Terminal<CrosstermBackend<Stdout>>,
+}
+
+impl Screen {
+    fn enter() -> Result<Self> {
+        terminal::enable_raw_mode()?;
+
+        let mut stdout = io::stdout();
+
+        if let Err(e) = execute!(stdout, EnterAlternateScreen) {
+            let _ = terminal::disable_raw_mode();
+
+            return Err(e.into());
+        }
+
+        Ok(Self {
+            terminal: Terminal::new(CrosstermBackend::new(stdout))?,
+        })
+    }
+}
+
+impl Drop for Screen {
+    fn drop(&mut self) {
+        let _ = terminal::disable_raw_mode();
+        let _ = execute!(self.terminal.backend_mut(), LeaveAlternateScreen);
+        let _ = self.terminal.show_cursor();
+    }
+}
+
+/// Latest status of the node and the message rates which are derived from the previous status
+#[derive(Default)]
+struct Dashboard {
+    health: Option<Health>,
+    /// Received messages per second by peer ID
+    rates: HashMap<String, f64>,
+    error: Option<String>,
+}
+
+impl Dashboard {
+    fn update(&mut self, result: Result<Health>) {
+        let health = match result {
+            Ok(health) => health,
+            Err(e) => {
+                self.error = Some(e.to_string());
+                return;
+            }
+        };
+
+        self.error = None;
+
+        // The status is only refreshed by the node every few seconds
+        if let Some(previous) = &self.health {
+            let elapsed = health.updated_at - previous.updated_at;
+
+            if elapsed <= 0 {
+                return;
+            }
+
+            self.rates = health
+                .connections
+                .iter()
+                .map(|peer| {
+                    let before = previous
+                        .connections
+                        .iter()
+                        .find(|other| other.peer_id == peer.peer_id)
+                        .map_or(0, |other| other.messages);
+
+                    (
+                        peer.peer_id.clone(),
+                        peer.messages.saturating_sub(before) as f64 / elapsed as f64,
+                    )
+                })
+                .collect();
+        }
+
+        self.health = Some(health);
+    }
+
+    fn render(&self, frame: &mut Frame<'_>, source: &str) {
+        let rows = Layout::default()
+            .direction(Direction::Vertical)
+            .constraints([
+                Constraint::Length(6),
+                Constraint::Min(6),
+                Constraint::Length(8),
+                Constraint::Length(1),
+            ])
+            .split(frame.size());
+        let columns = Layout::default()
+            .direction(Direction::Horizontal)
+            .constraints([Constraint::Percentage(60), Constraint::Percentage(40)])
+            .split(rows[1]);
+
+        self.render_summary(frame, rows[0], source);
+
+        if let Some(health) = &self.health {
+            self.render_peers(frame, columns[0], health);
+            render_heads(frame, columns[1], health);
+            render_errors(frame, rows[2], health);
+        }
+
+        frame.render_widget(
+            Paragraph::new("press q to quit").style(Style::default().fg(Color::DarkGray)),
+            rows[3],
+        );
+    }
+
+    fn render_summary(&self, frame: &mut Frame<'_>, area: Rect, source: &str) {
+        let mut lines = vec![];
+
+        match &self.health {
+            Some(health) => {
+                let (status, color) = if health.is_healthy() {
+                    ("healthy", Color::Green)
+                } else {
+                    ("unhealthy", Color::Red)
+                };
+
+                lines.push(Line::from(vec![
+                    Span::styled(status, Style::default().fg(color)),
+                    Span::raw(format!(
+                        "  sync: {}  updated: {}  last sync: {}",
+                        health.sync,
+                        ago(health.updated_at),
+                        health.last_sync.map(ago).as_deref().unwrap_or("never"),
+                    )),
+                ]));
+                lines.push(Line::from(format!(
+                    "height: {}  transactions: {}  heads: {}  orphans: {}",
+                    health.height,
+                    health.transactions,
+                    health.heads.len(),
+                    health.orphans
+                )));
+                lines.push(Line::from(format!(
+                    "peers: {} connected, {} synced with  payloads: {} retained, {} pending",
+                    health.connections.len(),
+                    health.peers,
+                    health.payloads.retained,
+                    health.payloads.pending
+                )));
+            }
+            None => lines.push(Line::from("waiting for the status of the node..")),
+        }
+
+        if let Some(e) = &self.error {
+            lines.push(Line::from(Span::styled(
+                e.as_str(),
+                Style::default().fg(Color::Red),
+            )));
+        }
+
+        frame.render_widget(
+            Paragraph::new(lines).block(Block::default().borders(Borders::ALL).title(source)),
+            area,
+        );
+    }
+
+    fn render_peers(&self, frame: &mut Frame<'_>, area: Rect, health: &Health) {
+        let rows = health.connections.iter().map(|peer| {
+            Row::new(vec![
+                peer.peer_id.clone(),
+                peer.protocol_version.to_string(),
+                peer.messages.to_string(),
+                self.rates
+                    .get(&peer.peer_id)
+                    .map_or_else(|| "-".to_string(), |rate| format!("{:.1}/s", rate)),
+            ])
+        });
+        let table = Table::new(
+            rows,
+            [
+                Constraint::Min(36),
+                Constraint::Length(8),
+                Constraint::Length(10),
+                Constraint::Length(10),
+            ],
+        )
+        .header(
+            Row::new(vec!["peer", "protocol", "messages", "rate"])
+                .style(Style::default().add_modifier(Modifier::BOLD)),
+        )
+        .block(Block::default().borders(Borders::ALL).title("Peers"));
+
+        frame.render_widget(table, area);
+    }
+}
+
+fn render_heads(frame: &mut Frame<'_>, area: Rect, health: &Health) {
+    let items = health
+        .heads
+        .iter()
+        .map(|head| ListItem::new(head.as_str()))
+        .collect::<Vec<_>>();
+
+    frame.render_widget(
+        List::new(items).block(Block::default().borders(Borders::ALL).title("Heads")),
+        area,
+    );
+}
+
+fn render_errors(frame: &mut Frame<'_>, area: Rect, health: &Health) {
+    let items = health
+        .errors
+        .iter()
+        .rev()
+        .map(|error| {
+            ListItem::new(Line::from(vec![
+                Span::styled(
+                    format!("{:>8} ", ago(error.at)),
+                    Style::default().fg(Color::DarkGray),
+                ),
+                Span::raw(format!("{}: {}", error.peer_id, error.message)),
+            ]))
+        })
+        .collect::<Vec<_>>();
+
+    frame.render_widget(
+        List::new(items).block(
+            Block::default()
+                .borders(Borders::ALL)
+                .title("Recent errors"),
+        ),
+        area,
+    );
+}
+
+fn ago(timestamp: i64) -> String {
+    format!("{}s ago", Utc::now().timestamp() - timestamp)
+}
+
+fn is_quit(key: &KeyEvent) -> bool {
+    match key.code {
+        KeyCode::Char('q') | KeyCode::Esc => true,
+        KeyCode::Char('c') => key.modifiers.contains(KeyModifiers::CONTROL),
+        _ => false,
+    }
+}
+
+/// Shows a live dashboard of a running node until it's closed, the status is read from the status file or the admin
+/// API just like `network status`
+pub async fn cmd(data_dir: &Path, opts: &Opts) -> Result<()> {
+    let source = match opts.admin_addr {
+        Some(addr) => format!("admin API on {}", addr),
+        None => status::path(data_dir).display().to_string(),
+    };
+    let mut screen = Screen::enter()?;
+    let mut dashboard = Dashboard::default();
+    let mut events = EventStream::new();
+    let mut refresh = time::interval(Duration::from_secs(opts.interval.max(1)));
+
+    loop {
+        screen
+            .terminal
+            .draw(|frame| dashboard.render(frame, &source))?;
+
+        tokio::select! {
+            _ = refresh.tick() => dashboard.update(status::load(data_dir, opts.admin_addr).await),
+            event = events.next() => match event {
+                Some(Ok(Event::Key(key))) if is_quit(&key) => break,
+                Some(Ok(_)) => {}
+                Some(Err(e)) => return Err(e.into()),
+                None => break,
+            },
+        }
+    }
+
+    Ok(())
+}
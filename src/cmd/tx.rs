@@ -0,0 +1,262 @@
+use std::path::{Path, PathBuf};
+
+use anyhow::{anyhow, Result};
+use biscuit::jwk::JWKSet;
+use biscuit::Empty;
+use clap::Clap;
+use nuts_rs::network::{
+    validate_header, Graph, Hash, Outbox, PayloadStore, Transaction, TransactionBuilder,
+    DEFAULT_MAX_CLOCK_SKEW,
+};
+use nuts_rs::pki::{KeyStorage, KeyStore, MemoryKeyStore};
+use sled::Db;
+use tokio::fs;
+use tokio::io::{self, AsyncReadExt};
+
+use crate::passphrase;
+
+#[derive(Clap)]
+pub struct Opts {
+    #[clap(subcommand)]
+    cmd: Cmd,
+}
+
+#[derive(Clap)]
+pub struct VerifyOpts {
+    /// Transaction in the compact JWS format, a file containing it or - to read it from stdin
+    jws: String,
+
+    /// Path to a JWKS file with the keys which can be used to verify the transaction
+    #[clap(long)]
+    jwks: Option<PathBuf>,
+}
+
+#[derive(Clap)]
+pub struct PublishOpts {
+    /// Content type of the payload (e.g. application/did+json)
+    #[clap(long)]
+    payload_type: String,
+
+    /// File containing the payload or - to read it from stdin
+    #[clap(long)]
+    file: String,
+
+    /// ID of the private key used to sign the transaction, defaults to the only private key of the node
+    #[clap(long)]
+    key_id: Option<String>,
+}
+
+#[derive(Clap)]
+pub enum Cmd {
+    /// Verifies the signature and header rules of a single transaction without a database or network
+    Verify(VerifyOpts),
+
+    /// Stores a payload and adds a transaction referencing it to the DAG, which is announced to peers when the node
+    /// runs
+    Publish(PublishOpts),
+}
+
+async fn read_jws(source: &str) -> Result<String> {
+    let jws = if source == "-" {
+        let mut jws = String::new();
+
+        io::stdin().read_to_string(&mut jws).await?;
+        jws
+    } else if Path::new(source).is_file() {
+        fs::read_to_string(source).await?
+    } else {
+        source.to_string()
+    };
+
+    Ok(jws.trim().to_string())
+}
+
+async fn read_jwks(path: &Path) -> Result<MemoryKeyStore> {
+    let data = fs::read(path)
+        .await
+        .map_err(|e| anyhow!("unable to read JWKS '{}': {}", path.display(), e))?;
+    let jwk_set: JWKSet<Empty> = serde_json::from_slice(&data)
+        .map_err(|e| anyhow!("invalid JWKS '{}': {}", path.display(), e))?;
+    let mut store = MemoryKeyStore::default();
+
+    for key in jwk_set.keys {
+        let key_id = key
+            .common
+            .key_id
+            .clone()
+            .ok_or_else(|| anyhow!("key in JWKS '{}' is missing it's ID", path.display()))?;
+
+        store.add(key_id, key)?;
+    }
+
+    Ok(store)
+}
+
+fn report(check: &str, result: &Result<()>) {
+    match result {
+        Ok(_) => println!("{}: ok", check),
+        Err(e) => println!("{}: failed ({})", check, e),
+    }
+}
+
+async fn verify(opts: &VerifyOpts) -> Result<()> {
+    let jws = read_jws(&opts.jws).await?;
+    let store = match &opts.jwks {
+        Some(path) => read_jwks(path).await?,
+        None => MemoryKeyStore::default(),
+    };
+
+    let header = validate_header(&jws).map_err(Into::into);
+
+    report("header", &header);
+
+    let tx = match Transaction::parse_unsafe(&jws) {
+        Ok(tx) => tx,
+        Err(e) => {
+            report("format", &Err(e.into()));
+
+            return Err(anyhow!("transaction is invalid"));
+        }
+    };
+
+    let key = match (&tx.key, store.contains(&tx.key_id)?) {
+        (Some(_), _) => Ok(()),
+        (None, true) => Ok(()),
+        (None, false) => Err(anyhow!(
+            "key '{}' isn't embedded and not found in the JWKS",
+            tx.key_id
+        )),
+    };
+
+    report("key", &key);
+
+    let signature = match &key {
+        Ok(_) => Transaction::parse(&store, &jws)
+            .map(|_| ())
+            .map_err(Into::into),
+        Err(_) => Err(anyhow!("skipped as the key is unknown")),
+    };
+
+    report("signature", &signature);
+
+    let sign_time = tx
+        .check_sign_time(DEFAULT_MAX_CLOCK_SKEW)
+        .map_err(Into::into);
+
+    report("sign time", &sign_time);
+
+    println!();
+    println!("id: {}", tx.id);
+    println!("key_id: {}", tx.key_id);
+    println!(
+        "key: {}",
+        if tx.key.is_some() {
+            "embedded"
+        } else {
+            "referenced"
+        }
+    );
+    println!("version: {}", tx.version);
+    println!("sign_algorithm: {:?}", tx.sign_algo);
+    println!("sign_at: {}", tx.sign_at);
+    println!("payload: {}", tx.payload);
+    println!("payload_type: {}", tx.payload_type);
+    println!(
+        "previous: {}",
+        tx.prevs
+            .iter()
+            .map(|id| id.to_string())
+            .collect::<Vec<_>>()
+            .join(", ")
+    );
+
+    if let Some(lamport_clock) = tx.lamport_clock {
+        println!("lamport_clock: {}", lamport_clock);
+    }
+
+    for (name, value) in tx.extra_headers.iter() {
+        println!("header {}: {}", name, value);
+    }
+
+    if tx.is_private() {
+        println!("participants: {} (encrypted)", tx.pal.len());
+    }
+
+    if header.is_err() || signature.is_err() || sign_time.is_err() {
+        return Err(anyhow!("transaction is invalid"));
+    }
+
+    Ok(())
+}
+
+async fn publish(db: Db, opts: PublishOpts) -> Result<()> {
+    let payload = if opts.file == "-" {
+        let mut payload = vec![];
+
+        io::stdin().read_to_end(&mut payload).await?;
+        payload
+    } else {
+        fs::read(&opts.file)
+            .await
+            .map_err(|e| anyhow!("unable to read payload '{}': {}", opts.file, e))?
+    };
+
+    let private_keys = passphrase::unlock(db.clone())?;
+    let key_id = match opts.key_id {
+        Some(key_id) => key_id,
+        None => {
+            let mut ids = private_keys.ids()?;
+
+            match ids.len() {
+                1 => ids.remove(0),
+                0 => return Err(anyhow!("no private keys found, generate one first")),
+                _ => {
+                    return Err(anyhow!(
+                        "multiple private keys found, specify one using --key-id ({})",
+                        ids.join(", ")
+                    ))
+                }
+            }
+        }
+    };
+    let key = private_keys
+        .get(&key_id)?
+        .ok_or_else(|| anyhow!("private key not found with ID: {}", key_id))?;
+
+    let mut graph = Graph::open(db.clone())?;
+    let key_store = KeyStore::open(db.clone())?;
+    let payload_hash = Hash::new(&payload)?;
+
+    // Peers only know the public key once it was embedded in a transaction
+    let embed_key = !graph.iter().any(|tx| tx.key_id == key_id);
+    let tx = TransactionBuilder::new(opts.payload_type, &payload)?
+        .append_to(&graph)
+        .embed_key(embed_key)
+        .sign(&key_id, &key)?;
+    let tx = Transaction::parse(&key_store, String::from_utf8(tx.data)?)?;
+
+    PayloadStore::open(db.clone())?.add(&payload_hash, payload)?;
+    graph.add(tx.clone())?;
+    graph.flush().await?;
+    Outbox::open(db)?.push(&tx.id)?;
+
+    println!("id: {}", tx.id);
+    println!("payload: {}", payload_hash);
+
+    Ok(())
+}
+
+/// Runs the commands which don't need the database, returns `None` for all other commands
+pub async fn offline(opts: &Opts) -> Option<Result<()>> {
+    match &opts.cmd {
+        Cmd::Verify(opts) => Some(verify(opts).await),
+        _ => None,
+    }
+}
+
+pub async fn cmd(db: Db, opts: Opts) -> Result<()> {
+    match opts.cmd {
+        Cmd::Verify(_) => unreachable!("transactions are verified before the database is opened"),
+        Cmd::Publish(opts) => publish(db, opts).await,
+    }
+}
@@ -0,0 +1,216 @@
+use std::collections::BTreeMap;
+use std::net::SocketAddr;
+use std::path::{Path, PathBuf};
+
+use anyhow::{anyhow, Result};
+use serde::de::DeserializeOwned;
+use serde::Deserialize;
+use serde_json::Value;
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct TlsConfig {
+    pub truststore: Option<PathBuf>,
+    pub certificate: Option<PathBuf>,
+    pub key: Option<PathBuf>,
+    pub reload_interval: Option<u64>,
+    pub min_version: Option<String>,
+    pub cipher_suites: Vec<String>,
+    pub alpn_protocols: Vec<String>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct NetworkConfig {
+    pub network_id: Option<String>,
+    pub node_did: Option<String>,
+    pub listen_addr: Option<SocketAddr>,
+    pub bootstrap_nodes: Vec<String>,
+    pub sync_min_interval: Option<u64>,
+    pub sync_max_interval: Option<u64>,
+    pub initial_sync_timeout: Option<u64>,
+    /// What happens when a peer sends messages faster than they're handled: "drop-oldest" or "disconnect"
+    pub overflow_policy: Option<String>,
+    /// DNS name which is resolved to discover peers, SRV records are queried for names like `_nuts._tcp.example.com`
+    pub discovery_name: Option<String>,
+    pub discovery_interval: Option<u64>,
+    /// Name server which is queried for SRV records instead of the one in `/etc/resolv.conf`
+    pub discovery_nameserver: Option<SocketAddr>,
+    pub target_peers: Option<usize>,
+    /// Certificate fingerprints, DNS names or CIDRs of the only peers which are allowed (e.g. "10.0.0.0/8")
+    pub allow_peers: Vec<String>,
+    /// Certificate fingerprints, DNS names or CIDRs of peers which are denied, takes precedence over `allow_peers`
+    pub deny_peers: Vec<String>,
+    /// Certificate fingerprints or DNS names of the peers which are a member of a group (e.g. "care-x" =
+    /// ["*.care-x.nl"]), groups advertised by other peers are ignored
+    pub group_members: BTreeMap<String, Vec<String>>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct AdminConfig {
+    pub listen_addr: Option<SocketAddr>,
+}
+
+/// Settings which are read from the configuration file, flags passed on the command-line take precedence
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Config {
+    pub data_dir: Option<PathBuf>,
+    pub log_level: Option<String>,
+    pub log_format: Option<String>,
+    pub profile: Option<String>,
+    pub cache_capacity: Option<u64>,
+    pub storage: Option<String>,
+    pub storage_backend: Option<String>,
+    pub tls: TlsConfig,
+    pub network: NetworkConfig,
+    pub admin: AdminConfig,
+    /// Period for which the payloads of a payload type are kept (e.g. "application/vc+json" = "90d")
+    pub retention: BTreeMap<String, String>,
+}
+
+impl Config {
+    pub fn load(path: impl AsRef<Path>) -> Result<Self> {
+        load(path.as_ref())
+    }
+}
+
+/// Settings of the supervisor, each table in `nodes` is the configuration of a single node (e.g. `[nodes.care-x]`)
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct SupervisorConfig {
+    /// Address on which the combined metrics of all nodes are served
+    pub metrics_addr: Option<SocketAddr>,
+    pub nodes: BTreeMap<String, Config>,
+}
+
+impl SupervisorConfig {
+    pub fn load(path: impl AsRef<Path>) -> Result<Self> {
+        load(path.as_ref())
+    }
+}
+
+/// Reads the configuration file without interpreting it's settings
+pub fn read(path: &Path) -> Result<Value> {
+    load(path)
+}
+
+fn load<T: DeserializeOwned>(path: &Path) -> Result<T> {
+    let source = std::fs::read_to_string(path)
+        .map_err(|e| anyhow!("unable to read config '{}': {}", path.display(), e))?;
+
+    parse(&source).map_err(|e| anyhow!("invalid config '{}': {}", path.display(), e))
+}
+
+fn parse<T: DeserializeOwned>(source: &str) -> Result<T> {
+    Ok(toml::from_str(source)?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn full_config() {
+        let config: Config = parse(
+            r#"
+            data_dir = "/var/lib/nuts"
+            cache_capacity = 1_073_741_824
+
+            [tls]
+            certificate = "/etc/nuts/node.pem"
+            cipher_suites = [
+                "TLS13_AES_256_GCM_SHA384", # preferred
+                "TLS13_CHACHA20_POLY1305_SHA256",
+            ]
+
+            [network]
+            network_id = "care-x"
+            listen_addr = "0.0.0.0:5555"
+            allow_peers = ["10.0.0.0/8", '*.care-x.nl']
+
+            [network.group_members]
+            care-x = ["*.care-x.nl"]
+            "care y" = []
+
+            [retention]
+            "application/vc+json" = "90d"
+            "#,
+        )
+        .unwrap();
+
+        assert_eq!(config.data_dir, Some(PathBuf::from("/var/lib/nuts")));
+        assert_eq!(config.cache_capacity, Some(1_073_741_824));
+        assert_eq!(config.tls.cipher_suites.len(), 2);
+        assert_eq!(config.network.network_id.as_deref(), Some("care-x"));
+        assert_eq!(
+            config.network.listen_addr,
+            Some("0.0.0.0:5555".parse().unwrap())
+        );
+        assert_eq!(
+            config.network.allow_peers,
+            vec!["10.0.0.0/8", "*.care-x.nl"]
+        );
+        assert_eq!(
+            config.network.group_members["care-x"],
+            vec!["*.care-x.nl".to_string()]
+        );
+        assert!(config.network.group_members["care y"].is_empty());
+        assert_eq!(config.retention["application/vc+json"], "90d");
+    }
+
+    #[test]
+    fn empty_config() {
+        let config: Config = parse("").unwrap();
+
+        assert!(config.data_dir.is_none());
+        assert!(config.network.bootstrap_nodes.is_empty());
+    }
+
+    #[test]
+    fn unknown_setting() {
+        assert!(parse::<Config>("unknown = 1").is_err());
+        assert!(parse::<Config>("[network]\nlisten = \"0.0.0.0:5555\"").is_err());
+        assert!(parse::<Config>("[unknown]").is_err());
+    }
+
+    #[test]
+    fn invalid_value() {
+        assert!(parse::<Config>("cache_capacity = \"1GB\"").is_err());
+        assert!(parse::<Config>("[network]\nlisten_addr = \"localhost\"").is_err());
+        assert!(parse::<Config>("data_dir = \"unterminated").is_err());
+        assert!(parse::<Config>("data_dir = \"a\"\ndata_dir = \"b\"").is_err());
+    }
+
+    #[test]
+    fn supervisor_config() {
+        let config: SupervisorConfig = parse(
+            r#"
+            metrics_addr = "127.0.0.1:9100"
+
+            [nodes.care-x.network]
+            network_id = "care-x"
+
+            [nodes.care-y]
+            storage = "memory"
+            "#,
+        )
+        .unwrap();
+
+        assert_eq!(config.nodes.len(), 2);
+        assert_eq!(
+            config.nodes["care-x"].network.network_id.as_deref(),
+            Some("care-x")
+        );
+        assert_eq!(config.nodes["care-y"].storage.as_deref(), Some("memory"));
+        assert!(parse::<SupervisorConfig>("[nodes.care-x]\nunknown = true").is_err());
+    }
+
+    #[test]
+    fn read_as_value() {
+        let value: Value = parse("[tls]\nkey = \"/etc/nuts/key.pem\"").unwrap();
+
+        assert_eq!(value["tls"]["key"], "/etc/nuts/key.pem");
+    }
+}
@@ -0,0 +1,16 @@
+//! Rust implementation of a Nuts node, which can be embedded in other services.
+//!
+//! The [`network::Graph`] stores the DAG of [`network::Transaction`]s, each identified by a [`network::Hash`], and is
+//! kept in sync with other nodes by the [`network::Server`]. The public keys used to verify transactions are stored
+//! in a [`pki::KeyStorage`] backend, which is the database backed [`pki::KeyStore`] by default. Databases written by
+//! older versions are upgraded using [`schema::migrate`]. The `nuts-rs` binary is a thin consumer of this library and
+//! is only built with the `cli` feature (enabled by default).
+
+pub mod backend;
+pub mod network;
+pub mod pki;
+pub mod retry;
+pub mod schema;
+
+pub mod metrics;
+mod proto;
@@ -0,0 +1,45 @@
+use std::str::FromStr;
+
+use anyhow::{anyhow, Error, Result};
+use tracing_subscriber::EnvFilter;
+
+/// Format of the log lines, JSON includes the fields of the spans (e.g. the peer and trace ID) as separate keys so
+/// that they can be filtered on by log aggregators
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum LogFormat {
+    Text,
+    Json,
+}
+
+impl FromStr for LogFormat {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "text" => Ok(LogFormat::Text),
+            "json" => Ok(LogFormat::Json),
+            _ => Err(anyhow!(
+                "invalid log format '{}' (expected text or json)",
+                s
+            )),
+        }
+    }
+}
+
+/// Installs the global subscriber, the level takes precedence over the RUST_LOG environment variable. Records of
+/// dependencies which use the `log` crate are forwarded to the subscriber as well
+pub fn init(level: Option<&str>, format: LogFormat) -> Result<()> {
+    let filter = match level {
+        Some(level) => EnvFilter::try_new(level)?,
+        None => EnvFilter::try_from_default_env().unwrap_or_else(|_| EnvFilter::new("error")),
+    };
+    let builder = tracing_subscriber::fmt()
+        .with_env_filter(filter)
+        .with_writer(std::io::stderr);
+
+    match format {
+        LogFormat::Text => builder.try_init(),
+        LogFormat::Json => builder.json().try_init(),
+    }
+    .map_err(|e| anyhow!("failed to initialize logging: {}", e))
+}
@@ -1,38 +1,263 @@
-use anyhow::Result;
+use std::path::PathBuf;
+
+use anyhow::{Error, Result};
 use clap::Clap;
+use nuts_rs::backend::{self, Backend};
+use nuts_rs::network::{GraphError, NetworkError, ParseError};
+use nuts_rs::pki::KeyStoreError;
+use nuts_rs::schema;
 
-use cmd::{graph as graph_cmd, pki as pki_cmd, run as run_cmd};
+use cmd::{
+    admin as admin_cmd, backup as backup_cmd, conformance as conformance_cmd, db as db_cmd,
+    graph as graph_cmd, network as network_cmd, payload as payload_cmd, pki as pki_cmd,
+    run as run_cmd, supervise as supervise_cmd, support as support_cmd, top as top_cmd,
+    tx as tx_cmd,
+};
+use config::Config;
+use logging::LogFormat;
+use output::Output;
+use profile::Profile;
+use storage::Storage;
 
+mod admin;
+mod annotations;
+mod backup;
+mod bundle;
 mod cmd;
-mod network;
-mod pki;
-mod proto;
+mod config;
+mod keyfile;
+mod logging;
+mod output;
+mod passphrase;
+mod profile;
+mod self_test;
+mod shutdown;
+mod status;
+mod storage;
+mod support;
+mod systemd;
+mod tls;
+mod webhook;
 
 #[derive(Clap)]
 struct Opts {
+    /// Path to the configuration file (e.g. nuts.toml)
+    #[clap(long, global = true)]
+    config: Option<PathBuf>,
+
+    /// Directory in which the database is stored (defaults to .nuts)
+    #[clap(long, global = true, env = "NUTS_DATA_DIR")]
+    data_dir: Option<PathBuf>,
+
+    /// Log level (e.g. info or nuts=debug) which takes precedence over the RUST_LOG environment variable
+    #[clap(long, global = true)]
+    log_level: Option<String>,
+
+    /// Format of the log lines (text or json)
+    #[clap(long, global = true, env = "NUTS_LOG_FORMAT")]
+    log_format: Option<LogFormat>,
+
+    /// Preset of settings for the hardware the node runs on (default, raspberry-pi or server)
+    #[clap(long, global = true, env = "NUTS_PROFILE")]
+    profile: Option<Profile>,
+
+    /// Maximum size in bytes of the database cache, overrides the profile
+    #[clap(long, global = true)]
+    cache_capacity: Option<u64>,
+
+    /// Where the database is stored (disk or memory), an in-memory database is removed when the process exits
+    #[clap(long, global = true, env = "NUTS_STORAGE")]
+    storage: Option<Storage>,
+
+    /// Backend in which the DAG, keys, payloads and peers of a new database are stored (sled or redb)
+    #[clap(long, global = true, env = "NUTS_STORAGE_BACKEND")]
+    storage_backend: Option<Backend>,
+
+    /// Format of the output (text or json), only supported by commands which list or show data
+    #[clap(long, global = true, default_value = "text")]
+    output: Output,
+
     #[clap(subcommand)]
     cmd: Cmd,
 }
 
+// The options of `run` are a lot larger but there is only a single instance
+#[allow(clippy::large_enum_variant)]
 #[derive(Clap)]
 enum Cmd {
     Run(run_cmd::Opts),
     Pki(pki_cmd::Opts),
     Graph(graph_cmd::Opts),
+    Network(network_cmd::Opts),
+    Payload(payload_cmd::Opts),
+    Db(db_cmd::Opts),
+    Admin(admin_cmd::Opts),
+    Tx(tx_cmd::Opts),
+    Supervise(supervise_cmd::Opts),
+    SupportBundle(support_cmd::Opts),
+    Conformance(conformance_cmd::Opts),
+    Backup(backup_cmd::Opts),
+    Restore(backup_cmd::RestoreOpts),
+    Top(top_cmd::Opts),
+}
+
+/// Returns the exit code for an error (based on `sysexits.h`) so that scripts can tell errors apart: 65 for invalid
+/// data (e.g. an invalid or duplicate transaction or key), 69 when a peer is unavailable, 74 for storage errors, 77
+/// when the private keys are locked or a peer isn't authorized and 1 for all other errors
+fn exit_code(e: &Error) -> i32 {
+    for cause in e.chain() {
+        if let Some(e) = cause.downcast_ref::<GraphError>() {
+            return match e {
+                GraphError::Storage(_) => 74,
+                _ => 65,
+            };
+        }
+
+        if let Some(e) = cause.downcast_ref::<KeyStoreError>() {
+            return match e {
+                KeyStoreError::Locked | KeyStoreError::InvalidPassphrase => 77,
+                KeyStoreError::Storage(_) => 74,
+                _ => 65,
+            };
+        }
+
+        if let Some(e) = cause.downcast_ref::<NetworkError>() {
+            return match e {
+                NetworkError::Unauthorized(_) => 77,
+                _ => 69,
+            };
+        }
+
+        if cause.is::<ParseError>() {
+            return 65;
+        }
+
+        if cause.is::<sled::Error>() || cause.is::<std::io::Error>() {
+            return 74;
+        }
+    }
+
+    1
 }
 
 #[tokio::main]
-async fn main() -> Result<()> {
+async fn main() {
+    if let Err(e) = execute().await {
+        eprintln!("Error: {:?}", e);
+        std::process::exit(exit_code(&e));
+    }
+}
+
+async fn execute() -> Result<()> {
     let opts = Opts::parse();
 
-    pretty_env_logger::init();
+    let config = match &opts.config {
+        Some(path) => Config::load(path)?,
+        None => Config::default(),
+    };
+
+    let log_format = match opts.log_format {
+        Some(log_format) => log_format,
+        None => config.log_format.as_deref().unwrap_or("text").parse()?,
+    };
+
+    logging::init(
+        opts.log_level.as_deref().or(config.log_level.as_deref()),
+        log_format,
+    )?;
+
+    let output = opts.output;
+    let data_dir = opts
+        .data_dir
+        .or(config.data_dir.clone())
+        .unwrap_or_else(|| ".nuts".into());
+
+    // These commands don't need the database, the status is even read while the node is running which holds the
+    // lock on the database
+    match &opts.cmd {
+        Cmd::Tx(opts) => {
+            if let Some(result) = tx_cmd::offline(opts).await {
+                return result;
+            }
+        }
+        Cmd::Network(opts) => {
+            if let Some(result) = network_cmd::status(&data_dir, opts, output).await {
+                return result;
+            }
+        }
+        Cmd::Backup(opts) => {
+            if let Some(result) = backup_cmd::online(opts).await {
+                return result;
+            }
+        }
+        // Every node has it's own database which is opened by the supervisor
+        Cmd::Supervise(opts) => return supervise_cmd::cmd(&data_dir, opts).await,
+        // Only connects to a remote peer
+        Cmd::Conformance(opts) => return conformance_cmd::cmd(&config, opts, output).await,
+        // Reads the status of the running node which holds the lock on the database
+        Cmd::Top(opts) => return top_cmd::cmd(&data_dir, opts).await,
+        _ => {}
+    }
+
+    let profile = match opts.profile {
+        Some(profile) => profile,
+        None => config.profile.as_deref().unwrap_or("default").parse()?,
+    };
+    let tuning = profile.tuning();
+    let storage = match opts.storage {
+        Some(storage) => storage,
+        None => config.storage.as_deref().unwrap_or("disk").parse()?,
+    };
+    let db = storage.open(
+        &data_dir,
+        opts.cache_capacity
+            .or(config.cache_capacity)
+            .unwrap_or(tuning.cache_capacity),
+    )?;
+    let storage_backend = match opts.storage_backend {
+        Some(storage_backend) => Some(storage_backend),
+        None => config
+            .storage_backend
+            .as_deref()
+            .map(str::parse)
+            .transpose()?,
+    };
+
+    if let Some(storage_backend) = storage_backend {
+        let data_dir = match storage {
+            Storage::Disk => Some(data_dir.as_path()),
+            Storage::Memory => None,
+        };
+
+        backend::select(&db, storage_backend, data_dir)?;
+    }
+
+    // The database commands work on the raw trees and a backup is only restored into an empty database
+    if !matches!(opts.cmd, Cmd::Db(_) | Cmd::Restore(_)) {
+        schema::migrate(&db)?;
+    }
 
-    let db = sled::open(".nuts")?;
+    // Nothing is written to the data directory when the database is stored in memory
+    let data_dir = match storage {
+        Storage::Disk => Some(data_dir),
+        Storage::Memory => None,
+    };
 
     match opts.cmd {
-        Cmd::Run(opts) => run_cmd::cmd(db, opts).await,
-        Cmd::Pki(opts) => pki_cmd::cmd(db, opts).await,
-        Cmd::Graph(opts) => graph_cmd::cmd(db, opts).await,
+        Cmd::Run(opts) => run_cmd::cmd(db, data_dir, config, tuning, opts).await,
+        Cmd::Pki(opts) => pki_cmd::cmd(db, opts, output).await,
+        Cmd::Graph(opts) => graph_cmd::cmd(db, opts, output).await,
+        Cmd::Network(opts) => network_cmd::cmd(db, opts, output).await,
+        Cmd::Payload(opts) => payload_cmd::cmd(db, opts).await,
+        Cmd::Db(opts) => db_cmd::cmd(db, opts).await,
+        Cmd::Admin(opts) => admin_cmd::cmd(db, opts).await,
+        Cmd::Tx(opts) => tx_cmd::cmd(db, opts).await,
+        Cmd::Backup(opts) => backup_cmd::cmd(db, opts).await,
+        Cmd::Restore(opts) => backup_cmd::restore(db, opts).await,
+        Cmd::SupportBundle(cmd_opts) => {
+            support_cmd::cmd(db, data_dir.as_deref(), opts.config.as_deref(), cmd_opts).await
+        }
+        Cmd::Supervise(_) | Cmd::Conformance(_) | Cmd::Top(_) => unreachable!(),
     }?;
 
     Ok(())
@@ -0,0 +1,45 @@
+use std::collections::BTreeMap;
+use std::sync::{Arc, Mutex};
+
+/// Counters of a single node, which are reported on shutdown and exported by the supervisor. Clones share the same
+/// counters, components which aren't given the counters of a node count in a set of their own
+#[derive(Debug, Clone, Default)]
+pub struct Metrics {
+    counters: Arc<Mutex<BTreeMap<String, u64>>>,
+}
+
+impl Metrics {
+    pub fn increment(&self, name: &str) {
+        let mut counters = self.counters.lock().unwrap();
+
+        *counters.entry(name.to_string()).or_default() += 1;
+    }
+
+    /// Returns the current value of all counters ordered by name
+    pub fn counters(&self) -> Vec<(String, u64)> {
+        self.counters
+            .lock()
+            .unwrap()
+            .iter()
+            .map(|(name, value)| (name.clone(), *value))
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn counters_per_node() {
+        let node_1 = Metrics::default();
+        let node_2 = Metrics::default();
+
+        node_1.increment("peers.blocked");
+        node_1.clone().increment("peers.blocked");
+        node_2.increment("orphans.dropped");
+
+        assert_eq!(node_1.counters(), vec![("peers.blocked".to_string(), 2)]);
+        assert_eq!(node_2.counters(), vec![("orphans.dropped".to_string(), 1)]);
+    }
+}
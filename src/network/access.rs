@@ -0,0 +1,369 @@
+use std::fmt::{self, Display, Formatter};
+use std::net::{IpAddr, SocketAddr};
+use std::str::FromStr;
+
+use anyhow::{anyhow, Error, Result};
+use chrono::Utc;
+use rmp_serde::{decode, encode};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use sled::{Db, Tree};
+use tonic::transport::Uri;
+
+use crate::network::certificate::PeerCertificate;
+use crate::network::identities::PeerIdentity;
+
+/// Range of IP addresses in CIDR notation (e.g. 10.0.0.0/8), an address without prefix length only matches itself
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Cidr {
+    addr: IpAddr,
+    prefix_len: u8,
+}
+
+impl Cidr {
+    pub fn contains(&self, ip: IpAddr) -> bool {
+        match (self.addr, normalize(ip)) {
+            (IpAddr::V4(net), IpAddr::V4(ip)) => {
+                prefix_matches(&net.octets(), &ip.octets(), self.prefix_len)
+            }
+            (IpAddr::V6(net), IpAddr::V6(ip)) => {
+                prefix_matches(&net.octets(), &ip.octets(), self.prefix_len)
+            }
+            _ => false,
+        }
+    }
+}
+
+impl Display for Cidr {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        write!(f, "{}/{}", self.addr, self.prefix_len)
+    }
+}
+
+impl FromStr for Cidr {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let invalid = || anyhow!("invalid CIDR '{}'", s);
+        let (addr, prefix_len) = match s.split_once('/') {
+            Some((addr, prefix_len)) => (addr, Some(prefix_len)),
+            None => (s, None),
+        };
+        let addr = normalize(addr.parse().map_err(|_| invalid())?);
+        let max_len = if addr.is_ipv4() { 32 } else { 128 };
+        let prefix_len = match prefix_len {
+            Some(prefix_len) => prefix_len.parse().map_err(|_| invalid())?,
+            None => max_len,
+        };
+
+        if prefix_len > max_len {
+            return Err(invalid());
+        }
+
+        Ok(Self { addr, prefix_len })
+    }
+}
+
+/// IPv4 addresses of dual-stack sockets are reported as IPv4-mapped IPv6 addresses
+fn normalize(ip: IpAddr) -> IpAddr {
+    match ip {
+        IpAddr::V6(v6) => v6.to_ipv4_mapped().map(IpAddr::V4).unwrap_or(ip),
+        ip => ip,
+    }
+}
+
+fn prefix_matches(net: &[u8], ip: &[u8], prefix_len: u8) -> bool {
+    let bytes = (prefix_len / 8) as usize;
+    let bits = prefix_len % 8;
+
+    if net[..bytes] != ip[..bytes] {
+        return false;
+    }
+
+    bits == 0 || (net[bytes] ^ ip[bytes]) >> (8 - bits) == 0
+}
+
+/// Rule which is matched against a peer, written as a SHA-256 certificate fingerprint (optionally prefixed by
+/// `sha256:` and separated by colons), a CIDR or a DNS name where a leading `*.` matches any subdomain
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum PeerRule {
+    /// Lowercase hex encoded SHA-256 of the DER encoded certificate
+    Fingerprint(String),
+    DnsName(String),
+    Cidr(Cidr),
+}
+
+impl PeerRule {
+    pub fn matches(&self, peer: &PeerSubject) -> bool {
+        match self {
+            PeerRule::Fingerprint(fingerprint) => {
+                peer.fingerprint.as_deref() == Some(fingerprint.as_str())
+            }
+            PeerRule::DnsName(pattern) => peer.dns_names.iter().any(|name| {
+                let name = name.to_ascii_lowercase();
+
+                match pattern.strip_prefix("*.") {
+                    Some(domain) => name
+                        .strip_suffix(domain)
+                        .is_some_and(|sub| sub.len() > 1 && sub.ends_with('.')),
+                    None => name == *pattern,
+                }
+            }),
+            PeerRule::Cidr(cidr) => peer.ips.iter().any(|ip| cidr.contains(*ip)),
+        }
+    }
+
+    /// Whether the peer has the attribute the rule is matched against
+    fn applies_to(&self, peer: &PeerSubject) -> bool {
+        match self {
+            PeerRule::Fingerprint(_) => peer.fingerprint.is_some(),
+            PeerRule::DnsName(_) => !peer.dns_names.is_empty(),
+            PeerRule::Cidr(_) => !peer.ips.is_empty(),
+        }
+    }
+}
+
+impl Display for PeerRule {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            PeerRule::Fingerprint(fingerprint) => write!(f, "sha256:{}", fingerprint),
+            PeerRule::DnsName(name) => write!(f, "{}", name),
+            PeerRule::Cidr(cidr) => write!(f, "{}", cidr),
+        }
+    }
+}
+
+impl FromStr for PeerRule {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let fingerprint = s
+            .strip_prefix("sha256:")
+            .unwrap_or(s)
+            .replace(':', "")
+            .to_ascii_lowercase();
+
+        if fingerprint.len() == 64 && fingerprint.chars().all(|c| c.is_ascii_hexdigit()) {
+            return Ok(PeerRule::Fingerprint(fingerprint));
+        }
+
+        if let Ok(cidr) = s.parse() {
+            return Ok(PeerRule::Cidr(cidr));
+        }
+
+        let name = s.to_ascii_lowercase();
+        let labels = name.strip_prefix("*.").unwrap_or(&name);
+
+        if !labels.is_empty()
+            && labels.split('.').all(|label| {
+                !label.is_empty()
+                    && label
+                        .chars()
+                        .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_')
+            })
+        {
+            return Ok(PeerRule::DnsName(name));
+        }
+
+        Err(anyhow!(
+            "invalid peer rule '{}' (expected a certificate fingerprint, CIDR or DNS name)",
+            s
+        ))
+    }
+}
+
+/// Attributes of a peer which rules are matched against, the certificate of a peer which this node connects to is
+/// only known once it's presented during the TLS handshake
+#[derive(Debug, Clone, Default)]
+pub struct PeerSubject {
+    pub ips: Vec<IpAddr>,
+    pub dns_names: Vec<String>,
+    pub fingerprint: Option<String>,
+}
+
+impl PeerSubject {
+    /// Describes a peer which connected to this node using it's remote address and DER encoded client certificate
+    pub fn inbound(addr: Option<SocketAddr>, certificate: Option<&[u8]>) -> Self {
+        Self {
+            ips: addr.map(|addr| addr.ip()).into_iter().collect(),
+            // Invalid certificates are rejected when the identity of the peer is bound
+            dns_names: certificate
+                .and_then(|der| PeerCertificate::parse(der).ok())
+                .map(|certificate| certificate.dns_names)
+                .unwrap_or_default(),
+            fingerprint: certificate.map(|der| hex::encode(Sha256::digest(der))),
+        }
+    }
+
+    /// Adds the DNS names and fingerprint of the DER encoded certificate which the peer presented
+    pub fn with_certificate(mut self, certificate: &[u8]) -> Result<Self> {
+        for name in PeerCertificate::parse(certificate)?.dns_names {
+            let name = name.to_ascii_lowercase();
+
+            if !self.dns_names.contains(&name) {
+                self.dns_names.push(name);
+            }
+        }
+
+        self.fingerprint = Some(hex::encode(Sha256::digest(certificate)));
+
+        Ok(self)
+    }
+
+    /// Describes a peer by the certificate it's peer ID is bound to
+    pub fn bound(identity: &PeerIdentity) -> Self {
+        Self {
+            ips: vec![],
+            dns_names: identity.dns_names.clone(),
+            fingerprint: identity.fingerprint.clone(),
+        }
+    }
+
+    /// Describes a peer which this node connects to using the host of it's address, host names are resolved to
+    /// match them against CIDRs. The certificate is added using `with_certificate` during the TLS handshake
+    pub async fn outbound(addr: &str) -> Self {
+        let uri = match addr.parse::<Uri>() {
+            Ok(uri) => uri,
+            Err(_) => return Self::default(),
+        };
+        let host = match uri.host() {
+            Some(host) => host.trim_start_matches('[').trim_end_matches(']'),
+            None => return Self::default(),
+        };
+
+        if let Ok(ip) = host.parse() {
+            return Self {
+                ips: vec![ip],
+                ..Self::default()
+            };
+        }
+
+        Self {
+            // Resolving only fails when the host is unreachable anyway
+            ips: tokio::net::lookup_host((host, uri.port_u16().unwrap_or(443)))
+                .await
+                .map(|addrs| addrs.map(|addr| addr.ip()).collect())
+                .unwrap_or_default(),
+            dns_names: vec![host.to_ascii_lowercase()],
+            fingerprint: None,
+        }
+    }
+}
+
+/// Rules which decide which peers are allowed to connect and be connected to, deny rules take precedence and when
+/// any allow rules are configured, peers must match one of them. Allow rules which can't be matched (e.g. a
+/// fingerprint before the certificate of the peer is known) are skipped
+#[derive(Debug, Clone, Default)]
+pub struct AccessPolicy {
+    pub allow: Vec<PeerRule>,
+    pub deny: Vec<PeerRule>,
+}
+
+/// Peer which was blocked using the command-line
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct BlockedPeer {
+    pub rule: String,
+    pub blocked_at: i64,
+}
+
+/// Deny rules which are managed using the command-line and persisted in the database, in addition to the rules of
+/// the access policy
+#[derive(Clone)]
+pub struct Blocklist {
+    tree: Tree,
+}
+
+impl Blocklist {
+    pub fn open(db: Db) -> Result<Self> {
+        Ok(Self {
+            tree: db.open_tree("nuts/blocked-peers")?,
+        })
+    }
+
+    /// Blocks peers matching the rule, returns false when they were already blocked
+    pub fn block(&self, rule: &PeerRule) -> Result<bool> {
+        let key = rule.to_string();
+
+        if self.tree.contains_key(&key)? {
+            return Ok(false);
+        }
+
+        self.tree.insert(
+            key.as_bytes(),
+            encode::to_vec(&BlockedPeer {
+                rule: key.clone(),
+                blocked_at: Utc::now().timestamp(),
+            })?,
+        )?;
+
+        Ok(true)
+    }
+
+    /// Removes the rule, returns false when it wasn't blocked
+    pub fn unblock(&self, rule: &PeerRule) -> Result<bool> {
+        Ok(self.tree.remove(rule.to_string())?.is_some())
+    }
+
+    /// Returns all blocked peers ordered by their rule
+    pub fn list(&self) -> Result<Vec<BlockedPeer>> {
+        let mut blocked = vec![];
+
+        for record in self.tree.iter() {
+            let (_, value) = record?;
+
+            blocked.push(decode::from_read(value.as_ref())?);
+        }
+
+        Ok(blocked)
+    }
+
+    fn rules(&self) -> Result<Vec<PeerRule>> {
+        self.list()?
+            .into_iter()
+            .map(|blocked| blocked.rule.parse())
+            .collect()
+    }
+}
+
+/// Enforces the access policy and blocklist on incoming and outgoing connections
+#[derive(Clone)]
+pub struct PeerAccess {
+    policy: AccessPolicy,
+    blocklist: Blocklist,
+}
+
+impl PeerAccess {
+    pub fn new(policy: AccessPolicy, blocklist: Blocklist) -> Self {
+        Self { policy, blocklist }
+    }
+
+    /// Returns an error describing why the peer isn't allowed, the blocklist is read on every check so that changes
+    /// apply to new connections right away
+    pub fn check(&self, peer: &PeerSubject) -> Result<()> {
+        if let Some(rule) = self.policy.deny.iter().find(|rule| rule.matches(peer)) {
+            return Err(anyhow!("peer matches deny rule: {}", rule));
+        }
+
+        if let Some(rule) = self
+            .blocklist
+            .rules()?
+            .into_iter()
+            .find(|rule| rule.matches(peer))
+        {
+            return Err(anyhow!("peer is blocked: {}", rule));
+        }
+
+        let mut allow = self
+            .policy
+            .allow
+            .iter()
+            .filter(|rule| rule.applies_to(peer))
+            .peekable();
+
+        if allow.peek().is_some() && !allow.any(|rule| rule.matches(peer)) {
+            return Err(anyhow!("peer doesn't match any allow rule"));
+        }
+
+        Ok(())
+    }
+}
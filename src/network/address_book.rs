@@ -0,0 +1,56 @@
+use std::sync::Arc;
+
+use anyhow::Result;
+use chrono::Utc;
+use rmp_serde::{decode, encode};
+use serde::{Deserialize, Serialize};
+use sled::Db;
+use uuid::Uuid;
+
+use crate::backend::{self, Tree};
+
+/// A peer which was connected to before
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PeerRecord {
+    pub addr: String,
+    pub peer_id: String,
+    pub last_seen: i64,
+}
+
+/// Persists the addresses of peers so that they can be reconnected to after a restart
+#[derive(Clone)]
+pub struct AddressBook {
+    tree: Arc<dyn Tree>,
+}
+
+impl AddressBook {
+    pub fn open(db: Db) -> Result<Self> {
+        Ok(Self {
+            tree: backend::open(&db)?.open_tree("nuts/peers")?,
+        })
+    }
+
+    /// Stores the address and peer ID of a peer and marks it as seen
+    pub fn record(&self, addr: &str, peer_id: Uuid) -> Result<()> {
+        self.tree.insert(
+            addr.as_bytes(),
+            &encode::to_vec(&PeerRecord {
+                addr: addr.to_string(),
+                peer_id: peer_id.to_string(),
+                last_seen: Utc::now().timestamp(),
+            })?,
+        )
+    }
+
+    pub fn list(&self) -> Result<Vec<PeerRecord>> {
+        let mut peers = vec![];
+
+        for record in self.tree.iter() {
+            let (_, value) = record?;
+
+            peers.push(decode::from_read(value.as_ref())?);
+        }
+
+        Ok(peers)
+    }
+}
@@ -0,0 +1,409 @@
+use std::collections::{BTreeMap, HashSet};
+use std::thread;
+use std::time::Duration;
+
+use anyhow::Result;
+use tokio::task;
+
+use crate::metrics::Metrics;
+use crate::network::{transaction, Graph, Hash, Transaction};
+use crate::pki::{Key, KeyStorage, KeyStoreError, TrustPolicy};
+
+/// Copy of the keys and revocations which are needed to verify a batch, as the key store can't be moved to the
+/// blocking pool
+#[derive(Default)]
+struct KeySnapshot {
+    keys: BTreeMap<String, Key>,
+    revocations: BTreeMap<String, i64>,
+}
+
+impl KeySnapshot {
+    /// Looks up the keys of the transactions which don't embed their key, transactions which can't be parsed are
+    /// skipped as verifying them fails anyway
+    fn take(key_store: &impl KeyStorage, batch: &[(usize, String)]) -> Result<Self> {
+        let mut snapshot = Self::default();
+
+        for (_, repr) in batch {
+            let tx = match Transaction::parse_unsafe(repr) {
+                Ok(tx) => tx,
+                _ => continue,
+            };
+
+            if let Some(revoked_at) = key_store.revoked_at(&tx.key_id)? {
+                snapshot.revocations.insert(tx.key_id.clone(), revoked_at);
+            }
+
+            if tx.key.is_some() || snapshot.keys.contains_key(&tx.key_id) {
+                continue;
+            }
+
+            if let Some(key) = key_store.get(&tx.key_id)? {
+                snapshot.keys.insert(tx.key_id, key);
+            }
+        }
+
+        Ok(snapshot)
+    }
+}
+
+impl KeyStorage for KeySnapshot {
+    fn get(&self, id: &str) -> Result<Option<Key>, KeyStoreError> {
+        Ok(self.keys.get(id).cloned())
+    }
+
+    fn contains(&self, id: &str) -> Result<bool, KeyStoreError> {
+        Ok(self.keys.contains_key(id))
+    }
+
+    fn add(&mut self, id: String, key: Key) -> Result<(), KeyStoreError> {
+        self.keys.insert(id, key);
+
+        Ok(())
+    }
+
+    fn list(&self) -> Result<Vec<Key>, KeyStoreError> {
+        Ok(self.keys.values().cloned().collect())
+    }
+
+    fn revoked_at(&self, id: &str) -> Result<Option<i64>, KeyStoreError> {
+        Ok(self.revocations.get(id).copied())
+    }
+}
+
+/// Admits encoded transactions to the graph by verifying their signatures on multiple workers and
+/// scheduling them in an order in which every previous transaction is applied before it's children
+#[derive(Clone)]
+pub struct Admission {
+    workers: usize,
+    strict: bool,
+    max_clock_skew: Duration,
+    trust: TrustPolicy,
+    allow_unverified: bool,
+    metrics: Metrics,
+}
+
+impl Admission {
+    pub fn new(workers: usize, strict: bool, max_clock_skew: Duration, trust: TrustPolicy) -> Self {
+        Self {
+            workers: workers.max(1),
+            strict,
+            max_clock_skew,
+            trust,
+            allow_unverified: false,
+            metrics: Metrics::default(),
+        }
+    }
+
+    /// Counts the rejected and unverified transactions in the counters of the node
+    pub fn metrics(mut self, metrics: Metrics) -> Self {
+        self.metrics = metrics;
+        self
+    }
+
+    /// Admits transactions which fail verification instead of rejecting them, as long as they can be decoded
+    pub fn allow_unverified(mut self, allow: bool) -> Self {
+        self.allow_unverified = allow;
+        self
+    }
+
+    fn parse(&self, key_store: &impl KeyStorage, repr: &str) -> transaction::Result<Transaction> {
+        if self.strict {
+            transaction::validate_header(repr)?;
+        }
+
+        let tx = Transaction::parse(key_store, repr)?;
+
+        // Prevents transactions from introducing a key under the ID of a key which belongs to someone else
+        if self.strict {
+            tx.check_key_id()?;
+        }
+
+        tx.check_sign_time(self.max_clock_skew)?;
+
+        self.trust.check(&tx.key_id, tx.sign_at.timestamp())?;
+
+        Ok(tx)
+    }
+
+    /// Verifies a batch of transactions on the configured number of workers, the results are returned in the same
+    /// order as the input regardless of the number of workers
+    fn verify_batch(
+        &self,
+        key_store: &impl KeyStorage,
+        batch: &[(usize, String)],
+    ) -> Vec<transaction::Result<Transaction>> {
+        if self.workers == 1 || batch.len() < 2 {
+            return batch
+                .iter()
+                .map(|(_, repr)| self.parse(key_store, repr))
+                .collect();
+        }
+
+        let chunk_size = batch.len().div_ceil(self.workers);
+        let span = tracing::Span::current();
+
+        thread::scope(|scope| {
+            let handles = batch
+                .chunks(chunk_size)
+                .map(|chunk| {
+                    let span = span.clone();
+
+                    scope.spawn(move || {
+                        let _span = span.enter();
+
+                        chunk
+                            .iter()
+                            .map(|(_, repr)| self.parse(key_store, repr))
+                            .collect::<Vec<_>>()
+                    })
+                })
+                .collect::<Vec<_>>();
+
+            handles
+                .into_iter()
+                .flat_map(|handle| handle.join().expect("admission worker panicked"))
+                .collect()
+        })
+    }
+
+    /// Verifies all encoded transactions, as transactions can refer to keys which are introduced by other
+    /// transactions in the same list, verification is done in rounds until no more progress can be made. The
+    /// signatures are verified on the blocking pool so that a large list doesn't stall the runtime, the graph
+    /// isn't touched until all rounds completed. Transactions which still fail verification after the last round
+    /// are rejected (or admitted unverified when allowed)
+    pub async fn verify(
+        &self,
+        key_store: &mut impl KeyStorage,
+        encoded: Vec<Vec<u8>>,
+    ) -> Result<Vec<(usize, Transaction)>> {
+        let mut verified = vec![];
+        let mut pending = vec![];
+        let mut errors = vec![];
+
+        for (i, data) in encoded.into_iter().enumerate() {
+            pending.push((i, String::from_utf8(data)?));
+        }
+
+        let mut keys = KeySnapshot::take(key_store, &pending)?;
+
+        while !pending.is_empty() {
+            let before = pending.len();
+            let admission = self.clone();
+            // Signatures are verified in the span of the message the transactions were received in
+            let span = tracing::Span::current();
+            let (batch, snapshot, results) = task::spawn_blocking(move || {
+                let _span = span.enter();
+                let results = admission.verify_batch(&keys, &pending);
+
+                (pending, keys, results)
+            })
+            .await?;
+            let mut staged = vec![];
+
+            keys = snapshot;
+            errors.clear();
+
+            for ((i, repr), result) in batch.into_iter().zip(results) {
+                match result {
+                    Ok(tx) => {
+                        // Add the key to the store if it doesn't exists
+                        if !key_store.contains(&tx.key_id)? {
+                            if let Some(key) = tx.key.clone() {
+                                key_store.add(tx.key_id.clone(), key)?;
+                            }
+                        }
+
+                        // Transactions in the next round can refer to the key without embedding it
+                        if let Some(key) = tx.key.clone() {
+                            keys.add(tx.key_id.clone(), key)?;
+                        }
+
+                        verified.push((i, tx));
+                    }
+                    Err(e) => {
+                        tracing::debug!(target: "nuts::network", "failed to process transaction '{}' in admission round: {}", repr, e);
+                        staged.push((i, repr));
+                        errors.push(e.to_string());
+                    }
+                }
+            }
+
+            pending = staged;
+
+            // We we're unable to process transactions anymore
+            if before == pending.len() {
+                tracing::error!(target: "nuts::network", "failed to parse all encoded transactions, there are '{}' unprocessed transactions", pending.len());
+                break;
+            }
+        }
+
+        for ((i, repr), error) in pending.into_iter().zip(errors) {
+            if self.allow_unverified {
+                if let Ok(tx) = Transaction::parse_unsafe(&repr) {
+                    tracing::warn!(target: "nuts::network", "admitting unverified transaction '{}': {}", tx.id, error);
+                    self.metrics.increment("transactions.unverified");
+                    verified.push((i, tx));
+                    continue;
+                }
+            }
+
+            tracing::warn!(target: "nuts::network", "rejected transaction: {}", error);
+            self.metrics.increment("transactions.rejected");
+        }
+
+        Ok(verified)
+    }
+
+    /// Orders verified transactions so that they can be applied to the graph one by one, a transaction is
+    /// scheduled as soon as all of it's previous transactions are either in the graph or scheduled before it
+    /// (transactions which are missing previous transactions are scheduled last)
+    pub fn schedule(graph: &Graph, mut verified: Vec<(usize, Transaction)>) -> Vec<Transaction> {
+        let mut scheduled = vec![];
+        let mut known: HashSet<Hash> = HashSet::new();
+
+        verified.sort_unstable_by_key(|(i, _)| *i);
+        verified.retain(|(_, tx)| graph.find(&tx.id).is_none() && known.insert(tx.id.clone()));
+        known.clear();
+
+        while !verified.is_empty() {
+            let before = verified.len();
+            let mut staged = vec![];
+
+            for (i, tx) in verified {
+                if tx
+                    .prevs
+                    .iter()
+                    .all(|id| known.contains(id) || graph.find(id).is_some())
+                {
+                    known.insert(tx.id.clone());
+                    scheduled.push(tx);
+                } else {
+                    staged.push((i, tx));
+                }
+            }
+
+            verified = staged;
+
+            // The remaining transactions are missing previous transactions and end up in the orphan pool
+            if before == verified.len() {
+                tracing::debug!(target: "nuts::network", "scheduling '{}' transactions with missing previous transactions", verified.len());
+                scheduled.extend(verified.into_iter().map(|(_, tx)| tx));
+                break;
+            }
+        }
+
+        scheduled
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use p256::ecdsa::SigningKey;
+    use rand::rngs::OsRng;
+
+    use super::*;
+    use crate::network::TransactionBuilder;
+    use crate::pki::MemoryKeyStore;
+
+    /// Signs a tree of transactions (every transaction refers to the transaction at half it's index), every key is
+    /// embedded in the first transaction signed using it and referenced by it's key ID afterwards
+    fn transactions(count: usize) -> Vec<Transaction> {
+        let keys = (0..3)
+            .map(|_| SigningKey::random(&mut OsRng))
+            .collect::<Vec<_>>();
+        let mut transactions: Vec<Transaction> = vec![];
+
+        for i in 0..count {
+            let prevs = match i {
+                0 => vec![],
+                _ => vec![transactions[(i - 1) / 2].id.clone()],
+            };
+            let tx = TransactionBuilder::new("application/did+json", format!("payload-{}", i))
+                .unwrap()
+                .prevs(prevs)
+                .embed_key(i < keys.len())
+                .sign(&format!("key-{}", i % keys.len()), &keys[i % keys.len()])
+                .unwrap();
+
+            transactions.push(tx);
+        }
+
+        transactions
+    }
+
+    /// Transactions in reverse order, so that keys are referenced before the transactions which embed them
+    fn encoded(transactions: &[Transaction]) -> Vec<Vec<u8>> {
+        transactions
+            .iter()
+            .rev()
+            .map(|tx| tx.data.clone())
+            .collect()
+    }
+
+    async fn admit(workers: usize, encoded: Vec<Vec<u8>>) -> Vec<Hash> {
+        let db = sled::Config::new().temporary(true).open().unwrap();
+        let graph = Graph::open(db.clone()).unwrap();
+        let admission = Admission::new(
+            workers,
+            false,
+            transaction::DEFAULT_MAX_CLOCK_SKEW,
+            TrustPolicy::open(db).unwrap(),
+        );
+        let verified = admission
+            .verify(&mut MemoryKeyStore::default(), encoded)
+            .await
+            .unwrap();
+
+        Admission::schedule(&graph, verified)
+            .into_iter()
+            .map(|tx| tx.id)
+            .collect()
+    }
+
+    #[tokio::test]
+    async fn deterministic_regardless_of_workers() {
+        let transactions = transactions(40);
+        let sequential = admit(1, encoded(&transactions)).await;
+
+        assert_eq!(sequential.len(), transactions.len());
+
+        for &workers in &[2, 3, 8] {
+            assert_eq!(admit(workers, encoded(&transactions)).await, sequential);
+        }
+    }
+
+    #[tokio::test]
+    async fn previous_transactions_are_scheduled_first() {
+        let transactions = transactions(40);
+        let prevs = transactions
+            .iter()
+            .map(|tx| (&tx.id, &tx.prevs))
+            .collect::<HashMap<_, _>>();
+        let scheduled = admit(4, encoded(&transactions)).await;
+
+        assert_eq!(scheduled.len(), transactions.len());
+
+        for (i, id) in scheduled.iter().enumerate() {
+            for prev in prevs[id].iter() {
+                assert!(
+                    scheduled[..i].contains(prev),
+                    "{} is scheduled before {}",
+                    id,
+                    prev
+                );
+            }
+        }
+    }
+
+    #[tokio::test]
+    async fn duplicates_are_scheduled_once() {
+        let transactions = transactions(10);
+        let mut encoded = encoded(&transactions);
+
+        encoded.extend(encoded.clone());
+
+        assert_eq!(admit(2, encoded).await.len(), transactions.len());
+    }
+}
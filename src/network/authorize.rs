@@ -0,0 +1,50 @@
+use std::sync::Arc;
+
+use anyhow::Result;
+use uuid::Uuid;
+
+use crate::metrics::Metrics;
+
+/// Details of a peer as known during the handshake, before it's admitted to the message loop
+#[derive(Debug, Clone)]
+pub struct PeerHandshake {
+    pub peer_id: Uuid,
+    /// DID of the node operator as claimed by the peer
+    pub did: Option<String>,
+    /// Network ID as claimed by the peer, which already matched the network ID of this node
+    pub network_id: Option<String>,
+    /// DER encoded certificate the peer presented, the client certificate of incoming connections and the server
+    /// certificate of outgoing connections
+    pub certificate: Option<Vec<u8>>,
+    /// Whether the peer connected to this node or this node connected to the peer
+    pub inbound: bool,
+}
+
+/// Decides whether a peer is admitted after the handshake succeeded, which makes it possible to enforce
+/// organization-specific rules (e.g. checking an external allowlist service)
+#[tonic::async_trait]
+pub trait AuthorizePeer: Send + Sync {
+    /// Returns an error describing why the peer is rejected
+    async fn authorize(&self, peer: &PeerHandshake) -> Result<()>;
+}
+
+/// Asks the authorizer (if any) whether the peer is admitted
+pub(crate) async fn authorize(
+    authorizer: &Option<Arc<dyn AuthorizePeer>>,
+    peer: PeerHandshake,
+    metrics: &Metrics,
+) -> Result<()> {
+    let authorizer = match authorizer {
+        Some(authorizer) => authorizer,
+        None => return Ok(()),
+    };
+
+    if let Err(e) = authorizer.authorize(&peer).await {
+        tracing::info!(target: "nuts::network", "peer '{}' was rejected by the authorizer: {}", peer.peer_id, e);
+        metrics.increment("peers.rejected");
+
+        return Err(e);
+    }
+
+    Ok(())
+}
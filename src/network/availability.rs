@@ -0,0 +1,78 @@
+use anyhow::{anyhow, Result};
+
+use crate::network::Hash;
+
+/// Number of bits reserved per payload, which results in a false positive rate of roughly 1%
+const BITS_PER_PAYLOAD: usize = 10;
+/// Number of bit positions set per payload, each is taken from 4 bytes of the (already uniformly distributed) hash
+const HASHES: u32 = 7;
+/// Maximum size of a filter received from a peer
+const MAX_SIZE: usize = 4 * 1024 * 1024;
+
+/// Bloom filter of the payload hashes a node holds, which peers advertise so that payloads are only queried from
+/// peers which are likely to have them
+#[derive(Debug, Clone, PartialEq)]
+pub struct PayloadFilter {
+    bits: Vec<u8>,
+    hashes: u32,
+}
+
+impl PayloadFilter {
+    pub fn new(hashes: &[Hash]) -> Self {
+        let size = (hashes.len() * BITS_PER_PAYLOAD).div_ceil(8).max(8);
+        let mut filter = Self {
+            bits: vec![0; size],
+            hashes: HASHES,
+        };
+
+        for hash in hashes {
+            for index in filter.indices(hash).collect::<Vec<_>>() {
+                filter.bits[index / 8] |= 1 << (index % 8);
+            }
+        }
+
+        filter
+    }
+
+    pub fn parse(bits: Vec<u8>, hashes: u32) -> Result<Self> {
+        if bits.is_empty() || bits.len() > MAX_SIZE {
+            return Err(anyhow!("invalid payload filter size: {}", bits.len()));
+        }
+
+        if hashes == 0 || hashes > 8 {
+            return Err(anyhow!(
+                "invalid number of payload filter hashes: {}",
+                hashes
+            ));
+        }
+
+        Ok(Self { bits, hashes })
+    }
+
+    fn indices<'a>(&'a self, hash: &'a Hash) -> impl Iterator<Item = usize> + 'a {
+        let size = self.bits.len() as u64 * 8;
+
+        hash.as_ref()
+            .chunks_exact(4)
+            .take(self.hashes as usize)
+            .map(move |chunk| {
+                let value = u32::from_be_bytes([chunk[0], chunk[1], chunk[2], chunk[3]]);
+
+                (value as u64 % size) as usize
+            })
+    }
+
+    /// Whether the payload is likely held by the node, false positives are possible but false negatives aren't
+    pub fn contains(&self, hash: &Hash) -> bool {
+        self.indices(hash)
+            .all(|index| self.bits[index / 8] & (1 << (index % 8)) != 0)
+    }
+
+    pub fn bits(&self) -> &[u8] {
+        &self.bits
+    }
+
+    pub fn hashes(&self) -> u32 {
+        self.hashes
+    }
+}
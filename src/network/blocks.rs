@@ -0,0 +1,47 @@
+use std::collections::HashSet;
+
+use chrono::NaiveDateTime;
+
+use crate::network::{Hash, Transaction};
+
+/// Duration of a block in seconds, as described in RFC005 the DAG is divided in blocks of a day (in UTC)
+pub const BLOCK_DURATION: u32 = 24 * 60 * 60;
+
+/// Number of blocks (including the current block) which are queried when syncing with a peer, older transactions
+/// are only retrieved by a full sync
+pub const BLOCK_COUNT: u32 = 3;
+
+/// Returns the start date of the block the moment belongs to
+pub fn block_date(at: NaiveDateTime) -> u32 {
+    let timestamp = at.timestamp().clamp(0, u32::MAX as i64) as u32;
+
+    timestamp - timestamp % BLOCK_DURATION
+}
+
+/// Returns the start dates of the blocks which are queried when syncing, starting with the oldest block
+pub fn recent_blocks(now: NaiveDateTime) -> Vec<u32> {
+    let current = block_date(now);
+
+    (0..BLOCK_COUNT)
+        .rev()
+        .filter_map(|offset| current.checked_sub(offset * BLOCK_DURATION))
+        .filter(|block_date| *block_date > 0)
+        .collect()
+}
+
+/// Returns the transactions which aren't referenced by any of the other transactions, which are the heads of a
+/// block when the transactions are the transactions of the block
+pub fn heads<'a>(transactions: impl IntoIterator<Item = &'a Transaction> + Clone) -> Vec<Hash> {
+    let prevs = transactions
+        .clone()
+        .into_iter()
+        .flat_map(|tx| tx.prevs.iter())
+        .collect::<HashSet<_>>();
+
+    transactions
+        .into_iter()
+        .map(|tx| &tx.id)
+        .filter(|id| !prevs.contains(id))
+        .cloned()
+        .collect()
+}
@@ -0,0 +1,158 @@
+use anyhow::{anyhow, Result};
+use sha2::{Digest, Sha256};
+use x509_parser::certificate::X509Certificate;
+use x509_parser::extensions::GeneralName;
+use x509_parser::prelude::FromDer;
+
+/// Short names of the attributes of a distinguished name, other attributes are written as their OID
+const ATTRIBUTE_NAMES: &[(&str, &str)] = &[
+    ("2.5.4.3", "CN"),
+    ("2.5.4.6", "C"),
+    ("2.5.4.7", "L"),
+    ("2.5.4.8", "ST"),
+    ("2.5.4.10", "O"),
+    ("2.5.4.11", "OU"),
+];
+
+/// Subject of the X.509 certificate presented by a peer
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PeerCertificate {
+    /// Distinguished name of the subject (e.g. CN=node-1,O=Care X)
+    pub subject: String,
+    /// DNS names from the subject alternative name extension
+    pub dns_names: Vec<String>,
+    /// Lowercase hex encoded SHA-256 of the DER encoded certificate
+    pub fingerprint: String,
+}
+
+impl PeerCertificate {
+    /// Parses the subject and the DNS names of a DER encoded certificate, the certificate itself was already
+    /// verified against the trust store during the TLS handshake
+    pub fn parse(der: &[u8]) -> Result<Self> {
+        let (rest, certificate) =
+            X509Certificate::from_der(der).map_err(|e| anyhow!("invalid certificate: {}", e))?;
+
+        if !rest.is_empty() {
+            return Err(anyhow!("invalid certificate: trailing data"));
+        }
+
+        let mut attributes = vec![];
+
+        // The attributes are kept in the order of the certificate as the subject is stored in the peer bindings
+        for attribute in certificate.subject().iter_attributes() {
+            let oid = attribute.attr_type().to_id_string();
+            let name = ATTRIBUTE_NAMES
+                .iter()
+                .find(|(id, _)| *id == oid)
+                .map_or(oid.as_str(), |(_, name)| name);
+
+            attributes.push(format!(
+                "{}={}",
+                name,
+                String::from_utf8_lossy(attribute.attr_value().data)
+            ));
+        }
+
+        let dns_names = match certificate
+            .subject_alternative_name()
+            .map_err(|e| anyhow!("invalid subject alternative name in certificate: {}", e))?
+        {
+            Some(extension) => extension
+                .value
+                .general_names
+                .iter()
+                .filter_map(|name| match name {
+                    GeneralName::DNSName(name) => Some(name.to_string()),
+                    _ => None,
+                })
+                .collect(),
+            None => vec![],
+        };
+
+        Ok(Self {
+            subject: attributes.join(","),
+            dns_names,
+            fingerprint: hex::encode(Sha256::digest(der)),
+        })
+    }
+
+    /// Returns the name the peer ID is bound to, which is the subject or the DNS names when the subject is empty
+    pub fn name(&self) -> String {
+        if self.subject.is_empty() {
+            format!("DNS={}", self.dns_names.join(","))
+        } else {
+            self.subject.clone()
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use rustls::internal::pemfile;
+
+    use super::*;
+
+    /// Self-signed certificates generated using OpenSSL
+    const NODE_1: &str = "-----BEGIN CERTIFICATE-----\n\
+MIIB5TCCAYugAwIBAgIUKBBc5JXL4dOxKatwdURvCCCNQjIwCgYIKoZIzj0EAwIw\n\
+LzELMAkGA1UEBhMCTkwxDzANBgNVBAoMBkNhcmUgWDEPMA0GA1UEAwwGbm9kZS0x\n\
+MB4XDTI2MTAxNjA4MTgxMVoXDTM2MTAxMzA4MTgxMVowLzELMAkGA1UEBhMCTkwx\n\
+DzANBgNVBAoMBkNhcmUgWDEPMA0GA1UEAwwGbm9kZS0xMFkwEwYHKoZIzj0CAQYI\n\
+KoZIzj0DAQcDQgAEAMuSoWtuaR/fdPuWE0M8da6bexyQOf3FWv68yA4y7/WT9/JO\n\
+0VNEPFGQvWfmPP3b1vqS4Cin1kd0/tFzcVaJpaOBhDCBgTAdBgNVHQ4EFgQUOmYD\n\
+Uya/Wujzxtk36eoEg1ookn4wHwYDVR0jBBgwFoAUOmYDUya/Wujzxtk36eoEg1oo\n\
+kn4wDwYDVR0TAQH/BAUwAwEB/zAuBgNVHREEJzAlghBub2RlLTEuY2FyZS14Lm5s\n\
+ggsqLmNhcmUteC5ubIcECgAAATAKBggqhkjOPQQDAgNIADBFAiEAoOAMgb8mjetC\n\
+yNqQqbzbEVS+J9HNEIECY5zvA2iFuUICIBnj2LzsYxwvtg8pPKdG2TS/P1bw3QQU\n\
+fLM3puLKLHeD\n\
+-----END CERTIFICATE-----";
+    const NODE_2: &str = "-----BEGIN CERTIFICATE-----\n\
+MIIBdTCCARugAwIBAgIUSynGYBMZ2RzVCGgPFAc9jMi6FRcwCgYIKoZIzj0EAwIw\n\
+ADAeFw0yNjEwMTYwODE4MTFaFw0zNjEwMTMwODE4MTFaMAAwWTATBgcqhkjOPQIB\n\
+BggqhkjOPQMBBwNCAARJlezEFI0JvMvxwVYu1zG6+Tu6qjfrXH998s0R2U9SOBMi\n\
+0aUDCMce9CWDeSIRs33Bbs3T32pDaHJxe6AuhUiJo3MwcTAdBgNVHQ4EFgQUbc2T\n\
+t8YCcIawghGG2tzk4Fv/aXIwHwYDVR0jBBgwFoAUbc2Tt8YCcIawghGG2tzk4Fv/\n\
+aXIwDwYDVR0TAQH/BAUwAwEB/zAeBgNVHREBAf8EFDASghBub2RlLTIuY2FyZS15\n\
+Lm5sMAoGCCqGSM49BAMCA0gAMEUCIG8Y1T8q8lYtfzGjAq5leWrPAAR6wweSwp1i\n\
+qo5hj864AiEAi6+XW/bXLUTqs+lb+jNRrmRQdTJMf15P368NEO19VTA=\n\
+-----END CERTIFICATE-----";
+
+    fn der(pem: &str) -> Vec<u8> {
+        pemfile::certs(&mut pem.as_bytes()).unwrap().remove(0).0
+    }
+
+    #[test]
+    fn subject_and_dns_names() {
+        let certificate = PeerCertificate::parse(&der(NODE_1)).unwrap();
+
+        assert_eq!(certificate.subject, "C=NL,O=Care X,CN=node-1");
+        assert_eq!(
+            certificate.dns_names,
+            vec!["node-1.care-x.nl", "*.care-x.nl"]
+        );
+        assert_eq!(
+            certificate.fingerprint,
+            "5a3de9c48b80d846efba67143eefc22a3310e5255c6f270ae27a0c7b888d99fa"
+        );
+        assert_eq!(certificate.name(), "C=NL,O=Care X,CN=node-1");
+    }
+
+    #[test]
+    fn empty_subject() {
+        let certificate = PeerCertificate::parse(&der(NODE_2)).unwrap();
+
+        assert_eq!(certificate.subject, "");
+        assert_eq!(certificate.dns_names, vec!["node-2.care-y.nl"]);
+        assert_eq!(certificate.name(), "DNS=node-2.care-y.nl");
+    }
+
+    #[test]
+    fn invalid_certificate() {
+        let der = der(NODE_1);
+
+        assert!(PeerCertificate::parse(&der[..der.len() - 1]).is_err());
+        assert!(PeerCertificate::parse(&[der.as_slice(), &[0]].concat()).is_err());
+        assert!(PeerCertificate::parse(b"not a certificate").is_err());
+        assert!(PeerCertificate::parse(&[]).is_err());
+    }
+}
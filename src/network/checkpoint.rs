@@ -0,0 +1,108 @@
+use anyhow::Result;
+use chrono::Utc;
+use rmp_serde::{decode, encode};
+use serde::{Deserialize, Serialize};
+use sled::Db;
+
+use crate::network::Hash;
+
+/// Sync interval of a peer at the moment the checkpoint was written
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SyncCursor {
+    pub peer_id: String,
+    pub interval: u64,
+}
+
+/// Summary of the state of the node which is written on a graceful shutdown and compared against the recomputed
+/// state on the next startup to detect corruption or partial writes
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Checkpoint {
+    pub written_at: i64,
+    pub transactions: usize,
+    pub orphans: usize,
+    pub payloads: usize,
+    pub heads: Vec<Hash>,
+    pub checksum: Hash,
+    pub sync_cursors: Vec<SyncCursor>,
+    pub known_peers: usize,
+}
+
+impl Checkpoint {
+    pub fn new(
+        transactions: &[Hash],
+        orphans: usize,
+        payloads: usize,
+        mut heads: Vec<Hash>,
+        sync_cursors: Vec<SyncCursor>,
+        known_peers: usize,
+    ) -> Self {
+        heads.sort_by(|a, b| a.as_ref().cmp(b.as_ref()));
+
+        Self {
+            written_at: Utc::now().timestamp(),
+            transactions: transactions.len(),
+            orphans,
+            payloads,
+            heads,
+            checksum: Hash::xor(transactions),
+            sync_cursors,
+            known_peers,
+        }
+    }
+
+    /// Returns the checkpoint written by the previous graceful shutdown and removes it, so that a crash in between
+    /// doesn't leave a stale checkpoint behind
+    pub fn take(db: &Db) -> Result<Option<Self>> {
+        match db.open_tree("nuts/checkpoint")?.remove("latest")? {
+            Some(value) => Ok(Some(decode::from_read(value.as_ref())?)),
+            None => Ok(None),
+        }
+    }
+
+    pub fn save(&self, db: &Db) -> Result<()> {
+        db.open_tree("nuts/checkpoint")?
+            .insert("latest", encode::to_vec(self)?)?;
+
+        Ok(())
+    }
+
+    /// Returns a description of every difference between the checkpoint and the current state, the sync cursors
+    /// aren't compared as they're reset on startup
+    pub fn compare(&self, current: &Checkpoint) -> Vec<String> {
+        let mut differences = vec![];
+
+        if self.transactions != current.transactions {
+            differences.push(format!(
+                "expected {} transactions but found {}",
+                self.transactions, current.transactions
+            ));
+        }
+
+        if self.orphans != current.orphans {
+            differences.push(format!(
+                "expected {} orphans but found {}",
+                self.orphans, current.orphans
+            ));
+        }
+
+        if self.payloads != current.payloads {
+            differences.push(format!(
+                "expected {} payloads but found {}",
+                self.payloads, current.payloads
+            ));
+        }
+
+        if self.heads != current.heads {
+            differences.push("heads of the DAG don't match".to_string());
+        }
+
+        if self.checksum != current.checksum {
+            differences.push(format!(
+                "expected checksum '{}' but got '{}'",
+                self.checksum, current.checksum
+            ));
+        }
+
+        differences
+    }
+}
@@ -0,0 +1,89 @@
+use std::collections::{HashMap, HashSet};
+use std::time::Duration;
+
+use tokio::time::Instant;
+use uuid::Uuid;
+
+use crate::metrics::Metrics;
+use crate::network::Hash;
+
+/// Transactions which are about to be queried from a peer
+struct PendingQuery {
+    refs: Vec<Hash>,
+    seen: HashSet<Hash>,
+    due_at: Instant,
+}
+
+/// Collects the transactions which need to be queried from each peer for a short while, so that several requests
+/// (e.g. gossip and the missing previous transactions of orphans) are sent as a single query
+pub struct QueryCoalescer {
+    delay: Duration,
+    pending: HashMap<Uuid, PendingQuery>,
+    metrics: Metrics,
+}
+
+impl QueryCoalescer {
+    pub fn new(delay: Duration, metrics: Metrics) -> Self {
+        Self {
+            delay,
+            pending: HashMap::new(),
+            metrics,
+        }
+    }
+
+    /// Adds transactions to the next query for the peer, the query is due after the delay since the first request
+    pub fn add(&mut self, peer_id: Uuid, refs: Vec<Hash>) {
+        if refs.is_empty() {
+            return;
+        }
+
+        let delay = self.delay;
+        let query = self.pending.entry(peer_id).or_insert_with(|| PendingQuery {
+            refs: vec![],
+            seen: HashSet::new(),
+            due_at: Instant::now() + delay,
+        });
+
+        if !query.refs.is_empty() {
+            self.metrics.increment("queries.coalesced");
+        }
+
+        for id in refs {
+            if query.seen.insert(id.clone()) {
+                query.refs.push(id);
+            }
+        }
+
+        self.metrics.increment("queries.requested");
+    }
+
+    pub fn remove(&mut self, peer_id: &Uuid) {
+        self.pending.remove(peer_id);
+    }
+
+    /// Returns when the next query is due
+    pub fn next_deadline(&self) -> Option<Instant> {
+        self.pending.values().map(|query| query.due_at).min()
+    }
+
+    /// Returns the queries which are due and removes them
+    pub fn due(&mut self) -> Vec<(Uuid, Vec<Hash>)> {
+        let now = Instant::now();
+        let due = self
+            .pending
+            .iter()
+            .filter(|(_, query)| query.due_at <= now)
+            .map(|(peer_id, _)| *peer_id)
+            .collect::<Vec<_>>();
+
+        due.into_iter()
+            .filter_map(|peer_id| {
+                let query = self.pending.remove(&peer_id)?;
+
+                self.metrics.increment("queries.sent");
+
+                Some((peer_id, query.refs))
+            })
+            .collect()
+    }
+}
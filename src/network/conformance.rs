@@ -0,0 +1,457 @@
+use std::time::Duration;
+
+use anyhow::{anyhow, Result};
+use serde::Serialize;
+use tokio::sync::mpsc::{channel, Sender};
+use tokio::time;
+use tonic::metadata::MetadataMap;
+use tonic::transport::{Certificate, Channel, ClientTlsConfig, Identity};
+use tonic::{Code, Request, Status, Streaming};
+use uuid::Uuid;
+
+use crate::network::handshake::NodeInfo;
+use crate::network::{Hash, Transaction};
+use crate::proto::v2::{protocol_client::ProtocolClient, Envelope};
+use crate::proto::{self, network_client::NetworkClient, network_message, NetworkMessage};
+
+/// Outcome of a single check of the conformance suite
+#[derive(Debug, Clone, Serialize)]
+pub struct CheckResult {
+    pub name: &'static str,
+    pub passed: bool,
+    /// What was observed, which explains why the check failed
+    pub detail: String,
+}
+
+impl CheckResult {
+    fn pass(name: &'static str, detail: impl Into<String>) -> Self {
+        Self {
+            name,
+            passed: true,
+            detail: detail.into(),
+        }
+    }
+
+    fn fail(name: &'static str, detail: impl Into<String>) -> Self {
+        Self {
+            name,
+            passed: false,
+            detail: detail.into(),
+        }
+    }
+
+    fn from_result(name: &'static str, result: Result<String>) -> Self {
+        match result {
+            Ok(detail) => Self::pass(name, detail),
+            Err(e) => Self::fail(name, e.to_string()),
+        }
+    }
+}
+
+/// Connection with the peer using version 1 of the protocol, which is driven by the checks
+struct Session {
+    outbound: Sender<NetworkMessage>,
+    inbound: Streaming<NetworkMessage>,
+}
+
+impl Session {
+    async fn send(&self, message: network_message::Message) -> Result<()> {
+        self.outbound
+            .send(netmsg!(message))
+            .await
+            .map_err(|_| anyhow!("connection closed"))
+    }
+
+    /// Waits for the first message which is accepted by the filter, other messages (e.g. adverts and diagnostics
+    /// which are sent periodically) are skipped
+    async fn receive<T>(
+        &mut self,
+        timeout: Duration,
+        filter: impl Fn(network_message::Message) -> Option<T>,
+    ) -> Result<T> {
+        let receive = async {
+            loop {
+                match self.inbound.message().await? {
+                    Some(NetworkMessage {
+                        message: Some(message),
+                    }) => {
+                        if let Some(message) = filter(message) {
+                            return Ok(message);
+                        }
+                    }
+                    Some(_) => {}
+                    None => return Err(anyhow!("connection closed by peer")),
+                }
+            }
+        };
+
+        time::timeout(timeout, receive)
+            .await
+            .map_err(|_| anyhow!("no response within {:?}", timeout))?
+    }
+
+    async fn query_transactions(&mut self, timeout: Duration) -> Result<proto::TransactionList> {
+        self.send(network_message::Message::TransactionListQuery(
+            proto::TransactionListQuery {
+                block_date: 0,
+                filter: None,
+            },
+        ))
+        .await?;
+        self.receive(timeout, |message| match message {
+            network_message::Message::TransactionList(list) => Some(list),
+            _ => None,
+        })
+        .await
+    }
+
+    async fn query_payload(
+        &mut self,
+        timeout: Duration,
+        payload_hash: &Hash,
+    ) -> Result<proto::TransactionPayload> {
+        self.send(network_message::Message::TransactionPayloadQuery(
+            proto::TransactionPayloadQuery {
+                payload_hash: payload_hash.as_ref().to_vec(),
+            },
+        ))
+        .await?;
+
+        let expected = payload_hash.as_ref().to_vec();
+
+        self.receive(timeout, move |message| match message {
+            network_message::Message::TransactionPayload(payload)
+                if payload.payload_hash == expected =>
+            {
+                Some(payload)
+            }
+            _ => None,
+        })
+        .await
+    }
+}
+
+/// Verifies that every transaction in the list is a valid JWS which matches it's hash and returns the parsed
+/// transactions
+fn verify_list(list: &proto::TransactionList) -> Result<Vec<Transaction>> {
+    if !list.compressed.is_empty() || list.message_number != 0 {
+        return Err(anyhow!(
+            "transaction list is compressed or paginated while neither was requested"
+        ));
+    }
+
+    list.transactions
+        .iter()
+        .map(|tx| {
+            let hash = Hash::parse(tx.hash.clone())
+                .map_err(|e| anyhow!("invalid transaction hash: {}", e))?;
+            let transaction = Transaction::parse_unsafe(String::from_utf8(tx.data.clone())?)
+                .map_err(|e| anyhow!("invalid transaction '{}': {}", hash, e))?;
+
+            if transaction.id != hash {
+                return Err(anyhow!(
+                    "hash of transaction '{}' doesn't match it's data",
+                    hash
+                ));
+            }
+
+            Ok(transaction)
+        })
+        .collect()
+}
+
+/// Runs a scripted suite of protocol checks against a remote peer, the runner connects like a regular peer but
+/// doesn't advertise any capabilities so that the responses of the peer are predictable
+pub struct Conformance {
+    ca: Certificate,
+    identity: Identity,
+    node: NodeInfo,
+    timeout: Duration,
+}
+
+impl Conformance {
+    pub fn new(
+        ca: Certificate,
+        identity: Identity,
+        peer_id: Uuid,
+        network_id: String,
+        timeout: Duration,
+    ) -> Self {
+        Self {
+            ca,
+            identity,
+            node: NodeInfo {
+                peer_id,
+                did: None,
+                network_id,
+            },
+            timeout,
+        }
+    }
+
+    async fn channel(&self, addr: &str) -> Result<Channel> {
+        let tls = ClientTlsConfig::new()
+            .ca_certificate(self.ca.clone())
+            .identity(self.identity.clone());
+        let channel = Channel::from_shared(addr.as_bytes().to_vec())
+            .map_err(|e| anyhow!("invalid peer address: {}", e))?
+            .tls_config(tls)?
+            .connect()
+            .await?;
+
+        Ok(channel)
+    }
+
+    fn new_request<T>(&self, version: &'static str, body: T) -> Result<Request<T>> {
+        let mut request = Request::new(body);
+
+        self.node.set_metadata(version, request.metadata_mut())?;
+        request.metadata_mut().remove("capabilities");
+
+        Ok(request)
+    }
+
+    /// Connects using version 1 of the protocol with the given version in the metadata
+    async fn connect_v1(
+        &self,
+        transport: Channel,
+        version: &'static str,
+    ) -> Result<(Session, MetadataMap), Status> {
+        let (outbound, mut outbound_rx) = channel(16);
+        let request = self
+            .new_request(
+                version,
+                async_stream::stream! {
+                    while let Some(message) = outbound_rx.recv().await {
+                        yield message;
+                    }
+                },
+            )
+            .map_err(|e| Status::internal(e.to_string()))?;
+        let response = NetworkClient::new(transport)
+            .connect_method(request)
+            .await?;
+        let metadata = response.metadata().clone();
+
+        Ok((
+            Session {
+                outbound,
+                inbound: response.into_inner(),
+            },
+            metadata,
+        ))
+    }
+
+    /// Checks whether the peer negotiates version 2 of the protocol correctly, peers which don't implement it pass
+    /// as long as they report it as unimplemented
+    async fn check_v2(&self, transport: Channel) -> Result<String> {
+        let (_outbound, mut outbound_rx) = channel::<Envelope>(1);
+        let request = self.new_request(
+            "2",
+            async_stream::stream! {
+                while let Some(envelope) = outbound_rx.recv().await {
+                    yield envelope;
+                }
+            },
+        )?;
+
+        match ProtocolClient::new(transport).stream(request).await {
+            Ok(response) => {
+                let info = self.node.parse_metadata(true, response.metadata())?;
+
+                if info.version != "2" {
+                    return Err(anyhow!(
+                        "peer responded with protocol version: {}",
+                        info.version
+                    ));
+                }
+
+                Ok("peer supports protocol version 2".to_string())
+            }
+            Err(status) if status.code() == Code::Unimplemented => {
+                Ok("peer only supports protocol version 1".to_string())
+            }
+            Err(status) => Err(anyhow!(
+                "peer rejected protocol version 2: {}",
+                status.message()
+            )),
+        }
+    }
+
+    async fn check_version_mismatch(&self, transport: Channel) -> Result<String> {
+        match self.connect_v1(transport, "99").await {
+            Ok(_) => Err(anyhow!("peer accepted protocol version 99")),
+            Err(status) => Ok(format!(
+                "peer rejected protocol version 99 ({:?})",
+                status.code()
+            )),
+        }
+    }
+
+    async fn check_payload(
+        &self,
+        session: &mut Session,
+        transaction: &Transaction,
+    ) -> Result<String> {
+        let payload = session
+            .query_payload(self.timeout, &transaction.payload)
+            .await?;
+
+        if payload.data.is_empty() {
+            return Ok(format!(
+                "peer doesn't hold payload '{}'",
+                transaction.payload
+            ));
+        }
+
+        if Hash::new(&payload.data)? != transaction.payload {
+            return Err(anyhow!(
+                "payload '{}' doesn't match it's hash",
+                transaction.payload
+            ));
+        }
+
+        Ok(format!("received payload '{}'", transaction.payload))
+    }
+
+    async fn check_unknown_payload(&self, session: &mut Session) -> Result<String> {
+        let hash = Hash::new(Uuid::new_v4().as_bytes())?;
+        let payload = session.query_payload(self.timeout, &hash).await?;
+
+        if !payload.data.is_empty() {
+            return Err(anyhow!("peer responded with data for an unknown payload"));
+        }
+
+        Ok("peer responded with an empty payload".to_string())
+    }
+
+    /// Sends messages which are invalid and verifies the peer still responds afterwards
+    async fn check_malformed(&self, session: &mut Session) -> Result<String> {
+        session
+            .outbound
+            .send(NetworkMessage { message: None })
+            .await
+            .map_err(|_| anyhow!("connection closed"))?;
+        session
+            .send(network_message::Message::TransactionPayloadQuery(
+                proto::TransactionPayloadQuery {
+                    payload_hash: vec![0; 3],
+                },
+            ))
+            .await?;
+        session
+            .send(network_message::Message::TransactionList(
+                proto::TransactionList {
+                    block_date: 0,
+                    transactions: vec![proto::Transaction {
+                        hash: vec![1; 32],
+                        data: b"not a transaction".to_vec(),
+                    }],
+                    compressed: vec![],
+                    message_number: 0,
+                    total_messages: 0,
+                },
+            ))
+            .await?;
+        session
+            .query_transactions(self.timeout)
+            .await
+            .map_err(|e| anyhow!("peer stopped responding after malformed messages: {}", e))?;
+
+        Ok("peer kept responding after malformed messages".to_string())
+    }
+
+    /// Runs all checks against the peer at the given address, checks which depend on an established connection
+    /// are skipped when the connection fails
+    pub async fn run(&self, addr: &str) -> Vec<CheckResult> {
+        let mut results = vec![];
+        let transport = match self.channel(addr).await {
+            Ok(transport) => {
+                results.push(CheckResult::pass(
+                    "tls",
+                    "mutual TLS connection established",
+                ));
+                transport
+            }
+            Err(e) => {
+                results.push(CheckResult::fail("tls", e.to_string()));
+                return results;
+            }
+        };
+        let (mut session, metadata) = match self.connect_v1(transport.clone(), "1").await {
+            Ok(connected) => connected,
+            Err(status) => {
+                results.push(CheckResult::fail(
+                    "metadata",
+                    format!("peer rejected the connection: {}", status.message()),
+                ));
+                return results;
+            }
+        };
+
+        results.push(CheckResult::from_result(
+            "metadata",
+            self.node.parse_metadata(true, &metadata).and_then(|info| {
+                if info.version != "1" {
+                    return Err(anyhow!(
+                        "peer responded with protocol version: {}",
+                        info.version
+                    ));
+                }
+
+                Ok(format!("peer ID: {}", info.peer_id))
+            }),
+        ));
+        results.push(CheckResult::from_result(
+            "version-negotiation",
+            self.check_v2(transport.clone()).await,
+        ));
+        results.push(CheckResult::from_result(
+            "version-mismatch",
+            self.check_version_mismatch(transport).await,
+        ));
+
+        let transactions = session
+            .query_transactions(self.timeout)
+            .await
+            .and_then(|list| verify_list(&list));
+
+        match &transactions {
+            Ok(transactions) => {
+                results.push(CheckResult::pass(
+                    "transaction-list-query",
+                    format!("received {} valid transaction(s)", transactions.len()),
+                ));
+
+                match transactions.first() {
+                    Some(transaction) => results.push(CheckResult::from_result(
+                        "payload-query",
+                        self.check_payload(&mut session, transaction).await,
+                    )),
+                    None => results.push(CheckResult::pass(
+                        "payload-query",
+                        "skipped because the peer has no transactions",
+                    )),
+                }
+            }
+            Err(e) => {
+                results.push(CheckResult::fail("transaction-list-query", e.to_string()));
+                results.push(CheckResult::fail(
+                    "payload-query",
+                    "skipped because the transaction list query failed",
+                ));
+            }
+        }
+
+        results.push(CheckResult::from_result(
+            "unknown-payload-query",
+            self.check_unknown_payload(&mut session).await,
+        ));
+        results.push(CheckResult::from_result(
+            "malformed-message",
+            self.check_malformed(&mut session).await,
+        ));
+
+        results
+    }
+}
@@ -0,0 +1,134 @@
+use std::fmt::{Display, Formatter};
+
+use anyhow::Result;
+use chrono::Utc;
+use rmp_serde::{decode, encode};
+use serde::{Deserialize, Serialize};
+use sled::Db;
+
+/// Maximum number of events which are kept per peer, older events are removed first
+const MAX_EVENTS: usize = 200;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum ConnectionEventKind {
+    /// An outgoing connection is being established
+    Dial,
+    /// The (mutual) TLS connection with the peer was established
+    TlsEstablished,
+    /// The transport or TLS connection with the peer couldn't be established
+    TlsFailed,
+    /// The peer completed the handshake, the detail contains the metadata it sent
+    Handshake,
+    /// The handshake was rejected (e.g. due to an unsupported protocol version or failed authorization)
+    HandshakeFailed,
+    /// The connection was lost, the detail contains the reason
+    Disconnect,
+}
+
+impl Display for ConnectionEventKind {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        f.write_str(match self {
+            ConnectionEventKind::Dial => "dial",
+            ConnectionEventKind::TlsEstablished => "tls-established",
+            ConnectionEventKind::TlsFailed => "tls-failed",
+            ConnectionEventKind::Handshake => "handshake",
+            ConnectionEventKind::HandshakeFailed => "handshake-failed",
+            ConnectionEventKind::Disconnect => "disconnect",
+        })
+    }
+}
+
+/// Event in the lifecycle of a connection with a peer
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ConnectionEvent {
+    /// Time of the event in milliseconds since the epoch
+    pub at: i64,
+    pub kind: ConnectionEventKind,
+    /// Address of the peer, unknown for incoming connections
+    pub addr: Option<String>,
+    pub detail: String,
+}
+
+/// Capped log of the connection events per peer, which is used to find out why a connection with a peer failed.
+/// Events are recorded by peer ID, or by address when the peer ID isn't known yet (e.g. when dialing)
+#[derive(Clone)]
+pub struct ConnectionLog {
+    db: Db,
+}
+
+impl ConnectionLog {
+    pub fn open(db: Db) -> Result<Self> {
+        Ok(Self { db })
+    }
+
+    /// Events are keyed by the peer followed by a monotonic ID so that a prefix scan returns them in order
+    fn prefix(peer: &str) -> Vec<u8> {
+        [peer.as_bytes(), &[0]].concat()
+    }
+
+    /// Records an event and removes the oldest events of the peer when the log is full
+    pub fn record(
+        &self,
+        peer: &str,
+        kind: ConnectionEventKind,
+        addr: Option<&str>,
+        detail: impl Into<String>,
+    ) -> Result<()> {
+        let tree = self.db.open_tree("nuts/connection-log")?;
+        let prefix = Self::prefix(peer);
+        let key = [prefix.as_slice(), &self.db.generate_id()?.to_be_bytes()].concat();
+
+        tree.insert(
+            key,
+            encode::to_vec(&ConnectionEvent {
+                at: Utc::now().timestamp_millis(),
+                kind,
+                addr: addr.map(str::to_string),
+                detail: detail.into(),
+            })?,
+        )?;
+
+        let count = tree.scan_prefix(&prefix).keys().count();
+
+        for key in tree
+            .scan_prefix(&prefix)
+            .keys()
+            .take(count.saturating_sub(MAX_EVENTS))
+        {
+            tree.remove(key?)?;
+        }
+
+        Ok(())
+    }
+
+    /// Same as `record` but only logs an error when the event couldn't be recorded, as the log is only used for
+    /// diagnostics it shouldn't affect the connection
+    pub fn log(
+        &self,
+        peer: &str,
+        kind: ConnectionEventKind,
+        addr: Option<&str>,
+        detail: impl Into<String>,
+    ) {
+        if let Err(e) = self.record(peer, kind, addr, detail) {
+            tracing::error!(target: "nuts::network", "failed to record {} event for peer '{}': {}", kind, peer, e);
+        }
+    }
+
+    /// Returns the recorded events of a peer (by peer ID or address) from old to new
+    pub fn history(&self, peer: &str) -> Result<Vec<ConnectionEvent>> {
+        let mut events = vec![];
+
+        for record in self
+            .db
+            .open_tree("nuts/connection-log")?
+            .scan_prefix(Self::prefix(peer))
+        {
+            let (_, value) = record?;
+
+            events.push(decode::from_read(value.as_ref())?);
+        }
+
+        Ok(events)
+    }
+}
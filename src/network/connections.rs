@@ -0,0 +1,135 @@
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+
+use tokio::sync::{oneshot, watch};
+use uuid::Uuid;
+
+struct Active {
+    id: u64,
+    inbound: bool,
+    /// Dropped to close the connection when it's replaced
+    replace: watch::Sender<()>,
+}
+
+#[derive(Default)]
+struct State {
+    next_id: u64,
+    active: HashMap<Uuid, Active>,
+}
+
+/// Keeps a single connection per peer, which prevents messages from being handled twice when two nodes connect to
+/// each other (e.g. because both list the other as bootstrap node). Of two connections in opposite directions the
+/// one initiated by the node with the lowest peer ID is kept, so that both nodes make the same decision
+#[derive(Clone)]
+pub struct Connections {
+    local_peer_id: Uuid,
+    state: Arc<Mutex<State>>,
+    /// Notified whenever a connection is closed
+    closed: Arc<watch::Sender<()>>,
+}
+
+impl Connections {
+    pub fn new(local_peer_id: Uuid) -> Self {
+        Self {
+            local_peer_id,
+            state: Arc::new(Mutex::new(State::default())),
+            closed: Arc::new(watch::channel(()).0),
+        }
+    }
+
+    /// Registers a connection with the peer after the handshake, returns none when an existing connection is kept
+    /// instead. An existing connection in the opposite direction which was initiated by the node with the highest
+    /// peer ID is closed. The stop sender is dropped together with the connection to end it's outbound stream
+    pub fn register(
+        &self,
+        peer_id: Uuid,
+        inbound: bool,
+        stop: oneshot::Sender<()>,
+    ) -> Option<Connection> {
+        let mut state = self.state.lock().unwrap();
+
+        if let Some(existing) = state.active.get(&peer_id) {
+            let prefer_inbound = peer_id < self.local_peer_id;
+
+            if existing.inbound == inbound || inbound != prefer_inbound {
+                return None;
+            }
+
+            tracing::info!(target: "nuts::network", "closing duplicate {} connection with peer: {}", if existing.inbound { "inbound" } else { "outbound" }, peer_id);
+        }
+
+        let (replace, replaced) = watch::channel(());
+
+        state.next_id += 1;
+
+        let id = state.next_id;
+
+        // Replacing the existing connection drops it's sender, which closes it
+        state.active.insert(
+            peer_id,
+            Active {
+                id,
+                inbound,
+                replace,
+            },
+        );
+
+        Some(Connection {
+            peer_id,
+            id,
+            replaced,
+            _stop: stop,
+            connections: self.clone(),
+        })
+    }
+
+    /// Number of peers which are connected
+    pub fn len(&self) -> usize {
+        self.state.lock().unwrap().active.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Returns a receiver which is notified whenever a connection is closed
+    pub fn subscribe_closed(&self) -> watch::Receiver<()> {
+        self.closed.subscribe()
+    }
+}
+
+/// Registered connection with a peer, which is unregistered when it's dropped
+pub struct Connection {
+    peer_id: Uuid,
+    id: u64,
+    replaced: watch::Receiver<()>,
+    _stop: oneshot::Sender<()>,
+    connections: Connections,
+}
+
+impl Connection {
+    /// Completes when the connection is closed in favor of a connection in the opposite direction
+    pub async fn replaced(&mut self) {
+        // Nothing is ever sent, so this only returns when the sender is dropped
+        let _ = self.replaced.changed().await;
+    }
+}
+
+impl Drop for Connection {
+    fn drop(&mut self) {
+        let mut state = self.connections.state.lock().unwrap();
+
+        if state
+            .active
+            .get(&self.peer_id)
+            .is_some_and(|active| active.id == self.id)
+        {
+            state.active.remove(&self.peer_id);
+        }
+
+        drop(state);
+
+        // Sending only fails when there are no receivers
+        let _ = self.connections.closed.send(());
+    }
+}
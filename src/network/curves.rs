@@ -0,0 +1,162 @@
+use anyhow::{anyhow, Result};
+use p384::ecdsa::signature::Verifier;
+
+/// Uncompressed point as described in SEC 1 section 2.3.3
+fn uncompressed_point(x: &[u8], y: &[u8]) -> Vec<u8> {
+    [&[0x04], x, y].concat()
+}
+
+/// Verifies an ES384 signature (fixed-size `r || s` as used by JWS) using a NIST P-384 public key
+pub fn verify_p384(x: &[u8], y: &[u8], message: &[u8], signature: &[u8]) -> Result<()> {
+    let key = p384::ecdsa::VerifyingKey::from_sec1_bytes(&uncompressed_point(x, y))
+        .map_err(|_| anyhow!("invalid ES384 signature: public key is not a point on the curve"))?;
+    let signature = p384::ecdsa::Signature::from_slice(signature)
+        .map_err(|_| anyhow!("invalid ES384 signature: malformed signature"))?;
+
+    key.verify(message, &signature)
+        .map_err(|_| anyhow!("invalid ES384 signature"))
+}
+
+/// Verifies an ES512 signature (fixed-size `r || s` as used by JWS) using a NIST P-521 public key
+pub fn verify_p521(x: &[u8], y: &[u8], message: &[u8], signature: &[u8]) -> Result<()> {
+    let key = p521::ecdsa::VerifyingKey::from_sec1_bytes(&uncompressed_point(x, y))
+        .map_err(|_| anyhow!("invalid ES512 signature: public key is not a point on the curve"))?;
+    let signature = p521::ecdsa::Signature::from_slice(signature)
+        .map_err(|_| anyhow!("invalid ES512 signature: malformed signature"))?;
+
+    key.verify(message, &signature)
+        .map_err(|_| anyhow!("invalid ES512 signature"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Signing input of a JWS, the keys and signatures below were generated using OpenSSL
+    const P384_MESSAGE: &str = "eyJhbGciOiJFUzM4NCJ9.eyJzdWIiOiJudXRzIn0";
+    const P384_X: &str = "2e8d8a541b65fac6d0045ba8bab2680ce82191c4aba954481a906e399de7a4cdf4decd8cc2a2566accdff5d052e61005";
+    const P384_Y: &str = "4248abc2eba29139520b2aa296a9be2a4afcab063839a0377dc6d2ebd7b1b055a723d82c5c73473242b188daa81b06e9";
+    const P384_SIGNATURE: &str = "c03baa6013a1dc2e16c07460d69e052d834e1345b6726d1981e19a92e4717545704678a4a08683826beee06d4ef3956b71398226d4421798690a4100a6a16cf3a6642849268c5bda1a9bafde583204a004acea807e650f6f6930f6edf18f6a9b";
+    /// Order of the P-384 group
+    const P384_N: &str = "ffffffffffffffffffffffffffffffffffffffffffffffffc7634d81f4372ddf581a0db248b0a77aecec196accc52973";
+
+    const P521_MESSAGE: &str = "eyJhbGciOiJFUzUxMiJ9.eyJzdWIiOiJudXRzIn0";
+    const P521_X: &str = "0161822ff63d0fd9d6fd6393d79008a3d18e043ecdf03d3b4142148cb5cb30999cc5eef6e05f8e45661cc5ed3d8d939ecd79544d18a4bb3117d9b2c1aa0d7f28c1bd";
+    const P521_Y: &str = "011df3b6b204fc68a71e7a2062fe090fb98e2dab1c40a6f8c11b9e14f1e98462009f6aa22afa3b2f9a0b88439c1f29cf03ed65238fb834dd377e7872dcc855f29aa5";
+    const P521_SIGNATURE: &str = "007aadb9f798eb65e36b1846e6c497fcf37e765b7f1a20e648072896a953a10a2847b4804dd9ebf9e2c04b0c0cd1b1e9d26a63e03ececd5408de41a523fadb79458c00f2a64311278b6c8094f47500104390862319a996bda961a22632513d2655c9f2996e754b95bf43fc6f5930f62736cbe7d78ffefdf0541f4ea48cd171de96a28e26";
+    /// Order of the P-521 group
+    const P521_N: &str = "01fffffffffffffffffffffffffffffffffffffffffffffffffffffffffffffffffa51868783bf2f966b7fcc0148f709a5d03bb5c9b8899c47aebb6fb71e91386409";
+
+    type Verify = fn(&[u8], &[u8], &[u8], &[u8]) -> Result<()>;
+
+    struct Vector {
+        verify: Verify,
+        message: &'static str,
+        x: Vec<u8>,
+        y: Vec<u8>,
+        signature: Vec<u8>,
+        n: Vec<u8>,
+    }
+
+    impl Vector {
+        fn p384() -> Self {
+            Self {
+                verify: verify_p384,
+                message: P384_MESSAGE,
+                x: hex::decode(P384_X).unwrap(),
+                y: hex::decode(P384_Y).unwrap(),
+                signature: hex::decode(P384_SIGNATURE).unwrap(),
+                n: hex::decode(P384_N).unwrap(),
+            }
+        }
+
+        fn p521() -> Self {
+            Self {
+                verify: verify_p521,
+                message: P521_MESSAGE,
+                x: hex::decode(P521_X).unwrap(),
+                y: hex::decode(P521_Y).unwrap(),
+                signature: hex::decode(P521_SIGNATURE).unwrap(),
+                n: hex::decode(P521_N).unwrap(),
+            }
+        }
+
+        fn verify(&self, message: &str, y: &[u8], signature: &[u8]) -> Result<()> {
+            (self.verify)(&self.x, y, message.as_bytes(), signature)
+        }
+
+        fn r(&self) -> &[u8] {
+            &self.signature[..self.n.len()]
+        }
+
+        fn s(&self) -> &[u8] {
+            &self.signature[self.n.len()..]
+        }
+    }
+
+    fn vectors() -> Vec<Vector> {
+        vec![Vector::p384(), Vector::p521()]
+    }
+
+    #[test]
+    fn valid_signature() {
+        for v in vectors() {
+            v.verify(v.message, &v.y, &v.signature).unwrap();
+        }
+    }
+
+    #[test]
+    fn tampered_message() {
+        for v in vectors() {
+            assert!(v
+                .verify("eyJhbGciOiJub25lIn0.e30", &v.y, &v.signature)
+                .is_err());
+        }
+    }
+
+    #[test]
+    fn point_not_on_curve() {
+        for v in vectors() {
+            let mut y = v.y.clone();
+
+            *y.last_mut().unwrap() ^= 1;
+
+            assert!(v.verify(v.message, &y, &v.signature).is_err());
+        }
+    }
+
+    #[test]
+    fn zero_scalars() {
+        for v in vectors() {
+            let zero = vec![0; v.n.len()];
+
+            assert!(v
+                .verify(v.message, &v.y, &[&zero[..], v.s()].concat())
+                .is_err());
+            assert!(v
+                .verify(v.message, &v.y, &[v.r(), &zero[..]].concat())
+                .is_err());
+        }
+    }
+
+    #[test]
+    fn scalars_out_of_range() {
+        for v in vectors() {
+            assert!(v
+                .verify(v.message, &v.y, &[&v.n[..], v.s()].concat())
+                .is_err());
+            assert!(v
+                .verify(v.message, &v.y, &[v.r(), &v.n[..]].concat())
+                .is_err());
+        }
+    }
+
+    #[test]
+    fn invalid_signature_length() {
+        for v in vectors() {
+            let signature = &v.signature[..v.signature.len() - 1];
+
+            assert!(v.verify(v.message, &v.y, signature).is_err());
+        }
+    }
+}
@@ -0,0 +1,62 @@
+use std::collections::{HashSet, VecDeque};
+use std::time::Duration;
+
+use prost::Message;
+use sha2::{Digest, Sha256};
+use tokio::time::Instant;
+use uuid::Uuid;
+
+use crate::proto::NetworkMessage;
+
+/// Time within which identical messages from the same peer are only handled once, which is kept short as peers
+/// legitimately repeat some messages (e.g. queries)
+pub const DEDUP_WINDOW: Duration = Duration::from_secs(2);
+
+/// SHA-256 of the encoded message
+pub fn digest(message: &NetworkMessage) -> [u8; 32] {
+    let mut digest = [0; 32];
+
+    digest.copy_from_slice(&Sha256::digest(&message.encode_to_vec()));
+    digest
+}
+
+/// Remembers the digests of the messages which were recently received from each peer, so that a message which is
+/// received more than once (e.g. over two connections with the same peer before one of them is closed) is only
+/// handled once
+pub struct RecentMessages {
+    window: Duration,
+    seen: HashSet<(Uuid, [u8; 32])>,
+    order: VecDeque<(Instant, Uuid, [u8; 32])>,
+}
+
+impl RecentMessages {
+    pub fn new(window: Duration) -> Self {
+        Self {
+            window,
+            seen: HashSet::new(),
+            order: VecDeque::new(),
+        }
+    }
+
+    /// Records the message, returns false when the same message was received from the peer within the window
+    pub fn insert(&mut self, peer_id: Uuid, digest: [u8; 32]) -> bool {
+        let now = Instant::now();
+
+        while let Some(&(at, peer_id, digest)) = self.order.front() {
+            if now.duration_since(at) < self.window {
+                break;
+            }
+
+            self.seen.remove(&(peer_id, digest));
+            self.order.pop_front();
+        }
+
+        if !self.seen.insert((peer_id, digest)) {
+            return false;
+        }
+
+        self.order.push_back((now, peer_id, digest));
+
+        true
+    }
+}
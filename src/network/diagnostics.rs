@@ -0,0 +1,40 @@
+use std::time::Duration;
+
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+use crate::proto::model::Diagnostics;
+
+/// Interval at which the diagnostics of the node are broadcast to it's peers
+pub const DIAGNOSTICS_INTERVAL: Duration = Duration::from_secs(60);
+
+/// Identifies this implementation to peers, the Nuts specification recommends using the URL of the repository
+pub const SOFTWARE_ID: &str = "https://github.com/dmeijboom/nuts-rs";
+
+/// Diagnostics which a connected peer reported about itself
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct PeerDiagnostics {
+    pub peer_id: String,
+    /// Time at which the diagnostics were received
+    pub received_at: i64,
+    /// Seconds since the peer started
+    pub uptime: u32,
+    pub peers: Vec<String>,
+    pub transactions: u32,
+    pub software_version: Option<String>,
+    pub software_id: Option<String>,
+}
+
+impl PeerDiagnostics {
+    pub fn new(peer_id: Uuid, received_at: i64, diagnostics: Diagnostics) -> Self {
+        Self {
+            peer_id: peer_id.to_string(),
+            received_at,
+            uptime: diagnostics.uptime,
+            peers: diagnostics.peers,
+            transactions: diagnostics.number_of_transactions,
+            software_version: diagnostics.software_version,
+            software_id: diagnostics.software_id,
+        }
+    }
+}
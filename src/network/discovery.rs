@@ -0,0 +1,178 @@
+use std::net::{IpAddr, SocketAddr};
+use std::time::Duration;
+
+use anyhow::Result;
+use trust_dns_resolver::config::{NameServerConfigGroup, ResolverConfig, ResolverOpts};
+use trust_dns_resolver::error::{ResolveError, ResolveErrorKind};
+use trust_dns_resolver::proto::rr::rdata::SRV;
+use trust_dns_resolver::TokioAsyncResolver;
+
+/// Port of peers which are discovered using A/AAAA records
+pub const DEFAULT_PORT: u16 = 5555;
+
+/// Finds peers by resolving a DNS name, SRV records are queried when the name starts with an underscore (e.g.
+/// `_nuts._tcp.example.com`). Otherwise every address the name resolves to is a peer, which is connected to by it's
+/// IP address while it's certificate is verified against the DNS name
+#[derive(Debug, Clone)]
+pub struct DiscoveryPolicy {
+    pub name: String,
+    pub interval: Duration,
+    /// Number of connected peers at which no more discovered peers are connected to
+    pub target_peers: usize,
+    /// Name server which is queried instead of the name servers in `/etc/resolv.conf`
+    pub nameserver: Option<SocketAddr>,
+}
+
+/// Peer which was found by resolving the name of the discovery policy
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DiscoveredPeer {
+    pub addr: String,
+    /// DNS name the certificate of the peer is verified against when it's address is an IP address
+    pub domain: Option<String>,
+}
+
+impl DiscoveryPolicy {
+    /// Returns the peers which are currently published, ordered by their preference
+    pub async fn resolve(&self) -> Result<Vec<DiscoveredPeer>> {
+        let resolver = self.resolver()?;
+
+        if self.name.starts_with('_') {
+            let records = resolver
+                .srv_lookup(self.name.as_str())
+                .await
+                .map(|lookup| lookup.iter().cloned().collect())
+                .or_else(no_records)?;
+
+            return Ok(srv_peers(records));
+        }
+
+        let ips = resolver
+            .lookup_ip(self.name.as_str())
+            .await
+            .map(|lookup| lookup.iter().collect())
+            .or_else(no_records)?;
+
+        Ok(ip_peers(&self.name, ips))
+    }
+
+    /// The resolver retries over TCP when a response is truncated
+    fn resolver(&self) -> Result<TokioAsyncResolver> {
+        Ok(match self.nameserver {
+            Some(nameserver) => {
+                let nameservers = NameServerConfigGroup::from_ips_clear(
+                    &[nameserver.ip()],
+                    nameserver.port(),
+                    true,
+                );
+
+                TokioAsyncResolver::tokio(
+                    ResolverConfig::from_parts(None, vec![], nameservers),
+                    ResolverOpts::default(),
+                )?
+            }
+            None => TokioAsyncResolver::tokio_from_system_conf()?,
+        })
+    }
+}
+
+/// A name which doesn't exist (or has no records of the type) means that no peers are published
+fn no_records<T>(e: ResolveError) -> Result<Vec<T>, ResolveError> {
+    match e.kind() {
+        ResolveErrorKind::NoRecordsFound { .. } => Ok(vec![]),
+        _ => Err(e),
+    }
+}
+
+fn srv_peers(mut records: Vec<SRV>) -> Vec<DiscoveredPeer> {
+    // Lower priorities are preferred, followed by higher weights
+    records.sort_by(|a, b| (a.priority(), b.weight()).cmp(&(b.priority(), a.weight())));
+
+    records
+        .into_iter()
+        // A target of "." means that the service isn't available
+        .filter(|record| !record.target().is_root())
+        .map(|record| DiscoveredPeer {
+            addr: format!(
+                "https://{}:{}",
+                record.target().to_utf8().trim_end_matches('.'),
+                record.port()
+            ),
+            domain: None,
+        })
+        .collect()
+}
+
+fn ip_peers(name: &str, ips: Vec<IpAddr>) -> Vec<DiscoveredPeer> {
+    let domain = name.trim_end_matches('.').to_ascii_lowercase();
+
+    ips.into_iter()
+        .map(|ip| DiscoveredPeer {
+            addr: format!("https://{}", SocketAddr::new(ip, DEFAULT_PORT)),
+            domain: Some(domain.clone()),
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::str::FromStr;
+
+    use trust_dns_resolver::proto::rr::Name;
+
+    use super::*;
+
+    fn srv(priority: u16, weight: u16, port: u16, target: &str) -> SRV {
+        SRV::new(priority, weight, port, Name::from_str(target).unwrap())
+    }
+
+    fn addrs(peers: Vec<DiscoveredPeer>) -> Vec<String> {
+        peers.into_iter().map(|peer| peer.addr).collect()
+    }
+
+    #[test]
+    fn srv_preference() {
+        let peers = srv_peers(vec![
+            srv(20, 0, 5555, "node-4.care-x.nl."),
+            srv(10, 10, 5555, "node-2.care-x.nl."),
+            srv(10, 50, 5556, "node-1.care-x.nl."),
+            srv(10, 0, 5555, "node-3.care-x.nl"),
+        ]);
+
+        assert_eq!(
+            addrs(peers),
+            vec![
+                "https://node-1.care-x.nl:5556",
+                "https://node-2.care-x.nl:5555",
+                "https://node-3.care-x.nl:5555",
+                "https://node-4.care-x.nl:5555",
+            ]
+        );
+    }
+
+    #[test]
+    fn srv_unavailable_service() {
+        assert!(srv_peers(vec![srv(0, 0, 0, ".")]).is_empty());
+    }
+
+    #[test]
+    fn every_address_is_a_peer() {
+        let peers = ip_peers(
+            "Nodes.Care-X.nl.",
+            vec!["10.0.0.1".parse().unwrap(), "2001:db8::1".parse().unwrap()],
+        );
+
+        assert_eq!(
+            peers,
+            vec![
+                DiscoveredPeer {
+                    addr: "https://10.0.0.1:5555".to_string(),
+                    domain: Some("nodes.care-x.nl".to_string()),
+                },
+                DiscoveredPeer {
+                    addr: "https://[2001:db8::1]:5555".to_string(),
+                    domain: Some("nodes.care-x.nl".to_string()),
+                },
+            ]
+        );
+    }
+}
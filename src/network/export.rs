@@ -0,0 +1,254 @@
+use std::fmt::Write;
+use std::str::FromStr;
+
+use anyhow::{anyhow, Result};
+use serde::{Deserialize, Serialize};
+
+use crate::network::Graph;
+
+/// Formats in which the DAG can be exported for analysis in external tooling or for importing in another node
+#[derive(Debug, Clone, Copy)]
+pub enum ExportFormat {
+    GraphML,
+    Gexf,
+    Dot,
+    /// JSON object with the compact JWS of every transaction on it's own line, previous transactions come first
+    Ndjson,
+}
+
+/// Line of an NDJSON export
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ExportedTransaction {
+    /// Compact JWS of the transaction
+    pub jws: String,
+}
+
+impl FromStr for ExportFormat {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "graphml" => Ok(ExportFormat::GraphML),
+            "gexf" => Ok(ExportFormat::Gexf),
+            "dot" => Ok(ExportFormat::Dot),
+            "ndjson" => Ok(ExportFormat::Ndjson),
+            _ => Err(anyhow!("unsupported export format: {}", s)),
+        }
+    }
+}
+
+/// Attributes which are exported for every transaction with their type, which is the same in GraphML and GEXF
+const ATTRIBUTES: &[(&str, &str)] = &[
+    ("payload_type", "string"),
+    ("signer", "string"),
+    ("sign_at", "string"),
+    ("lamport_clock", "long"),
+];
+
+fn escape(value: &str) -> String {
+    value
+        .replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+        .replace('\'', "&apos;")
+}
+
+/// Escapes a value for use in a quoted Graphviz ID
+fn escape_dot(value: &str) -> String {
+    value.replace('\\', "\\\\").replace('"', "\\\"")
+}
+
+struct Node {
+    id: String,
+    label: String,
+    values: Vec<String>,
+}
+
+/// Returns the nodes with their attribute values of all transactions and the edges between them
+fn collect(graph: &Graph) -> (Vec<Node>, Vec<(String, String)>) {
+    let mut nodes = vec![];
+    let mut edges = vec![];
+
+    for tx in graph.to_vec() {
+        let id = tx.id.to_string();
+
+        for prev in tx.prevs.iter() {
+            edges.push((prev.to_string(), id.clone()));
+        }
+
+        nodes.push(Node {
+            label: id[..8].to_string(),
+            id,
+            values: vec![
+                tx.payload_type.clone(),
+                tx.key_id.clone(),
+                tx.sign_at.to_string(),
+                graph.clock(&tx.id).unwrap_or_default().to_string(),
+            ],
+        });
+    }
+
+    (nodes, edges)
+}
+
+fn to_graphml(graph: &Graph) -> Result<String> {
+    let (nodes, edges) = collect(graph);
+    let mut out = String::new();
+
+    writeln!(out, r#"<?xml version="1.0" encoding="UTF-8"?>"#)?;
+    writeln!(
+        out,
+        r#"<graphml xmlns="http://graphml.graphdrawing.org/xmlns">"#
+    )?;
+
+    for (name, kind) in ATTRIBUTES.iter() {
+        writeln!(
+            out,
+            r#"  <key id="{0}" for="node" attr.name="{0}" attr.type="{1}"/>"#,
+            name, kind
+        )?;
+    }
+
+    writeln!(out, r#"  <graph id="dag" edgedefault="directed">"#)?;
+
+    for node in nodes {
+        writeln!(out, r#"    <node id="{}">"#, node.id)?;
+
+        for ((name, _), value) in ATTRIBUTES.iter().zip(node.values.iter()) {
+            writeln!(
+                out,
+                r#"      <data key="{}">{}</data>"#,
+                name,
+                escape(value)
+            )?;
+        }
+
+        writeln!(out, "    </node>")?;
+    }
+
+    for (source, target) in edges {
+        writeln!(
+            out,
+            r#"    <edge source="{}" target="{}"/>"#,
+            source, target
+        )?;
+    }
+
+    writeln!(out, "  </graph>")?;
+    writeln!(out, "</graphml>")?;
+
+    Ok(out)
+}
+
+fn to_gexf(graph: &Graph) -> Result<String> {
+    let (nodes, edges) = collect(graph);
+    let mut out = String::new();
+
+    writeln!(out, r#"<?xml version="1.0" encoding="UTF-8"?>"#)?;
+    writeln!(out, r#"<gexf xmlns="http://gexf.net/1.3" version="1.3">"#)?;
+    writeln!(out, r#"  <graph mode="static" defaultedgetype="directed">"#)?;
+    writeln!(out, r#"    <attributes class="node">"#)?;
+
+    for (i, (name, kind)) in ATTRIBUTES.iter().enumerate() {
+        writeln!(
+            out,
+            r#"      <attribute id="{}" title="{}" type="{}"/>"#,
+            i, name, kind
+        )?;
+    }
+
+    writeln!(out, "    </attributes>")?;
+    writeln!(out, "    <nodes>")?;
+
+    for node in nodes {
+        writeln!(
+            out,
+            r#"      <node id="{}" label="{}">"#,
+            node.id, node.label
+        )?;
+        writeln!(out, "        <attvalues>")?;
+
+        for (i, value) in node.values.iter().enumerate() {
+            writeln!(
+                out,
+                r#"          <attvalue for="{}" value="{}"/>"#,
+                i,
+                escape(value)
+            )?;
+        }
+
+        writeln!(out, "        </attvalues>")?;
+        writeln!(out, "      </node>")?;
+    }
+
+    writeln!(out, "    </nodes>")?;
+    writeln!(out, "    <edges>")?;
+
+    for (i, (source, target)) in edges.into_iter().enumerate() {
+        writeln!(
+            out,
+            r#"      <edge id="{}" source="{}" target="{}"/>"#,
+            i, source, target
+        )?;
+    }
+
+    writeln!(out, "    </edges>")?;
+    writeln!(out, "  </graph>")?;
+    writeln!(out, "</gexf>")?;
+
+    Ok(out)
+}
+
+fn to_dot(graph: &Graph) -> Result<String> {
+    let (nodes, edges) = collect(graph);
+    let mut out = String::new();
+
+    writeln!(out, "digraph dag {{")?;
+    writeln!(out, "  node [shape=box];")?;
+
+    for node in nodes {
+        // The values are the payload type and signer, the signing time is left out to keep the nodes small
+        writeln!(
+            out,
+            r#"  "{}" [label="{}\n{}\n{}"];"#,
+            node.id,
+            node.label,
+            escape_dot(&node.values[0]),
+            escape_dot(&node.values[1])
+        )?;
+    }
+
+    for (source, target) in edges {
+        writeln!(out, r#"  "{}" -> "{}";"#, source, target)?;
+    }
+
+    writeln!(out, "}}")?;
+
+    Ok(out)
+}
+
+fn to_ndjson(graph: &Graph) -> Result<String> {
+    let mut out = String::new();
+
+    // Ordered by Lamport clock so that every transaction can be admitted when the lines are read in order
+    for tx in graph.iter_ordered() {
+        let line = ExportedTransaction {
+            jws: String::from_utf8(tx.data.clone())?,
+        };
+
+        writeln!(out, "{}", serde_json::to_string(&line)?)?;
+    }
+
+    Ok(out)
+}
+
+/// Renders all transactions in the DAG and the edges between them in the given format
+pub fn export(graph: &Graph, format: ExportFormat) -> Result<String> {
+    match format {
+        ExportFormat::GraphML => to_graphml(graph),
+        ExportFormat::Gexf => to_gexf(graph),
+        ExportFormat::Dot => to_dot(graph),
+        ExportFormat::Ndjson => to_ndjson(graph),
+    }
+}
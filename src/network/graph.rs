@@ -1,31 +1,82 @@
-use std::fmt::{Debug, Formatter};
+use std::cmp::Reverse;
+use std::collections::{BinaryHeap, HashMap, HashSet};
+use std::error::Error;
+use std::fmt::{Debug, Display, Formatter};
+use std::string::FromUtf8Error;
+use std::sync::Arc;
 
 use anyhow::{anyhow, Result};
-use daggy::{Dag, NodeIndex, Walker};
+use chrono::Utc;
+use daggy::{Dag, NodeIndex, Walker, WouldCycle};
 use rmp_serde::{decode, encode};
 use serde::{Deserialize, Serialize};
 use sled::Db;
+use tokio::sync::broadcast::{self, Sender};
+use tokio::task;
+use uuid::Uuid;
 
+use crate::backend::{self, Tree};
+use crate::metrics::Metrics;
+use crate::network::blocks::block_date;
+use crate::network::transaction::Verification;
+use crate::network::writer::{PendingWrites, Writer};
 use crate::network::{Hash, Transaction};
 
-fn walk_recursive<T>(
-    dag: &Dag<Transaction, Transaction>,
-    idx: NodeIndex<u32>,
-    predicate: impl Fn(&Transaction, NodeIndex<u32>) -> Option<T> + Clone,
-) -> Option<T> {
-    if let Some(tx) = dag.node_weight(idx) {
-        if let Some(output) = predicate(tx, idx) {
-            return Some(output);
+/// Depth-first iterator over the DAG starting at the root transaction, which uses a worklist instead of recursion
+/// so that long chains of transactions don't overflow the stack
+struct Nodes<'a> {
+    dag: &'a Dag<Transaction, ()>,
+    stack: Vec<NodeIndex<u32>>,
+    visited: HashSet<NodeIndex<u32>>,
+}
+
+impl<'a> Nodes<'a> {
+    fn new(dag: &'a Dag<Transaction, ()>) -> Self {
+        Self {
+            dag,
+            stack: if dag.node_count() > 0 {
+                vec![0.into()]
+            } else {
+                vec![]
+            },
+            visited: HashSet::new(),
         }
     }
+}
+
+impl<'a> Iterator for Nodes<'a> {
+    type Item = (NodeIndex<u32>, &'a Transaction);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        while let Some(idx) = self.stack.pop() {
+            // Transactions with multiple previous transactions can be reached through each of them
+            if !self.visited.insert(idx) {
+                continue;
+            }
 
-    for (_, n) in dag.children(idx).iter(dag) {
-        if let Some(output) = walk_recursive(dag, n, predicate.clone()) {
-            return Some(output);
+            let children = self
+                .dag
+                .children(idx)
+                .iter(self.dag)
+                .map(|(_, child)| child)
+                .collect::<Vec<_>>();
+
+            // Reversed so that the children are visited in the same order as they're returned by the DAG
+            self.stack.extend(children.into_iter().rev());
+
+            if let Some(tx) = self.dag.node_weight(idx) {
+                return Some((idx, tx));
+            }
         }
+
+        None
     }
+}
 
-    None
+/// The payload index is keyed by the payload hash followed by the transaction ID so that all transactions
+/// referencing a payload can be found using a prefix scan
+fn payload_ref_key(payload: &Hash, tx_id: &Hash) -> Vec<u8> {
+    [payload.as_ref(), tx_id.as_ref()].concat()
 }
 
 #[derive(Serialize, Deserialize)]
@@ -33,11 +84,154 @@ struct Node {
     idx: u32,
     tx_id: Hash,
     tx_data: String,
+    #[serde(default)]
+    verification: Option<Verification>,
+}
+
+/// A transaction which can't be added to the DAG yet because one or more previous transactions are missing
+#[derive(Clone, Serialize, Deserialize)]
+struct Orphan {
+    tx_data: String,
+    received_at: i64,
+    /// Peer from which the transaction was received, unknown for orphans parked before this was recorded
+    #[serde(default)]
+    peer_id: Option<String>,
+    /// Whether the missing previous transactions should be queried again on the next sync
+    #[serde(default)]
+    retry: bool,
+}
+
+/// Details of a transaction in the orphan pool
+#[derive(Debug, Clone)]
+pub struct OrphanInfo {
+    pub id: Hash,
+    /// Previous transactions which aren't present in the DAG yet
+    pub missing: Vec<Hash>,
+    pub received_at: i64,
+    pub peer_id: Option<Uuid>,
+    pub retry: bool,
+}
+
+/// Errors which occur when adding transactions to the DAG
+#[derive(Debug)]
+pub enum GraphError {
+    /// The transaction is already present in the DAG
+    DuplicateTransaction(Hash),
+    /// The transaction is a root transaction while the DAG already has a root transaction
+    DuplicateRoot(Hash),
+    /// A previous transaction is missing from the DAG
+    MissingPrev(Hash),
+    /// The Lamport clock claimed by the transaction doesn't match it's previous transactions
+    InvalidClock {
+        tx: Hash,
+        expected: u32,
+        actual: u32,
+    },
+    /// Reading or writing the database failed
+    Storage(anyhow::Error),
+}
+
+impl Display for GraphError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            GraphError::DuplicateTransaction(id) => {
+                write!(f, "transaction '{}' is already present in graph", id)
+            }
+            GraphError::DuplicateRoot(id) => write!(
+                f,
+                "unable to add root transaction '{}' to a graph with an existing root transaction",
+                id
+            ),
+            GraphError::MissingPrev(id) => write!(f, "previous transaction '{}' is missing", id),
+            GraphError::InvalidClock {
+                tx,
+                expected,
+                actual,
+            } => write!(
+                f,
+                "transaction '{}' has an invalid lamport clock (expected {}, got {})",
+                tx, expected, actual
+            ),
+            GraphError::Storage(e) => write!(f, "graph storage error: {}", e),
+        }
+    }
+}
+
+impl Error for GraphError {}
+
+impl From<anyhow::Error> for GraphError {
+    fn from(e: anyhow::Error) -> Self {
+        GraphError::Storage(e)
+    }
+}
+
+impl From<sled::Error> for GraphError {
+    fn from(e: sled::Error) -> Self {
+        GraphError::Storage(e.into())
+    }
+}
+
+impl From<encode::Error> for GraphError {
+    fn from(e: encode::Error) -> Self {
+        GraphError::Storage(e.into())
+    }
+}
+
+impl From<decode::Error> for GraphError {
+    fn from(e: decode::Error) -> Self {
+        GraphError::Storage(e.into())
+    }
+}
+
+impl From<FromUtf8Error> for GraphError {
+    fn from(e: FromUtf8Error) -> Self {
+        GraphError::Storage(e.into())
+    }
+}
+
+impl From<WouldCycle<()>> for GraphError {
+    fn from(e: WouldCycle<()>) -> Self {
+        GraphError::Storage(anyhow!("{:?}", e))
+    }
+}
+
+/// Outcome of rebuilding the edges of the persisted DAG from the previous transactions of each transaction
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct EdgeRepair {
+    pub transactions: usize,
+    /// Links to previous transactions which couldn't be restored because the previous transaction was stored after
+    /// the transaction referencing it
+    pub corrected: usize,
+    /// Transactions which got a different position in the DAG
+    pub reindexed: usize,
 }
 
+/// DAG of transactions which is persisted in the database, transactions whose previous transactions are missing
+/// are kept as orphans until they arrive
 pub struct Graph {
-    db: Db,
-    dag: Dag<Transaction, Transaction>,
+    /// Handles of the trees which are opened once as opening a tree on every operation is relatively expensive
+    dag_tree: Arc<dyn Tree>,
+    orphan_tree: Arc<dyn Tree>,
+    payload_refs: Arc<dyn Tree>,
+    /// Writes transactions and orphans to the trees in the background, so that adding a transaction doesn't wait
+    /// for the disk
+    writer: Writer,
+    dag: Dag<Transaction, ()>,
+    /// Index of every transaction in the DAG by it's ID, kept up-to-date on every add
+    nodes: HashMap<Hash, NodeIndex<u32>>,
+    /// Transactions which aren't referenced by any other transaction, kept up-to-date on every add
+    heads: Vec<Hash>,
+    /// Lamport clock of every transaction, which is the length of the longest chain from the root transaction
+    clocks: HashMap<Hash, u32>,
+    orphans: Vec<Transaction>,
+    /// Stored records of the orphans, kept in memory so that they can be read without waiting for the writer
+    orphan_records: HashMap<Hash, Orphan>,
+    /// IDs of the transactions referencing each payload, mirrors the payload index in the database
+    payload_index: HashMap<Hash, Vec<Hash>>,
+    added: Sender<Transaction>,
+    /// Channels of the subscribers which are only interested in a single payload type
+    subscriptions: HashMap<String, Sender<Transaction>>,
+    metrics: Metrics,
 }
 
 impl Debug for Graph {
@@ -47,20 +241,34 @@ impl Debug for Graph {
 }
 
 impl Graph {
+    /// Loads the DAG from the database
     pub fn open(db: Db) -> Result<Self> {
+        let storage = backend::open(&db)?;
         let mut graph = Self {
-            db,
+            dag_tree: storage.open_tree("nuts/dag")?,
+            orphan_tree: storage.open_tree("nuts/orphans")?,
+            payload_refs: storage.open_tree("nuts/payload-refs")?,
+            writer: Writer::spawn("graph-writer")?,
             dag: Dag::new(),
+            nodes: HashMap::new(),
+            heads: vec![],
+            clocks: HashMap::new(),
+            orphans: vec![],
+            orphan_records: HashMap::new(),
+            payload_index: HashMap::new(),
+            added: broadcast::channel(100).0,
+            subscriptions: HashMap::new(),
+            metrics: Metrics::default(),
         };
 
-        let tree = graph.db.open_tree("nuts/dag")?;
         let mut transactions = vec![];
 
-        for record in tree.iter() {
+        for record in graph.dag_tree.iter() {
             let (_, value) = record?;
             let node: Node = decode::from_read(value.as_ref())?;
-            let tx = Transaction::parse_unsafe(node.tx_data)?;
+            let mut tx = Transaction::parse_unsafe(node.tx_data)?;
 
+            tx.verification = node.verification;
             transactions.push((node.idx, tx));
         }
 
@@ -70,80 +278,651 @@ impl Graph {
             graph.add_local(tx)?;
         }
 
+        for record in graph.orphan_tree.iter() {
+            let (_, value) = record?;
+            let orphan: Orphan = decode::from_read(value.as_ref())?;
+            let tx = Transaction::parse_unsafe(&orphan.tx_data)?;
+
+            graph.orphan_records.insert(tx.id.clone(), orphan);
+            graph.orphans.push(tx);
+        }
+
+        graph.attach_orphans()?;
+
         Ok(graph)
     }
 
-    pub fn walk(&self, predicate: impl Fn(&Transaction)) {
-        let _: Option<()> = walk_recursive(&self.dag, 0.into(), |tx, _| {
+    /// Counts what happens to orphans in the counters of the node
+    pub fn metrics(mut self, metrics: Metrics) -> Self {
+        self.metrics = metrics;
+        self
+    }
+
+    /// Indexes the payloads of the persisted transactions, which is needed for databases created before the payload
+    /// index existed
+    pub(crate) fn index_payloads(db: &Db) -> Result<()> {
+        let storage = backend::open(db)?;
+        let tree = storage.open_tree("nuts/dag")?;
+        let payload_refs = storage.open_tree("nuts/payload-refs")?;
+
+        for record in tree.iter() {
+            let (_, value) = record?;
+            let node: Node = decode::from_read(value.as_ref())?;
+            let tx = Transaction::parse_unsafe(&node.tx_data)?;
+
+            payload_refs.insert(&payload_ref_key(&tx.payload, &tx.id), &[])?;
+        }
+
+        Ok(())
+    }
+
+    /// Rebuilds the edges of the persisted DAG from the previous transactions of each (re-parsed) transaction and
+    /// stores the transactions in topological order, so that databases which modeled merges incorrectly can be loaded
+    pub fn repair_edges(db: &Db) -> Result<EdgeRepair> {
+        let tree = backend::open(db)?.open_tree("nuts/dag")?;
+        let mut nodes = vec![];
+
+        for record in tree.iter() {
+            let (_, value) = record?;
+            let node: Node = decode::from_read(value.as_ref())?;
+            let tx = Transaction::parse_unsafe(&node.tx_data)?;
+
+            if tx.id != node.tx_id {
+                return Err(anyhow!(
+                    "stored transaction '{}' has a different ID: {}",
+                    node.tx_id,
+                    tx.id
+                ));
+            }
+
+            nodes.push((node, tx));
+        }
+
+        // Keep the stored order as much as possible so that only the transactions which are out of order move
+        nodes.sort_by_key(|(node, _)| node.idx);
+
+        let positions = nodes
+            .iter()
+            .enumerate()
+            .map(|(pos, (_, tx))| (tx.id.clone(), pos))
+            .collect::<HashMap<_, _>>();
+        let mut children = vec![vec![]; nodes.len()];
+        let mut pending = vec![0; nodes.len()];
+        let mut corrected = 0;
+
+        for (pos, (_, tx)) in nodes.iter().enumerate() {
+            for prev in tx.prevs.iter() {
+                let prev_pos = *positions.get(prev).ok_or_else(|| {
+                    anyhow!(
+                        "transaction '{}' references missing previous transaction '{}'",
+                        tx.id,
+                        prev
+                    )
+                })?;
+
+                if prev_pos >= pos {
+                    corrected += 1;
+                }
+
+                children[prev_pos].push(pos);
+                pending[pos] += 1;
+            }
+        }
+
+        let roots = nodes.iter().filter(|(_, tx)| tx.is_root()).count();
+
+        if !nodes.is_empty() && roots != 1 {
+            return Err(anyhow!(
+                "expected a single root transaction but found {}",
+                roots
+            ));
+        }
+
+        let mut ready = (0..nodes.len())
+            .filter(|pos| pending[*pos] == 0)
+            .map(Reverse)
+            .collect::<BinaryHeap<_>>();
+        let mut order = vec![];
+
+        while let Some(Reverse(pos)) = ready.pop() {
+            order.push(pos);
+
+            for child in children[pos].iter() {
+                pending[*child] -= 1;
+
+                if pending[*child] == 0 {
+                    ready.push(Reverse(*child));
+                }
+            }
+        }
+
+        if order.len() != nodes.len() {
+            return Err(anyhow!(
+                "unable to order {} transactions which reference each other",
+                nodes.len() - order.len()
+            ));
+        }
+
+        let mut records = vec![];
+        let mut reindexed = 0;
+
+        for (idx, pos) in order.into_iter().enumerate() {
+            let node = &nodes[pos].0;
+
+            if node.idx as usize == idx {
+                continue;
+            }
+
+            reindexed += 1;
+            records.push((
+                node.tx_id.as_ref().to_vec(),
+                encode::to_vec(&Node {
+                    idx: idx as u32,
+                    tx_id: node.tx_id.clone(),
+                    tx_data: node.tx_data.clone(),
+                    verification: node.verification.clone(),
+                })?,
+            ));
+        }
+
+        tree.insert_all(records)?;
+        tree.flush()?;
+
+        Ok(EdgeRepair {
+            transactions: nodes.len(),
+            corrected,
+            reindexed,
+        })
+    }
+
+    /// Returns the IDs of all transactions which reference the given payload ordered by their ID
+    pub fn payload_refs(&self, payload: &Hash) -> Result<Vec<Hash>> {
+        let mut ids = self.payload_index.get(payload).cloned().unwrap_or_default();
+
+        ids.sort_by(|a, b| a.as_ref().cmp(b.as_ref()));
+
+        Ok(ids)
+    }
+
+    /// Resolves a complete or abbreviated transaction ID of a transaction in the DAG
+    pub async fn resolve_id(&self, source: &str) -> Result<Hash> {
+        self.writer.drain().await?;
+
+        Hash::resolve(&*self.dag_tree, source)
+    }
+
+    /// Resolves a complete or abbreviated transaction ID of an orphan
+    pub async fn resolve_orphan(&self, source: &str) -> Result<Hash> {
+        self.writer.drain().await?;
+
+        Hash::resolve(&*self.orphan_tree, source)
+    }
+
+    /// Resolves a complete or abbreviated hash of a payload which is referenced by a transaction
+    pub async fn resolve_payload(&self, source: &str) -> Result<Hash> {
+        self.writer.drain().await?;
+
+        Hash::resolve(&*self.payload_refs, source)
+    }
+
+    /// Returns the size in bytes of the keys and values of the stored DAG, which excludes the overhead of the storage
+    /// engine itself
+    pub fn stored_size(db: &Db) -> Result<u64> {
+        let mut size = 0;
+
+        for record in backend::open(db)?.open_tree("nuts/dag")?.iter() {
+            let (key, value) = record?;
+
+            size += (key.len() + value.len()) as u64;
+        }
+
+        Ok(size)
+    }
+
+    /// Whether the transaction is stored in the database without loading the DAG
+    pub fn is_stored(db: &Db, id: &Hash) -> Result<bool> {
+        backend::open(db)?
+            .open_tree("nuts/dag")?
+            .contains_key(id.as_ref())
+    }
+
+    /// Returns the channel on which every transaction is published after it's added to the DAG, transactions which
+    /// are loaded from the database aren't published
+    pub fn added(&self) -> Sender<Transaction> {
+        self.added.clone()
+    }
+
+    /// Returns a receiver on which every transaction is published after it's persisted, or only the transactions
+    /// with the given payload type
+    pub fn subscribe(&mut self, payload_type: Option<&str>) -> broadcast::Receiver<Transaction> {
+        match payload_type {
+            Some(payload_type) => self
+                .subscriptions
+                .entry(payload_type.to_string())
+                .or_insert_with(|| broadcast::channel(100).0)
+                .subscribe(),
+            None => self.added.subscribe(),
+        }
+    }
+
+    /// Returns the number of transactions in the DAG, orphans excluded
+    pub fn len(&self) -> usize {
+        self.dag.node_count()
+    }
+
+    /// Whether the DAG doesn't have a root transaction yet
+    pub fn is_empty(&self) -> bool {
+        self.dag.node_count() == 0
+    }
+
+    /// Returns an iterator over all transactions in the DAG starting at the root transaction
+    pub fn iter(&self) -> impl Iterator<Item = &Transaction> {
+        Nodes::new(&self.dag).map(|(_, tx)| tx)
+    }
+
+    /// Calls the predicate for every transaction in the DAG starting at the root transaction
+    pub fn walk(&self, mut predicate: impl FnMut(&Transaction)) {
+        for tx in self.iter() {
             predicate(tx);
+        }
+    }
 
-            // Keep walking the entire DAG
-            None
-        });
+    /// Returns a copy of all transactions in the DAG starting at the root transaction
+    pub fn to_vec(&self) -> Vec<Transaction> {
+        self.iter().cloned().collect()
     }
 
+    /// Returns a copy of the transactions which were signed within the block starting at the given date, block date
+    /// 0 refers to the entire DAG
+    pub fn block(&self, date: u32) -> Vec<Transaction> {
+        if date == 0 {
+            return self.to_vec();
+        }
+
+        self.iter()
+            .filter(|tx| block_date(tx.sign_at) == date)
+            .cloned()
+            .collect()
+    }
+
+    /// Returns a copy of the transactions matching the predicate and all of their ancestors, which is the minimal
+    /// sub-DAG needed to verify them
+    pub fn sub_dag(&self, predicate: impl Fn(&Transaction) -> bool) -> Vec<Transaction> {
+        let mut included = HashSet::new();
+        let mut stack = self
+            .dag
+            .graph()
+            .node_indices()
+            .filter(|idx| self.dag.node_weight(*idx).is_some_and(&predicate))
+            .collect::<Vec<_>>();
+
+        while let Some(idx) = stack.pop() {
+            if included.insert(idx) {
+                stack.extend(
+                    self.dag
+                        .parents(idx)
+                        .iter(&self.dag)
+                        .map(|(_, parent)| parent),
+                );
+            }
+        }
+
+        Nodes::new(&self.dag)
+            .filter(|(idx, _)| included.contains(idx))
+            .map(|(_, tx)| tx.clone())
+            .collect()
+    }
+
+    /// Returns the root transaction which is the first transaction in the DAG
     pub fn root(&self) -> Option<&Transaction> {
         self.dag.node_weight(0.into())
     }
 
+    /// Returns the index of the transaction in the DAG
     pub fn find(&self, id: &Hash) -> Option<NodeIndex<u32>> {
-        match self.root() {
-            Some(_) => walk_recursive(&self.dag, 0.into(), |tx, idx| {
-                if &tx.id == id {
-                    Some(idx)
-                } else {
-                    None
-                }
-            }),
-            None => None,
-        }
+        self.nodes.get(id).copied()
     }
 
+    /// Returns the transaction with the given ID
     pub fn get(&self, id: &Hash) -> Option<&Transaction> {
         self.find(id).and_then(|id| self.dag.node_weight(id))
     }
 
-    pub fn add(&mut self, tx: Transaction) -> Result<NodeIndex<u32>> {
-        log::debug!(
+    /// Returns the XOR of the hashes of all transactions, which is equal for nodes with the same DAG
+    pub fn xor(&self) -> Hash {
+        Hash::xor(self.iter().map(|tx| &tx.id))
+    }
+
+    /// Returns the highest Lamport clock value, which is the length of the longest chain of transactions after the
+    /// root transaction
+    pub fn lamport_clock(&self) -> u32 {
+        self.clocks.values().copied().max().unwrap_or(0)
+    }
+
+    /// Returns the Lamport clock of a transaction in the DAG
+    pub fn clock(&self, id: &Hash) -> Option<u32> {
+        self.clocks.get(id).copied()
+    }
+
+    /// Returns the Lamport clock of a transaction with the given previous transactions, which is one higher than the
+    /// highest clock of the previous transactions (or zero for a root transaction)
+    pub fn next_clock(&self, prevs: &[Hash]) -> u32 {
+        prevs
+            .iter()
+            .filter_map(|id| self.clock(id))
+            .map(|clock| clock + 1)
+            .max()
+            .unwrap_or(0)
+    }
+
+    /// Returns an iterator over all transactions in the DAG ordered by their Lamport clock and then by hash, which
+    /// is the same order on every node with the same DAG
+    pub fn iter_ordered(&self) -> impl Iterator<Item = &Transaction> {
+        let mut transactions = self.iter().collect::<Vec<_>>();
+
+        transactions.sort_by(|a, b| {
+            self.clock(&a.id)
+                .cmp(&self.clock(&b.id))
+                .then_with(|| a.id.as_ref().cmp(b.id.as_ref()))
+        });
+
+        transactions.into_iter()
+    }
+
+    /// Returns an error when the Lamport clock claimed by the transaction doesn't match it's previous transactions
+    fn verify_clock(&self, tx: &Transaction) -> Result<(), GraphError> {
+        let expected = self.next_clock(&tx.prevs);
+
+        match tx.lamport_clock {
+            Some(actual) if actual != expected => Err(GraphError::InvalidClock {
+                tx: tx.id.clone(),
+                expected,
+                actual,
+            }),
+            _ => Ok(()),
+        }
+    }
+
+    /// Returns the transactions which are waiting for their previous transactions to arrive
+    pub fn orphans(&self) -> &[Transaction] {
+        &self.orphans
+    }
+
+    /// Returns the details of all transactions in the orphan pool ordered by the time they were received
+    pub fn pending_orphans(&self) -> Result<Vec<OrphanInfo>> {
+        let mut orphans = vec![];
+
+        for tx in self.orphans.iter() {
+            let orphan = match self.orphan_records.get(&tx.id) {
+                Some(orphan) => orphan,
+                None => continue,
+            };
+
+            orphans.push(OrphanInfo {
+                id: tx.id.clone(),
+                missing: tx
+                    .prevs
+                    .iter()
+                    .filter(|id| self.find(id).is_none())
+                    .cloned()
+                    .collect(),
+                received_at: orphan.received_at,
+                peer_id: orphan
+                    .peer_id
+                    .as_ref()
+                    .and_then(|peer_id| Uuid::parse_str(peer_id).ok()),
+                retry: orphan.retry,
+            });
+        }
+
+        orphans.sort_by_key(|orphan| orphan.received_at);
+
+        Ok(orphans)
+    }
+
+    /// Removes a transaction from the orphan pool, it's parked again when a peer sends it another time
+    pub fn drop_orphan(&mut self, id: &Hash) -> Result<bool> {
+        let i = match self.orphans.iter().position(|orphan| &orphan.id == id) {
+            Some(i) => i,
+            None => return Ok(false),
+        };
+
+        tracing::debug!(target: "nuts::network", "dropping orphan transaction: {}", id);
+
+        self.orphans.remove(i);
+        self.orphan_records.remove(id);
+        self.writer.remove(&self.orphan_tree, id)?;
+
+        self.metrics.increment("orphans.dropped");
+
+        Ok(true)
+    }
+
+    /// Marks an orphan so that it's missing previous transactions are queried again on the next sync
+    pub fn retry_orphan(&mut self, id: &Hash) -> Result<bool> {
+        let orphan = match self.orphan_records.get_mut(id) {
+            Some(orphan) => orphan,
+            None => return Ok(false),
+        };
+
+        orphan.retry = true;
+
+        let value = encode::to_vec(&*orphan)?;
+
+        self.writer.insert(&self.orphan_tree, id, value)?;
+
+        Ok(true)
+    }
+
+    /// Returns the orphans which are marked to be retried and clears the mark
+    pub fn take_orphan_retries(&mut self) -> Result<Vec<OrphanInfo>> {
+        let retries = self
+            .pending_orphans()?
+            .into_iter()
+            .filter(|orphan| orphan.retry)
+            .collect::<Vec<_>>();
+
+        for info in retries.iter() {
+            if let Some(orphan) = self.orphan_records.get_mut(&info.id) {
+                orphan.retry = false;
+
+                let value = encode::to_vec(&*orphan)?;
+
+                self.writer.insert(&self.orphan_tree, &info.id, value)?;
+            }
+
+            self.metrics.increment("orphans.retried");
+        }
+
+        Ok(retries)
+    }
+
+    /// Returns the IDs of all transactions which aren't referenced by any other transaction, which are the previous
+    /// transactions of the next transaction
+    pub fn heads(&self) -> Vec<Hash> {
+        self.heads.clone()
+    }
+
+    /// Returns the previous transactions of a transaction as connected in the DAG
+    pub fn parents(&self, id: &Hash) -> Option<Vec<&Transaction>> {
+        let idx = self.find(id)?;
+
+        Some(
+            self.dag
+                .parents(idx)
+                .iter(&self.dag)
+                .filter_map(|(_, parent_idx)| self.dag.node_weight(parent_idx))
+                .collect(),
+        )
+    }
+
+    /// Adds a transaction to the DAG or parks it in the orphan pool when not all previous transactions are
+    /// present yet, orphans are attached automatically as soon as their previous transactions arrive
+    pub fn add(&mut self, tx: Transaction) -> Result<Option<NodeIndex<u32>>, GraphError> {
+        self.add_from(tx, None)
+    }
+
+    /// Same as [`Graph::add`] but remembers from which peer the transaction was received in case it's parked
+    #[tracing::instrument(
+        target = "nuts::network",
+        name = "graph_add",
+        level = "debug",
+        skip_all,
+        fields(tx = %tx.id)
+    )]
+    pub fn add_from(
+        &mut self,
+        tx: Transaction,
+        peer_id: Option<Uuid>,
+    ) -> Result<Option<NodeIndex<u32>>, GraphError> {
+        if !tx.is_root() && tx.prevs.iter().any(|id| self.find(id).is_none()) {
+            self.park(tx, peer_id)?;
+
+            return Ok(None);
+        }
+
+        self.verify_clock(&tx)?;
+
+        let idx = self.persist(tx)?;
+
+        self.attach_orphans()?;
+
+        Ok(Some(idx))
+    }
+
+    fn park(&mut self, tx: Transaction, peer_id: Option<Uuid>) -> Result<(), GraphError> {
+        // Peers will keep sending the same orphans until the previous transactions arrive
+        if self.orphans.iter().any(|orphan| orphan.id == tx.id) {
+            return Ok(());
+        }
+
+        tracing::debug!(target: "nuts::network", "parking orphan transaction: {}", tx.id);
+
+        let orphan = Orphan {
+            tx_data: String::from_utf8(tx.data.clone())?,
+            received_at: Utc::now().timestamp(),
+            peer_id: peer_id.map(|peer_id| peer_id.to_string()),
+            retry: false,
+        };
+
+        self.writer
+            .insert(&self.orphan_tree, &tx.id, encode::to_vec(&orphan)?)?;
+        self.orphan_records.insert(tx.id.clone(), orphan);
+        self.orphans.push(tx);
+
+        self.metrics.increment("orphans.parked");
+
+        Ok(())
+    }
+
+    /// Adds all orphans for which the previous transactions are present to the DAG
+    fn attach_orphans(&mut self) -> Result<(), GraphError> {
+        while let Some(i) = self
+            .orphans
+            .iter()
+            .position(|orphan| orphan.prevs.iter().all(|id| self.find(id).is_some()))
+        {
+            let tx = self.orphans.remove(i);
+
+            self.orphan_records.remove(&tx.id);
+            self.writer.remove(&self.orphan_tree, &tx.id)?;
+
+            if let Err(e) = self.verify_clock(&tx) {
+                tracing::warn!(target: "nuts::network", "dropping orphan transaction: {}", e);
+                self.metrics.increment("orphans.dropped");
+                continue;
+            }
+
+            tracing::debug!(target: "nuts::network", "attaching orphan transaction: {}", tx.id);
+
+            self.persist(tx)?;
+
+            self.metrics.increment("orphans.attached");
+        }
+
+        Ok(())
+    }
+
+    /// Returns a handle to wait until the transactions and orphans which were added are written
+    pub fn pending_writes(&self) -> PendingWrites {
+        self.writer.pending()
+    }
+
+    /// Waits until all transactions and orphans are written and flushes the database, used on shutdown
+    pub async fn flush(&self) -> Result<()> {
+        let tree = self.dag_tree.clone();
+
+        self.writer.drain().await?;
+        task::spawn_blocking(move || tree.flush()).await??;
+
+        Ok(())
+    }
+
+    /// Adds a transaction to the DAG and queues it to be written to the database
+    fn persist(&mut self, tx: Transaction) -> Result<NodeIndex<u32>, GraphError> {
+        tracing::debug!(
             target: "nuts::network",
             "adding a {}transaction: {}",if tx.is_root() { "root " } else { "" }, tx.id
         );
 
         let tx_id = tx.id.clone();
         let tx_data = String::from_utf8(tx.data.clone())?;
-        let idx = self.add_local(tx)?;
-        let tree = self.db.open_tree("nuts/dag")?;
+        let payload_ref = payload_ref_key(&tx.payload, &tx.id);
+        let verification = tx.verification.clone();
+        let idx = self.add_local(tx.clone())?;
 
-        tree.insert(
+        self.writer
+            .insert(&self.payload_refs, payload_ref, vec![])?;
+        self.writer.insert(
+            &self.dag_tree,
             tx_id.clone(),
             encode::to_vec(&Node {
                 // This shouldn't overflow as the index type used is `u32`
                 idx: idx.index() as u32,
                 tx_id,
                 tx_data,
+                verification,
             })?,
         )?;
 
+        // Sending only fails when there are no subscribers
+        if let Some(subscription) = self.subscriptions.get(&tx.payload_type) {
+            let _ = subscription.send(tx.clone());
+        }
+
+        let _ = self.added.send(tx);
+
         Ok(idx)
     }
 
+    fn index_payload(&mut self, tx: &Transaction) {
+        self.payload_index
+            .entry(tx.payload.clone())
+            .or_default()
+            .push(tx.id.clone());
+    }
+
     /// Adds a transaction to the DAG but doesn't write it to the database
-    fn add_local(&mut self, tx: Transaction) -> Result<NodeIndex<u32>> {
+    fn add_local(&mut self, tx: Transaction) -> Result<NodeIndex<u32>, GraphError> {
         if self.find(&tx.id).is_some() {
-            return Err(anyhow!(
-                "transaction '{}' is already present in graph",
-                tx.id,
-            ));
+            return Err(GraphError::DuplicateTransaction(tx.id));
         }
 
         if tx.is_root() {
             if self.root().is_some() {
-                return Err(anyhow!(
-                    "unable to add a root transaction to a graph with an existing root transaction"
-                ));
+                return Err(GraphError::DuplicateRoot(tx.id));
             }
 
-            return Ok(self.dag.add_node(tx));
+            self.heads.push(tx.id.clone());
+            self.clocks.insert(tx.id.clone(), 0);
+            self.index_payload(&tx);
+
+            let id = tx.id.clone();
+            let idx = self.dag.add_node(tx);
+
+            self.nodes.insert(id, idx);
+
+            return Ok(idx);
         }
 
         // Make sure all previous transactions are present
@@ -152,21 +931,234 @@ impl Graph {
         for id in tx.prevs.iter() {
             match self.find(id) {
                 Some(idx) => prevs.push(idx),
-                None => {
-                    return Err(anyhow!(
-                    "unable to process transaction '{}' when previous transaction '{}' is missing",
-                    tx.id,
-                    id
-                ))
-                }
+                None => return Err(GraphError::MissingPrev(id.clone())),
             };
         }
 
-        let parent_idx = *prevs.last().unwrap();
+        self.index_payload(&tx);
+
+        let id = tx.id.clone();
         let idx = self.dag.add_node(tx);
 
-        self.dag.extend_with_edges(&[(parent_idx, idx)])?;
+        self.nodes.insert(id, idx);
+
+        self.dag
+            .extend_with_edges(prevs.into_iter().map(|parent_idx| (parent_idx, idx)))?;
+
+        // The previous transactions are no longer heads now that they're referenced
+        let tx = &self.dag[idx];
+        let clock = self.next_clock(&tx.prevs);
+
+        self.clocks.insert(tx.id.clone(), clock);
+
+        self.heads.retain(|id| !tx.prevs.contains(id));
+        self.heads.push(tx.id.clone());
 
         Ok(idx)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn graph() -> Graph {
+        Graph::open(sled::Config::new().temporary(true).open().unwrap()).unwrap()
+    }
+
+    /// Unsigned transaction, the graph doesn't verify the transactions which are added to it
+    fn tx(n: u32, prevs: &[&Hash]) -> Transaction {
+        Transaction {
+            id: Hash::new(n.to_be_bytes()).unwrap(),
+            prevs: prevs.iter().map(|&id| id.clone()).collect(),
+            payload: Hash::new(format!("payload-{}", n)).unwrap(),
+            payload_type: "application/did+json".to_string(),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn deep_chain() {
+        let mut graph = graph();
+        let mut chain: Vec<Hash> = vec![];
+
+        // Deep enough to overflow the stack of the test thread when traversing recursively
+        for n in 0..50_000 {
+            let tx = tx(n, &chain.last().into_iter().collect::<Vec<_>>());
+
+            chain.push(tx.id.clone());
+            graph.add(tx).unwrap();
+        }
+
+        let mut walked = 0;
+
+        graph.walk(|_| walked += 1);
+
+        assert_eq!(walked, chain.len());
+        assert_eq!(
+            graph.iter().map(|tx| tx.id.clone()).collect::<Vec<_>>(),
+            chain
+        );
+        assert_eq!(graph.to_vec().len(), chain.len());
+        assert_eq!(graph.heads(), vec![chain[chain.len() - 1].clone()]);
+        assert_eq!(graph.clock(&chain[12_345]), Some(12_345));
+    }
+
+    #[test]
+    fn merges_are_visited_once() {
+        let mut graph = graph();
+        let root = tx(0, &[]);
+        let left = tx(1, &[&root.id]);
+        let right = tx(2, &[&root.id]);
+        let merge = tx(3, &[&left.id, &right.id]);
+        let ids = [&root.id, &left.id, &right.id, &merge.id]
+            .iter()
+            .map(|&id| id.clone())
+            .collect::<HashSet<_>>();
+
+        for tx in [&root, &left, &right, &merge].iter() {
+            graph.add((*tx).clone()).unwrap();
+        }
+
+        let visited = graph.iter().map(|tx| tx.id.clone()).collect::<Vec<_>>();
+
+        assert_eq!(visited.len(), 4);
+        assert_eq!(visited[0], root.id);
+        assert_eq!(visited.into_iter().collect::<HashSet<_>>(), ids);
+    }
+
+    #[test]
+    fn empty_graph() {
+        let graph = graph();
+
+        assert!(graph.is_empty());
+        assert_eq!(graph.iter().count(), 0);
+        assert!(graph.to_vec().is_empty());
+    }
+
+    /// Same as [`tx`] but claims a Lamport clock
+    fn tx_at(n: u32, prevs: &[&Hash], clock: u32) -> Transaction {
+        Transaction {
+            lamport_clock: Some(clock),
+            ..tx(n, prevs)
+        }
+    }
+
+    #[test]
+    fn clock_of_merge_is_one_higher_than_longest_branch() {
+        let mut graph = graph();
+        let root = tx_at(0, &[], 0);
+        let left = tx_at(1, &[&root.id], 1);
+        let right = tx_at(2, &[&left.id], 2);
+        let merge = tx_at(3, &[&root.id, &right.id], 3);
+
+        for tx in [&root, &left, &right, &merge].iter() {
+            graph.add((*tx).clone()).unwrap();
+        }
+
+        assert_eq!(graph.clock(&root.id), Some(0));
+        assert_eq!(graph.clock(&merge.id), Some(3));
+        assert_eq!(graph.lamport_clock(), 3);
+        assert_eq!(graph.next_clock(&[root.id.clone()]), 1);
+        assert_eq!(graph.next_clock(&graph.heads()), 4);
+        assert_eq!(graph.next_clock(&[]), 0);
+    }
+
+    #[test]
+    fn invalid_clock_is_rejected() {
+        let mut graph = graph();
+        let root = tx_at(0, &[], 0);
+        let child = tx_at(1, &[&root.id], 5);
+
+        graph.add(root).unwrap();
+
+        match graph.add(child.clone()) {
+            Err(GraphError::InvalidClock {
+                tx,
+                expected,
+                actual,
+            }) => {
+                assert_eq!(tx, child.id);
+                assert_eq!(expected, 1);
+                assert_eq!(actual, 5);
+            }
+            result => panic!("expected an invalid clock, got: {:?}", result),
+        }
+
+        assert!(graph.find(&child.id).is_none());
+        assert_eq!(graph.len(), 1);
+    }
+
+    #[test]
+    fn missing_clock_is_computed() {
+        let mut graph = graph();
+        let root = tx_at(0, &[], 0);
+        let child = tx(1, &[&root.id]);
+        let id = child.id.clone();
+
+        graph.add(root).unwrap();
+        graph.add(child).unwrap();
+
+        assert_eq!(graph.clock(&id), Some(1));
+    }
+
+    #[test]
+    fn orphan_with_invalid_clock_is_dropped() {
+        let mut graph = graph();
+        let root = tx_at(0, &[], 0);
+        let parent = tx_at(1, &[&root.id], 1);
+        let valid = tx_at(2, &[&parent.id], 2);
+        let invalid = tx_at(3, &[&parent.id], 1);
+
+        graph.add(root).unwrap();
+
+        assert_eq!(graph.add(valid.clone()).unwrap(), None);
+        assert_eq!(graph.add(invalid.clone()).unwrap(), None);
+        assert_eq!(graph.orphans().len(), 2);
+
+        graph.add(parent).unwrap();
+
+        assert!(graph.orphans().is_empty());
+        assert_eq!(graph.clock(&valid.id), Some(2));
+        assert!(graph.find(&invalid.id).is_none());
+    }
+
+    #[test]
+    fn ordered_by_clock_then_hash() {
+        let mut graph = graph();
+        let root = tx_at(0, &[], 0);
+        let children = (1..6).map(|n| tx_at(n, &[&root.id], 1)).collect::<Vec<_>>();
+        let merge = tx_at(6, &children.iter().map(|tx| &tx.id).collect::<Vec<_>>(), 2);
+
+        graph.add(root.clone()).unwrap();
+
+        for tx in children.iter().rev() {
+            graph.add(tx.clone()).unwrap();
+        }
+
+        graph.add(merge.clone()).unwrap();
+
+        let mut expected = vec![root.id];
+
+        expected.extend(sorted_ids(&children));
+        expected.push(merge.id);
+
+        assert_eq!(
+            graph
+                .iter_ordered()
+                .map(|tx| tx.id.clone())
+                .collect::<Vec<_>>(),
+            expected
+        );
+    }
+
+    fn sorted_ids(transactions: &[Transaction]) -> Vec<Hash> {
+        let mut ids = transactions
+            .iter()
+            .map(|tx| tx.id.clone())
+            .collect::<Vec<_>>();
+
+        ids.sort_by(|a, b| a.as_ref().cmp(b.as_ref()));
+        ids
+    }
+}
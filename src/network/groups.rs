@@ -0,0 +1,53 @@
+use std::collections::HashMap;
+
+use crate::network::access::{PeerRule, PeerSubject};
+
+/// Logical groups of peers (e.g. per care domain), payloads of restricted payload types are only exchanged with
+/// peers which are a member of the same group
+#[derive(Debug, Clone, Default)]
+pub struct PeerGroups {
+    /// Groups this node is a member of
+    pub memberships: Vec<String>,
+    /// Payload types which are restricted to a single group
+    pub restricted: HashMap<String, String>,
+    /// Certificate fingerprints or DNS names of the peers which are a member of a group
+    pub members: HashMap<String, Vec<PeerRule>>,
+}
+
+impl PeerGroups {
+    /// Returns the group the payload type is restricted to
+    pub fn group_of(&self, payload_type: &str) -> Option<&str> {
+        self.restricted.get(payload_type).map(String::as_str)
+    }
+
+    /// Whether payloads of the given type may be received by this node
+    pub fn accepts(&self, payload_type: &str) -> bool {
+        self.allows(payload_type, &self.memberships)
+    }
+
+    /// Returns the advertised groups of which the certificate of the peer matches the member rules, as peers can
+    /// advertise any group. Groups without member rules are never accepted
+    pub fn verify(&self, advertised: Vec<String>, peer: Option<&PeerSubject>) -> Vec<String> {
+        let peer = match peer {
+            Some(peer) => peer,
+            None => return vec![],
+        };
+
+        advertised
+            .into_iter()
+            .filter(|group| {
+                self.members
+                    .get(group)
+                    .map_or(false, |rules| rules.iter().any(|rule| rule.matches(peer)))
+            })
+            .collect()
+    }
+
+    /// Whether payloads of the given type may be exchanged with a peer which is a member of the given groups
+    pub fn allows(&self, payload_type: &str, groups: &[String]) -> bool {
+        match self.group_of(payload_type) {
+            Some(group) => groups.iter().any(|g| g == group),
+            None => true,
+        }
+    }
+}
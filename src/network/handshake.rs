@@ -0,0 +1,173 @@
+use anyhow::{anyhow, Result};
+use tonic::metadata::{MetadataMap, MetadataValue};
+use uuid::Uuid;
+
+use crate::network::compression;
+use crate::network::PeerHandshake;
+
+/// Identity of the local node which is sent to peers when a connection is established
+#[derive(Debug, Clone)]
+pub struct NodeInfo {
+    pub peer_id: Uuid,
+    pub did: Option<String>,
+    pub network_id: String,
+}
+
+/// Optional protocol extensions which are supported by a peer
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Capabilities {
+    /// Sub-DAGs can be queried using a filter
+    pub subdag: bool,
+    /// Transaction lists can be compressed using the current version of the shared dictionary
+    pub compression: bool,
+    /// Transaction lists can be split into multiple messages
+    pub pagination: bool,
+}
+
+impl Capabilities {
+    fn parse(value: &str) -> Self {
+        let mut capabilities = Self::default();
+
+        for name in value.split(',').map(str::trim) {
+            match name {
+                "subdag" => capabilities.subdag = true,
+                compression::CAPABILITY => capabilities.compression = true,
+                "pagination" => capabilities.pagination = true,
+                _ => {}
+            }
+        }
+
+        capabilities
+    }
+}
+
+/// Identity of a remote peer as parsed from the connection metadata
+#[derive(Debug, Clone)]
+pub struct PeerInfo {
+    pub peer_id: Uuid,
+    pub version: String,
+    pub did: Option<String>,
+    pub network_id: Option<String>,
+    pub capabilities: Capabilities,
+}
+
+impl PeerInfo {
+    /// Returns the details of the peer which are passed to the authorizer
+    pub fn handshake(&self, certificate: Option<Vec<u8>>, inbound: bool) -> PeerHandshake {
+        PeerHandshake {
+            peer_id: self.peer_id,
+            did: self.did.clone(),
+            network_id: self.network_id.clone(),
+            certificate,
+            inbound,
+        }
+    }
+
+    /// Returns the metadata of the peer as a single line, which is recorded in the connection log
+    pub fn describe(&self) -> String {
+        let capabilities = [
+            ("subdag", self.capabilities.subdag),
+            ("compression", self.capabilities.compression),
+            ("pagination", self.capabilities.pagination),
+        ]
+        .iter()
+        .filter(|(_, enabled)| *enabled)
+        .map(|(name, _)| *name)
+        .collect::<Vec<_>>();
+
+        format!(
+            "protocol version: {}, DID: {}, network ID: {}, capabilities: {}",
+            self.version,
+            self.did.as_deref().unwrap_or("unknown"),
+            self.network_id.as_deref().unwrap_or("unknown"),
+            if capabilities.is_empty() {
+                "none".to_string()
+            } else {
+                capabilities.join(",")
+            }
+        )
+    }
+}
+
+impl NodeInfo {
+    pub fn set_metadata(&self, version: &'static str, metadata: &mut MetadataMap) -> Result<()> {
+        // Sets the Peer ID as described in: https://nuts-foundation.gitbook.io/drafts/rfc/rfc005-distributed-network-using-grpc#6-1-peer-identification
+        metadata.insert(
+            "peerid",
+            MetadataValue::from_str(&self.peer_id.to_string())?,
+        );
+
+        // Sets the protocol version described in: https://nuts-foundation.gitbook.io/drafts/rfc/rfc005-distributed-network-using-grpc#6-4-protocol-version
+        metadata.insert("version", MetadataValue::from_static(version));
+
+        // The network ID and node DID make it possible to reject peers from another network before exchanging any data
+        metadata.insert("networkid", MetadataValue::from_str(&self.network_id)?);
+
+        // Lets peers know that sub-DAGs can be queried using a filter and transaction lists can be compressed and
+        // split into multiple messages
+        metadata.insert(
+            "capabilities",
+            MetadataValue::from_str(&format!("subdag,{},pagination", compression::CAPABILITY))?,
+        );
+
+        if let Some(did) = &self.did {
+            metadata.insert("nodedid", MetadataValue::from_str(did)?);
+        }
+
+        Ok(())
+    }
+
+    pub fn parse_metadata(&self, strict: bool, metadata: &MetadataMap) -> Result<PeerInfo> {
+        let peer_id = metadata
+            .get("peerid")
+            .ok_or_else(|| anyhow!("unable to connect to peer because of missing peer ID"))?
+            .to_str()?;
+        let peer_id = Uuid::parse_str(peer_id)?;
+        let did = match metadata.get("nodedid") {
+            Some(did) => Some(did.to_str()?.to_string()),
+            None => None,
+        };
+        let capabilities = match metadata.get("capabilities") {
+            Some(capabilities) => Capabilities::parse(capabilities.to_str()?),
+            None => Capabilities::default(),
+        };
+
+        // Peers which don't send their network ID are only accepted when strict isn't enabled
+        let network_id = match metadata.get("networkid") {
+            Some(network_id) => {
+                let network_id = network_id.to_str()?;
+
+                if network_id != self.network_id {
+                    return Err(anyhow!(
+                        "peer '{}' is part of network '{}' instead of '{}'",
+                        peer_id,
+                        network_id,
+                        self.network_id
+                    ));
+                }
+
+                Some(network_id.to_string())
+            }
+            None if strict => {
+                return Err(anyhow!("peer '{}' didn't provide the network ID", peer_id))
+            }
+            None => None,
+        };
+
+        // It looks like the protocol version header is not implemented by all nodes, so when strict isn't enabled
+        // a missing version is interpreted as 1
+        let version = match metadata.get("version") {
+            Some(version) => version.to_str()?,
+            None if strict => return Err(anyhow!("peer didn't provide the protocol version")),
+            None => "1",
+        };
+
+        Ok(PeerInfo {
+            peer_id,
+            version: version.to_string(),
+            did,
+            network_id,
+            capabilities,
+        })
+    }
+}
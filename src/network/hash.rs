@@ -5,6 +5,8 @@ use anyhow::{anyhow, Result};
 use serde::{Deserialize, Serialize};
 use sha2::{Digest, Sha256};
 
+use crate::backend::Tree;
+
 fn to_fixed(bytes: Vec<u8>) -> Result<[u8; 32]> {
     let output: Box<[u8; 32]> = bytes
         .into_boxed_slice()
@@ -14,24 +16,25 @@ fn to_fixed(bytes: Vec<u8>) -> Result<[u8; 32]> {
     Ok(*output)
 }
 
-#[derive(Clone, Default, Serialize, Deserialize)]
+/// Minimum number of hex characters of an abbreviated hash, shorter prefixes are likely to be ambiguous
+const MIN_PREFIX_LEN: usize = 4;
+
+/// Maximum number of candidates which are listed when an abbreviated hash is ambiguous
+const MAX_CANDIDATES: usize = 10;
+
+/// SHA-256 hash which identifies transactions and payloads
+#[derive(Clone, Default, PartialEq, Eq, Hash, Serialize, Deserialize)]
 pub struct Hash([u8; 32]);
 
 impl Debug for Hash {
     fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
-        write!(f, "{}", self.to_string())
+        write!(f, "{}", self)
     }
 }
 
 impl Display for Hash {
     fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
-        write!(f, "{}", hex::encode(&self.0))
-    }
-}
-
-impl PartialEq for Hash {
-    fn eq(&self, other: &Self) -> bool {
-        self.0.eq(&other.0)
+        write!(f, "{}", hex::encode(self.0))
     }
 }
 
@@ -42,6 +45,7 @@ impl AsRef<[u8]> for Hash {
 }
 
 impl Hash {
+    /// Computes the SHA-256 hash of the data
     pub fn new(data: impl AsRef<[u8]>) -> Result<Self> {
         let mut hasher = Sha256::new();
 
@@ -52,11 +56,110 @@ impl Hash {
         Ok(Hash(to_fixed(digest.to_vec())?))
     }
 
+    /// Parses a hash from it's raw 32 bytes
     pub fn parse(source: Vec<u8>) -> Result<Self> {
         Ok(Hash(to_fixed(source)?))
     }
 
+    /// Parses a hex encoded hash as used by the Nuts specification
     pub fn parse_hex(source: &[u8]) -> Result<Self> {
         Self::parse(hex::decode(source)?)
     }
+
+    /// Parses an unpadded base64url encoded hash as used by some other implementations
+    pub fn parse_base64url(source: &[u8]) -> Result<Self> {
+        Self::parse(base64::decode_config(source, base64::URL_SAFE_NO_PAD)?)
+    }
+
+    /// Parses a hash which is either hex or base64url encoded, which is convenient for user input
+    pub fn parse_encoded(source: &str) -> Result<Self> {
+        if source.len() == 64 {
+            Self::parse_hex(source.as_bytes())
+        } else {
+            Self::parse_base64url(source.as_bytes())
+        }
+    }
+
+    /// Parses a hash which is either complete (hex or base64url encoded) or abbreviated to a unique hex prefix, like
+    /// git short hashes. Abbreviated hashes are looked up among the keys of the tree, which must start with a hash,
+    /// using an ordered scan which only reads the keys matching the prefix
+    pub fn resolve(tree: &dyn Tree, source: &str) -> Result<Self> {
+        if source.len() == 64 || source.len() == 43 {
+            return Self::parse_encoded(source);
+        }
+
+        let prefix = source.to_lowercase();
+
+        if prefix.len() < MIN_PREFIX_LEN
+            || prefix.len() > 64
+            || !prefix.chars().all(|c| c.is_ascii_hexdigit())
+        {
+            return Err(anyhow!(
+                "invalid hash '{}' (expected a hash or a hex prefix of at least {} characters)",
+                source,
+                MIN_PREFIX_LEN
+            ));
+        }
+
+        // The scan is limited to the whole bytes of the prefix, an odd number of characters is matched afterwards
+        let bytes = hex::decode(&prefix[..prefix.len() - prefix.len() % 2])?;
+        let mut candidates: Vec<Hash> = vec![];
+
+        for record in tree.scan_prefix(&bytes) {
+            let (key, _) = record?;
+
+            if key.len() < 32 || !hex::encode(&key[..32]).starts_with(&prefix) {
+                continue;
+            }
+
+            let hash = Self::parse(key[..32].to_vec())?;
+
+            // Keys which consist of multiple hashes (e.g. payload references) can share the same first hash
+            if candidates.last() != Some(&hash) {
+                candidates.push(hash);
+            }
+
+            if candidates.len() > MAX_CANDIDATES {
+                break;
+            }
+        }
+
+        match candidates.len() {
+            0 => Err(anyhow!("no hash found with prefix: {}", source)),
+            1 => Ok(candidates.remove(0)),
+            _ => Err(anyhow!(
+                "hash prefix '{}' is ambiguous, candidates:\n{}{}",
+                source,
+                candidates
+                    .iter()
+                    .take(MAX_CANDIDATES)
+                    .map(|hash| hash.to_string())
+                    .collect::<Vec<_>>()
+                    .join("\n"),
+                if candidates.len() > MAX_CANDIDATES {
+                    "\n..."
+                } else {
+                    ""
+                }
+            )),
+        }
+    }
+
+    /// Returns the unpadded base64url encoding of the hash
+    pub fn to_base64url(&self) -> String {
+        base64::encode_config(self.0, base64::URL_SAFE_NO_PAD)
+    }
+
+    /// Combines the hashes using XOR which results in a checksum that doesn't depend on their order
+    pub fn xor<'a>(hashes: impl IntoIterator<Item = &'a Hash>) -> Self {
+        let mut output = [0; 32];
+
+        for hash in hashes {
+            for (byte, other) in output.iter_mut().zip(hash.0.iter()) {
+                *byte ^= other;
+            }
+        }
+
+        Hash(output)
+    }
 }
@@ -0,0 +1,120 @@
+use std::fmt::{self, Display, Formatter};
+use std::time::Duration;
+
+use serde::{Deserialize, Serialize};
+
+use crate::network::diagnostics::PeerDiagnostics;
+use crate::network::retrieval::PayloadFailures;
+
+/// Interval at which the health of the node is refreshed
+pub const HEALTH_INTERVAL: Duration = Duration::from_secs(10);
+
+/// Status of the database of the node
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct StorageHealth {
+    pub ok: bool,
+    pub size_on_disk: u64,
+    pub error: Option<String>,
+}
+
+/// Number of payloads which are held by the node, which were dropped by the retention policy and which are being
+/// retrieved from peers
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct PayloadHealth {
+    pub retained: usize,
+    pub expired: usize,
+    #[serde(default)]
+    pub pending: usize,
+    /// Peers which failed to serve payloads they were queried for
+    #[serde(default)]
+    pub failures: Vec<PayloadFailures>,
+}
+
+/// Progress of the initial sync after the node started
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum SyncState {
+    /// The node is catching up with it's peers and isn't available yet
+    #[default]
+    Syncing,
+    /// The initial sync didn't complete in time, the node is available but might be missing transactions
+    Degraded,
+    /// The node caught up with a peer
+    Synced,
+}
+
+impl SyncState {
+    /// Whether the node is available for local operations
+    pub fn is_ready(&self) -> bool {
+        *self != SyncState::Syncing
+    }
+}
+
+impl Display for SyncState {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{}",
+            match self {
+                SyncState::Syncing => "syncing",
+                SyncState::Degraded => "degraded",
+                SyncState::Synced => "synced",
+            }
+        )
+    }
+}
+
+/// Connection with a peer of a running node
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PeerActivity {
+    pub peer_id: String,
+    pub protocol_version: u8,
+    /// Number of messages which were received from the peer since the node started
+    pub messages: u64,
+}
+
+/// Error which occurred while handling a message from a peer
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RecentError {
+    pub at: i64,
+    pub peer_id: String,
+    pub message: String,
+}
+
+/// Snapshot of the health of a running node which is cheap enough to be polled by probes
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct Health {
+    pub updated_at: i64,
+    /// Highest lamport clock in the DAG
+    pub height: u32,
+    pub transactions: usize,
+    pub root: Option<String>,
+    pub peers: usize,
+    /// Time at which the last sync with a peer completed
+    pub last_sync: Option<i64>,
+    #[serde(default)]
+    pub sync: SyncState,
+    pub storage: StorageHealth,
+    #[serde(default)]
+    pub payloads: PayloadHealth,
+    /// Diagnostics which the connected peers reported about themselves
+    #[serde(default)]
+    pub diagnostics: Vec<PeerDiagnostics>,
+    /// Transactions without any children
+    #[serde(default)]
+    pub heads: Vec<String>,
+    /// Transactions which are waiting for their previous transactions
+    #[serde(default)]
+    pub orphans: usize,
+    #[serde(default)]
+    pub connections: Vec<PeerActivity>,
+    /// Most recent errors which occurred while handling messages, oldest first
+    #[serde(default)]
+    pub errors: Vec<RecentError>,
+}
+
+impl Health {
+    pub fn is_healthy(&self) -> bool {
+        self.storage.ok
+    }
+}
@@ -0,0 +1,242 @@
+use anyhow::{anyhow, Result};
+use chrono::Utc;
+use rmp_serde::{decode, encode};
+use serde::{Deserialize, Serialize};
+use sled::Db;
+use uuid::Uuid;
+
+use crate::network::certificate::PeerCertificate;
+
+/// Certificate a peer ID is bound to
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PeerIdentity {
+    pub peer_id: String,
+    /// Subject of the certificate (or it's DNS names when the subject is empty)
+    pub subject: String,
+    pub dns_names: Vec<String>,
+    /// Fingerprint of the certificate which was last presented, bindings made by older versions don't have one
+    #[serde(default)]
+    pub fingerprint: Option<String>,
+    pub bound_at: i64,
+}
+
+/// Binds the self-reported peer ID of a peer to the subject of it's client certificate the first time it connects
+/// (see RFC005 on node identity), which prevents a peer from claiming the peer ID of another peer. Both incoming and
+/// outgoing connections are bound, using the client and server certificate of the peer respectively
+#[derive(Clone)]
+pub struct PeerIdentities {
+    db: Db,
+}
+
+impl PeerIdentities {
+    pub fn open(db: Db) -> Result<Self> {
+        Ok(Self { db })
+    }
+
+    /// Returns the peer ID of this node, which is generated once so that it stays the same across restarts
+    pub fn local_peer_id(&self) -> Result<Uuid> {
+        let tree = self.db.open_tree("nuts/node")?;
+
+        if let Some(value) = tree.get("peer-id")? {
+            return Ok(Uuid::from_slice(&value)?);
+        }
+
+        self.reset_local_peer_id()
+    }
+
+    /// Replaces the peer ID of this node with a newly generated one, peers rebind the certificate of this node to it
+    /// when it connects using the same certificate
+    pub fn reset_local_peer_id(&self) -> Result<Uuid> {
+        let peer_id = Uuid::new_v4();
+
+        self.db
+            .open_tree("nuts/node")?
+            .insert("peer-id", &peer_id.as_bytes()[..])?;
+
+        Ok(peer_id)
+    }
+
+    /// Binds the peer ID to the certificate when it's first seen, returns an error when the peer ID is bound to
+    /// another certificate. A certificate which is bound to another peer ID is rebound to the new peer ID, as only
+    /// the peer holding the key of the certificate can present it (e.g. after it reset it's peer ID)
+    pub fn bind(&self, peer_id: Uuid, certificate: &PeerCertificate) -> Result<()> {
+        let tree = self.db.open_tree("nuts/peer-identities")?;
+        let subject = certificate.name();
+        let peer_id = peer_id.to_string();
+
+        for record in tree.iter() {
+            let (_, value) = record?;
+            let identity: PeerIdentity = decode::from_read(value.as_ref())?;
+
+            if identity.peer_id == peer_id && identity.subject != subject {
+                return Err(anyhow!(
+                    "peer '{}' is bound to certificate '{}' instead of '{}'",
+                    peer_id,
+                    identity.subject,
+                    subject
+                ));
+            }
+        }
+
+        let bound_at = match tree.get(&subject)? {
+            Some(value) => {
+                let identity: PeerIdentity = decode::from_read(value.as_ref())?;
+
+                if identity.peer_id != peer_id {
+                    tracing::info!(target: "nuts::network", "rebound certificate '{}' from peer '{}' to peer '{}'", subject, identity.peer_id, peer_id);
+
+                    Utc::now().timestamp()
+                } else if identity.dns_names == certificate.dns_names
+                    && identity.fingerprint.as_ref() == Some(&certificate.fingerprint)
+                {
+                    return Ok(());
+                } else {
+                    // The certificate is renewed with the same subject
+                    identity.bound_at
+                }
+            }
+            None => {
+                tracing::info!(target: "nuts::network", "bound peer '{}' to certificate: {}", peer_id, subject);
+
+                Utc::now().timestamp()
+            }
+        };
+
+        tree.insert(
+            subject.as_bytes().to_vec(),
+            encode::to_vec(&PeerIdentity {
+                peer_id,
+                subject,
+                dns_names: certificate.dns_names.clone(),
+                fingerprint: Some(certificate.fingerprint.clone()),
+                bound_at,
+            })?,
+        )?;
+
+        Ok(())
+    }
+
+    /// Returns the binding of the peer ID, which is only known when this node was connected to the peer before
+    pub fn get(&self, peer_id: Uuid) -> Result<Option<PeerIdentity>> {
+        let peer_id = peer_id.to_string();
+
+        Ok(self
+            .list()?
+            .into_iter()
+            .find(|identity| identity.peer_id == peer_id))
+    }
+
+    /// Returns all bindings ordered by the subject of the certificate
+    pub fn list(&self) -> Result<Vec<PeerIdentity>> {
+        let mut identities = vec![];
+
+        for record in self.db.open_tree("nuts/peer-identities")?.iter() {
+            let (_, value) = record?;
+
+            identities.push(decode::from_read(value.as_ref())?);
+        }
+
+        Ok(identities)
+    }
+
+    /// Removes the bindings of a peer ID or certificate subject so that the peer can connect using another peer ID
+    /// or certificate (e.g. after it's database was reset), returns the number of removed bindings
+    pub fn unbind(&self, peer: &str) -> Result<usize> {
+        let tree = self.db.open_tree("nuts/peer-identities")?;
+        let mut count = 0;
+
+        for identity in self.list()? {
+            if identity.peer_id == peer || identity.subject == peer {
+                tree.remove(&identity.subject)?;
+                count += 1;
+            }
+        }
+
+        Ok(count)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn identities() -> PeerIdentities {
+        PeerIdentities::open(sled::Config::new().temporary(true).open().unwrap()).unwrap()
+    }
+
+    fn certificate(subject: &str, fingerprint: &str) -> PeerCertificate {
+        PeerCertificate {
+            subject: subject.to_string(),
+            dns_names: vec!["node-1.care-x.nl".to_string()],
+            fingerprint: fingerprint.to_string(),
+        }
+    }
+
+    #[test]
+    fn bind() {
+        let identities = identities();
+        let peer_id = Uuid::new_v4();
+
+        identities
+            .bind(peer_id, &certificate("CN=node-1", "aa"))
+            .unwrap();
+
+        let identity = identities.get(peer_id).unwrap().unwrap();
+
+        assert_eq!(identity.subject, "CN=node-1");
+        assert_eq!(identity.fingerprint.as_deref(), Some("aa"));
+    }
+
+    #[test]
+    fn renewed_certificate() {
+        let identities = identities();
+        let peer_id = Uuid::new_v4();
+
+        identities
+            .bind(peer_id, &certificate("CN=node-1", "aa"))
+            .unwrap();
+        identities
+            .bind(peer_id, &certificate("CN=node-1", "bb"))
+            .unwrap();
+
+        let identities = identities.list().unwrap();
+
+        assert_eq!(identities.len(), 1);
+        assert_eq!(identities[0].fingerprint.as_deref(), Some("bb"));
+    }
+
+    #[test]
+    fn rotated_peer_id() {
+        let identities = identities();
+        let (old, new) = (Uuid::new_v4(), Uuid::new_v4());
+
+        identities
+            .bind(old, &certificate("CN=node-1", "aa"))
+            .unwrap();
+        identities
+            .bind(new, &certificate("CN=node-1", "aa"))
+            .unwrap();
+
+        assert!(identities.get(old).unwrap().is_none());
+        assert_eq!(identities.get(new).unwrap().unwrap().subject, "CN=node-1");
+        assert_eq!(identities.list().unwrap().len(), 1);
+    }
+
+    #[test]
+    fn peer_id_of_another_certificate() {
+        let identities = identities();
+        let peer_id = Uuid::new_v4();
+
+        identities
+            .bind(peer_id, &certificate("CN=node-1", "aa"))
+            .unwrap();
+
+        assert!(identities
+            .bind(peer_id, &certificate("CN=node-2", "bb"))
+            .is_err());
+        assert_eq!(
+            identities.get(peer_id).unwrap().unwrap().subject,
+            "CN=node-1"
+        );
+    }
+}
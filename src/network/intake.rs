@@ -0,0 +1,236 @@
+use std::collections::{HashMap, VecDeque};
+use std::fmt::{self, Display, Formatter};
+use std::str::FromStr;
+use std::sync::{Arc, Mutex};
+
+use anyhow::{anyhow, Error, Result};
+use tokio::sync::Notify;
+
+use crate::metrics::Metrics;
+
+/// What happens when the queue of a peer is full because messages are received faster than the server handles them
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum OverflowPolicy {
+    /// Drops the oldest queued message of the peer, lost messages are recovered by syncing
+    #[default]
+    DropOldest,
+    /// Closes the connection with the peer
+    Disconnect,
+}
+
+impl Display for OverflowPolicy {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            OverflowPolicy::DropOldest => write!(f, "drop-oldest"),
+            OverflowPolicy::Disconnect => write!(f, "disconnect"),
+        }
+    }
+}
+
+impl FromStr for OverflowPolicy {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "drop-oldest" => Ok(OverflowPolicy::DropOldest),
+            "disconnect" => Ok(OverflowPolicy::Disconnect),
+            _ => Err(anyhow!(
+                "invalid overflow policy '{}' (expected drop-oldest or disconnect)",
+                s
+            )),
+        }
+    }
+}
+
+struct Queue<T> {
+    messages: VecDeque<T>,
+    /// Whether the sender of the queue still exists, closed queues are removed once they're drained
+    open: bool,
+}
+
+struct State<T> {
+    next_id: u64,
+    queues: HashMap<u64, Queue<T>>,
+    /// Queues which have messages, in the order in which they're handled
+    ready: VecDeque<u64>,
+    closed: bool,
+}
+
+struct Shared<T> {
+    state: Mutex<State<T>>,
+    notify: Notify,
+    capacity: usize,
+    policy: OverflowPolicy,
+    metrics: Metrics,
+}
+
+/// Messages received from peers which are waiting to be handled by the server. Every connection has it's own
+/// bounded queue and the queues are handled in turns, so that a peer which sends a lot of messages doesn't delay the
+/// messages of other peers and a slow handler never blocks the connections
+pub struct Intake<T> {
+    shared: Arc<Shared<T>>,
+}
+
+impl<T> Clone for Intake<T> {
+    fn clone(&self) -> Self {
+        Self {
+            shared: self.shared.clone(),
+        }
+    }
+}
+
+impl<T> Intake<T> {
+    pub fn new(capacity: usize, policy: OverflowPolicy, metrics: Metrics) -> Self {
+        Self {
+            shared: Arc::new(Shared {
+                state: Mutex::new(State {
+                    next_id: 0,
+                    queues: HashMap::new(),
+                    ready: VecDeque::new(),
+                    closed: false,
+                }),
+                notify: Notify::new(),
+                capacity: capacity.max(1),
+                policy,
+                metrics,
+            }),
+        }
+    }
+
+    /// Creates the queue of a new connection
+    pub fn sender(&self) -> IntakeSender<T> {
+        let mut state = self.shared.state.lock().unwrap();
+
+        state.next_id += 1;
+
+        let id = state.next_id;
+
+        state.queues.insert(
+            id,
+            Queue {
+                messages: VecDeque::new(),
+                open: true,
+            },
+        );
+
+        IntakeSender {
+            id,
+            shared: self.shared.clone(),
+        }
+    }
+
+    /// Returns the next message of the peer whose turn it is, returns none when the intake is closed and all
+    /// messages are handled. Cancelling doesn't lose messages, so it can be used in a select
+    pub async fn recv(&self) -> Option<T> {
+        loop {
+            if let Some(message) = self.try_recv() {
+                return Some(message);
+            }
+
+            if self.shared.state.lock().unwrap().closed {
+                return None;
+            }
+
+            self.shared.notify.notified().await;
+        }
+    }
+
+    fn try_recv(&self) -> Option<T> {
+        let mut guard = self.shared.state.lock().unwrap();
+        let state = &mut *guard;
+
+        while let Some(id) = state.ready.pop_front() {
+            let queue = match state.queues.get_mut(&id) {
+                Some(queue) => queue,
+                None => continue,
+            };
+            let message = queue.messages.pop_front();
+
+            if !queue.messages.is_empty() {
+                state.ready.push_back(id);
+            } else if !queue.open {
+                state.queues.remove(&id);
+            }
+
+            if message.is_some() {
+                return message;
+            }
+        }
+
+        None
+    }
+
+    /// Rejects new messages, the messages which are already queued can still be received
+    pub fn close(&self) {
+        self.shared.state.lock().unwrap().closed = true;
+        self.shared.notify.notify_one();
+    }
+}
+
+/// Queues the messages of a single connection, the queue is removed once the sender is dropped and it's drained
+pub struct IntakeSender<T> {
+    id: u64,
+    shared: Arc<Shared<T>>,
+}
+
+impl<T> IntakeSender<T> {
+    /// Queues the message without waiting for the server, returns an error when the intake is closed or the queue is
+    /// full and the overflow policy is to disconnect
+    pub fn send(&self, message: T) -> Result<()> {
+        let mut state = self.shared.state.lock().unwrap();
+
+        if state.closed {
+            return Err(anyhow!("message queue is closed"));
+        }
+
+        let queue = state
+            .queues
+            .get_mut(&self.id)
+            .expect("queue is removed before it's sender");
+
+        if queue.messages.len() >= self.shared.capacity {
+            match self.shared.policy {
+                OverflowPolicy::DropOldest => {
+                    queue.messages.pop_front();
+                    self.shared.metrics.increment("messages.dropped");
+                }
+                OverflowPolicy::Disconnect => {
+                    self.shared.metrics.increment("peers.overflowed");
+
+                    return Err(anyhow!(
+                        "message queue is full ({} messages)",
+                        self.shared.capacity
+                    ));
+                }
+            }
+        }
+
+        let was_empty = queue.messages.is_empty();
+
+        queue.messages.push_back(message);
+
+        if was_empty {
+            state.ready.push_back(self.id);
+        }
+
+        drop(state);
+
+        self.shared.notify.notify_one();
+
+        Ok(())
+    }
+}
+
+impl<T> Drop for IntakeSender<T> {
+    fn drop(&mut self) {
+        let mut state = self.shared.state.lock().unwrap();
+
+        if let Some(queue) = state.queues.get_mut(&self.id) {
+            if queue.messages.is_empty() {
+                state.queues.remove(&self.id);
+            } else {
+                queue.open = false;
+            }
+        }
+    }
+}
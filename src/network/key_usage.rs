@@ -0,0 +1,218 @@
+use std::collections::BTreeMap;
+use std::fmt::{self, Display, Formatter};
+
+use anyhow::Result;
+use rmp_serde::{decode, encode};
+use serde::{Deserialize, Serialize};
+use sled::Db;
+
+use crate::metrics::Metrics;
+use crate::network::Transaction;
+
+const HOUR: i64 = 60 * 60;
+
+/// Number of hours of signing history which is kept per key, which is a week
+const WINDOW: i64 = 7 * 24;
+
+/// Thresholds used to decide whether a key is used in an unusual way
+#[derive(Debug, Clone)]
+pub struct KeyUsagePolicy {
+    /// Number of hours a key must have been in use before spikes are detected
+    pub min_history: i64,
+    /// Factor by which the hourly signing rate must exceed the baseline
+    pub spike_factor: f64,
+    /// Minimum number of signatures within an hour before it's considered a spike
+    pub min_spike: u64,
+    /// Number of signatures a key must have made before new payload types are considered unusual
+    pub min_signatures: u64,
+}
+
+impl Default for KeyUsagePolicy {
+    fn default() -> Self {
+        Self {
+            min_history: 24,
+            spike_factor: 10.0,
+            min_spike: 20,
+            min_signatures: 20,
+        }
+    }
+}
+
+/// Unusual usage of a signing key which might indicate that the key is compromised
+#[derive(Debug, Clone, Serialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum Anomaly {
+    /// The key signed far more transactions within an hour than it usually does
+    RateSpike {
+        kid: String,
+        hour: i64,
+        count: u64,
+        baseline: f64,
+    },
+    /// The key signed a payload type which it never signed before
+    PayloadType { kid: String, payload_type: String },
+}
+
+impl Anomaly {
+    pub fn kid(&self) -> &str {
+        match self {
+            Anomaly::RateSpike { kid, .. } | Anomaly::PayloadType { kid, .. } => kid,
+        }
+    }
+}
+
+impl Display for Anomaly {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            Anomaly::RateSpike {
+                kid,
+                count,
+                baseline,
+                ..
+            } => write!(
+                f,
+                "key '{}' signed {} transactions within an hour (baseline: {:.1} per hour)",
+                kid, count, baseline
+            ),
+            Anomaly::PayloadType { kid, payload_type } => write!(
+                f,
+                "key '{}' signed a transaction with unusual payload type: {}",
+                kid, payload_type
+            ),
+        }
+    }
+}
+
+/// Receives the anomalies which are detected while transactions are admitted (e.g. to forward them to a webhook)
+pub trait AnomalyHandler: Send + Sync {
+    fn handle(&self, anomaly: &Anomaly);
+}
+
+/// Signing history of a single key
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct KeyUsageRecord {
+    /// Number of signatures per hour (by signing time) within the window
+    pub hours: BTreeMap<i64, u64>,
+    pub payload_types: BTreeMap<String, u64>,
+    pub total: u64,
+    pub first_hour: i64,
+    /// Hour of the last rate spike, so that a spike is only reported once
+    pub alerted_hour: Option<i64>,
+}
+
+impl KeyUsageRecord {
+    /// Returns the average number of signatures per hour before the given hour
+    pub fn baseline(&self, hour: i64) -> f64 {
+        let start = self.first_hour.max(hour - WINDOW);
+        let hours = (hour - start).max(1);
+        let count: u64 = self.hours.range(start..hour).map(|(_, count)| count).sum();
+
+        count as f64 / hours as f64
+    }
+}
+
+/// Tracks how often each key signs transactions, based on the signing time of the transactions so that syncing a
+/// backlog doesn't look like a spike
+pub struct KeyUsage {
+    db: Db,
+    policy: KeyUsagePolicy,
+    metrics: Metrics,
+}
+
+impl KeyUsage {
+    pub fn open(db: Db, policy: KeyUsagePolicy) -> Result<Self> {
+        Ok(Self {
+            db,
+            policy,
+            metrics: Metrics::default(),
+        })
+    }
+
+    /// Counts the signatures and anomalies in the counters of the node
+    pub fn metrics(mut self, metrics: Metrics) -> Self {
+        self.metrics = metrics;
+        self
+    }
+
+    /// Records the signature of an admitted transaction and returns the anomalies it caused
+    pub fn observe(&self, tx: &Transaction) -> Result<Vec<Anomaly>> {
+        let tree = self.db.open_tree("nuts/key-usage")?;
+        let hour = tx.sign_at.timestamp().div_euclid(HOUR);
+        let mut record = match tree.get(&tx.key_id)? {
+            Some(value) => decode::from_read(value.as_ref())?,
+            None => KeyUsageRecord {
+                first_hour: hour,
+                ..KeyUsageRecord::default()
+            },
+        };
+        let mut anomalies = vec![];
+
+        if record.total >= self.policy.min_signatures
+            && !record.payload_types.contains_key(&tx.payload_type)
+        {
+            anomalies.push(Anomaly::PayloadType {
+                kid: tx.key_id.clone(),
+                payload_type: tx.payload_type.clone(),
+            });
+        }
+
+        let baseline = record.baseline(hour);
+        let count = record.hours.entry(hour).or_default();
+
+        *count += 1;
+
+        if hour - record.first_hour >= self.policy.min_history
+            && *count >= self.policy.min_spike
+            && *count as f64 > baseline * self.policy.spike_factor
+            && record.alerted_hour != Some(hour)
+        {
+            anomalies.push(Anomaly::RateSpike {
+                kid: tx.key_id.clone(),
+                hour: hour * HOUR,
+                count: *count,
+                baseline,
+            });
+            record.alerted_hour = Some(hour);
+        }
+
+        *record
+            .payload_types
+            .entry(tx.payload_type.clone())
+            .or_default() += 1;
+        record.total += 1;
+        record.first_hour = record.first_hour.min(hour);
+
+        if let Some(&latest) = record.hours.keys().next_back() {
+            record.hours = record.hours.split_off(&(latest - WINDOW));
+        }
+
+        tree.insert(&tx.key_id, encode::to_vec(&record)?)?;
+
+        self.metrics.increment("keys.signatures");
+
+        for anomaly in anomalies.iter() {
+            self.metrics.increment(match anomaly {
+                Anomaly::RateSpike { .. } => "keys.anomalies.rate_spike",
+                Anomaly::PayloadType { .. } => "keys.anomalies.payload_type",
+            });
+        }
+
+        Ok(anomalies)
+    }
+
+    /// Returns the signing history of all keys ordered by key ID
+    pub fn list(&self) -> Result<Vec<(String, KeyUsageRecord)>> {
+        let mut records = vec![];
+
+        for record in self.db.open_tree("nuts/key-usage")?.iter() {
+            let (kid, value) = record?;
+
+            records.push((
+                String::from_utf8_lossy(&kid).to_string(),
+                decode::from_read(value.as_ref())?,
+            ));
+        }
+
+        Ok(records)
+    }
+}
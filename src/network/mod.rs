@@ -1,9 +1,83 @@
-pub use graph::Graph;
+pub use access::{AccessPolicy, BlockedPeer, Blocklist, Cidr, PeerRule};
+pub use address_book::{AddressBook, PeerRecord};
+pub use admission::Admission;
+pub use authorize::{AuthorizePeer, PeerHandshake};
+pub use availability::PayloadFilter;
+pub use conformance::{CheckResult, Conformance};
+pub use connection_log::{ConnectionEvent, ConnectionEventKind, ConnectionLog};
+pub use diagnostics::PeerDiagnostics;
+pub use discovery::{DiscoveredPeer, DiscoveryPolicy};
+pub use export::{export, ExportFormat, ExportedTransaction};
+pub use graph::{EdgeRepair, Graph, GraphError, OrphanInfo};
+pub use groups::PeerGroups;
 pub use hash::Hash;
-pub use server::{Msg, Server};
-pub use transaction::Transaction;
+pub use health::{Health, PayloadHealth, PeerActivity, RecentError, StorageHealth, SyncState};
+pub use identities::{PeerIdentities, PeerIdentity};
+pub use intake::OverflowPolicy;
+pub use key_usage::{Anomaly, AnomalyHandler, KeyUsage, KeyUsagePolicy, KeyUsageRecord};
+pub use outbox::Outbox;
+pub use pal::PalDecrypter;
+pub use payload_store::PayloadStore;
+pub use peers::{NetworkError, TlsReloader};
+pub use retention::RetentionPolicy;
+pub use retrieval::PayloadFailures;
+pub use server::{Server, ServerOptions};
+pub use stats::{parse_period, Sample, Stats};
+pub use sync::SyncPolicy;
+pub use tls::{TlsMaterial, TlsPolicy, TlsVersion};
+pub use trace::{PeerTrace, TraceId};
+pub use transaction::{
+    validate_header, ParseError, Transaction, TransactionBuilder, ValidationError, Verification,
+    DEFAULT_MAX_CLOCK_SKEW, MAX_EXTRA_HEADERS_SIZE, VALIDATION_POLICY_VERSION,
+};
+pub use writer::PendingWrites;
 
+macro_rules! netmsg {
+    ($message: expr) => {
+        NetworkMessage {
+            message: Some($message),
+        }
+    };
+}
+
+mod access;
+mod address_book;
+mod admission;
+mod authorize;
+mod availability;
+mod blocks;
+mod certificate;
+mod checkpoint;
+mod coalesce;
+mod compression;
+mod conformance;
+mod connection_log;
+mod connections;
+mod curves;
+mod dedup;
+mod diagnostics;
+mod discovery;
+mod export;
 mod graph;
+mod groups;
+mod handshake;
 mod hash;
+mod health;
+mod identities;
+mod intake;
+mod key_usage;
+mod outbox;
+mod pal;
+mod payload_store;
+mod peers;
+mod retention;
+mod retrieval;
+mod rsa;
 mod server;
+mod service;
+mod stats;
+mod sync;
+mod tls;
+mod trace;
 mod transaction;
+mod writer;
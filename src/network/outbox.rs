@@ -0,0 +1,38 @@
+use anyhow::Result;
+use sled::Db;
+
+use crate::network::Hash;
+
+/// Transactions which were created while the node wasn't running (e.g. published using the CLI), these are
+/// announced to the peers which are connected once the node runs
+#[derive(Clone)]
+pub struct Outbox {
+    db: Db,
+}
+
+impl Outbox {
+    pub fn open(db: Db) -> Result<Self> {
+        Ok(Self { db })
+    }
+
+    pub fn push(&self, id: &Hash) -> Result<()> {
+        self.db.open_tree("nuts/outbox")?.insert(id, vec![])?;
+
+        Ok(())
+    }
+
+    /// Returns the queued transactions and removes them from the outbox
+    pub fn take(&self) -> Result<Vec<Hash>> {
+        let tree = self.db.open_tree("nuts/outbox")?;
+        let mut ids = vec![];
+
+        for key in tree.iter().keys() {
+            let key = key?;
+
+            tree.remove(&key)?;
+            ids.push(Hash::parse(key.to_vec())?);
+        }
+
+        Ok(ids)
+    }
+}
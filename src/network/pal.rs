@@ -0,0 +1,11 @@
+use anyhow::Result;
+
+use crate::network::Transaction;
+
+/// Decrypts the participant addresses (the `pal` header) of private transactions, each address is encrypted using
+/// the key agreement key of a participant as described in RFC004
+pub trait PalDecrypter: Send + Sync {
+    /// Returns the DIDs of the participants when one of the addresses can be decrypted using the keys of this
+    /// node, or nothing when this node isn't a participant
+    fn decrypt(&self, tx: &Transaction) -> Result<Option<Vec<String>>>;
+}
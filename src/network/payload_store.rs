@@ -0,0 +1,87 @@
+use std::sync::Arc;
+
+use anyhow::{anyhow, Result};
+use bytes::Bytes;
+use chrono::Utc;
+use sled::Db;
+
+use crate::backend::{self, Tree};
+use crate::network::Hash;
+
+/// Stores the payloads of transactions keyed by their SHA-256 hash, payloads which expired are remembered so that
+/// they're not retrieved again
+pub struct PayloadStore {
+    payloads: Arc<dyn Tree>,
+    expired: Arc<dyn Tree>,
+}
+
+impl PayloadStore {
+    pub fn open(db: Db) -> Result<Self> {
+        let storage = backend::open(&db)?;
+
+        Ok(Self {
+            payloads: storage.open_tree("nuts/payloads")?,
+            expired: storage.open_tree("nuts/expired-payloads")?,
+        })
+    }
+
+    pub fn get(&self, hash: &Hash) -> Result<Option<Vec<u8>>> {
+        self.payloads.get(hash.as_ref())
+    }
+
+    /// Returns the payload without copying it out of the database cache, which keeps the memory usage flat when large
+    /// payloads are served to several peers at once
+    pub fn get_shared(&self, hash: &Hash) -> Result<Option<Bytes>> {
+        self.payloads.get_shared(hash.as_ref())
+    }
+
+    pub fn count(&self) -> Result<usize> {
+        self.payloads.len()
+    }
+
+    /// Returns the hashes of all stored payloads
+    pub fn hashes(&self) -> Result<Vec<Hash>> {
+        self.payloads
+            .iter()
+            .map(|record| Hash::parse(record?.0))
+            .collect()
+    }
+
+    /// Returns the number of payloads which were dropped because they expired
+    pub fn expired_count(&self) -> Result<usize> {
+        self.expired.len()
+    }
+
+    pub fn is_expired(&self, hash: &Hash) -> Result<bool> {
+        self.expired.contains_key(hash.as_ref())
+    }
+
+    /// Drops the payload and remembers when it expired
+    pub fn expire(&self, hash: &Hash) -> Result<()> {
+        self.payloads.remove(hash.as_ref())?;
+        self.expired
+            .insert(hash.as_ref(), &Utc::now().timestamp().to_be_bytes())?;
+
+        Ok(())
+    }
+
+    pub fn contains(&self, hash: &Hash) -> Result<bool> {
+        self.payloads.contains_key(hash.as_ref())
+    }
+
+    /// Stores the payload after verifying that it matches the hash
+    pub fn add(&self, hash: &Hash, data: impl AsRef<[u8]>) -> Result<()> {
+        let data = data.as_ref();
+        let actual = Hash::new(data)?;
+
+        if &actual != hash {
+            return Err(anyhow!(
+                "payload hash mismatch (expected '{}' but got '{}')",
+                hash,
+                actual
+            ));
+        }
+
+        self.payloads.insert(hash.as_ref(), data)
+    }
+}
@@ -0,0 +1,1030 @@
+use std::collections::{HashMap, HashSet};
+use std::convert::TryFrom;
+use std::error::Error;
+use std::fmt::{Display, Formatter};
+use std::net::SocketAddr;
+use std::sync::{Arc, Mutex};
+
+use anyhow::{anyhow, Result};
+use futures::future::BoxFuture;
+use futures::{FutureExt, Stream};
+use tokio::net::TcpListener;
+use tokio::sync::broadcast::{self, error::RecvError};
+use tokio::sync::mpsc::{channel, Receiver, Sender};
+use tokio::sync::{oneshot, watch};
+use tokio::task::JoinHandle;
+use tokio::time;
+use tonic::transport::{Channel, ClientTlsConfig, Server as TransportServer, ServerTlsConfig};
+use tonic::{Code, Request, Response, Status, Streaming};
+use tracing::Instrument;
+use uuid::Uuid;
+
+use crate::metrics::Metrics;
+use crate::network::access::{PeerAccess, PeerSubject};
+use crate::network::address_book::AddressBook;
+use crate::network::authorize::authorize;
+use crate::network::certificate::PeerCertificate;
+use crate::network::compression::decompress_list;
+use crate::network::connection_log::{ConnectionEventKind, ConnectionLog};
+use crate::network::connections::{Connection, Connections};
+use crate::network::dedup;
+use crate::network::discovery::DiscoveryPolicy;
+use crate::network::handshake::{Capabilities, NodeInfo, PeerInfo};
+use crate::network::identities::PeerIdentities;
+use crate::network::intake::{Intake, IntakeSender};
+use crate::network::service::{Service, ServiceV2};
+use crate::network::tls::{check_peer_certificate, TlsMaterial, TlsPolicy};
+use crate::network::trace::PeerTrace;
+use crate::network::{AuthorizePeer, Transaction};
+use crate::proto::model::{self, Message, TransactionList, TransactionListQuery};
+use crate::proto::v2::{
+    protocol_client::ProtocolClient, protocol_server::ProtocolServer, Envelope,
+};
+use crate::proto::{
+    network_client::NetworkClient, network_message, network_server::NetworkServer, NetworkMessage,
+};
+use crate::retry::RetryPolicy;
+
+/// Errors which occur when listening for or connecting to peers
+#[derive(Debug)]
+pub enum NetworkError {
+    /// Unable to listen on the given address or socket
+    Listen(std::io::Error),
+    /// The (TLS) connection with the peer couldn't be established
+    Transport(tonic::transport::Error),
+    /// The peer rejected the connection request with the given status code and message
+    Rejected(Code, String),
+    /// The metadata of the peer is invalid or incompatible (e.g. an unsupported protocol version or another network)
+    Handshake(String),
+    /// The peer was rejected by the authorizer
+    Unauthorized(String),
+    /// Another connection with the peer is kept instead
+    Duplicate,
+    Other(anyhow::Error),
+}
+
+impl NetworkError {
+    /// Whether this or the other node rejected the connection as there's already a connection between them
+    pub fn is_duplicate(&self) -> bool {
+        matches!(
+            self,
+            NetworkError::Duplicate | NetworkError::Rejected(Code::AlreadyExists, _)
+        )
+    }
+}
+
+impl Display for NetworkError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            NetworkError::Listen(e) => write!(f, "unable to listen: {}", e),
+            NetworkError::Transport(e) => {
+                write!(f, "{}", e)?;
+
+                // The transport error itself doesn't contain the reason (e.g. connection refused)
+                let mut source = e.source();
+
+                while let Some(e) = source {
+                    write!(f, ": {}", e)?;
+                    source = e.source();
+                }
+
+                Ok(())
+            }
+            NetworkError::Rejected(code, message) => write!(
+                f,
+                "connection rejected by peer ({:?}): {}",
+                code, message
+            ),
+            NetworkError::Handshake(message) => write!(f, "handshake failed: {}", message),
+            NetworkError::Unauthorized(message) => write!(f, "peer not authorized: {}", message),
+            NetworkError::Duplicate => write!(f, "already connected to peer"),
+            NetworkError::Other(e) => write!(f, "{}", e),
+        }
+    }
+}
+
+impl Error for NetworkError {}
+
+impl From<anyhow::Error> for NetworkError {
+    fn from(e: anyhow::Error) -> Self {
+        NetworkError::Other(e)
+    }
+}
+
+impl From<std::io::Error> for NetworkError {
+    fn from(e: std::io::Error) -> Self {
+        NetworkError::Listen(e)
+    }
+}
+
+impl From<tonic::transport::Error> for NetworkError {
+    fn from(e: tonic::transport::Error) -> Self {
+        NetworkError::Transport(e)
+    }
+}
+
+impl From<Status> for NetworkError {
+    fn from(status: Status) -> Self {
+        NetworkError::Rejected(status.code(), status.message().to_string())
+    }
+}
+
+#[derive(Debug)]
+pub struct Msg {
+    pub(super) peer_id: Uuid,
+    pub(super) capabilities: Capabilities,
+    pub(super) message: Message,
+    /// Digest of the message as it was received, used to handle identical messages only once
+    pub(super) digest: [u8; 32],
+    /// Outbound channel of the connection the message was received on which can be used to reply
+    pub(super) outbound: Sender<NetworkMessage>,
+    pub(super) trace: PeerTrace,
+}
+
+/// Message received from a peer which uses version 2 of the protocol
+#[derive(Debug)]
+pub struct MsgV2 {
+    pub(super) peer_id: Uuid,
+    /// Empty when the connection was just established
+    pub(super) message: Option<model::v2::Message>,
+    /// Outbound channel of the connection the message was received on which can be used to reply
+    pub(super) outbound: Sender<Envelope>,
+    pub(super) trace: PeerTrace,
+}
+
+/// Outbound channel of a connection with a peer
+#[derive(Debug, Clone)]
+pub enum Outbound {
+    V1(Sender<NetworkMessage>),
+    V2(Sender<Envelope>),
+}
+
+impl Outbound {
+    pub fn is_closed(&self) -> bool {
+        match self {
+            Outbound::V1(outbound) => outbound.is_closed(),
+            Outbound::V2(outbound) => outbound.is_closed(),
+        }
+    }
+}
+
+/// Stream of messages which is sent to a peer after the connection has been established, transactions which are
+/// added to the DAG are pushed to the peer as soon as they're added (gossip), the stream ends when the node is
+/// shutting down or the stop sender is dropped
+pub(super) fn outbound_stream(
+    mut rx: Receiver<NetworkMessage>,
+    mut added: broadcast::Receiver<Transaction>,
+    mut closing: watch::Receiver<bool>,
+    mut stop: oneshot::Receiver<()>,
+) -> impl Stream<Item = NetworkMessage> {
+    async_stream::stream! {
+        // Initially, ask for the complete transaction list
+        yield Message::TransactionListQuery(TransactionListQuery {
+            block_date: 0,
+            filter: None,
+        }).into();
+
+        loop {
+            let message = tokio::select! {
+                message = rx.recv() => match message {
+                    Some(message) => message,
+                    None => break,
+                },
+                tx = added.recv() => match tx {
+                    Ok(tx) => Message::TransactionList(TransactionList {
+                        block_date: 0,
+                        transactions: vec![tx.into()],
+                        message_number: 0,
+                        total_messages: 0,
+                    }).into(),
+                    Err(RecvError::Lagged(skipped)) => {
+                        tracing::warn!(target: "nuts::network", "unable to push {} transactions to peer, these will be synced instead", skipped);
+                        continue;
+                    }
+                    Err(RecvError::Closed) => break,
+                },
+                _ = closing.changed() => break,
+                _ = &mut stop => break,
+            };
+
+            yield message;
+        }
+    }
+}
+
+/// Stream of envelopes which is sent to a peer using version 2 of the protocol, all messages (including gossip) are
+/// sent by the server as they depend on it's state
+pub(super) fn outbound_stream_v2(
+    mut rx: Receiver<Envelope>,
+    mut closing: watch::Receiver<bool>,
+    mut stop: oneshot::Receiver<()>,
+) -> impl Stream<Item = Envelope> {
+    async_stream::stream! {
+        loop {
+            tokio::select! {
+                envelope = rx.recv() => match envelope {
+                    Some(envelope) => yield envelope,
+                    None => break,
+                },
+                _ = closing.changed() => break,
+                _ = &mut stop => break,
+            }
+        }
+    }
+}
+
+/// Receives envelopes from a peer using version 2 of the protocol and forwards them to the server until the stream
+/// is closed, the server is notified of the connection first so that it can start the conversation. Returns the
+/// reason the connection was closed
+pub(super) async fn receive_envelopes(
+    peer_id: Uuid,
+    mut stream: Streaming<Envelope>,
+    tx: IntakeSender<MsgV2>,
+    outbound: Sender<Envelope>,
+    mut connection: Connection,
+    trace: PeerTrace,
+) -> String {
+    let mut message = None;
+
+    loop {
+        if let Err(e) = tx.send(MsgV2 {
+            peer_id,
+            message,
+            outbound: outbound.clone(),
+            trace: trace.clone(),
+        }) {
+            tracing::warn!(target: "nuts::network", "closing connection with peer '{}': {}", peer_id, e);
+            return e.to_string();
+        }
+
+        message = loop {
+            let received = tokio::select! {
+                received = stream.message() => received,
+                _ = connection.replaced() => return "replaced by another connection".to_string(),
+            };
+
+            match received {
+                Ok(Some(Envelope {
+                    message: Some(message),
+                })) => match model::v2::Message::try_from(message) {
+                    Ok(message) => break Some(message),
+                    Err(e) => {
+                        tracing::warn!(target: "nuts::network", "ignoring invalid message from peer '{}': {}", peer_id, e);
+                        continue;
+                    }
+                },
+                Ok(Some(_)) => continue,
+                Ok(None) => {
+                    tracing::info!(target: "nuts::network", "connection closed by peer: {}", peer_id);
+                    return "connection closed by peer".to_string();
+                }
+                Err(e) => {
+                    tracing::error!(target: "nuts::network", "failed to receive message for peer '{}': {}", peer_id, e);
+                    return format!("failed to receive message: {}", e.message());
+                }
+            }
+        };
+    }
+}
+
+/// Converts a received message into it's model, compressed transaction lists are decompressed first
+fn to_model(message: network_message::Message) -> Result<Message> {
+    Message::try_from(match message {
+        network_message::Message::TransactionList(list) => {
+            network_message::Message::TransactionList(decompress_list(list)?)
+        }
+        message => message,
+    })
+}
+
+/// Receives messages from a peer and forwards them to the server until the stream is closed or the connection is
+/// replaced, returns the reason the connection was closed
+pub(super) async fn receive_messages(
+    peer_id: Uuid,
+    capabilities: Capabilities,
+    mut stream: Streaming<NetworkMessage>,
+    tx: IntakeSender<Msg>,
+    outbound: Sender<NetworkMessage>,
+    mut connection: Connection,
+    trace: PeerTrace,
+) -> String {
+    loop {
+        let received = tokio::select! {
+            received = stream.message() => received,
+            _ = connection.replaced() => return "replaced by another connection".to_string(),
+        };
+
+        match received {
+            Ok(Some(network_message)) => {
+                let digest = dedup::digest(&network_message);
+
+                if let Some(message) = network_message.message {
+                    let message = match to_model(message) {
+                        Ok(message) => message,
+                        Err(e) => {
+                            tracing::warn!(target: "nuts::network", "ignoring invalid message from peer '{}': {}", peer_id, e);
+                            continue;
+                        }
+                    };
+                    let msg = Msg {
+                        peer_id,
+                        capabilities,
+                        message,
+                        digest,
+                        outbound: outbound.clone(),
+                        trace: trace.clone(),
+                    };
+
+                    if let Err(e) = tx.send(msg) {
+                        tracing::warn!(target: "nuts::network", "closing connection with peer '{}': {}", peer_id, e);
+                        return e.to_string();
+                    }
+                }
+            }
+            Ok(None) => {
+                tracing::info!(target: "nuts::network", "connection closed by peer: {}", peer_id);
+                return "connection closed by peer".to_string();
+            }
+            Err(e) => {
+                tracing::error!(target: "nuts::network", "failed to receive message for peer '{}': {}", peer_id, e);
+                return format!("failed to receive message: {}", e.message());
+            }
+        }
+    }
+}
+
+/// Configuration of the listener and outbound connections which is built from the TLS material and policy
+#[derive(Clone)]
+struct TlsConfigs {
+    client: rustls::ClientConfig,
+    server: rustls::ServerConfig,
+}
+
+impl TlsConfigs {
+    fn new(policy: &TlsPolicy, material: &TlsMaterial) -> Result<Self> {
+        Ok(Self {
+            client: policy.client_config(material)?,
+            server: policy.server_config(material)?,
+        })
+    }
+}
+
+/// Replaces the TLS material of a running node (e.g. after a certificate rotation), it's used by the listener and
+/// new outbound connections while connections which are already established are kept open
+#[derive(Clone)]
+pub struct TlsReloader {
+    policy: TlsPolicy,
+    configs: Arc<watch::Sender<TlsConfigs>>,
+}
+
+impl TlsReloader {
+    /// Fails when the material can't be used in which case the previous material is kept, returns false when the
+    /// node no longer exists
+    pub fn reload(&self, material: &TlsMaterial) -> Result<bool> {
+        let configs = TlsConfigs::new(&self.policy, material)?;
+
+        Ok(self.configs.send(configs).is_ok())
+    }
+}
+
+/// Manages the connections with other peers, both incoming and outgoing, and forwards all received messages to
+/// the server
+#[derive(Clone)]
+pub struct PeerManager {
+    strict: bool,
+    node: NodeInfo,
+    tls_policy: TlsPolicy,
+    tls: watch::Receiver<TlsConfigs>,
+    tls_reload: Arc<watch::Sender<TlsConfigs>>,
+    reconnect: RetryPolicy,
+    address_book: AddressBook,
+    connection_log: ConnectionLog,
+    identities: PeerIdentities,
+    channel_capacity: usize,
+    intake: Intake<Msg>,
+    intake_v2: Intake<MsgV2>,
+    added: broadcast::Sender<Transaction>,
+    access: PeerAccess,
+    connections: Connections,
+    /// Addresses of the peers which are connected to (or being reconnected to) in the background
+    bootstrapped: Arc<Mutex<HashSet<String>>>,
+    /// DNS names the certificates of discovered peers are verified against, by the IP address they're connected to
+    domains: Arc<Mutex<HashMap<String, String>>>,
+    authorizer: Option<Arc<dyn AuthorizePeer>>,
+    close: Arc<watch::Sender<bool>>,
+    closing: watch::Receiver<bool>,
+    metrics: Metrics,
+}
+
+impl PeerManager {
+    #[allow(clippy::too_many_arguments)]
+    pub fn new(
+        strict: bool,
+        node: NodeInfo,
+        tls_policy: TlsPolicy,
+        tls_material: &TlsMaterial,
+        reconnect: RetryPolicy,
+        address_book: AddressBook,
+        connection_log: ConnectionLog,
+        identities: PeerIdentities,
+        channel_capacity: usize,
+        intake: Intake<Msg>,
+        intake_v2: Intake<MsgV2>,
+        added: broadcast::Sender<Transaction>,
+        access: PeerAccess,
+        authorizer: Option<Arc<dyn AuthorizePeer>>,
+        metrics: Metrics,
+    ) -> Result<Self> {
+        let (close, closing) = watch::channel(false);
+        let (tls_reload, tls) = watch::channel(TlsConfigs::new(&tls_policy, tls_material)?);
+
+        Ok(Self {
+            strict,
+            connections: Connections::new(node.peer_id),
+            bootstrapped: Arc::new(Mutex::new(HashSet::new())),
+            domains: Arc::new(Mutex::new(HashMap::new())),
+            node,
+            tls_policy,
+            tls,
+            tls_reload: Arc::new(tls_reload),
+            reconnect,
+            address_book,
+            connection_log,
+            identities,
+            channel_capacity,
+            intake,
+            intake_v2,
+            added,
+            access,
+            authorizer,
+            close: Arc::new(close),
+            closing,
+            metrics,
+        })
+    }
+
+    /// Stops accepting connections, closes the streams to all peers and stops reconnecting
+    pub fn close(&self) {
+        // Sending only fails when there are no receivers
+        let _ = self.close.send(true);
+    }
+
+    fn is_closing(&self) -> bool {
+        *self.closing.borrow()
+    }
+
+    /// Returns a handle to replace the TLS material while the node is running
+    pub fn tls_reloader(&self) -> TlsReloader {
+        TlsReloader {
+            policy: self.tls_policy.clone(),
+            configs: self.tls_reload.clone(),
+        }
+    }
+
+    /// Connects to the peer using mTLS with the current material and returns the certificate of the peer, which is
+    /// checked against the access rules and captured during the handshake as the transport doesn't expose it
+    async fn channel(
+        &self,
+        addr: String,
+        subject: PeerSubject,
+    ) -> Result<(Channel, Vec<u8>), NetworkError> {
+        let access = self.access.clone();
+        let metrics = self.metrics.clone();
+        let presented = Arc::new(Mutex::new(None));
+        let captured = presented.clone();
+        let client = check_peer_certificate(&self.tls.borrow().client, move |certificate| {
+            let peer = subject.clone().with_certificate(certificate)?;
+
+            access.check(&peer).map_err(|e| {
+                metrics.increment("peers.blocked");
+                e
+            })?;
+
+            *captured.lock().unwrap() = Some(certificate.to_vec());
+
+            Ok(())
+        });
+        let mut tls = ClientTlsConfig::new().rustls_client_config(client);
+
+        if let Some(domain) = self.domains.lock().unwrap().get(&addr) {
+            tls = tls.domain_name(domain);
+        }
+
+        let channel = Channel::from_shared(addr.into_bytes())
+            .map_err(|e| anyhow!("invalid peer address: {}", e))?
+            .tls_config(tls)?
+            .connect()
+            .await?;
+        let certificate = presented
+            .lock()
+            .unwrap()
+            .take()
+            .ok_or_else(|| anyhow!("peer didn't present a certificate"))?;
+
+        Ok((channel, certificate))
+    }
+
+    /// Binds the peer ID to the subject of the server certificate, like the peer IDs of incoming connections are bound
+    /// to their client certificate
+    fn bind_identity(&self, peer_id: Uuid, certificate: &[u8]) -> Result<(), NetworkError> {
+        if let Err(e) = PeerCertificate::parse(certificate)
+            .and_then(|certificate| self.identities.bind(peer_id, &certificate))
+        {
+            tracing::info!(target: "nuts::network", "closing connection to peer '{}' due to it's certificate: {}", peer_id, e);
+
+            return Err(NetworkError::Unauthorized(e.to_string()));
+        }
+
+        Ok(())
+    }
+
+    fn new_request<T>(&self, version: &'static str, body: T) -> Result<Request<T>> {
+        let mut request = Request::new(body);
+
+        self.node.set_metadata(version, request.metadata_mut())?;
+
+        Ok(request)
+    }
+
+    /// Starts accepting incoming connections from other peers on the given address
+    pub fn listen(&self, addr: SocketAddr) -> Result<(), NetworkError> {
+        self.listen_on(std::net::TcpListener::bind(addr)?)
+    }
+
+    /// Starts accepting incoming connections from other peers on an already bound socket, the listener is restarted
+    /// on the same socket whenever the TLS material is replaced
+    pub fn listen_on(&self, listener: std::net::TcpListener) -> Result<(), NetworkError> {
+        listener.set_nonblocking(true)?;
+
+        let listener = Arc::new(TcpListener::from_std(listener)?);
+        let configs = self.tls.borrow().clone();
+        let mut stop = self.serve(&configs, listener.clone())?;
+
+        tracing::info!(target: "nuts::network", "listening on {}", listener.local_addr()?);
+
+        let peers = self.clone();
+
+        tokio::spawn(async move {
+            let mut tls = peers.tls.clone();
+            let mut closing = peers.closing.clone();
+
+            loop {
+                tokio::select! {
+                    changed = tls.changed() => if changed.is_err() {
+                        break;
+                    },
+                    _ = closing.changed() => break,
+                }
+
+                let configs = tls.borrow().clone();
+
+                match peers.serve(&configs, listener.clone()) {
+                    Ok(next) => {
+                        // The previous listener stops accepting connections but keeps serving the ones it accepted
+                        let _ = std::mem::replace(&mut stop, next).send(());
+
+                        tracing::info!(target: "nuts::network", "reloaded TLS material of the listener");
+                    }
+                    Err(e) => {
+                        tracing::error!(target: "nuts::network", "failed to reload TLS material, the listener keeps using the previous one: {}", e);
+                    }
+                }
+            }
+        });
+
+        Ok(())
+    }
+
+    /// Serves incoming connections on the listener using the given TLS configuration until the node is closing or the
+    /// returned sender is used (or dropped), connections which were already accepted are served until they're closed
+    fn serve(
+        &self,
+        configs: &TlsConfigs,
+        listener: Arc<TcpListener>,
+    ) -> Result<oneshot::Sender<()>, NetworkError> {
+        let tls = ServerTlsConfig::new().rustls_server_config(configs.server.clone());
+        let router = TransportServer::builder()
+            .tls_config(tls)?
+            .add_service(NetworkServer::new(Service::new(
+                self.strict,
+                self.node.clone(),
+                self.access.clone(),
+                self.connections.clone(),
+                self.channel_capacity,
+                self.intake.clone(),
+                self.added.clone(),
+                self.closing.clone(),
+                self.authorizer.clone(),
+                self.connection_log.clone(),
+                self.identities.clone(),
+                self.metrics.clone(),
+            )))
+            .add_service(ProtocolServer::new(ServiceV2::new(
+                self.strict,
+                self.node.clone(),
+                self.access.clone(),
+                self.connections.clone(),
+                self.channel_capacity,
+                self.intake_v2.clone(),
+                self.closing.clone(),
+                self.authorizer.clone(),
+                self.connection_log.clone(),
+                self.identities.clone(),
+                self.metrics.clone(),
+            )));
+        let (stop, stopped) = oneshot::channel();
+        let mut closing = self.closing.clone();
+
+        tokio::spawn(async move {
+            let incoming = async_stream::stream! {
+                loop {
+                    yield listener.accept().await.map(|(stream, _)| stream);
+                }
+            };
+
+            let shutdown = async move {
+                tokio::select! {
+                    _ = closing.changed() => {},
+                    _ = stopped => {},
+                }
+            };
+
+            if let Err(e) = router
+                .serve_with_incoming_shutdown(incoming, shutdown)
+                .await
+            {
+                tracing::error!(target: "nuts::network", "failed to serve incoming connections: {}", e);
+            }
+        });
+
+        Ok(stop)
+    }
+
+    /// Connects to a peer using version 2 of the protocol, returns nothing when the peer only supports version 1
+    async fn connect_v2(
+        &self,
+        transport: Channel,
+        certificate: &[u8],
+        trace: &PeerTrace,
+    ) -> Result<Option<(PeerInfo, BoxFuture<'static, String>)>, NetworkError> {
+        let (outbound, outbound_rx) = channel(self.channel_capacity);
+        let (stop, stopped) = oneshot::channel();
+        let request = self.new_request(
+            "2",
+            outbound_stream_v2(outbound_rx, self.closing.clone(), stopped),
+        )?;
+
+        let response = match ProtocolClient::new(transport).stream(request).await {
+            Ok(response) => response,
+            Err(status) if status.code() == Code::Unimplemented => return Ok(None),
+            Err(e) => return Err(e.into()),
+        };
+        let info = self
+            .node
+            .parse_metadata(self.strict, response.metadata())
+            .map_err(|e| NetworkError::Handshake(e.to_string()))?;
+        let PeerInfo {
+            peer_id,
+            version,
+            did,
+            ..
+        } = info.clone();
+
+        trace.identify(peer_id);
+
+        if version != "2" {
+            return Err(NetworkError::Handshake(format!(
+                "peer responded with protocol version: {}",
+                version
+            )));
+        }
+
+        self.bind_identity(peer_id, certificate)?;
+
+        let handshake = info.handshake(Some(certificate.to_vec()), false);
+
+        authorize(&self.authorizer, handshake, &self.metrics)
+            .await
+            .map_err(|e| NetworkError::Unauthorized(e.to_string()))?;
+
+        let connection = self
+            .connections
+            .register(peer_id, false, stop)
+            .ok_or(NetworkError::Duplicate)?;
+
+        tracing::info!(target: "nuts::network", "connected to peer: {} (DID: {}, protocol version: 2)", peer_id, did.as_deref().unwrap_or("unknown"));
+
+        Ok(Some((
+            info,
+            receive_envelopes(
+                peer_id,
+                response.into_inner(),
+                self.intake_v2.sender(),
+                outbound,
+                connection,
+                trace.clone(),
+            )
+            .boxed(),
+        )))
+    }
+
+    async fn connect_v1(
+        &self,
+        transport: Channel,
+        certificate: &[u8],
+        trace: &PeerTrace,
+    ) -> Result<(PeerInfo, BoxFuture<'static, String>), NetworkError> {
+        let (outbound, outbound_rx) = channel(self.channel_capacity);
+        let (stop, stopped) = oneshot::channel();
+
+        // Create the initial connection request
+        let request = self.new_request(
+            "1",
+            outbound_stream(
+                outbound_rx,
+                self.added.subscribe(),
+                self.closing.clone(),
+                stopped,
+            ),
+        )?;
+
+        // Connect to the peer, get it's peer ID and start the message loop in a task
+        let response: Response<_> = NetworkClient::new(transport)
+            .connect_method(request)
+            .await?;
+        let info = self
+            .node
+            .parse_metadata(self.strict, response.metadata())
+            .map_err(|e| NetworkError::Handshake(e.to_string()))?;
+        let PeerInfo {
+            peer_id,
+            version,
+            did,
+            capabilities,
+            ..
+        } = info.clone();
+
+        trace.identify(peer_id);
+
+        if version != "1" {
+            tracing::info!(target: "nuts::network", "closing connection to peer '{}' due to invalid protocol version: {}", peer_id, version);
+
+            return Err(NetworkError::Handshake(format!(
+                "invalid protocol version: {}",
+                version
+            )));
+        }
+
+        self.bind_identity(peer_id, certificate)?;
+
+        let handshake = info.handshake(Some(certificate.to_vec()), false);
+
+        authorize(&self.authorizer, handshake, &self.metrics)
+            .await
+            .map_err(|e| NetworkError::Unauthorized(e.to_string()))?;
+
+        let connection = self
+            .connections
+            .register(peer_id, false, stop)
+            .ok_or(NetworkError::Duplicate)?;
+
+        tracing::info!(target: "nuts::network", "connected to peer: {} (DID: {}, protocol version: 1)", peer_id, did.as_deref().unwrap_or("unknown"));
+
+        Ok((
+            info,
+            receive_messages(
+                peer_id,
+                capabilities,
+                response.into_inner(),
+                self.intake.sender(),
+                outbound,
+                connection,
+                trace.clone(),
+            )
+            .boxed(),
+        ))
+    }
+
+    /// Connects to a peer and returns the handle of the task receiving it's messages, which completes when the
+    /// connection is lost. Version 2 of the protocol is preferred, peers which don't implement it are connected to
+    /// using version 1
+    pub async fn connect(&self, addr: String) -> Result<JoinHandle<()>, NetworkError> {
+        let trace = PeerTrace::new(false, Some(&addr));
+
+        self.dial(addr, trace.clone())
+            .instrument(trace.span())
+            .await
+    }
+
+    async fn dial(&self, addr: String, trace: PeerTrace) -> Result<JoinHandle<()>, NetworkError> {
+        let subject = PeerSubject::outbound(&addr).await;
+
+        if let Err(e) = self.access.check(&subject) {
+            tracing::info!(target: "nuts::network", "not connecting to {}: {}", addr, e);
+            self.metrics.increment("peers.blocked");
+
+            return Err(NetworkError::Unauthorized(e.to_string()));
+        }
+
+        tracing::info!(target: "nuts::network", "connecting to {}..", addr);
+
+        self.connection_log
+            .log(&addr, ConnectionEventKind::Dial, Some(&addr), "");
+
+        let (transport, certificate) = match self.channel(addr.clone(), subject).await {
+            Ok(connected) => connected,
+            Err(e) => {
+                self.connection_log.log(
+                    &addr,
+                    ConnectionEventKind::TlsFailed,
+                    Some(&addr),
+                    trace.annotate(&e),
+                );
+
+                return Err(e);
+            }
+        };
+
+        self.connection_log
+            .log(&addr, ConnectionEventKind::TlsEstablished, Some(&addr), "");
+
+        let connected = match self
+            .connect_v2(transport.clone(), &certificate, &trace)
+            .await
+        {
+            Ok(Some(connected)) => Ok(connected),
+            Ok(None) => {
+                tracing::debug!(target: "nuts::network", "peer '{}' doesn't support protocol version 2, falling back to version 1", addr);
+
+                self.connect_v1(transport, &certificate, &trace).await
+            }
+            Err(e) => Err(e),
+        };
+        let (info, receive) = match connected {
+            Ok(connected) => connected,
+            Err(e) => {
+                self.connection_log.log(
+                    &addr,
+                    ConnectionEventKind::HandshakeFailed,
+                    Some(&addr),
+                    trace.annotate(&e),
+                );
+
+                return Err(e);
+            }
+        };
+        let peer_id = info.peer_id;
+        let address_book = self.address_book.clone();
+        let connection_log = self.connection_log.clone();
+
+        connection_log.log(
+            &peer_id.to_string(),
+            ConnectionEventKind::Handshake,
+            Some(&addr),
+            info.describe(),
+        );
+        address_book.record(&addr, peer_id)?;
+
+        Ok(tokio::spawn(
+            async move {
+                let reason = receive.await;
+
+                connection_log.log(
+                    &peer_id.to_string(),
+                    ConnectionEventKind::Disconnect,
+                    Some(&addr),
+                    trace.annotate(reason),
+                );
+
+                // Remember when the peer was last seen
+                if let Err(e) = address_book.record(&addr, peer_id) {
+                    tracing::error!(target: "nuts::network", "failed to update address book for peer '{}': {}", peer_id, e);
+                }
+            }
+            .in_current_span(),
+        ))
+    }
+
+    /// Connects to a peer in the background and reconnects using exponential backoff whenever the peer is
+    /// unreachable or the connection is lost, addresses which are already connected to are ignored
+    pub fn bootstrap(&self, addr: String) {
+        if !self.bootstrapped.lock().unwrap().insert(addr.clone()) {
+            return;
+        }
+
+        let peers = self.clone();
+
+        tokio::spawn(async move {
+            let mut backoff = peers.reconnect.backoff("peer_reconnect", &peers.metrics);
+            let mut closing = peers.closing.clone();
+
+            while !peers.is_closing() {
+                // Subscribed before connecting so that a connection which closes in the meantime isn't missed
+                let mut closed = peers.connections.subscribe_closed();
+                let delay = match peers.connect(addr.clone()).await {
+                    Ok(handle) => {
+                        backoff.reset();
+
+                        // Wait for the connection to break before reconnecting
+                        if let Err(e) = handle.await {
+                            tracing::error!(target: "nuts::network", "message loop for peer '{}' failed: {}", addr, e);
+                        }
+
+                        if peers.is_closing() {
+                            break;
+                        }
+
+                        let delay = backoff.next_delay().unwrap_or_default();
+
+                        tracing::warn!(target: "nuts::network", "lost connection to peer '{}' (reconnecting in {}ms)", addr, delay.as_millis());
+
+                        delay
+                    }
+                    Err(e) if e.is_duplicate() => {
+                        tracing::debug!(target: "nuts::network", "already connected to peer '{}', connecting again when a connection closes", addr);
+
+                        backoff.reset();
+
+                        tokio::select! {
+                            _ = closed.changed() => continue,
+                            _ = closing.changed() => break,
+                        }
+                    }
+                    Err(e) => {
+                        let delay = match backoff.next_delay() {
+                            Some(delay) => delay,
+                            None => {
+                                tracing::error!(target: "nuts::network", "giving up on peer '{}' after {} attempts: {}", addr, backoff.attempts() + 1, e);
+                                break;
+                            }
+                        };
+
+                        tracing::warn!(target: "nuts::network", "failed to connect to peer '{}' (retrying in {}ms): {}", addr, delay.as_millis(), e);
+
+                        delay
+                    }
+                };
+
+                tokio::select! {
+                    _ = time::sleep(delay) => {},
+                    _ = closing.changed() => break,
+                }
+            }
+
+            // Allows the peer to be discovered again after giving up on it
+            peers.bootstrapped.lock().unwrap().remove(&addr);
+        });
+    }
+
+    /// Resolves the DNS name of the discovery policy on an interval and connects to the discovered peers until the
+    /// target number of peers is connected, the peers are added to the address book once connected
+    pub fn discover(&self, policy: DiscoveryPolicy) {
+        let peers = self.clone();
+
+        tokio::spawn(async move {
+            let mut interval = time::interval(policy.interval);
+            let mut closing = peers.closing.clone();
+
+            loop {
+                tokio::select! {
+                    _ = interval.tick() => {},
+                    _ = closing.changed() => break,
+                }
+
+                let discovered = match policy.resolve().await {
+                    Ok(discovered) => discovered,
+                    Err(e) => {
+                        tracing::warn!(target: "nuts::network", "failed to discover peers using '{}': {}", policy.name, e);
+                        continue;
+                    }
+                };
+
+                peers.metrics.increment("discovery.resolved");
+
+                for peer in discovered {
+                    // Peers which are still being connected to count towards the target as well
+                    if peers.connections.len() >= policy.target_peers
+                        || peers.bootstrapped.lock().unwrap().len() >= policy.target_peers
+                    {
+                        break;
+                    }
+
+                    if let Some(domain) = peer.domain {
+                        peers
+                            .domains
+                            .lock()
+                            .unwrap()
+                            .insert(peer.addr.clone(), domain);
+                    }
+
+                    if !peers.bootstrapped.lock().unwrap().contains(&peer.addr) {
+                        tracing::info!(target: "nuts::network", "discovered peer: {}", peer.addr);
+
+                        peers.metrics.increment("discovery.peers");
+                        peers.bootstrap(peer.addr);
+                    }
+                }
+            }
+        });
+    }
+}
@@ -0,0 +1,32 @@
+use std::collections::HashMap;
+use std::time::Duration;
+
+use chrono::NaiveDateTime;
+
+/// Interval at which expired payloads are dropped
+pub const GC_INTERVAL: Duration = Duration::from_secs(60 * 60);
+
+/// How long the payloads of each payload type are kept (e.g. 90 days for bulk data), payloads of other payload types
+/// are kept forever. Only the payload is dropped when it expires, the transaction itself is always kept
+#[derive(Debug, Clone, Default)]
+pub struct RetentionPolicy {
+    pub max_age: HashMap<String, Duration>,
+}
+
+impl RetentionPolicy {
+    /// Whether payloads of all payload types are kept forever
+    pub fn is_empty(&self) -> bool {
+        self.max_age.is_empty()
+    }
+
+    /// Whether the payload of a transaction of the given payload type which was signed at the given time expired
+    pub fn is_expired(&self, payload_type: &str, sign_at: NaiveDateTime, now: NaiveDateTime) -> bool {
+        match self.max_age.get(payload_type) {
+            Some(max_age) => (now - sign_at)
+                .to_std()
+                .map(|age| age > *max_age)
+                .unwrap_or(false),
+            None => false,
+        }
+    }
+}
@@ -0,0 +1,226 @@
+use std::collections::{HashMap, HashSet};
+
+use chrono::Utc;
+use serde::{Deserialize, Serialize};
+use tokio::time::Instant;
+use uuid::Uuid;
+
+use crate::metrics::Metrics;
+use crate::network::Hash;
+use crate::retry::{Backoff, RetryPolicy};
+
+/// Why a peer didn't serve a payload it was queried for
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PayloadFailure {
+    /// The peer responded with an empty payload
+    Missing,
+    /// The content of the payload doesn't match it's hash
+    Invalid,
+    /// The peer didn't respond before the payload was queried again
+    Timeout,
+}
+
+/// Number of payloads a peer failed to serve
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct PayloadFailures {
+    pub peer_id: String,
+    pub missing: u64,
+    pub invalid: u64,
+    pub timeouts: u64,
+    pub last_failure_at: i64,
+}
+
+impl PayloadFailures {
+    pub fn total(&self) -> u64 {
+        self.missing + self.invalid + self.timeouts
+    }
+}
+
+/// Payload which is being retrieved
+struct Pending {
+    backoff: Backoff,
+    retry_at: Instant,
+    /// Peer which was queried last and didn't respond yet
+    queried: Option<Uuid>,
+    /// Peers which were queried in the current round, a new round starts once all peers were queried
+    tried: HashSet<Uuid>,
+}
+
+/// Keeps track of the transactions whose payloads are missing and decides which peer to query them from next. Each
+/// attempt queries a single peer, taking turns between the peers which might hold the payload, and the peer is
+/// recorded as failed when it doesn't serve the payload
+pub struct PayloadRetrieval {
+    policy: RetryPolicy,
+    pending: HashMap<Hash, Pending>,
+    failures: HashMap<Uuid, PayloadFailures>,
+    metrics: Metrics,
+}
+
+impl PayloadRetrieval {
+    pub fn new(policy: RetryPolicy, metrics: Metrics) -> Self {
+        Self {
+            policy,
+            pending: HashMap::new(),
+            failures: HashMap::new(),
+            metrics,
+        }
+    }
+
+    /// Starts retrieving the payload from the peer, returns false when it's already being retrieved
+    pub fn track(&mut self, hash: Hash, peer_id: Uuid) -> bool {
+        if self.pending.contains_key(&hash) {
+            return false;
+        }
+
+        self.pending.insert(
+            hash,
+            Pending {
+                backoff: self.policy.backoff("payload_fetch", &self.metrics),
+                retry_at: Instant::now() + self.policy.interval,
+                queried: Some(peer_id),
+                tried: vec![peer_id].into_iter().collect(),
+            },
+        );
+
+        true
+    }
+
+    /// Number of payloads which are being retrieved
+    pub fn len(&self) -> usize {
+        self.pending.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.pending.is_empty()
+    }
+
+    /// Moment at which the next payload should be queried again
+    pub fn next_deadline(&self) -> Option<Instant> {
+        self.pending.values().map(|pending| pending.retry_at).min()
+    }
+
+    /// Returns the payloads which should be queried again, the peers which didn't respond in time are recorded as
+    /// failed
+    pub fn due(&mut self, now: Instant) -> Vec<Hash> {
+        let mut due = vec![];
+        let mut timeouts = vec![];
+
+        for (hash, pending) in self.pending.iter_mut() {
+            if pending.retry_at > now {
+                continue;
+            }
+
+            if let Some(peer_id) = pending.queried.take() {
+                timeouts.push(peer_id);
+            }
+
+            due.push(hash.clone());
+        }
+
+        for peer_id in timeouts {
+            self.record(peer_id, PayloadFailure::Timeout);
+        }
+
+        due
+    }
+
+    /// Picks the peer to query the payload from next out of the peers which might hold it, peers which were already
+    /// queried in this round and peers which failed more often are skipped. Returns none when the retry policy is
+    /// exhausted or no peer is left, in which case the payload is no longer retrieved
+    pub fn next_source(&mut self, hash: &Hash, candidates: &[Uuid], now: Instant) -> Option<Uuid> {
+        let pending = self.pending.get_mut(hash)?;
+        let delay = match pending.backoff.next_delay() {
+            Some(delay) => delay,
+            None => {
+                tracing::warn!(target: "nuts::network", "giving up on payload '{}' after {} attempts", hash, pending.backoff.attempts() + 1);
+                self.metrics.increment("payloads.abandoned");
+
+                self.pending.remove(hash);
+
+                return None;
+            }
+        };
+
+        if candidates.is_empty() {
+            tracing::debug!(target: "nuts::network", "no longer querying payload '{}' as no peer is left to query", hash);
+
+            self.pending.remove(hash);
+
+            return None;
+        }
+
+        if candidates
+            .iter()
+            .all(|peer_id| pending.tried.contains(peer_id))
+        {
+            pending.tried.clear();
+        }
+
+        let failures = &self.failures;
+        let peer_id = candidates
+            .iter()
+            .filter(|peer_id| !pending.tried.contains(peer_id))
+            .min_by_key(|peer_id| {
+                (
+                    failures
+                        .get(peer_id)
+                        .map(|failures| failures.total())
+                        .unwrap_or_default(),
+                    **peer_id,
+                )
+            })
+            .copied()?;
+
+        pending.tried.insert(peer_id);
+        pending.queried = Some(peer_id);
+        pending.retry_at = now + delay;
+
+        Some(peer_id)
+    }
+
+    /// Stops retrieving the payload, returns false when it wasn't being retrieved
+    pub fn received(&mut self, hash: &Hash) -> bool {
+        self.pending.remove(hash).is_some()
+    }
+
+    /// Records that the peer didn't serve the payload, so that the next peer is queried right away
+    pub fn failed(&mut self, hash: &Hash, peer_id: Uuid, failure: PayloadFailure) {
+        let pending = match self.pending.get_mut(hash) {
+            Some(pending) if pending.queried == Some(peer_id) => pending,
+            _ => return,
+        };
+
+        pending.queried = None;
+        pending.retry_at = Instant::now();
+
+        self.record(peer_id, failure);
+    }
+
+    fn record(&mut self, peer_id: Uuid, failure: PayloadFailure) {
+        let failures = self
+            .failures
+            .entry(peer_id)
+            .or_insert_with(|| PayloadFailures {
+                peer_id: peer_id.to_string(),
+                ..PayloadFailures::default()
+            });
+
+        match failure {
+            PayloadFailure::Missing => failures.missing += 1,
+            PayloadFailure::Invalid => failures.invalid += 1,
+            PayloadFailure::Timeout => failures.timeouts += 1,
+        }
+
+        failures.last_failure_at = Utc::now().timestamp();
+
+        self.metrics.increment("payloads.failed");
+    }
+
+    /// Returns the peers which failed to serve payloads ordered by their number of failures
+    pub fn failures(&self) -> Vec<PayloadFailures> {
+        let mut failures = self.failures.values().cloned().collect::<Vec<_>>();
+
+        failures.sort_by_key(|failures| std::cmp::Reverse(failures.total()));
+        failures
+    }
+}
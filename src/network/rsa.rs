@@ -0,0 +1,121 @@
+use anyhow::{anyhow, Result};
+use biscuit::jwa::SignatureAlgorithm;
+use biscuit::jwk::RSAKeyParameters;
+use ring::signature::{
+    RsaParameters, RsaPublicKeyComponents, RSA_PSS_2048_8192_SHA256, RSA_PSS_2048_8192_SHA384,
+    RSA_PSS_2048_8192_SHA512,
+};
+
+/// Minimum size of the modulus of RSA keys as required by RFC004
+pub const MIN_KEY_BITS: u64 = 2048;
+
+/// Size of the modulus of the RSA key in bits
+pub fn key_bits(params: &RSAKeyParameters) -> u64 {
+    params.n.bits()
+}
+
+/// Verifies a RSASSA-PSS signature (PS256, PS384 or PS512) using the modulus and public exponent of a RSA key,
+/// the salt length equals the length of the hash as required by RFC7518
+pub fn verify_pss(
+    params: &RSAKeyParameters,
+    algorithm: SignatureAlgorithm,
+    message: &[u8],
+    signature: &[u8],
+) -> Result<()> {
+    let parameters: &RsaParameters = match algorithm {
+        SignatureAlgorithm::PS256 => &RSA_PSS_2048_8192_SHA256,
+        SignatureAlgorithm::PS384 => &RSA_PSS_2048_8192_SHA384,
+        SignatureAlgorithm::PS512 => &RSA_PSS_2048_8192_SHA512,
+        algorithm => {
+            return Err(anyhow!(
+                "algorithm {:?} can't be used with a RSA key",
+                algorithm
+            ))
+        }
+    };
+
+    RsaPublicKeyComponents {
+        n: params.n.to_bytes_be(),
+        e: params.e.to_bytes_be(),
+    }
+    .verify(parameters, message, signature)
+    .map_err(|_| anyhow!("invalid {:?} signature", algorithm))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Signing input of a JWS, the keys and signatures below were generated using OpenSSL
+    const MESSAGE: &str = "eyJhbGciOiJQUzI1NiJ9.eyJzdWIiOiJudXRzIn0";
+    /// Modulus of a 2048 bits key
+    const N: &str = "wNoVqGJjyHJRIZL4HcNoUcjYlIqjlD_e68qFEUK933jXdIOGoV9pZ-36RHK9PiaDnxmIerRoV17YWRLgOQXC8_9euaBSf5lX61Vwqlp7HZzTz3FNlAZaL2CS_9u2o-6JKneXEFi00LTaFr4IjEkBEm6-R3MJRhGf7sSX_aS6D_Id_j6iRCzu3z2qspYN2jo4fehc05y2Jus6OoIYjj4fC6NFcf21iZS7etFDlMFNscJw9Rfh5ca3IUFS4L_xX80iWcJWOtM0cGteDaorwfCPnf644DWj6h-zMgqIUts9uDVRqAm11NDQgXb5Io61cw_czkXTbT8eQWc-LddsBknbjQ";
+    const PS256_SIGNATURE: &str = "lT82CxG4A5VN5cR1xNYX72wNStj-bua4sMn8thHQnRwkn8RsDJnFE3nl90VtmwPRRdwEfKbqZp_sPQTBIwz2pXREQh2SIeNR8jpBeYDc2X0dX5rhBbzNbxM3PHqCJR6lM-D34_JeWPbEw5QUK9zYwSN_1eg-uRiZJZsBpPUrdy4y7SSd35AxXO7dH4pOoqc-vZfSfIUHZiwEPWyWfUkHVFVdXRxX3fNkkb9jkGQ9DSY3bIx_4yoifbM69hs8CRsu9wL3BVW8gNZHb_0aoUxdDmANkPLGsQ-uuI0P72k8TzTT5ypDzpayerxhaQAsTcDHXDc9CttSnWdrmAZOIffwfw";
+    const PS384_SIGNATURE: &str = "cxGygxJRAZW8ybDx8pjCP59MTLToHIeyDaVFE0jEl7RqZfC5WpxyH3z3uO9qKxHD0kTck9IEUzBJNNe6eUtwhkFrQOVMSFACh5-v4Y3BF1nRkJGudN-Pz8gtJoECBWiNlJJ258OzZVeby3gIgHLuc6jIZIL44W2setyo4f4CF4ZMP1mSr-18sSgKH2VQu8uzId43F1dSoY0y38PQgl-3ZP4qUt5Nc5-LR3L6J-c5O8RviDD4D8bt1t4GmW5PvNCVGPihISntNtp-JGoCDOFll-jfXGUydXvH0Jer3Sm9J0zcJGsVEc5hOFfBCHiX23Xvne6_WUN7CteiG9-aaFyX6Q";
+    const PS512_SIGNATURE: &str = "qaysamVS-WzSh5XFfcci1Bt7WPmLU9vhX65g3WBx_wwxC7dPN6AypqkJDswWrQJOf6ahDVUZ5m7tmRDiOBKd9E5F42pBJKb_dhJP9SvwGZt45NJRlFcd5aDw8WlV6WiBBputjeBiPoEKwgKBxRcJvgVK5o_jHZp3cR-RPVR9gQb3BKdKVUUya2pmyy_Dne8yissUQ2_j5tyObvkuO2nJISMf4pGAzyMsn5sY67oSUzIu2Ab4HnJDKQMfFHFOBCWvj7ImUTHCgvcup-5ASd0ciJDHfLgGK0vk3XyuEijFI3Wz2fJgZq_075h6Gow9GMyQ4mxWYD6Qwe4sf4p2ztp4Vw";
+    /// PS256 signature with an empty salt instead of a salt with the length of the hash
+    const EMPTY_SALT_SIGNATURE: &str = "LQ2miq5CybchLa3fn7WWX1dD_9fZZlhFy8QgUwftlKd3_vhEbEWyjRTKpelMUmk9zDRrO-XbVz-tv3Dgwd02-LbZrTeYizDkhexTJJ6IpUrA0lFnsLWFHEqtYXM9v75sQGMptIj5XdoXwJxpTZxn1Eq_1U3swBNb3mG3i0isXZJlq_zwCPwYRrA_oTPSPqPCX2-EnTV5uHiZzwLWsodUPonfZVrP0YG2n7hd0HdlyouGJjI6m3M4H-bQuGZyyflv6NU3OAFBEKtl1e3tjPuE0FMMNpIW9yl_pB1IxrIvVbHxstRgAAdoN3p01N3E5Rt-lDu-tLSPsDzxJzNxieguww";
+    /// Modulus of a 1024 bits key and a PS256 signature made using it
+    const SMALL_N: &str = "y2mHqdp8eqsTDGhF-EF8nLkM8oPD5jKuS3MyjBWylPXCbC6VCiMS5BtDkSjaTA9czylUax9AUfuTYdbIuQqA1eErfTETmbFqeXCF8PgV-q3_K7lTDsxP8UmiqFuvggsfZbkb1zGElAOG_qXcFTfYElA8RdLyYY-KF8ss0_WPg0c";
+    const SMALL_SIGNATURE: &str = "I2c5C0lf5IEdj0WYQeUgkFucGrJtTUpAdfzO0RdaOmf0QATAPdH45J18NbqMO0km_zi6x053Rne1dR_yTltKgqPBJ7TlH0JJsKryn-A8zrRcuEl3itu5cCiPgm3JMwpvJoQwONx4Nd6TpRzeh3Tlw37T8-IdOl4ScFAGOtsYVUI";
+
+    fn key(n: &str) -> RSAKeyParameters {
+        serde_json::from_value(serde_json::json!({
+            "kty": "RSA",
+            "n": n,
+            "e": "AQAB",
+        }))
+        .unwrap()
+    }
+
+    fn verify(n: &str, algorithm: SignatureAlgorithm, signature: &str) -> Result<()> {
+        let signature = base64::decode_config(signature, base64::URL_SAFE_NO_PAD).unwrap();
+
+        verify_pss(&key(n), algorithm, MESSAGE.as_bytes(), &signature)
+    }
+
+    #[test]
+    fn valid_signatures() {
+        verify(N, SignatureAlgorithm::PS256, PS256_SIGNATURE).unwrap();
+        verify(N, SignatureAlgorithm::PS384, PS384_SIGNATURE).unwrap();
+        verify(N, SignatureAlgorithm::PS512, PS512_SIGNATURE).unwrap();
+    }
+
+    #[test]
+    fn signature_of_another_algorithm() {
+        assert!(verify(N, SignatureAlgorithm::PS256, PS384_SIGNATURE).is_err());
+        assert!(verify(N, SignatureAlgorithm::PS512, PS256_SIGNATURE).is_err());
+    }
+
+    #[test]
+    fn wrong_salt_length() {
+        assert!(verify(N, SignatureAlgorithm::PS256, EMPTY_SALT_SIGNATURE).is_err());
+    }
+
+    #[test]
+    fn tampered_message() {
+        let signature = base64::decode_config(PS256_SIGNATURE, base64::URL_SAFE_NO_PAD).unwrap();
+
+        assert!(verify_pss(
+            &key(N),
+            SignatureAlgorithm::PS256,
+            b"eyJhbGciOiJub25lIn0.e30",
+            &signature
+        )
+        .is_err());
+    }
+
+    #[test]
+    fn key_too_small() {
+        assert_eq!(key_bits(&key(N)), 2048);
+        assert_eq!(key_bits(&key(SMALL_N)), 1024);
+        assert!(key_bits(&key(SMALL_N)) < MIN_KEY_BITS);
+        assert!(verify(SMALL_N, SignatureAlgorithm::PS256, SMALL_SIGNATURE).is_err());
+    }
+
+    #[test]
+    fn algorithm_of_another_key_type() {
+        assert!(verify(N, SignatureAlgorithm::ES256, PS256_SIGNATURE).is_err());
+        assert!(verify(N, SignatureAlgorithm::RS256, PS256_SIGNATURE).is_err());
+    }
+}
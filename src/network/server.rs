@@ -1,128 +1,1458 @@
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::future::Future;
+use std::net::SocketAddr;
+use std::sync::Arc;
 use std::time::Duration;
 
 use anyhow::{anyhow, Result};
-use futures::Stream;
+use bytes::Bytes;
+use chrono::Utc;
 use sled::Db;
-use tokio::sync::mpsc::{channel, Receiver, Sender};
-use tokio::time;
-use tonic::metadata::MetadataValue;
-use tonic::transport::{Certificate, Channel, ClientTlsConfig, Identity};
-use tonic::{Request, Response};
+use tokio::sync::broadcast::{
+    self,
+    error::{RecvError, TryRecvError},
+};
+use tokio::sync::mpsc::Sender;
+use tokio::sync::watch;
+use tokio::time::{self, Instant};
+use tracing::Instrument;
 use uuid::Uuid;
 
-use crate::network::{Graph, Transaction};
-use crate::pki::KeyStore;
-use crate::proto::{
-    network_client::NetworkClient, network_message::Message, AdvertHashes, NetworkMessage,
-    TransactionList, TransactionListQuery,
+use crate::metrics::Metrics;
+use crate::network::access::{AccessPolicy, Blocklist, PeerAccess, PeerSubject};
+use crate::network::address_book::AddressBook;
+use crate::network::connection_log::ConnectionLog;
+use crate::network::admission::Admission;
+use crate::network::blocks::{self, recent_blocks};
+use crate::network::checkpoint::{Checkpoint, SyncCursor};
+use crate::network::coalesce::QueryCoalescer;
+use crate::network::compression::compress_list;
+use crate::network::dedup::{RecentMessages, DEDUP_WINDOW};
+use crate::network::diagnostics::{PeerDiagnostics, DIAGNOSTICS_INTERVAL, SOFTWARE_ID};
+use crate::network::groups::PeerGroups;
+use crate::network::handshake::NodeInfo;
+use crate::network::identities::PeerIdentities;
+use crate::network::health::{
+    Health, PayloadHealth, PeerActivity, RecentError, StorageHealth, SyncState, HEALTH_INTERVAL,
+};
+use crate::network::intake::Intake;
+use crate::network::key_usage::{AnomalyHandler, KeyUsage, KeyUsagePolicy};
+use crate::network::outbox::Outbox;
+use crate::network::payload_store::PayloadStore;
+use crate::network::peers::{Msg, MsgV2, NetworkError, Outbound, PeerManager, TlsReloader};
+use crate::network::retention::{RetentionPolicy, GC_INTERVAL};
+use crate::network::retrieval::{PayloadFailure, PayloadRetrieval};
+use crate::network::stats::{Sample, Stats, SAMPLE_INTERVAL};
+use crate::network::sync::{Scheduler, SyncPolicy};
+use crate::network::tls::{TlsMaterial, TlsPolicy};
+use crate::network::writer::PendingWrites;
+use crate::network::{
+    AuthorizePeer, DiscoveryPolicy, Graph, Hash, OverflowPolicy, PalDecrypter, PayloadFilter,
+    Transaction, DEFAULT_MAX_CLOCK_SKEW,
 };
+use crate::pki::{KeyStorage, KeyStore, TrustPolicy};
+use crate::proto::model::{
+    v2, AdvertHashes, Diagnostics, EncodedTransaction, Message, SubDagFilter, TransactionList,
+    TransactionListQuery, TransactionPayload, TransactionPayloadQuery,
+};
+use crate::proto::v2::Envelope;
+use crate::proto::{self, network_message, NetworkMessage};
+use crate::retry::RetryPolicy;
+use crate::schema;
+
+/// Options of the [`Server`], the defaults are suitable for most nodes
+pub struct ServerOptions {
+    /// Number of workers used to verify transaction signatures in parallel
+    pub admission_workers: usize,
+    /// Number of messages which are buffered per connection, received messages are queued per connection
+    pub channel_capacity: usize,
+    /// What happens when more received messages are queued for a connection than the channel capacity
+    pub overflow_policy: OverflowPolicy,
+    /// Maximum number of transactions which are queried from a peer at once
+    pub sync_batch_size: usize,
+    /// Time to wait for more transactions to query from the same peer before sending the query
+    pub query_delay: Duration,
+    /// DID of the node operator which is sent to peers
+    pub node_did: Option<String>,
+    /// ID of the network, peers which are part of another network are rejected
+    pub network_id: String,
+    /// Store which key and rules were used to verify each admitted transaction
+    pub record_verification: bool,
+    /// Reject peers and transactions which don't strictly follow the specification
+    pub strict: bool,
+    /// Maximum time the signing time of a transaction may be ahead of the clock of this node
+    pub max_clock_skew: Duration,
+    /// Admit transactions which fail verification (e.g. due to an invalid signature) instead of rejecting them,
+    /// this allows peers to inject forged transactions and is only meant to recover a network
+    pub allow_unverified: bool,
+    /// Policy used to reconnect to a peer when the connection is lost
+    pub reconnect: RetryPolicy,
+    /// Policy used to query a payload again when it wasn't received from the peer
+    pub payload_retry: RetryPolicy,
+    /// Bounds of the interval at which peers are queried for new transactions
+    pub sync: SyncPolicy,
+    /// Groups this node is a member of, used to restrict which peers payloads are exchanged with
+    pub groups: PeerGroups,
+    /// How long the payloads of each payload type are kept, payloads are kept forever by default
+    pub retention: RetentionPolicy,
+    /// Used to determine whether this node is a participant of private transactions, without it the payloads of
+    /// private transactions are never retrieved
+    pub pal_decrypter: Option<Arc<dyn PalDecrypter>>,
+    /// Thresholds used to detect unusual usage of signing keys
+    pub key_usage: KeyUsagePolicy,
+    /// Receives the detected key usage anomalies in addition to the audit log
+    pub anomaly_handler: Option<Arc<dyn AnomalyHandler>>,
+    /// Certificate fingerprints, DNS names and CIDRs of the peers which are allowed or denied to connect and be
+    /// connected to, in addition to the peers which were blocked using the command-line
+    pub access: AccessPolicy,
+    /// Decides whether peers are admitted after the handshake, all peers of the same network are admitted when not set
+    pub authorize_peer: Option<Arc<dyn AuthorizePeer>>,
+    /// Maximum time to wait for the initial sync before the node is flagged as degraded, it waits until it caught
+    /// up with a peer when not set
+    pub initial_sync_timeout: Option<Duration>,
+    /// TLS versions, cipher suites and ALPN protocols used for connections with peers
+    pub tls_policy: TlsPolicy,
+    /// Counters of the node, which are shared with components outside of the server (e.g. the anomaly handler)
+    pub metrics: Metrics,
+}
 
-macro_rules! netmsg {
-    ($message: expr) => {
-        NetworkMessage {
-            message: Some($message),
+impl Default for ServerOptions {
+    fn default() -> Self {
+        Self {
+            admission_workers: 1,
+            channel_capacity: 10,
+            overflow_policy: OverflowPolicy::default(),
+            sync_batch_size: 1000,
+            query_delay: Duration::from_millis(50),
+            node_did: None,
+            network_id: "default".to_string(),
+            record_verification: false,
+            strict: false,
+            max_clock_skew: DEFAULT_MAX_CLOCK_SKEW,
+            allow_unverified: false,
+            reconnect: RetryPolicy::default(),
+            payload_retry: RetryPolicy::exponential(Duration::from_secs(5))
+                .max_interval(Duration::from_secs(300))
+                .jitter(0.1)
+                .max_attempts(Some(10))
+                .budget(Some(Duration::from_secs(3600))),
+            sync: SyncPolicy::default(),
+            groups: PeerGroups::default(),
+            retention: RetentionPolicy::default(),
+            pal_decrypter: None,
+            key_usage: KeyUsagePolicy::default(),
+            anomaly_handler: None,
+            access: AccessPolicy::default(),
+            authorize_peer: None,
+            initial_sync_timeout: None,
+            tls_policy: TlsPolicy::default(),
+            metrics: Metrics::default(),
         }
-    };
+    }
 }
 
-#[derive(Debug)]
-pub struct Msg {
+/// Maximum size of the transactions in a single transaction list message, larger lists are split into multiple
+/// messages for peers which support it to stay well below the default gRPC message size limit of 4 MiB
+const MAX_PAGE_SIZE: usize = 1024 * 1024;
+
+/// Maximum number of messages of a paginated transaction list which is received from a peer
+const MAX_PAGES: u32 = 1024;
+
+/// Maximum size of the transactions of a paginated transaction list which is buffered until all pages are received
+const MAX_LIST_SIZE: usize = 64 * 1024 * 1024;
+
+/// Conversations which aren't answered within this period are forgotten
+const CONVERSATION_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// Number of errors which are kept for the health of the node
+const RECENT_ERRORS: usize = 20;
+
+/// Conversation with a peer using version 2 of the protocol, responses are only accepted as part of a conversation
+/// which was started by this node
+struct Conversation {
     peer_id: Uuid,
-    message: Message,
+    started_at: Instant,
 }
 
-pub struct Server {
-    strict: bool,
+/// Connects to other nodes and keeps the [`Graph`] in sync with them, the public keys used to verify transactions
+/// are kept in the key storage which defaults to the database of the node
+pub struct Server<S = KeyStore> {
+    db: Db,
+    /// Peer ID of this node
     peer_id: Uuid,
-    ca: Certificate,
-    identity: Identity,
+    started_at: Instant,
     graph: Graph,
-    key_store: KeyStore,
+    key_store: S,
+    admission: Admission,
+    peers: PeerManager,
+    address_book: AddressBook,
+    payload_store: PayloadStore,
+    outbox: Outbox,
+    stats: Stats,
+    /// Number of transactions admitted since the last sample
+    admitted: u64,
+    scheduler: Scheduler,
+    adverts: HashMap<Uuid, (u32, Hash)>,
+    /// Transaction lists of which not all pages were received yet, together with their size so far
+    pages: HashMap<Uuid, (TransactionList, usize)>,
+    /// Peers which should be fully synced instead of only their recent blocks, as older transactions are missing
+    full_sync: HashSet<Uuid>,
+    groups: PeerGroups,
+    retention: RetentionPolicy,
+    identities: PeerIdentities,
+    /// Groups of the peers which were verified against the certificate their peer ID is bound to
+    peer_groups: HashMap<Uuid, Vec<String>>,
+    /// Payloads which peers advertised to hold
+    payload_filters: HashMap<Uuid, PayloadFilter>,
+    /// Diagnostics which the connected peers reported about themselves
+    diagnostics: HashMap<Uuid, PeerDiagnostics>,
+    recent_messages: RecentMessages,
+    /// Payloads of transactions which are missing and queried from peers
+    retrieval: PayloadRetrieval,
+    sync_batch_size: usize,
+    queries: QueryCoalescer,
+    pal_decrypter: Option<Arc<dyn PalDecrypter>>,
+    key_usage: KeyUsage,
+    anomaly_handler: Option<Arc<dyn AnomalyHandler>>,
+    record_verification: bool,
+    /// Time at which the last sync with a peer completed
+    last_sync: Option<i64>,
+    sync_state: SyncState,
+    initial_sync_timeout: Option<Duration>,
+    health: watch::Sender<Health>,
+    /// Number of messages received per connected peer
+    received: HashMap<Uuid, u64>,
+    /// Most recent errors which occurred while handling messages
+    errors: VecDeque<RecentError>,
+    /// Outbound channels of the peers which use version 1 of the protocol
+    peers_v1: HashMap<Uuid, Sender<NetworkMessage>>,
+    /// Peers which use version 2 of the protocol, these are sent gossip by the server instead of their stream
+    peers_v2: HashMap<Uuid, Sender<Envelope>>,
+    conversations: HashMap<Vec<u8>, Conversation>,
+    added: broadcast::Receiver<Transaction>,
+    /// Transactions which were persisted while processing a transaction list, used to fetch their payloads
+    persisted: broadcast::Receiver<Transaction>,
 
-    rx: Receiver<Msg>,
-    tx: Sender<Msg>,
+    intake: Intake<Msg>,
+    intake_v2: Intake<MsgV2>,
+    metrics: Metrics,
 }
 
 impl Server {
-    pub fn new(db: Db, ca: Certificate, identity: Identity) -> Result<Self> {
-        let (tx, rx) = channel(10);
-        let graph = Graph::open(db.clone())?;
+    /// Creates a server which uses the CA certificates in the TLS material to verify peers and the certificate and
+    /// private key to authenticate itself
+    pub fn new(db: Db, tls: &TlsMaterial, options: ServerOptions) -> Result<Self> {
+        let key_store = KeyStore::open(db.clone())?;
+
+        Self::with_key_store(db, tls, key_store, options)
+    }
+}
+
+impl<S: KeyStorage> Server<S> {
+    /// Same as [`Server::new`] but keeps the public keys in the given key storage
+    pub fn with_key_store(
+        db: Db,
+        tls: &TlsMaterial,
+        key_store: S,
+        options: ServerOptions,
+    ) -> Result<Self> {
+        options.tls_policy.validate()?;
+
+        if options.allow_unverified {
+            tracing::warn!(target: "nuts::network", "transactions which fail verification are admitted, peers are able to inject forged transactions");
+        }
+
+        schema::migrate(&db)?;
+
+        let metrics = options.metrics;
+        let intake = Intake::new(
+            options.channel_capacity,
+            options.overflow_policy,
+            metrics.clone(),
+        );
+        let intake_v2 = Intake::new(
+            options.channel_capacity,
+            options.overflow_policy,
+            metrics.clone(),
+        );
+        let mut graph = Graph::open(db.clone())?.metrics(metrics.clone());
+        let address_book = AddressBook::open(db.clone())?;
+        let identities = PeerIdentities::open(db.clone())?;
+        let peer_id = identities.local_peer_id()?;
+
+        let node = NodeInfo {
+            peer_id,
+            did: options.node_did,
+            network_id: options.network_id,
+        };
 
         Ok(Self {
-            strict: false,
-            ca,
-            identity,
-            peer_id: Uuid::new_v4(),
-            tx,
-            rx,
+            peers: PeerManager::new(
+                options.strict,
+                node,
+                options.tls_policy,
+                tls,
+                options.reconnect,
+                address_book.clone(),
+                ConnectionLog::open(db.clone())?,
+                identities.clone(),
+                options.channel_capacity,
+                intake.clone(),
+                intake_v2.clone(),
+                graph.added(),
+                PeerAccess::new(options.access, Blocklist::open(db.clone())?),
+                options.authorize_peer.clone(),
+                metrics.clone(),
+            )?,
+            peers_v1: HashMap::new(),
+            peers_v2: HashMap::new(),
+            conversations: HashMap::new(),
+            added: graph.subscribe(None),
+            persisted: graph.subscribe(None),
+            intake,
+            intake_v2,
             graph,
-            key_store: KeyStore::open(db)?,
+            address_book,
+            key_store,
+            payload_store: PayloadStore::open(db.clone())?,
+            outbox: Outbox::open(db.clone())?,
+            stats: Stats::open(db.clone())?,
+            admitted: 0,
+            admission: Admission::new(
+                options.admission_workers,
+                options.strict,
+                options.max_clock_skew,
+                TrustPolicy::open(db.clone())?,
+            )
+            .allow_unverified(options.allow_unverified)
+            .metrics(metrics.clone()),
+            scheduler: Scheduler::new(options.sync),
+            adverts: HashMap::new(),
+            pages: HashMap::new(),
+            full_sync: HashSet::new(),
+            groups: options.groups,
+            retention: options.retention,
+            identities,
+            peer_groups: HashMap::new(),
+            payload_filters: HashMap::new(),
+            diagnostics: HashMap::new(),
+            recent_messages: RecentMessages::new(DEDUP_WINDOW),
+            retrieval: PayloadRetrieval::new(options.payload_retry, metrics.clone()),
+            sync_batch_size: options.sync_batch_size,
+            queries: QueryCoalescer::new(options.query_delay, metrics.clone()),
+            pal_decrypter: options.pal_decrypter,
+            key_usage: KeyUsage::open(db.clone(), options.key_usage)?.metrics(metrics.clone()),
+            anomaly_handler: options.anomaly_handler,
+            record_verification: options.record_verification,
+            last_sync: None,
+            sync_state: SyncState::Syncing,
+            initial_sync_timeout: options.initial_sync_timeout,
+            health: watch::channel(Health::default()).0,
+            received: HashMap::new(),
+            errors: VecDeque::new(),
+            peer_id,
+            started_at: Instant::now(),
+            db,
+            metrics,
         })
     }
 
-    pub async fn run(mut self) {
-        while let Some(msg) = self.rx.recv().await {
-            if let Err(e) = match msg.message {
-                Message::TransactionList(data) => self.handle_transaction_list(data),
-                message => {
-                    log::debug!(target: "nuts::network", "ignoring unsupported message: {:?}", message);
+    /// Handles messages from peers until the shutdown future completes
+    pub async fn run(&mut self, shutdown: impl Future<Output = Result<()>>) {
+        tokio::pin!(shutdown);
+
+        let mut stats = time::interval_at(Instant::now() + SAMPLE_INTERVAL, SAMPLE_INTERVAL);
+        let mut health = time::interval(HEALTH_INTERVAL);
+        let mut gc = time::interval_at(Instant::now() + GC_INTERVAL, GC_INTERVAL);
+        let mut diagnostics =
+            time::interval_at(Instant::now() + DIAGNOSTICS_INTERVAL, DIAGNOSTICS_INTERVAL);
+        let initial_sync = self
+            .initial_sync_timeout
+            .map(|timeout| Instant::now() + timeout);
+
+        loop {
+            let deadline = self.next_deadline();
+
+            tokio::select! {
+                msg = self.intake.recv() => match msg {
+                    Some(msg) => {
+                        let span = msg.trace.message(msg.message.kind());
+
+                        self.handle_message(msg).instrument(span).await
+                    }
+                    None => break,
+                },
+                msg = self.intake_v2.recv() => match msg {
+                    Some(msg) => {
+                        let kind = msg.message.as_ref().map_or("connected", |message| message.kind());
+                        let span = msg.trace.message(kind);
+
+                        self.handle_envelope(msg).instrument(span).await
+                    }
+                    None => break,
+                },
+                tx = self.added.recv() => match tx {
+                    Ok(tx) => self.gossip(vec![tx.id]).await,
+                    // Peers query the skipped transactions when they don't know the heads
+                    Err(RecvError::Lagged(skipped)) => {
+                        tracing::warn!(target: "nuts::network", "unable to gossip {} transactions, gossiping the heads instead", skipped);
+
+                        self.gossip(self.graph.heads()).await
+                    }
+                    Err(RecvError::Closed) => {}
+                },
+                _ = time::sleep_until(deadline.unwrap_or_else(Instant::now)), if deadline.is_some() => self.sync().await,
+                _ = stats.tick() => if let Err(e) = self.record_stats() {
+                    tracing::error!(target: "nuts::network", "failed to record statistics: {}", e);
+                },
+                _ = health.tick() => self.publish_health(),
+                _ = diagnostics.tick() => self.broadcast_diagnostics().await,
+                _ = gc.tick() => if let Err(e) = self.collect_garbage() {
+                    tracing::error!(target: "nuts::network", "failed to drop expired payloads: {}", e);
+                },
+                _ = time::sleep_until(initial_sync.unwrap_or_else(Instant::now)), if initial_sync.is_some() && self.sync_state == SyncState::Syncing => {
+                    tracing::warn!(target: "nuts::network", "initial sync didn't complete in time, continuing in degraded state");
+
+                    self.sync_state = SyncState::Degraded;
+                    self.publish_health();
+                },
+                result = &mut shutdown => {
+                    if let Err(e) = result {
+                        tracing::error!(target: "nuts::network", "failed to wait for shutdown signal: {}", e);
+                    }
+
+                    break;
+                },
+            }
+        }
+    }
+
+    fn record_stats(&mut self) -> Result<()> {
+        self.stats.record(&Sample {
+            timestamp: Utc::now().timestamp(),
+            transactions: self.graph.len(),
+            heads: self.graph.heads().len(),
+            admitted: self.admitted,
+            peers: self.scheduler.intervals().len(),
+        })?;
+        self.admitted = 0;
+
+        Ok(())
+    }
+
+    /// Drops the payloads of which all referencing transactions are older than the retention of their payload type,
+    /// the transactions themselves are kept
+    fn collect_garbage(&self) -> Result<()> {
+        if self.retention.is_empty() {
+            return Ok(());
+        }
+
+        let now = Utc::now().naive_utc();
+        let mut expired = 0;
+
+        for hash in self.payload_store.hashes()? {
+            let transactions = self
+                .graph
+                .payload_refs(&hash)?
+                .iter()
+                .filter_map(|id| self.graph.get(id))
+                .collect::<Vec<_>>();
+
+            if !transactions.is_empty()
+                && transactions
+                    .iter()
+                    .all(|tx| self.retention.is_expired(&tx.payload_type, tx.sign_at, now))
+            {
+                self.payload_store.expire(&hash)?;
+                expired += 1;
+            }
+        }
+
+        if expired > 0 {
+            tracing::info!(target: "nuts::network", "dropped {} expired payloads", expired);
+        }
+
+        Ok(())
+    }
+
+    /// Returns the current health of the node
+    pub fn health(&self) -> Health {
+        let storage = match self.db.size_on_disk() {
+            Ok(size_on_disk) => StorageHealth {
+                ok: true,
+                size_on_disk,
+                error: None,
+            },
+            Err(e) => StorageHealth {
+                ok: false,
+                size_on_disk: 0,
+                error: Some(e.to_string()),
+            },
+        };
 
-                    Ok(())
+        let mut diagnostics = self.diagnostics.values().cloned().collect::<Vec<_>>();
+
+        diagnostics.sort_by(|a, b| a.peer_id.cmp(&b.peer_id));
+
+        let mut connections = self
+            .peers_v1
+            .keys()
+            .map(|peer_id| (peer_id, 1))
+            .chain(self.peers_v2.keys().map(|peer_id| (peer_id, 2)))
+            .map(|(peer_id, protocol_version)| PeerActivity {
+                peer_id: peer_id.to_string(),
+                protocol_version,
+                messages: self.received.get(peer_id).copied().unwrap_or_default(),
+            })
+            .collect::<Vec<_>>();
+
+        connections.sort_by(|a, b| a.peer_id.cmp(&b.peer_id));
+
+        Health {
+            updated_at: Utc::now().timestamp(),
+            height: self.graph.lamport_clock(),
+            transactions: self.graph.len(),
+            root: self.graph.root().map(|tx| tx.id.to_string()),
+            peers: self.scheduler.intervals().len(),
+            last_sync: self.last_sync,
+            sync: self.sync_state,
+            storage,
+            payloads: PayloadHealth {
+                retained: self.payload_store.count().unwrap_or_default(),
+                expired: self.payload_store.expired_count().unwrap_or_default(),
+                pending: self.retrieval.len(),
+                failures: self.retrieval.failures(),
+            },
+            diagnostics,
+            heads: self.graph.heads().iter().map(ToString::to_string).collect(),
+            orphans: self.graph.orphans().len(),
+            connections,
+            errors: self.errors.iter().cloned().collect(),
+        }
+    }
+
+    /// Keeps the error for the health of the node, the oldest error is dropped when there are too many
+    fn record_error(&mut self, peer_id: Uuid, message: String) {
+        if self.errors.len() == RECENT_ERRORS {
+            self.errors.pop_front();
+        }
+
+        self.errors.push_back(RecentError {
+            at: Utc::now().timestamp(),
+            peer_id: peer_id.to_string(),
+            message,
+        });
+    }
+
+    fn publish_health(&self) {
+        // Sending only fails when there are no receivers
+        let _ = self.health.send(self.health());
+    }
+
+    /// Marks the initial sync as completed, which is used by nodes without any peers to sync with
+    pub fn skip_initial_sync(&mut self) {
+        self.sync_state = SyncState::Synced;
+    }
+
+    /// Returns a receiver which is updated with the health of the node while it's running
+    pub fn subscribe_health(&self) -> watch::Receiver<Health> {
+        self.health.subscribe()
+    }
+
+    /// Returns a handle to wait until the transactions added to the DAG are written to the database
+    pub fn pending_writes(&self) -> PendingWrites {
+        self.graph.pending_writes()
+    }
+
+    /// Returns the counters of the node
+    pub fn metrics(&self) -> &Metrics {
+        &self.metrics
+    }
+
+    /// Marks the sync with a peer as completed and schedules the next one
+    fn synced(&mut self, peer_id: &Uuid, fresh: bool) {
+        self.last_sync = Some(Utc::now().timestamp());
+        self.scheduler.update(peer_id, fresh);
+
+        // The initial sync is completed as soon as a peer has no new transactions for this node
+        if !fresh && self.sync_state != SyncState::Synced {
+            tracing::info!(target: "nuts::network", "initial sync completed with peer: {}", peer_id);
+
+            self.sync_state = SyncState::Synced;
+            self.publish_health();
+        }
+    }
+
+    /// Recomputes the checkpoint from the current state of the node
+    fn checkpoint(&self) -> Result<Checkpoint> {
+        let transactions = self
+            .graph
+            .to_vec()
+            .into_iter()
+            .map(|tx| tx.id)
+            .collect::<Vec<_>>();
+
+        Ok(Checkpoint::new(
+            &transactions,
+            self.graph.orphans().len(),
+            self.payload_store.count()?,
+            self.graph.heads(),
+            self.scheduler
+                .intervals()
+                .into_iter()
+                .map(|(peer_id, interval)| SyncCursor {
+                    peer_id: peer_id.to_string(),
+                    interval: interval.as_secs(),
+                })
+                .collect(),
+            self.address_book.list()?.len(),
+        ))
+    }
+
+    /// Compares the checkpoint written by the previous graceful shutdown against the current state
+    pub fn verify_checkpoint(&self) -> Result<()> {
+        let current = self.checkpoint()?;
+
+        match Checkpoint::take(&self.db)? {
+            Some(previous) => {
+                let differences = previous.compare(&current);
+
+                if differences.is_empty() {
+                    tracing::info!(target: "nuts::network", "state matches the checkpoint written at {}", previous.written_at);
+                }
+
+                for difference in differences {
+                    tracing::error!(target: "nuts::network", "state doesn't match the checkpoint written at {}: {}", previous.written_at, difference);
                 }
-            } {
-                log::error!(target: "nuts::network", "error handling message for peer '{}': {}", msg.peer_id, e);
             }
+            None if current.transactions > 0 => {
+                tracing::warn!(target: "nuts::network", "no checkpoint found, the previous run didn't shut down gracefully");
+            }
+            None => {}
         }
+
+        Ok(())
     }
 
-    fn parse_transaction_list(&mut self, data: TransactionList) -> Result<Vec<Transaction>> {
-        let mut transactions = vec![];
-        let mut staged = data.transactions;
+    /// Stops handling messages, closes the connections with all peers, waits until the graph is written, writes a
+    /// checkpoint of the current state and flushes the database
+    pub async fn shutdown(&mut self) -> Result<()> {
+        self.intake.close();
+        self.intake_v2.close();
+        self.peers.close();
+        self.graph.flush().await?;
 
-        loop {
-            let before = staged.len();
-
-            'process: for _ in 0..before {
-                let tx_info = staged.remove(0);
-                let repr = std::str::from_utf8(&tx_info.data)?;
-
-                match Transaction::parse(&self.key_store, repr) {
-                    Ok(tx) => {
-                        // Add the key to the store if it doesn't exists
-                        if !self.key_store.contains(&tx.key_id)? {
-                            if let Some(key) = tx.key.clone() {
-                                self.key_store.add(tx.key_id.clone(), key)?;
-                            }
-                        }
-
-                        transactions.push(tx);
+        let checkpoint = self.checkpoint()?;
+
+        checkpoint.save(&self.db)?;
+        self.db.flush_async().await?;
+
+        tracing::info!(target: "nuts::network", "wrote checkpoint: {} transactions, {} orphans, {} payloads, {} heads, {} known peers, {} synced peers (checksum: {})", checkpoint.transactions, checkpoint.orphans, checkpoint.payloads, checkpoint.heads.len(), checkpoint.known_peers, checkpoint.sync_cursors.len(), checkpoint.checksum);
+
+        for (name, value) in self.metrics.counters() {
+            tracing::info!(target: "nuts::network", "{}: {}", name, value);
+        }
+
+        Ok(())
+    }
+
+    async fn handle_message(&mut self, msg: Msg) {
+        let peer_id = msg.peer_id;
+        let trace = msg.trace;
+
+        *self.received.entry(peer_id).or_default() += 1;
+
+        self.scheduler
+            .register(peer_id, Outbound::V1(msg.outbound.clone()));
+        let connected = self.peers_v1.insert(peer_id, msg.outbound).is_none();
+
+        if connected {
+            self.flush_outbox().await;
+        }
+
+        if !self.recent_messages.insert(peer_id, msg.digest) {
+            tracing::debug!(target: "nuts::network", "ignoring duplicate message from peer: {}", peer_id);
+            self.metrics.increment("messages.duplicate");
+
+            return;
+        }
+
+        if let Err(e) = match msg.message {
+            Message::TransactionListQuery(query) => {
+                self.handle_transaction_list_query(
+                    peer_id,
+                    query,
+                    msg.capabilities.compression,
+                    msg.capabilities.pagination,
+                )
+                .await
+            }
+            Message::TransactionPayloadQuery(query) => {
+                self.handle_transaction_payload_query(peer_id, query).await
+            }
+            Message::TransactionPayload(payload) => {
+                self.handle_transaction_payload(peer_id, payload)
+            }
+            Message::AdvertHashes(advert) => self.handle_advert_hashes(peer_id, advert),
+            Message::TransactionList(page) => match self.receive_page(peer_id, page) {
+                Ok(Some(list)) => self.receive_transaction_list(peer_id, list).await,
+                Ok(None) => Ok(()),
+                Err(e) => Err(e),
+            },
+            Message::Diagnostics(diagnostics) => {
+                tracing::debug!(target: "nuts::network", "received diagnostics of peer: {} (version: {})", peer_id, diagnostics.software_version.as_deref().unwrap_or("unknown"));
+
+                self.diagnostics.insert(
+                    peer_id,
+                    PeerDiagnostics::new(peer_id, Utc::now().timestamp(), diagnostics),
+                );
+
+                Ok(())
+            }
+        } {
+            let message = trace.annotate(e);
+
+            tracing::error!(target: "nuts::network", "error handling message for peer '{}': {}", peer_id, message);
+            self.record_error(peer_id, message);
+        }
+    }
+
+    /// Returns the moment at which either a peer should be synced with or a payload should be queried again
+    fn next_deadline(&self) -> Option<Instant> {
+        self.retrieval
+            .next_deadline()
+            .into_iter()
+            .chain(self.scheduler.next_deadline())
+            .chain(self.queries.next_deadline())
+            .min()
+    }
+
+    /// Queries all peers which are due for a sync for their transaction list
+    async fn sync(&mut self) {
+        self.retry_payloads().await;
+
+        if let Err(e) = self.retry_orphans() {
+            tracing::error!(target: "nuts::network", "failed to retry orphans: {}", e);
+        }
+
+        self.flush_queries().await;
+
+        for (peer_id, outbound) in self.scheduler.due() {
+            let sent = match outbound {
+                Outbound::V1(outbound) => {
+                    // Only the recent blocks are queried unless older transactions turned out to be missing
+                    let block_dates = if self.full_sync.remove(&peer_id) {
+                        vec![0]
+                    } else {
+                        recent_blocks(Utc::now().naive_utc())
+                    };
+                    let mut sent = true;
+
+                    tracing::debug!(target: "nuts::network", "querying transaction list of peer '{}' (blocks: {:?})", peer_id, block_dates);
+
+                    for block_date in block_dates {
+                        sent &= outbound
+                            .send(
+                                Message::TransactionListQuery(TransactionListQuery {
+                                    block_date,
+                                    filter: None,
+                                })
+                                .into(),
+                            )
+                            .await
+                            .is_ok();
                     }
-                    Err(e) => {
-                        log::debug!(target: "nuts::network", "failed to process transaction '{}' in process loop: {}", repr, e);
-                        staged.push(tx_info);
 
-                        continue 'process;
+                    sent
+                }
+                Outbound::V2(outbound) => {
+                    tracing::debug!(target: "nuts::network", "comparing state with peer: {}", peer_id);
+
+                    let state = self.state(peer_id);
+
+                    outbound.send(state).await.is_ok()
+                }
+            };
+
+            if !sent {
+                tracing::debug!(target: "nuts::network", "no longer syncing with disconnected peer: {}", peer_id);
+
+                self.scheduler.remove(&peer_id);
+                self.peers_v1.remove(&peer_id);
+                self.received.remove(&peer_id);
+                self.diagnostics.remove(&peer_id);
+                self.pages.remove(&peer_id);
+                self.full_sync.remove(&peer_id);
+                self.payload_filters.remove(&peer_id);
+            }
+        }
+    }
+
+    /// Queries the missing previous transactions of the orphans which were marked to be retried, from the peer which
+    /// sent the orphan or from all peers when it isn't connected. Peers using version 1 of the protocol aren't
+    /// queried as they already send their full transaction list on every sync
+    fn retry_orphans(&mut self) -> Result<()> {
+        for orphan in self.graph.take_orphan_retries()? {
+            let peers = match orphan
+                .peer_id
+                .filter(|peer_id| self.peers_v2.contains_key(peer_id))
+            {
+                Some(peer_id) => vec![peer_id],
+                None => self.peers_v2.keys().copied().collect(),
+            };
+
+            tracing::info!(target: "nuts::network", "querying {} missing previous transactions of orphan '{}' from {} peers", orphan.missing.len(), orphan.id, peers.len());
+
+            for peer_id in peers {
+                self.queries.add(peer_id, orphan.missing.clone());
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Sends the coalesced queries which are due, queries for peers which disconnected in the meantime are dropped
+    async fn flush_queries(&mut self) {
+        for (peer_id, refs) in self.queries.due() {
+            let outbound = match self.peers_v2.get(&peer_id).cloned() {
+                Some(outbound) => outbound,
+                None => continue,
+            };
+
+            tracing::debug!(target: "nuts::network", "querying {} transactions of peer: {}", refs.len(), peer_id);
+
+            if let Err(e) = self
+                .query_transactions(peer_id, None, refs, &outbound)
+                .await
+            {
+                tracing::error!(target: "nuts::network", "failed to query transactions of peer '{}': {}", peer_id, e);
+            }
+        }
+    }
+
+    /// Queries the payloads which weren't received in time from the next peer which might hold them, or gives up on
+    /// them when the retry policy is exhausted
+    async fn retry_payloads(&mut self) {
+        let now = Instant::now();
+
+        for hash in self.retrieval.due(now) {
+            let sources = self.payload_sources(&hash);
+            let peer_id = match self.retrieval.next_source(&hash, &sources, now) {
+                Some(peer_id) => peer_id,
+                None => continue,
+            };
+            let query: NetworkMessage = Message::TransactionPayloadQuery(TransactionPayloadQuery {
+                payload_hash: hash.clone(),
+            })
+            .into();
+
+            if let Err(e) = self.send_to(&peer_id, query).await {
+                tracing::debug!(target: "nuts::network", "failed to query payload '{}': {}", hash, e);
+            } else {
+                tracing::debug!(target: "nuts::network", "queried payload '{}' again from peer: {}", hash, peer_id);
+            }
+        }
+    }
+
+    /// Returns the peers to query the payload from, which are the peers that advertised to hold it or when none
+    /// did, every peer without availability info
+    fn payload_sources(&self, hash: &Hash) -> Vec<Uuid> {
+        let available = self
+            .peers_v1
+            .keys()
+            .filter(|peer_id| {
+                self.payload_filters
+                    .get(peer_id)
+                    .is_some_and(|filter| filter.contains(hash))
+            })
+            .copied()
+            .collect::<Vec<_>>();
+
+        if !available.is_empty() {
+            self.metrics.increment("payloads.targeted");
+
+            return available;
+        }
+
+        self.metrics.increment("payloads.untargeted");
+
+        self.peers_v1
+            .keys()
+            .filter(|peer_id| !self.payload_filters.contains_key(peer_id))
+            .copied()
+            .collect()
+    }
+
+    /// Sends a message to a peer which uses version 1 of the protocol
+    pub async fn send_to(&self, peer_id: &Uuid, msg: NetworkMessage) -> Result<()> {
+        let outbound = self
+            .peers_v1
+            .get(peer_id)
+            .ok_or_else(|| anyhow!("peer '{}' isn't connected", peer_id))?;
+
+        outbound
+            .send(msg)
+            .await
+            .map_err(|_| anyhow!("peer '{}' disconnected", peer_id))
+    }
+
+    /// Sends a message to all peers which use version 1 of the protocol and returns the number of peers it was sent
+    /// to, peers which disconnected are forgotten
+    pub async fn broadcast(&mut self, msg: NetworkMessage) -> usize {
+        let mut disconnected = vec![];
+
+        for (peer_id, outbound) in self.peers_v1.iter() {
+            if outbound.send(msg.clone()).await.is_err() {
+                disconnected.push(*peer_id);
+            }
+        }
+
+        for peer_id in disconnected.iter() {
+            self.peers_v1.remove(peer_id);
+            self.received.remove(peer_id);
+            self.diagnostics.remove(peer_id);
+            self.pages.remove(peer_id);
+            self.payload_filters.remove(peer_id);
+        }
+
+        self.peers_v1.len()
+    }
+
+    /// Broadcasts the uptime, peers, number of transactions and software version of this node to the peers which use
+    /// version 1 of the protocol
+    async fn broadcast_diagnostics(&mut self) {
+        let diagnostics = Diagnostics {
+            uptime: self.started_at.elapsed().as_secs() as u32,
+            peer_id: Some(self.peer_id.to_string()),
+            peers: self
+                .peers_v1
+                .keys()
+                .chain(self.peers_v2.keys())
+                .map(Uuid::to_string)
+                .collect(),
+            number_of_transactions: self.graph.len() as u32,
+            software_version: Some(env!("CARGO_PKG_VERSION").to_string()),
+            software_id: Some(SOFTWARE_ID.to_string()),
+        };
+
+        self.broadcast(Message::Diagnostics(diagnostics).into())
+            .await;
+    }
+
+    /// Returns the payload type of the transactions referencing the payload
+    fn payload_type(&self, hash: &Hash) -> Result<Option<String>> {
+        Ok(self
+            .graph
+            .payload_refs(hash)?
+            .iter()
+            .find_map(|id| self.graph.get(id))
+            .map(|tx| tx.payload_type.clone()))
+    }
+
+    /// Whether the payload is only referenced by private transactions
+    fn is_private_payload(&self, hash: &Hash) -> Result<bool> {
+        let refs = self.graph.payload_refs(hash)?;
+
+        Ok(!refs.is_empty()
+            && refs
+                .iter()
+                .filter_map(|id| self.graph.get(id))
+                .all(Transaction::is_private))
+    }
+
+    /// Whether this node is a participant of one of the private transactions referencing the payload
+    fn is_participant(&self, hash: &Hash) -> Result<bool> {
+        let decrypter = match &self.pal_decrypter {
+            Some(decrypter) => decrypter,
+            None => return Ok(false),
+        };
+
+        for id in self.graph.payload_refs(hash)? {
+            if let Some(tx) = self.graph.get(&id) {
+                match decrypter.decrypt(tx) {
+                    Ok(Some(participants)) => {
+                        tracing::debug!(target: "nuts::network", "participant of private transaction '{}' with {} participants", tx.id, participants.len());
+
+                        return Ok(true);
                     }
-                };
+                    Ok(None) => {}
+                    Err(e) => {
+                        tracing::warn!(target: "nuts::network", "failed to decrypt participants of transaction '{}': {}", tx.id, e)
+                    }
+                }
             }
+        }
 
-            if staged.is_empty() {
-                break;
+        Ok(false)
+    }
+
+    /// Answers the query with the payload or an empty payload when it's not present, restricted to a group the
+    /// peer isn't a member of or belongs to a private transaction
+    pub async fn handle_transaction_payload_query(
+        &self,
+        peer_id: Uuid,
+        query: TransactionPayloadQuery,
+    ) -> Result<()> {
+        let hash = query.payload_hash;
+        let peer_groups = self.peer_groups.get(&peer_id).cloned().unwrap_or_default();
+        let data = match self.payload_type(&hash)? {
+            Some(payload_type) if !self.groups.allows(&payload_type, &peer_groups) => {
+                tracing::debug!(target: "nuts::network", "not sharing payload '{}' with peer '{}' outside of group: {}", hash, peer_id, self.groups.group_of(&payload_type).unwrap_or_default());
+
+                Bytes::new()
             }
+            // The participants of the transaction aren't known so private payloads aren't shared
+            _ if self.is_private_payload(&hash)? => {
+                tracing::debug!(target: "nuts::network", "not sharing payload '{}' of private transaction with peer: {}", hash, peer_id);
 
-            // We we're unable to process transactions anymore
-            if before == staged.len() {
-                log::error!(target: "nuts::network", "failed to parse all encoded transactions, there are '{}' unprocessed transactions", staged.len());
-                break;
+                Bytes::new()
+            }
+            _ => self.payload_store.get_shared(&hash)?.unwrap_or_default(),
+        };
+
+        self.send_to(
+            &peer_id,
+            Message::TransactionPayload(TransactionPayload {
+                payload_hash: hash,
+                data,
+            })
+            .into(),
+        )
+        .await
+    }
+
+    /// Stores a payload received from a peer if it's referenced by a transaction and matches it's hash, peers which
+    /// don't serve a payload they were queried for are recorded as failed
+    pub fn handle_transaction_payload(
+        &mut self,
+        peer_id: Uuid,
+        payload: TransactionPayload,
+    ) -> Result<()> {
+        let hash = payload.payload_hash;
+
+        if payload.data.is_empty() {
+            tracing::debug!(target: "nuts::network", "peer '{}' doesn't have payload: {}", peer_id, hash);
+
+            self.retrieval.failed(&hash, peer_id, PayloadFailure::Missing);
+
+            return Ok(());
+        }
+
+        if Hash::new(&payload.data)? != hash {
+            self.retrieval.failed(&hash, peer_id, PayloadFailure::Invalid);
+
+            return Err(anyhow!(
+                "received payload which doesn't match it's hash: {}",
+                hash
+            ));
+        }
+
+        // Expired payloads are still held by peers with a longer retention
+        if self.payload_store.is_expired(&hash)? {
+            tracing::debug!(target: "nuts::network", "ignoring expired payload: {}", hash);
+
+            self.retrieval.received(&hash);
+
+            return Ok(());
+        }
+
+        match self.payload_type(&hash)? {
+            Some(payload_type) if !self.groups.accepts(&payload_type) => {
+                return Err(anyhow!(
+                    "received payload '{}' for group '{}' which this node isn't a member of",
+                    hash,
+                    self.groups.group_of(&payload_type).unwrap_or_default()
+                ))
+            }
+            Some(_) => {}
+            None => {
+                return Err(anyhow!(
+                    "received payload which isn't referenced by any transaction: {}",
+                    hash
+                ))
             }
         }
 
-        Ok(transactions)
+        self.payload_store.add(&hash, payload.data)?;
+        self.retrieval.received(&hash);
+
+        tracing::debug!(target: "nuts::network", "stored payload: {}", hash);
+
+        Ok(())
     }
 
-    pub fn handle_transaction_list(&mut self, transaction_list: TransactionList) -> Result<()> {
-        // First, parse all transactions
-        let mut transactions = self.parse_transaction_list(transaction_list)?;
+    /// Remembers the checksum of the heads advertised by the peer to verify the next transaction list against, and
+    /// which payloads the peer holds
+    pub fn handle_advert_hashes(&mut self, peer_id: Uuid, advert: AdvertHashes) -> Result<()> {
+        let hashes = advert.blocks.into_iter().flatten().collect::<Vec<_>>();
+
+        self.adverts
+            .insert(peer_id, (advert.current_block_date, Hash::xor(&hashes)));
+        let peer = self
+            .identities
+            .get(peer_id)?
+            .map(|identity| PeerSubject::bound(&identity));
+        let advertised = advert.groups.len();
+        let groups = self.groups.verify(advert.groups, peer.as_ref());
+
+        if groups.len() < advertised {
+            tracing::debug!(target: "nuts::network", "ignoring {} group(s) advertised by peer '{}' which don't match it's certificate", advertised - groups.len(), peer_id);
+        }
+
+        self.peer_groups.insert(peer_id, groups);
+
+        match advert.payloads {
+            Some(filter) => self.payload_filters.insert(peer_id, filter),
+            None => self.payload_filters.remove(&peer_id),
+        };
+
+        Ok(())
+    }
+
+    /// Verifies the transaction list against the checksum of the last advert of the peer, which is a lot cheaper
+    /// than verifying the signatures of a truncated or tampered list
+    fn verify_checksum(
+        &mut self,
+        peer_id: &Uuid,
+        transaction_list: &TransactionList,
+    ) -> Result<bool> {
+        let expected = match self.adverts.remove(peer_id) {
+            Some((block_date, checksum)) if block_date == transaction_list.block_date => checksum,
+            _ => return Ok(true),
+        };
+
+        let mut transactions = vec![];
+
+        for tx in transaction_list.transactions.iter() {
+            transactions.push(Transaction::parse_unsafe(std::str::from_utf8(&tx.data)?)?);
+        }
+
+        Ok(Hash::xor(&blocks::heads(&transactions)) == expected)
+    }
+
+    /// Collects the pages of a transaction list and returns the list once all pages were received, lists which
+    /// aren't paginated are returned right away. Lists with too many pages or which grow too large are dropped
+    fn receive_page(
+        &mut self,
+        peer_id: Uuid,
+        page: TransactionList,
+    ) -> Result<Option<TransactionList>> {
+        if page.total_messages <= 1 {
+            return Ok(Some(page));
+        }
+
+        if page.total_messages > MAX_PAGES {
+            self.pages.remove(&peer_id);
+
+            return Err(anyhow!(
+                "transaction-list of {} messages exceeds the limit of {} messages",
+                page.total_messages,
+                MAX_PAGES
+            ));
+        }
+
+        let page_size = page
+            .transactions
+            .iter()
+            .map(|tx| tx.hash.len() + tx.data.len())
+            .sum::<usize>();
+        let (list, size) = match self.pages.remove(&peer_id) {
+            Some((mut list, size))
+                if list.block_date == page.block_date
+                    && list.total_messages == page.total_messages
+                    && list.message_number + 1 == page.message_number =>
+            {
+                list.transactions.extend(page.transactions);
+                list.message_number = page.message_number;
+                (list, size + page_size)
+            }
+            _ if page.message_number == 1 => (page, page_size),
+            _ => {
+                return Err(anyhow!(
+                    "received message {} of {} of transaction-list out of order",
+                    page.message_number,
+                    page.total_messages
+                ))
+            }
+        };
+
+        if size > MAX_LIST_SIZE {
+            return Err(anyhow!(
+                "transaction-list exceeds the limit of {} bytes after message {} of {}",
+                MAX_LIST_SIZE,
+                list.message_number,
+                list.total_messages
+            ));
+        }
+
+        if list.message_number < list.total_messages {
+            self.pages.insert(peer_id, (list, size));
+
+            return Ok(None);
+        }
+
+        Ok(Some(list))
+    }
+
+    async fn receive_transaction_list(
+        &mut self,
+        peer_id: Uuid,
+        transaction_list: TransactionList,
+    ) -> Result<()> {
+        if !self.verify_checksum(&peer_id, &transaction_list)? {
+            tracing::warn!(target: "nuts::network", "checksum of transaction-list from peer '{}' doesn't match it's advert, requesting a resend", peer_id);
+
+            return self
+                .send_to(
+                    &peer_id,
+                    Message::TransactionListQuery(TransactionListQuery {
+                        block_date: transaction_list.block_date,
+                        filter: None,
+                    })
+                    .into(),
+                )
+                .await;
+        }
+
+        // Transactions which were persisted before this list was received are irrelevant
+        self.skip_persisted();
+
+        let block_date = transaction_list.block_date;
+        let orphans = self.graph.orphans().len();
+        let payloads = self
+            .handle_transaction_list(peer_id, transaction_list)
+            .await?;
+
+        // Transactions of a recent block can refer to transactions of older blocks which weren't synced yet
+        if block_date != 0 && self.graph.orphans().len() > orphans {
+            tracing::debug!(target: "nuts::network", "transactions of block {} refer to missing transactions, fully syncing with peer '{}' next time", block_date, peer_id);
+
+            self.full_sync.insert(peer_id);
+        }
+
+        self.admitted += payloads.len() as u64;
+
+        self.synced(&peer_id, !payloads.is_empty());
+
+        // Fetch the payloads of the new transactions from the same peer, including the orphans which were attached
+        let mut payloads = payloads;
+
+        for hash in self.take_persisted()? {
+            if !payloads.contains(&hash) {
+                payloads.push(hash);
+            }
+        }
+
+        for hash in payloads {
+            if self.payload_store.contains(&hash)? || self.payload_store.is_expired(&hash)? {
+                continue;
+            }
+
+            // Payloads of groups this node isn't a member of won't be shared by the peer anyway
+            if let Some(payload_type) = self.payload_type(&hash)? {
+                if !self.groups.accepts(&payload_type) {
+                    continue;
+                }
+            }
+
+            if self.is_private_payload(&hash)? && !self.is_participant(&hash)? {
+                tracing::debug!(target: "nuts::network", "not retrieving payload '{}' of private transaction this node isn't a participant of", hash);
+
+                continue;
+            }
+
+            // Payloads which are already queried are retried from another peer when they don't arrive
+            if !self.retrieval.track(hash.clone(), peer_id) {
+                continue;
+            }
+
+            self.send_to(
+                &peer_id,
+                Message::TransactionPayloadQuery(TransactionPayloadQuery { payload_hash: hash })
+                    .into(),
+            )
+            .await?;
+        }
+
+        Ok(())
+    }
+
+    /// Discards the transactions which were persisted since the last call
+    fn skip_persisted(&mut self) {
+        loop {
+            match self.persisted.try_recv() {
+                Ok(_) | Err(TryRecvError::Lagged(_)) => continue,
+                Err(_) => break,
+            }
+        }
+    }
+
+    /// Returns the payloads of the transactions which were persisted since the last call, when transactions were
+    /// skipped because the server lagged behind all payloads which are missing from the payload store are returned
+    fn take_persisted(&mut self) -> Result<Vec<Hash>> {
+        let mut payloads = vec![];
+        let mut lagged = false;
+
+        loop {
+            match self.persisted.try_recv() {
+                Ok(tx) => payloads.push(tx.payload),
+                Err(TryRecvError::Lagged(_)) => lagged = true,
+                Err(_) => break,
+            }
+        }
+
+        if lagged {
+            payloads.clear();
+
+            for tx in self.graph.iter() {
+                if !self.payload_store.contains(&tx.payload)? && !payloads.contains(&tx.payload) {
+                    payloads.push(tx.payload.clone());
+                }
+            }
+        }
+
+        Ok(payloads)
+    }
+
+    /// Answers the query with the transactions of the requested block (or all transactions in the local DAG for
+    /// block date 0) so that the peer can sync from us, or only the sub-DAG matching the filter
+    pub async fn handle_transaction_list_query(
+        &self,
+        peer_id: Uuid,
+        query: TransactionListQuery,
+        compression: bool,
+        pagination: bool,
+    ) -> Result<()> {
+        if let Some(SubDagFilter { did, payload_type }) = query.filter {
+            let transactions = self.graph.sub_dag(|tx| {
+                did.as_deref()
+                    .map_or(true, |did| tx.key_id.split('#').next() == Some(did))
+                    && payload_type
+                        .as_ref()
+                        .map_or(true, |payload_type| &tx.payload_type == payload_type)
+            });
+
+            // The sub-DAG doesn't match the advertised heads so no advert is sent
+            return self
+                .send_list(
+                    peer_id,
+                    query.block_date,
+                    transactions,
+                    compression,
+                    pagination,
+                )
+                .await;
+        }
+
+        let transactions = self.graph.block(query.block_date);
+        let heads = if query.block_date == 0 {
+            self.graph.heads()
+        } else {
+            blocks::heads(&transactions)
+        };
+
+        // Only the requested block is advertised as the checksum of the transaction list is verified against it
+        self.send_to(
+            &peer_id,
+            Message::AdvertHashes(AdvertHashes {
+                current_block_date: query.block_date,
+                blocks: vec![heads],
+                historic_hash: None,
+                groups: self.groups.memberships.clone(),
+                payloads: Some(PayloadFilter::new(&self.payload_store.hashes()?)),
+            })
+            .into(),
+        )
+        .await?;
+        self.send_list(
+            peer_id,
+            query.block_date,
+            transactions,
+            compression,
+            pagination,
+        )
+        .await
+    }
+
+    /// Sends the transactions as a transaction list, which is split into multiple messages of at most
+    /// [`MAX_PAGE_SIZE`] when the peer supports it
+    async fn send_list(
+        &self,
+        peer_id: Uuid,
+        block_date: u32,
+        transactions: Vec<Transaction>,
+        compression: bool,
+        pagination: bool,
+    ) -> Result<()> {
+        let mut pages = vec![vec![]];
+        let mut size = 0;
+
+        for tx in transactions {
+            if pagination && size > 0 && size + tx.data.len() > MAX_PAGE_SIZE {
+                pages.push(vec![]);
+                size = 0;
+            }
+
+            size += tx.data.len();
+            pages.last_mut().unwrap().push(tx.into());
+        }
+
+        let total_messages = if pagination { pages.len() as u32 } else { 0 };
+
+        for (idx, transactions) in pages.into_iter().enumerate() {
+            let list = proto::TransactionList::from(TransactionList {
+                block_date,
+                transactions,
+                message_number: if pagination { idx as u32 + 1 } else { 0 },
+                total_messages,
+            });
+
+            // Compression is applied to the wire format as the compressed list replaces the transactions
+            self.send_to(
+                &peer_id,
+                netmsg!(network_message::Message::TransactionList(if compression {
+                    compress_list(list)
+                } else {
+                    list
+                })),
+            )
+            .await?;
+        }
+
+        Ok(())
+    }
+
+    /// Adds the transactions to the graph and returns the payload hashes of the transactions which were new
+    pub async fn handle_transaction_list(
+        &mut self,
+        peer_id: Uuid,
+        transaction_list: TransactionList,
+    ) -> Result<Vec<Hash>> {
+        // First, verify all transactions and schedule them in an order which can be applied to the graph
+        let verified = self
+            .admission
+            .verify(
+                &mut self.key_store,
+                transaction_list
+                    .transactions
+                    .into_iter()
+                    .map(|tx| tx.data)
+                    .collect(),
+            )
+            .await?;
+        let mut transactions = Admission::schedule(&self.graph, verified);
+
+        if transactions.is_empty() {
+            return Ok(vec![]);
+        }
+
+        if !self.record_verification {
+            for tx in transactions.iter_mut() {
+                tx.verification = None;
+            }
+        }
+
+        let mut added = vec![];
 
         // Then, verify if we have a root transaction or that we can get it from another node
         if self.graph.root().is_none() {
@@ -133,7 +1463,15 @@ impl Server {
                     continue;
                 }
 
-                self.graph.add(transactions.remove(i))?;
+                let tx = transactions.remove(i);
+                let payload = tx.payload.clone();
+
+                self.observe_key_usage(&tx);
+
+                if self.graph.add_from(tx, Some(peer_id))?.is_some() {
+                    added.push(payload);
+                }
+
                 break;
             }
 
@@ -152,125 +1490,467 @@ impl Server {
                 continue;
             }
 
-            self.graph.add(tx)?;
+            let payload = tx.payload.clone();
+
+            self.observe_key_usage(&tx);
+
+            if self.graph.add_from(tx, Some(peer_id))?.is_some() {
+                added.push(payload);
+            }
         }
 
-        Ok(())
+        Ok(added)
     }
 
-    async fn connect(&self, addr: String) -> Result<NetworkClient<Channel>> {
-        // Configure mTLS and initialize the client
-        let tls = ClientTlsConfig::new()
-            .ca_certificate(self.ca.clone())
-            .identity(self.identity.clone());
-        let channel = Channel::from_shared(addr.into_bytes())?
-            .tls_config(tls)?
-            .connect()
-            .await?;
+    /// Records which key signed an admitted transaction and reports the anomalies this causes
+    fn observe_key_usage(&self, tx: &Transaction) {
+        match self.key_usage.observe(tx) {
+            Ok(anomalies) => {
+                for anomaly in anomalies {
+                    tracing::warn!(target: "nuts::audit", "possible key compromise: {} (transaction: {})", anomaly, tx.id);
 
-        Ok(NetworkClient::new(channel))
+                    if let Some(handler) = &self.anomaly_handler {
+                        handler.handle(&anomaly);
+                    }
+                }
+            }
+            Err(e) => {
+                tracing::error!(target: "nuts::network", "failed to record usage of key '{}': {}", tx.key_id, e)
+            }
+        }
     }
 
-    fn client_stream(&self) -> Result<impl Stream<Item = NetworkMessage>> {
-        let outbound = async_stream::stream! {
-            let mut interval = time::interval(Duration::from_secs(2));
+    /// Starts a conversation with a peer and returns it's ID
+    fn start_conversation(&mut self, peer_id: Uuid) -> Vec<u8> {
+        let now = Instant::now();
+        let id = Uuid::new_v4().as_bytes().to_vec();
 
-            // Initially, ask for the complete transaction list
-            yield netmsg!(Message::TransactionListQuery(TransactionListQuery {
-                block_date: 0,
-            }));
+        self.conversations
+            .retain(|_, conversation| now - conversation.started_at < CONVERSATION_TIMEOUT);
+        self.conversations.insert(
+            id.clone(),
+            Conversation {
+                peer_id,
+                started_at: now,
+            },
+        );
 
-            while let _ = interval.tick().await {
-                continue;
-                //yield netmsg!(Message::AdvertHashes(AdvertHashes {
-                //    block_date: 0,
-                //    transactions: vec![],
-                //}));
+        id
+    }
+
+    /// Returns an error when the message isn't part of a conversation which was started with the peer
+    fn verify_conversation(&self, peer_id: Uuid, id: &[u8]) -> Result<()> {
+        match self.conversations.get(id) {
+            Some(conversation) if conversation.peer_id == peer_id => Ok(()),
+            _ => Err(anyhow!("unknown conversation: {}", hex::encode(id))),
+        }
+    }
+
+    /// Returns the hashes of all transactions which are either in the DAG or waiting as orphan
+    fn known_transactions(&self) -> HashSet<Hash> {
+        self.graph
+            .iter()
+            .chain(self.graph.orphans())
+            .map(|tx| tx.id.clone())
+            .collect()
+    }
+
+    /// Returns the hashes which aren't known by this node
+    fn unknown_refs(&self, refs: Vec<Hash>) -> Vec<Hash> {
+        let known = self.known_transactions();
+
+        refs.into_iter().filter(|id| !known.contains(id)).collect()
+    }
+
+    /// Queries transactions from a peer in batches of at most `sync_batch_size` hashes, as every response ends its
+    /// conversation each batch after the first one is sent in a new conversation
+    async fn query_transactions(
+        &mut self,
+        peer_id: Uuid,
+        mut conversation_id: Option<Vec<u8>>,
+        refs: Vec<Hash>,
+        outbound: &Sender<Envelope>,
+    ) -> Result<()> {
+        for batch in refs.chunks(self.sync_batch_size.max(1)) {
+            let conversation_id = match conversation_id.take() {
+                Some(id) => id,
+                None => self.start_conversation(peer_id),
+            };
+
+            outbound
+                .send(
+                    v2::Message::TransactionListQuery(v2::TransactionListQuery {
+                        conversation_id,
+                        refs: batch.to_vec(),
+                    })
+                    .into(),
+                )
+                .await?;
+        }
+
+        Ok(())
+    }
+
+    /// Starts a conversation to compare the DAG with a peer using version 2 of the protocol
+    fn state(&mut self, peer_id: Uuid) -> Envelope {
+        v2::Message::State(v2::State {
+            conversation_id: self.start_conversation(peer_id),
+            xor: self.graph.xor(),
+            lc: self.graph.lamport_clock(),
+        })
+        .into()
+    }
+
+    /// Sends the transactions which were added to the DAG to all peers which use version 2 of the protocol, the
+    /// transactions which are added at the same time are sent together. When transactions were skipped because the
+    /// server lagged behind, the heads are sent as well so that peers can query what they're missing
+    async fn gossip(&mut self, mut transactions: Vec<Hash>) {
+        let mut lagged = false;
+
+        loop {
+            match self.added.try_recv() {
+                Ok(tx) => transactions.push(tx.id),
+                Err(TryRecvError::Lagged(_)) => lagged = true,
+                Err(_) => break,
             }
+        }
+
+        if lagged {
+            for id in self.graph.heads() {
+                if !transactions.contains(&id) {
+                    transactions.push(id);
+                }
+            }
+        }
+
+        self.gossip_ids(transactions).await;
+    }
+
+    /// Sends the IDs of new transactions to all peers which use version 2 of the protocol
+    async fn gossip_ids(&mut self, transactions: Vec<Hash>) {
+        if self.peers_v2.is_empty() {
+            return;
+        }
+
+        let gossip = v2::Gossip {
+            xor: self.graph.xor(),
+            lc: self.graph.lamport_clock(),
+            transactions,
         };
+        let mut disconnected = vec![];
 
-        Ok(outbound)
+        for (peer_id, outbound) in self.peers_v2.iter() {
+            let envelope = v2::Message::Gossip(gossip.clone()).into();
+
+            if outbound.send(envelope).await.is_err() {
+                disconnected.push(*peer_id);
+            }
+        }
+
+        for peer_id in disconnected {
+            self.peers_v2.remove(&peer_id);
+            self.received.remove(&peer_id);
+            self.queries.remove(&peer_id);
+        }
     }
 
-    fn new_request<T>(&self, body: T) -> Result<Request<T>> {
-        let mut request = Request::new(body);
-        let metadata = request.metadata_mut();
+    async fn handle_envelope(&mut self, msg: MsgV2) {
+        let peer_id = msg.peer_id;
+        let outbound = msg.outbound;
+        let trace = msg.trace;
 
-        // Sets the Peer ID as described in: https://nuts-foundation.gitbook.io/drafts/rfc/rfc005-distributed-network-using-grpc#6-1-peer-identification
-        metadata.insert(
-            "peerid",
-            MetadataValue::from_str(&self.peer_id.to_string())?,
-        );
+        // The first message only signals that the connection was established
+        if msg.message.is_some() {
+            *self.received.entry(peer_id).or_default() += 1;
+        }
+
+        if let Err(e) = match msg.message {
+            None => self.handle_connected_v2(peer_id, outbound).await,
+            Some(v2::Message::Gossip(gossip)) => self.handle_gossip(peer_id, gossip),
+            Some(v2::Message::State(state)) => self.handle_state(state, &outbound).await,
+            Some(v2::Message::TransactionSet(set)) => {
+                self.handle_transaction_set(peer_id, set, &outbound).await
+            }
+            Some(v2::Message::TransactionListQuery(query)) => {
+                self.handle_transaction_refs_query(query, &outbound).await
+            }
+            Some(v2::Message::TransactionList(list)) => {
+                self.handle_transaction_list_v2(peer_id, list).await
+            }
+        } {
+            let message = trace.annotate(e);
+
+            tracing::error!(target: "nuts::network", "error handling message for peer '{}': {}", peer_id, message);
+            self.record_error(peer_id, message);
+        }
+    }
+
+    /// Starts syncing with a peer which uses version 2 of the protocol by comparing the state of the DAGs
+    async fn handle_connected_v2(
+        &mut self,
+        peer_id: Uuid,
+        outbound: Sender<Envelope>,
+    ) -> Result<()> {
+        self.peers_v2.insert(peer_id, outbound.clone());
+        self.scheduler
+            .register(peer_id, Outbound::V2(outbound.clone()));
+
+        let state = self.state(peer_id);
+
+        outbound.send(state).await?;
+        self.flush_outbox().await;
+
+        Ok(())
+    }
+
+    /// Announces the transactions which were created while the node wasn't running to all connected peers, peers
+    /// which aren't connected yet retrieve these when syncing
+    async fn flush_outbox(&mut self) {
+        let ids = match self.outbox.take() {
+            Ok(ids) => ids,
+            Err(e) => {
+                tracing::error!(target: "nuts::network", "failed to read outbox: {}", e);
+                return;
+            }
+        };
+        let transactions = ids
+            .into_iter()
+            .filter_map(|id| self.graph.get(&id).cloned())
+            .collect::<Vec<_>>();
 
-        // Sets the protocol version described in: https://nuts-foundation.gitbook.io/drafts/rfc/rfc005-distributed-network-using-grpc#6-4-protocol-version
-        metadata.insert("version", MetadataValue::from_static("1"));
+        if transactions.is_empty() {
+            return;
+        }
+
+        tracing::info!(target: "nuts::network", "announcing {} published transactions to peers", transactions.len());
 
-        Ok(request)
+        let ids = transactions.iter().map(|tx| tx.id.clone()).collect();
+
+        self.broadcast(
+            Message::TransactionList(TransactionList {
+                block_date: 0,
+                transactions: transactions.into_iter().map(Into::into).collect(),
+                message_number: 0,
+                total_messages: 0,
+            })
+            .into(),
+        )
+        .await;
+        self.gossip_ids(ids).await;
     }
 
-    fn parse_metadata<'r, T>(&self, response: &'r Response<T>) -> Result<(Uuid, &'r str)> {
-        let metadata = response.metadata();
-        let peer_id = metadata
-            .get("peerid")
-            .ok_or_else(|| anyhow!("unable to connect to peer because of missing peer ID"))?
-            .to_str()?;
+    /// Queries the gossiped transactions which aren't known yet
+    fn handle_gossip(&mut self, peer_id: Uuid, gossip: v2::Gossip) -> Result<()> {
+        let refs = self.unknown_refs(gossip.transactions);
 
-        // It looks like the protocol version header is not implemented yet, so when strict isn't enabled just return 1 instead
-        if !self.strict {
-            return Ok((Uuid::parse_str(peer_id)?, "1"));
+        if refs.is_empty() {
+            return Ok(());
         }
 
-        let version = metadata
-            .get("version")
-            .ok_or_else(|| anyhow!("peer didn't provide the protocol version"))?
-            .to_str()?;
+        tracing::debug!(target: "nuts::network", "queueing query for {} gossiped transactions of peer: {}", refs.len(), peer_id);
 
-        Ok((Uuid::parse_str(peer_id)?, version))
+        self.queries.add(peer_id, refs);
+
+        Ok(())
     }
 
-    pub async fn connect_to_peer(&mut self, addr: String) -> Result<()> {
-        log::info!(target: "nuts::network", "connecting to {}..", addr);
+    /// Responds to the state of a peer with the hashes of all transactions when the DAGs differ, this is a
+    /// simplification of the IBLT which is used by other implementations
+    async fn handle_state(&self, state: v2::State, outbound: &Sender<Envelope>) -> Result<()> {
+        let xor = self.graph.xor();
+        let transactions = if state.xor == xor {
+            vec![]
+        } else {
+            self.graph.iter().map(|tx| tx.id.clone()).collect()
+        };
 
-        let mut client = self.connect(addr.clone()).await?;
-        let tx = self.tx.clone();
+        outbound
+            .send(
+                v2::Message::TransactionSet(v2::TransactionSet {
+                    conversation_id: state.conversation_id,
+                    xor,
+                    lc: self.graph.lamport_clock(),
+                    transactions,
+                })
+                .into(),
+            )
+            .await?;
+
+        Ok(())
+    }
 
-        // Create the initial connection request
-        let request = self.new_request(self.client_stream()?)?;
+    /// Queries the transactions of the peer which aren't known yet, or ends the conversation when the DAGs match
+    async fn handle_transaction_set(
+        &mut self,
+        peer_id: Uuid,
+        set: v2::TransactionSet,
+        outbound: &Sender<Envelope>,
+    ) -> Result<()> {
+        self.verify_conversation(peer_id, &set.conversation_id)?;
 
-        // Connect to the peer, get it's peer ID and start the message loop in a task
-        let response: Response<_> = client.connect_method(request).await?;
-        let (peer_id, version) = self.parse_metadata(&response)?;
+        let refs = self.unknown_refs(set.transactions);
 
-        // Currently only protocol version 1 is supported
-        if version != "1" {
-            log::info!(target: "nuts::network", "closing connection to peer '{}' due to invalid protocol version: {}", peer_id, version);
+        if set.xor == self.graph.xor() || refs.is_empty() {
+            self.conversations.remove(&set.conversation_id);
+            self.synced(&peer_id, false);
 
-            return Err(anyhow!("invalid protocol version: {}", version));
+            return Ok(());
         }
 
-        tokio::spawn(async move {
-            let mut stream = response.into_inner();
+        tracing::debug!(target: "nuts::network", "querying {} transactions of peer: {}", refs.len(), peer_id);
 
-            log::info!(target: "nuts::network", "connected to peer: {}", peer_id);
+        self.query_transactions(peer_id, Some(set.conversation_id), refs, outbound)
+            .await
+    }
 
-            loop {
-                match stream.message().await {
-                    Ok(network_message) => {
-                        if let Some(network_message) = network_message {
-                            if let Some(message) = network_message.message {
-                                if let Err(e) = tx.send(Msg { peer_id, message }).await {
-                                    log::error!(target: "nuts::network", "failed to handle message for peer '{}': {}", peer_id, e);
-                                }
-                            }
-                        }
-                    }
-                    Err(e) => {
-                        log::error!(target: "nuts::network", "failed to receiver message for peer '{}': {}", peer_id, e)
-                    }
+    /// Responds with the queried transactions, payloads are included unless they're private or restricted to a group
+    /// as the groups of peers using version 2 of the protocol are unknown
+    async fn handle_transaction_refs_query(
+        &self,
+        query: v2::TransactionListQuery,
+        outbound: &Sender<Envelope>,
+    ) -> Result<()> {
+        let refs = query.refs.into_iter().collect::<HashSet<_>>();
+        let mut transactions = vec![];
+
+        for tx in self.graph.iter().filter(|tx| refs.contains(&tx.id)) {
+            let payload = if self.groups.allows(&tx.payload_type, &[]) && !tx.is_private() {
+                self.payload_store.get_shared(&tx.payload)?
+            } else {
+                None
+            };
+
+            transactions.push(v2::Transaction {
+                hash: tx.id.clone(),
+                data: tx.data.clone(),
+                payload,
+            });
+        }
+
+        outbound
+            .send(
+                v2::Message::TransactionList(v2::TransactionList {
+                    conversation_id: query.conversation_id,
+                    transactions,
+                })
+                .into(),
+            )
+            .await?;
+
+        Ok(())
+    }
+
+    /// Adds the queried transactions to the graph and stores the payloads which were included
+    async fn handle_transaction_list_v2(
+        &mut self,
+        peer_id: Uuid,
+        list: v2::TransactionList,
+    ) -> Result<()> {
+        self.verify_conversation(peer_id, &list.conversation_id)?;
+        self.conversations.remove(&list.conversation_id);
+
+        let mut payloads = vec![];
+        let transactions = list
+            .transactions
+            .into_iter()
+            .map(|tx| {
+                payloads.extend(tx.payload);
+
+                EncodedTransaction {
+                    hash: tx.hash,
+                    data: tx.data,
                 }
+            })
+            .collect();
+        let orphans = self
+            .graph
+            .orphans()
+            .iter()
+            .map(|tx| tx.id.clone())
+            .collect::<HashSet<_>>();
+        let added = self
+            .handle_transaction_list(
+                peer_id,
+                TransactionList {
+                    block_date: 0,
+                    transactions,
+                    message_number: 0,
+                    total_messages: 0,
+                },
+            )
+            .await?;
+
+        self.admitted += added.len() as u64;
+        self.synced(&peer_id, !added.is_empty());
+
+        // Only the orphans which were just parked are resolved, otherwise a peer which doesn't have the missing
+        // previous transactions would be queried over and over again
+        let missing = self
+            .graph
+            .orphans()
+            .iter()
+            .filter(|tx| !orphans.contains(&tx.id))
+            .flat_map(|tx| tx.prevs.iter().cloned())
+            .collect::<Vec<_>>();
+
+        self.queries.add(peer_id, self.unknown_refs(missing));
+
+        for data in payloads {
+            let payload_hash = Hash::new(&data)?;
+
+            if self.payload_store.contains(&payload_hash)? {
+                continue;
             }
-        });
+
+            if let Err(e) =
+                self.handle_transaction_payload(peer_id, TransactionPayload { payload_hash, data })
+            {
+                tracing::warn!(target: "nuts::network", "ignoring payload from peer '{}': {}", peer_id, e);
+            }
+        }
 
         Ok(())
     }
+
+    /// Starts accepting incoming connections from other peers on the given address
+    pub fn listen(&self, addr: SocketAddr) -> Result<(), NetworkError> {
+        self.peers.listen(addr)
+    }
+
+    /// Starts accepting incoming connections from other peers on an already bound socket
+    pub fn listen_on(&self, listener: std::net::TcpListener) -> Result<(), NetworkError> {
+        self.peers.listen_on(listener)
+    }
+
+    /// Returns a handle to replace the CA certificates and identity of the node while it's running
+    pub fn tls_reloader(&self) -> TlsReloader {
+        self.peers.tls_reloader()
+    }
+
+    /// Returns the addresses of all peers which were connected to before
+    pub fn known_peers(&self) -> Result<Vec<String>> {
+        Ok(self
+            .address_book
+            .list()?
+            .into_iter()
+            .map(|peer| peer.addr)
+            .collect())
+    }
+
+    /// Connects to the peer and closes the connection as soon as it's established
+    pub async fn probe(&self, addr: String) -> Result<(), NetworkError> {
+        self.peers.connect(addr).await?.abort();
+
+        Ok(())
+    }
+
+    /// Connects to the peer in the background and reconnects whenever the connection is lost
+    pub fn connect_to_peer(&self, addr: String) {
+        self.peers.bootstrap(addr);
+    }
+
+    /// Connects to the peers which are published in DNS in the background, see [`DiscoveryPolicy`]
+    pub fn discover(&self, policy: DiscoveryPolicy) {
+        self.peers.discover(policy);
+    }
 }
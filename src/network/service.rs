@@ -0,0 +1,405 @@
+use std::pin::Pin;
+use std::sync::Arc;
+
+use anyhow::anyhow;
+use futures::{Stream, StreamExt};
+use tokio::sync::broadcast;
+use tokio::sync::mpsc::channel;
+use tokio::sync::{oneshot, watch};
+use tonic::{Request, Response, Status, Streaming};
+use tracing::Instrument;
+use uuid::Uuid;
+
+use crate::metrics::Metrics;
+use crate::network::access::{PeerAccess, PeerSubject};
+use crate::network::authorize::authorize;
+use crate::network::certificate::PeerCertificate;
+use crate::network::connection_log::{ConnectionEventKind, ConnectionLog};
+use crate::network::connections::{Connection, Connections};
+use crate::network::handshake::{NodeInfo, PeerInfo};
+use crate::network::identities::PeerIdentities;
+use crate::network::intake::Intake;
+use crate::network::peers::{
+    outbound_stream, outbound_stream_v2, receive_envelopes, receive_messages, Msg, MsgV2,
+};
+use crate::network::trace::PeerTrace;
+use crate::network::{AuthorizePeer, Transaction};
+use crate::proto::v2::{protocol_server::Protocol, Envelope};
+use crate::proto::{network_server::Network, NetworkMessage};
+
+type ConnectStream = Pin<Box<dyn Stream<Item = Result<NetworkMessage, Status>> + Send + Sync>>;
+type EnvelopeStream = Pin<Box<dyn Stream<Item = Result<Envelope, Status>> + Send + Sync>>;
+
+/// Binds the peer ID to the subject of the client certificate, peers without a client certificate are rejected
+fn bind_identity(
+    identities: &PeerIdentities,
+    peer_id: Uuid,
+    certificate: Option<&[u8]>,
+) -> anyhow::Result<()> {
+    let certificate = certificate
+        .ok_or_else(|| anyhow!("peer '{}' didn't present a client certificate", peer_id))?;
+
+    identities.bind(peer_id, &PeerCertificate::parse(certificate)?)
+}
+
+/// Parses and authorizes the metadata of an incoming connection using the given protocol version, the outcome of the
+/// handshake is recorded in the connection log (by address when the peer ID is unknown)
+#[allow(clippy::too_many_arguments)]
+async fn accept<T>(
+    strict: bool,
+    node: &NodeInfo,
+    access: &PeerAccess,
+    authorizer: &Option<Arc<dyn AuthorizePeer>>,
+    connection_log: &ConnectionLog,
+    identities: &PeerIdentities,
+    metrics: &Metrics,
+    expected_version: &str,
+    request: &Request<T>,
+    trace: &PeerTrace,
+) -> Result<PeerInfo, Status> {
+    let addr = request.remote_addr().map(|addr| addr.to_string());
+    let certificate = request
+        .peer_certs()
+        .and_then(|certs| certs.first().map(|cert| cert.get_ref().to_vec()));
+
+    // Rejected before the handshake, so the peer ID isn't known yet
+    if let Err(e) = access.check(&PeerSubject::inbound(
+        request.remote_addr(),
+        certificate.as_deref(),
+    )) {
+        tracing::info!(target: "nuts::network", "rejecting connection from {}: {}", addr.as_deref().unwrap_or("unknown address"), e);
+        metrics.increment("peers.blocked");
+
+        if let Some(addr) = &addr {
+            connection_log.log(
+                addr,
+                ConnectionEventKind::HandshakeFailed,
+                Some(addr),
+                trace.annotate(&e),
+            );
+        }
+
+        return Err(Status::permission_denied(e.to_string()));
+    }
+
+    let info = match node.parse_metadata(strict, request.metadata()) {
+        Ok(info) => info,
+        Err(e) => {
+            if let Some(addr) = &addr {
+                connection_log.log(
+                    addr,
+                    ConnectionEventKind::HandshakeFailed,
+                    Some(addr),
+                    trace.annotate(&e),
+                );
+            }
+
+            return Err(Status::permission_denied(e.to_string()));
+        }
+    };
+    let peer = info.peer_id.to_string();
+
+    trace.identify(info.peer_id);
+
+    if info.version != expected_version {
+        tracing::info!(target: "nuts::network", "rejecting connection from peer '{}' due to invalid protocol version: {}", info.peer_id, info.version);
+
+        let message = format!("invalid protocol version: {}", info.version);
+
+        connection_log.log(
+            &peer,
+            ConnectionEventKind::HandshakeFailed,
+            addr.as_deref(),
+            trace.annotate(&message),
+        );
+
+        return Err(Status::failed_precondition(message));
+    }
+
+    if let Err(e) = bind_identity(identities, info.peer_id, certificate.as_deref()) {
+        tracing::info!(target: "nuts::network", "rejecting connection from peer '{}' due to it's certificate: {}", info.peer_id, e);
+
+        connection_log.log(
+            &peer,
+            ConnectionEventKind::HandshakeFailed,
+            addr.as_deref(),
+            trace.annotate(&e),
+        );
+
+        return Err(Status::permission_denied(e.to_string()));
+    }
+
+    if let Err(e) = authorize(authorizer, info.handshake(certificate, true), metrics).await {
+        connection_log.log(
+            &peer,
+            ConnectionEventKind::HandshakeFailed,
+            addr.as_deref(),
+            trace.annotate(&e),
+        );
+
+        return Err(Status::permission_denied(e.to_string()));
+    }
+
+    tracing::info!(target: "nuts::network", "accepted connection from peer: {} (DID: {}, protocol version: {})", info.peer_id, info.did.as_deref().unwrap_or("unknown"), info.version);
+
+    connection_log.log(
+        &peer,
+        ConnectionEventKind::Handshake,
+        addr.as_deref(),
+        info.describe(),
+    );
+
+    Ok(info)
+}
+
+/// Registers the incoming connection, which is rejected when another connection with the peer is kept instead
+fn register(
+    connections: &Connections,
+    peer_id: Uuid,
+    stop: oneshot::Sender<()>,
+) -> Result<Connection, Status> {
+    connections.register(peer_id, true, stop).ok_or_else(|| {
+        tracing::info!(target: "nuts::network", "rejecting duplicate connection from peer: {}", peer_id);
+
+        Status::already_exists("already connected to this node")
+    })
+}
+
+/// Implementation of the `Network` gRPC service which accepts incoming connections from other peers
+pub struct Service {
+    strict: bool,
+    node: NodeInfo,
+    access: PeerAccess,
+    connections: Connections,
+    channel_capacity: usize,
+    intake: Intake<Msg>,
+    added: broadcast::Sender<Transaction>,
+    closing: watch::Receiver<bool>,
+    authorizer: Option<Arc<dyn AuthorizePeer>>,
+    connection_log: ConnectionLog,
+    identities: PeerIdentities,
+    metrics: Metrics,
+}
+
+impl Service {
+    #[allow(clippy::too_many_arguments)]
+    pub fn new(
+        strict: bool,
+        node: NodeInfo,
+        access: PeerAccess,
+        connections: Connections,
+        channel_capacity: usize,
+        intake: Intake<Msg>,
+        added: broadcast::Sender<Transaction>,
+        closing: watch::Receiver<bool>,
+        authorizer: Option<Arc<dyn AuthorizePeer>>,
+        connection_log: ConnectionLog,
+        identities: PeerIdentities,
+        metrics: Metrics,
+    ) -> Self {
+        Self {
+            strict,
+            node,
+            access,
+            connections,
+            channel_capacity,
+            intake,
+            added,
+            closing,
+            authorizer,
+            connection_log,
+            identities,
+            metrics,
+        }
+    }
+}
+
+#[tonic::async_trait]
+impl Network for Service {
+    type ConnectStream = ConnectStream;
+
+    async fn connect_method(
+        &self,
+        request: Request<Streaming<NetworkMessage>>,
+    ) -> Result<Response<Self::ConnectStream>, Status> {
+        let addr = request.remote_addr().map(|addr| addr.to_string());
+        let trace = PeerTrace::new(true, addr.as_deref());
+        // Currently only protocol version 1 is supported
+        let PeerInfo {
+            peer_id,
+            capabilities,
+            ..
+        } = accept(
+            self.strict,
+            &self.node,
+            &self.access,
+            &self.authorizer,
+            &self.connection_log,
+            &self.identities,
+            &self.metrics,
+            "1",
+            &request,
+            &trace,
+        )
+        .instrument(trace.span())
+        .await?;
+        let (stop, stopped) = oneshot::channel();
+        let connection = trace
+            .span()
+            .in_scope(|| register(&self.connections, peer_id, stop))?;
+        let (outbound, outbound_rx) = channel(self.channel_capacity);
+        let receive = receive_messages(
+            peer_id,
+            capabilities,
+            request.into_inner(),
+            self.intake.sender(),
+            outbound,
+            connection,
+            trace.clone(),
+        );
+        let connection_log = self.connection_log.clone();
+
+        tokio::spawn(
+            async move {
+                let reason = receive.await;
+
+                connection_log.log(
+                    &peer_id.to_string(),
+                    ConnectionEventKind::Disconnect,
+                    addr.as_deref(),
+                    trace.annotate(reason),
+                );
+            }
+            .instrument(trace.span()),
+        );
+
+        let stream: ConnectStream = Box::pin(
+            outbound_stream(
+                outbound_rx,
+                self.added.subscribe(),
+                self.closing.clone(),
+                stopped,
+            )
+            .map(Ok),
+        );
+        let mut response = Response::new(stream);
+
+        self.node
+            .set_metadata("1", response.metadata_mut())
+            .map_err(|e| Status::internal(e.to_string()))?;
+
+        Ok(response)
+    }
+}
+
+/// Implementation of the `Protocol` gRPC service which accepts incoming connections from peers using version 2 of
+/// the protocol
+pub struct ServiceV2 {
+    strict: bool,
+    node: NodeInfo,
+    access: PeerAccess,
+    connections: Connections,
+    channel_capacity: usize,
+    intake: Intake<MsgV2>,
+    closing: watch::Receiver<bool>,
+    authorizer: Option<Arc<dyn AuthorizePeer>>,
+    connection_log: ConnectionLog,
+    identities: PeerIdentities,
+    metrics: Metrics,
+}
+
+impl ServiceV2 {
+    #[allow(clippy::too_many_arguments)]
+    pub fn new(
+        strict: bool,
+        node: NodeInfo,
+        access: PeerAccess,
+        connections: Connections,
+        channel_capacity: usize,
+        intake: Intake<MsgV2>,
+        closing: watch::Receiver<bool>,
+        authorizer: Option<Arc<dyn AuthorizePeer>>,
+        connection_log: ConnectionLog,
+        identities: PeerIdentities,
+        metrics: Metrics,
+    ) -> Self {
+        Self {
+            strict,
+            node,
+            access,
+            connections,
+            channel_capacity,
+            intake,
+            closing,
+            authorizer,
+            connection_log,
+            identities,
+            metrics,
+        }
+    }
+}
+
+#[tonic::async_trait]
+impl Protocol for ServiceV2 {
+    type StreamStream = EnvelopeStream;
+
+    async fn stream(
+        &self,
+        request: Request<Streaming<Envelope>>,
+    ) -> Result<Response<Self::StreamStream>, Status> {
+        let addr = request.remote_addr().map(|addr| addr.to_string());
+        let trace = PeerTrace::new(true, addr.as_deref());
+        let PeerInfo { peer_id, .. } = accept(
+            self.strict,
+            &self.node,
+            &self.access,
+            &self.authorizer,
+            &self.connection_log,
+            &self.identities,
+            &self.metrics,
+            "2",
+            &request,
+            &trace,
+        )
+        .instrument(trace.span())
+        .await?;
+        let (stop, stopped) = oneshot::channel();
+        let connection = trace
+            .span()
+            .in_scope(|| register(&self.connections, peer_id, stop))?;
+        let (outbound, outbound_rx) = channel(self.channel_capacity);
+        let receive = receive_envelopes(
+            peer_id,
+            request.into_inner(),
+            self.intake.sender(),
+            outbound,
+            connection,
+            trace.clone(),
+        );
+        let connection_log = self.connection_log.clone();
+
+        tokio::spawn(
+            async move {
+                let reason = receive.await;
+
+                connection_log.log(
+                    &peer_id.to_string(),
+                    ConnectionEventKind::Disconnect,
+                    addr.as_deref(),
+                    trace.annotate(reason),
+                );
+            }
+            .instrument(trace.span()),
+        );
+
+        let stream: EnvelopeStream =
+            Box::pin(outbound_stream_v2(outbound_rx, self.closing.clone(), stopped).map(Ok));
+        let mut response = Response::new(stream);
+
+        self.node
+            .set_metadata("2", response.metadata_mut())
+            .map_err(|e| Status::internal(e.to_string()))?;
+
+        Ok(response)
+    }
+}
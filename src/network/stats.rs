@@ -0,0 +1,97 @@
+use std::convert::TryInto;
+use std::time::Duration;
+
+use anyhow::{anyhow, Result};
+use chrono::Utc;
+use rmp_serde::{decode, encode};
+use serde::{Deserialize, Serialize};
+use sled::Db;
+
+/// Interval at which the statistics of the DAG are recorded
+pub const SAMPLE_INTERVAL: Duration = Duration::from_secs(60);
+
+/// Number of samples which are kept, which is a week when sampling every minute
+const CAPACITY: usize = 7 * 24 * 60;
+
+/// Parses a period such as 30m, 24h or 7d
+pub fn parse_period(value: &str) -> Result<Duration> {
+    let (amount, unit) = value.split_at(value.len().saturating_sub(1));
+    let amount = amount
+        .parse::<u64>()
+        .map_err(|_| anyhow!("invalid period: {}", value))?;
+    let seconds = match unit {
+        "s" => amount,
+        "m" => amount * 60,
+        "h" => amount * 60 * 60,
+        "d" => amount * 60 * 60 * 24,
+        _ => {
+            return Err(anyhow!(
+                "invalid period '{}' (expected s, m, h or d)",
+                value
+            ))
+        }
+    };
+
+    Ok(Duration::from_secs(seconds))
+}
+
+/// Statistics of the DAG and the network at a single moment
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Sample {
+    pub timestamp: i64,
+    pub transactions: usize,
+    pub heads: usize,
+    /// Number of transactions admitted since the previous sample
+    pub admitted: u64,
+    pub peers: usize,
+}
+
+/// Time-series of samples stored as a ring-buffer, the oldest samples are removed once the capacity is reached
+pub struct Stats {
+    db: Db,
+}
+
+impl Stats {
+    pub fn open(db: Db) -> Result<Self> {
+        Ok(Self { db })
+    }
+
+    pub fn record(&self, sample: &Sample) -> Result<()> {
+        let tree = self.db.open_tree("nuts/stats")?;
+
+        // Big-endian keys are ordered by time
+        tree.insert(sample.timestamp.to_be_bytes(), encode::to_vec(sample)?)?;
+
+        while tree.len() > CAPACITY {
+            if tree.pop_min()?.is_none() {
+                break;
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Returns the samples which were recorded after the given timestamp, the oldest first
+    fn since(&self, timestamp: i64) -> Result<Vec<Sample>> {
+        let mut samples = vec![];
+
+        for record in self
+            .db
+            .open_tree("nuts/stats")?
+            .range(timestamp.to_be_bytes()..)
+        {
+            let (_, value) = record?;
+
+            samples.push(decode::from_read(value.as_ref())?);
+        }
+
+        Ok(samples)
+    }
+
+    /// Returns the samples recorded within the given period before now
+    pub fn history(&self, period: Duration) -> Result<Vec<Sample>> {
+        let period: i64 = period.as_secs().try_into()?;
+
+        self.since(Utc::now().timestamp() - period)
+    }
+}
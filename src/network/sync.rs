@@ -0,0 +1,107 @@
+use std::collections::HashMap;
+use std::time::Duration;
+
+use tokio::time::Instant;
+use uuid::Uuid;
+
+use crate::network::peers::Outbound;
+
+/// Bounds of the interval at which peers are queried for new transactions
+#[derive(Debug, Clone)]
+pub struct SyncPolicy {
+    pub min_interval: Duration,
+    pub max_interval: Duration,
+}
+
+impl Default for SyncPolicy {
+    fn default() -> Self {
+        Self {
+            min_interval: Duration::from_secs(5),
+            max_interval: Duration::from_secs(300),
+        }
+    }
+}
+
+struct PeerSync {
+    outbound: Outbound,
+    interval: Duration,
+    next_at: Instant,
+}
+
+/// Keeps track of when each peer should be queried next, peers which produce new transactions are queried more
+/// often while idle peers are backed off
+pub struct Scheduler {
+    policy: SyncPolicy,
+    peers: HashMap<Uuid, PeerSync>,
+}
+
+impl Scheduler {
+    pub fn new(policy: SyncPolicy) -> Self {
+        Self {
+            policy,
+            peers: HashMap::new(),
+        }
+    }
+
+    /// Starts scheduling queries for a peer unless it's already known
+    pub fn register(&mut self, peer_id: Uuid, outbound: Outbound) {
+        let interval = self.policy.min_interval;
+
+        // The outbound channel changes when the peer reconnects
+        let peer = self.peers.entry(peer_id).or_insert_with(|| PeerSync {
+            outbound: outbound.clone(),
+            interval,
+            next_at: Instant::now() + interval,
+        });
+
+        if peer.outbound.is_closed() {
+            peer.outbound = outbound;
+        }
+    }
+
+    pub fn remove(&mut self, peer_id: &Uuid) {
+        self.peers.remove(peer_id);
+    }
+
+    /// Adapts the interval of a peer based on whether the last transaction list contained new transactions
+    pub fn update(&mut self, peer_id: &Uuid, fresh: bool) {
+        if let Some(peer) = self.peers.get_mut(peer_id) {
+            peer.interval = if fresh {
+                (peer.interval / 2).max(self.policy.min_interval)
+            } else {
+                (peer.interval * 2).min(self.policy.max_interval)
+            };
+            peer.next_at = Instant::now() + peer.interval;
+
+            tracing::trace!(target: "nuts::network", "next sync with peer '{}' in {}s", peer_id, peer.interval.as_secs());
+        }
+    }
+
+    /// Returns the current sync interval of every peer
+    pub fn intervals(&self) -> Vec<(Uuid, Duration)> {
+        self.peers
+            .iter()
+            .map(|(peer_id, peer)| (*peer_id, peer.interval))
+            .collect()
+    }
+
+    /// Returns the moment at which the first peer should be queried
+    pub fn next_deadline(&self) -> Option<Instant> {
+        self.peers.values().map(|peer| peer.next_at).min()
+    }
+
+    /// Returns all peers which should be queried now and reschedules them using their current interval
+    pub fn due(&mut self) -> Vec<(Uuid, Outbound)> {
+        let now = Instant::now();
+
+        self.peers
+            .iter_mut()
+            .filter(|(_, peer)| peer.next_at <= now)
+            .map(|(peer_id, peer)| {
+                peer.next_at = now + peer.interval;
+
+                (*peer_id, peer.outbound.clone())
+            })
+            .collect()
+    }
+}
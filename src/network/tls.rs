@@ -0,0 +1,273 @@
+use std::fmt::{Display, Formatter};
+use std::str::FromStr;
+use std::sync::Arc;
+
+use anyhow::{anyhow, Result};
+use rustls::internal::pemfile;
+use rustls::{
+    AllowAnyAuthenticatedClient, Certificate, ClientConfig, PrivateKey, ProtocolVersion,
+    RootCertStore, ServerCertVerified, ServerCertVerifier, ServerConfig, SupportedCipherSuite,
+    TLSError, WebPKIVerifier, ALL_CIPHERSUITES,
+};
+
+/// PEM encoded CA certificates which are trusted, certificate (chain) and private key of the node
+#[derive(Debug, Clone)]
+pub struct TlsMaterial {
+    pub truststore: Vec<u8>,
+    pub certificate: Vec<u8>,
+    pub key: Vec<u8>,
+}
+
+/// Lowest TLS version which is accepted, rustls doesn't support anything older than TLS 1.2
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TlsVersion {
+    Tls12,
+    Tls13,
+}
+
+impl Display for TlsVersion {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            TlsVersion::Tls12 => write!(f, "1.2"),
+            TlsVersion::Tls13 => write!(f, "1.3"),
+        }
+    }
+}
+
+impl FromStr for TlsVersion {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "1.2" => Ok(TlsVersion::Tls12),
+            "1.3" => Ok(TlsVersion::Tls13),
+            _ => Err(anyhow!("invalid TLS version '{}' (expected 1.2 or 1.3)", s)),
+        }
+    }
+}
+
+/// Versions, cipher suites and ALPN protocols which are used for connections with peers, both by the listener and
+/// outbound connections. Cipher suites are named like `TLS13_AES_256_GCM_SHA384` and all suites supported by
+/// rustls are allowed when none are configured
+#[derive(Debug, Clone)]
+pub struct TlsPolicy {
+    pub min_version: TlsVersion,
+    pub cipher_suites: Vec<String>,
+    pub alpn_protocols: Vec<String>,
+}
+
+impl Default for TlsPolicy {
+    fn default() -> Self {
+        Self {
+            min_version: TlsVersion::Tls12,
+            cipher_suites: vec![],
+            alpn_protocols: vec!["h2".to_string()],
+        }
+    }
+}
+
+fn suite_name(suite: &SupportedCipherSuite) -> String {
+    format!("{:?}", suite.suite)
+}
+
+impl TlsPolicy {
+    /// Returns the names of all cipher suites supported by rustls
+    pub fn supported_cipher_suites() -> Vec<String> {
+        ALL_CIPHERSUITES
+            .iter()
+            .map(|suite| suite_name(suite))
+            .collect()
+    }
+
+    fn versions(&self) -> Vec<ProtocolVersion> {
+        match self.min_version {
+            TlsVersion::Tls12 => vec![ProtocolVersion::TLSv1_3, ProtocolVersion::TLSv1_2],
+            TlsVersion::Tls13 => vec![ProtocolVersion::TLSv1_3],
+        }
+    }
+
+    fn cipher_suites(&self) -> Result<Vec<&'static SupportedCipherSuite>> {
+        if self.cipher_suites.is_empty() {
+            return Ok(ALL_CIPHERSUITES.to_vec());
+        }
+
+        self.cipher_suites
+            .iter()
+            .map(|name| {
+                ALL_CIPHERSUITES
+                    .iter()
+                    .copied()
+                    .find(|suite| suite_name(suite).eq_ignore_ascii_case(name))
+                    .ok_or_else(|| {
+                        anyhow!(
+                            "unsupported cipher suite '{}' (expected one of: {})",
+                            name,
+                            Self::supported_cipher_suites().join(", ")
+                        )
+                    })
+            })
+            .collect()
+    }
+
+    /// Verifies that the cipher suites are supported and usable with the allowed versions and that the ALPN
+    /// protocols include HTTP/2, which gRPC requires
+    pub fn validate(&self) -> Result<()> {
+        let suites = self.cipher_suites()?;
+        let versions = self.versions();
+
+        if !suites.iter().any(|suite| {
+            versions
+                .iter()
+                .any(|version| suite.usable_for_version(*version))
+        }) {
+            return Err(anyhow!(
+                "none of the cipher suites can be used with TLS {} or higher",
+                self.min_version
+            ));
+        }
+
+        if !self.alpn_protocols.iter().any(|protocol| protocol == "h2") {
+            return Err(anyhow!(
+                "ALPN protocols must include h2 as gRPC requires HTTP/2"
+            ));
+        }
+
+        Ok(())
+    }
+
+    fn alpn_protocols(&self) -> Vec<Vec<u8>> {
+        self.alpn_protocols
+            .iter()
+            .map(|protocol| protocol.as_bytes().to_vec())
+            .collect()
+    }
+
+    /// Builds the configuration of outbound connections
+    pub fn client_config(&self, material: &TlsMaterial) -> Result<ClientConfig> {
+        let mut config = ClientConfig::new();
+
+        config.root_store = root_store(&material.truststore)?;
+        config
+            .set_single_client_cert(
+                certificates(&material.certificate)?,
+                private_key(&material.key)?,
+            )
+            .map_err(|e| anyhow!("invalid certificate or private key: {}", e))?;
+        config.versions = self.versions();
+        config.ciphersuites = self.cipher_suites()?;
+        config.alpn_protocols = self.alpn_protocols();
+
+        Ok(config)
+    }
+
+    /// Builds the configuration of the listener, which requires peers to present a certificate issued by one of
+    /// the trusted CAs
+    pub fn server_config(&self, material: &TlsMaterial) -> Result<ServerConfig> {
+        let mut config = ServerConfig::new(AllowAnyAuthenticatedClient::new(root_store(
+            &material.truststore,
+        )?));
+
+        config
+            .set_single_cert(
+                certificates(&material.certificate)?,
+                private_key(&material.key)?,
+            )
+            .map_err(|e| anyhow!("invalid certificate or private key: {}", e))?;
+        config.versions = self.versions();
+        config.ciphersuites = self.cipher_suites()?;
+        config.alpn_protocols = self.alpn_protocols();
+
+        Ok(config)
+    }
+}
+
+/// Verifies the certificate of a peer which this node connects to against the trust store and passes it to a check,
+/// which rejects the handshake when it returns an error
+struct PeerVerifier<F> {
+    webpki: WebPKIVerifier,
+    check: F,
+}
+
+impl<F> ServerCertVerifier for PeerVerifier<F>
+where
+    F: Fn(&[u8]) -> Result<()> + Send + Sync,
+{
+    fn verify_server_cert(
+        &self,
+        roots: &RootCertStore,
+        presented_certs: &[Certificate],
+        dns_name: webpki::DNSNameRef,
+        ocsp_response: &[u8],
+    ) -> Result<ServerCertVerified, TLSError> {
+        let verified =
+            self.webpki
+                .verify_server_cert(roots, presented_certs, dns_name, ocsp_response)?;
+        let certificate = presented_certs
+            .first()
+            .ok_or(TLSError::NoCertificatesPresented)?;
+
+        (self.check)(&certificate.0).map_err(|e| TLSError::General(e.to_string()))?;
+
+        Ok(verified)
+    }
+}
+
+/// Returns a copy of the configuration of outbound connections which passes the DER encoded certificate of the peer
+/// to `check` once it's verified against the trust store, the handshake fails when the check returns an error
+pub fn check_peer_certificate<F>(config: &ClientConfig, check: F) -> ClientConfig
+where
+    F: Fn(&[u8]) -> Result<()> + Send + Sync + 'static,
+{
+    let mut config = config.clone();
+
+    config
+        .dangerous()
+        .set_certificate_verifier(Arc::new(PeerVerifier {
+            webpki: WebPKIVerifier::new(),
+            check,
+        }));
+
+    config
+}
+
+fn root_store(pem: &[u8]) -> Result<RootCertStore> {
+    let mut store = RootCertStore::empty();
+    let (valid, _) = store
+        .add_pem_file(&mut &pem[..])
+        .map_err(|_| anyhow!("invalid PEM in truststore"))?;
+
+    if valid == 0 {
+        return Err(anyhow!(
+            "truststore doesn't contain any valid CA certificate"
+        ));
+    }
+
+    Ok(store)
+}
+
+fn certificates(pem: &[u8]) -> Result<Vec<rustls::Certificate>> {
+    let certificates =
+        pemfile::certs(&mut &pem[..]).map_err(|_| anyhow!("invalid PEM in certificate"))?;
+
+    if certificates.is_empty() {
+        return Err(anyhow!("certificate file doesn't contain any certificate"));
+    }
+
+    Ok(certificates)
+}
+
+/// Parses the first PKCS#8 or RSA private key
+fn private_key(pem: &[u8]) -> Result<PrivateKey> {
+    let keys = pemfile::pkcs8_private_keys(&mut &pem[..])
+        .map_err(|_| anyhow!("invalid PEM in private key"))?;
+
+    if let Some(key) = keys.into_iter().next() {
+        return Ok(key);
+    }
+
+    pemfile::rsa_private_keys(&mut &pem[..])
+        .map_err(|_| anyhow!("invalid PEM in private key"))?
+        .into_iter()
+        .next()
+        .ok_or_else(|| anyhow!("key file doesn't contain a PKCS#8 or RSA private key"))
+}
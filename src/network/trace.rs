@@ -0,0 +1,62 @@
+use std::fmt::{self, Display, Formatter};
+
+use tracing::field::{self, Empty};
+use tracing::Span;
+use uuid::Uuid;
+
+/// Identifies a single connection with a peer, it's included in the errors and the connection log so that the log
+/// records of the connection can be looked up
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct TraceId(u64);
+
+impl Display for TraceId {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        write!(f, "{:016x}", self.0)
+    }
+}
+
+/// Span of a connection with a peer, the messages received on the connection are handled in child spans
+#[derive(Debug, Clone)]
+pub struct PeerTrace {
+    id: TraceId,
+    span: Span,
+}
+
+impl PeerTrace {
+    pub fn new(inbound: bool, addr: Option<&str>) -> Self {
+        let id = TraceId(rand::random());
+        let span = tracing::info_span!(
+            target: "nuts::network",
+            "peer",
+            trace_id = %id,
+            inbound,
+            addr = addr.unwrap_or("unknown"),
+            peer_id = Empty,
+        );
+
+        Self { id, span }
+    }
+
+    pub fn id(&self) -> TraceId {
+        self.id
+    }
+
+    pub fn span(&self) -> Span {
+        self.span.clone()
+    }
+
+    /// Records the ID of the peer once it's known from the handshake
+    pub fn identify(&self, peer_id: Uuid) {
+        self.span.record("peer_id", &field::display(peer_id));
+    }
+
+    /// Span in which a message received on the connection is handled
+    pub fn message(&self, kind: &'static str) -> Span {
+        tracing::debug_span!(target: "nuts::network", parent: &self.span, "message", kind)
+    }
+
+    /// Appends the trace ID to the reason of an error or a closed connection
+    pub fn annotate(&self, reason: impl Display) -> String {
+        format!("{} (trace: {})", reason, self.id)
+    }
+}
@@ -1,25 +1,115 @@
-use std::convert::TryFrom;
 use std::error::Error;
 use std::fmt::{Display, Formatter};
 use std::result;
+use std::time::Duration;
 
 use anyhow::anyhow;
 use biscuit::jwa::SignatureAlgorithm;
-use biscuit::jwk::AlgorithmParameters;
-use biscuit::jws::{Compact, Header, Secret};
-use biscuit::CompactJson;
-use chrono::NaiveDateTime;
-use ecdsa::signature::Verifier;
-use ecdsa::{EncodedPoint, Signature, VerifyingKey};
-use p256::NistP256;
+use biscuit::jwk::{AlgorithmParameters, EllipticCurve};
+use biscuit::jws::{Compact, Header, RegisteredHeader, Secret};
+use biscuit::{CompactJson, CompactPart};
+use chrono::{Duration as ChronoDuration, NaiveDateTime, Utc};
+use ecdsa::signature::{Signer, Verifier};
+use p256::ecdsa::{Signature, SigningKey, VerifyingKey};
 use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
 
-use crate::network::Hash;
-use crate::pki::{Key, KeyStore};
+use crate::network::{curves, rsa, Graph, Hash};
+use crate::pki::{public_jwk, Key, KeyExt, KeyStorage, KeyStoreError};
+
+/// Maximum time the signing time of a transaction may be ahead of the clock of this node by default
+pub const DEFAULT_MAX_CLOCK_SKEW: Duration = Duration::from_secs(10 * 60);
+
+/// Maximum size of the unknown header parameters (encoded as JSON) which are preserved
+pub const MAX_EXTRA_HEADERS_SIZE: usize = 4 * 1024;
+
+/// Violation of the rules in RFC004 which a transaction must follow
+#[derive(Debug)]
+pub enum ValidationError {
+    /// Only version 1 of the transaction format exists
+    UnsupportedVersion(usize),
+    /// The transaction is signed further in the future than the allowed clock skew
+    FutureSignTime {
+        sign_at: NaiveDateTime,
+        max_skew: Duration,
+    },
+    /// The same previous transaction is referenced more than once
+    DuplicatePrevious(Hash),
+    /// The type of the signing key can't be used with the signing algorithm
+    KeyNotAllowed {
+        algorithm: SignatureAlgorithm,
+        key_type: String,
+    },
+    /// The modulus of the RSA signing key is smaller than the minimum size
+    KeyTooSmall(u64),
+    /// The ID of an embedded key doesn't end with it's thumbprint, so the transaction might claim the ID of a key
+    /// which belongs to someone else
+    KeyIdMismatch {
+        key_id: String,
+        thumbprint: String,
+    },
+    /// The unknown header parameters exceed the size which is preserved
+    ExtraHeadersTooLarge(usize),
+    /// The signing key was revoked at or before the signing time
+    KeyRevoked {
+        key_id: String,
+        revoked_at: NaiveDateTime,
+    },
+    Invalid(String),
+}
+
+impl Display for ValidationError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ValidationError::UnsupportedVersion(version) => {
+                write!(f, "unsupported version: {}", version)
+            }
+            ValidationError::FutureSignTime { sign_at, max_skew } => write!(
+                f,
+                "signed at {} which is more than {}s in the future",
+                sign_at,
+                max_skew.as_secs()
+            ),
+            ValidationError::DuplicatePrevious(id) => {
+                write!(f, "previous transaction '{}' is referenced twice", id)
+            }
+            ValidationError::KeyNotAllowed {
+                algorithm,
+                key_type,
+            } => write!(
+                f,
+                "algorithm {:?} can't be used with a {} key",
+                algorithm, key_type
+            ),
+            ValidationError::KeyTooSmall(bits) => write!(
+                f,
+                "RSA key of {} bits is smaller than the minimum of {} bits",
+                bits,
+                rsa::MIN_KEY_BITS
+            ),
+            ValidationError::KeyIdMismatch { key_id, thumbprint } => write!(
+                f,
+                "key ID '{}' doesn't match the thumbprint of the embedded key: {}",
+                key_id, thumbprint
+            ),
+            ValidationError::ExtraHeadersTooLarge(size) => write!(
+                f,
+                "unknown headers are {} bytes which exceeds the maximum of {} bytes",
+                size, MAX_EXTRA_HEADERS_SIZE
+            ),
+            ValidationError::KeyRevoked { key_id, revoked_at } => write!(
+                f,
+                "key '{}' was revoked at {} before the transaction was signed",
+                key_id, revoked_at
+            ),
+            ValidationError::Invalid(e) => write!(f, "{}", e),
+        }
+    }
+}
 
 #[derive(Debug)]
 pub enum ParseError {
-    NutsValidationError(String),
+    NutsValidationError(ValidationError),
     JoseError(biscuit::errors::Error),
     ECDSAError(ecdsa::Error),
     Other(anyhow::Error),
@@ -42,6 +132,12 @@ impl Display for ParseError {
 
 impl Error for ParseError {}
 
+impl ParseError {
+    fn invalid(message: impl Into<String>) -> Self {
+        ParseError::NutsValidationError(ValidationError::Invalid(message.into()))
+    }
+}
+
 impl From<biscuit::errors::Error> for ParseError {
     fn from(e: biscuit::errors::Error) -> Self {
         ParseError::JoseError(e)
@@ -54,6 +150,12 @@ impl From<anyhow::Error> for ParseError {
     }
 }
 
+impl From<KeyStoreError> for ParseError {
+    fn from(e: KeyStoreError) -> Self {
+        ParseError::Other(e.into())
+    }
+}
+
 impl From<ecdsa::Error> for ParseError {
     fn from(e: ecdsa::Error) -> Self {
         ParseError::ECDSAError(e)
@@ -62,9 +164,24 @@ impl From<ecdsa::Error> for ParseError {
 
 pub type Result<T> = result::Result<T, ParseError>;
 
+/// Version of the rules used to validate transactions, this should be increased when the rules change
+pub const VALIDATION_POLICY_VERSION: u32 = 1;
+
+/// Proof of which key and rules were used to verify a transaction when it was admitted
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Verification {
+    pub key_thumbprint: String,
+    pub algorithm: String,
+    pub policy_version: u32,
+    pub verified_at: i64,
+}
+
+/// Transaction in the DAG which is signed using JWS and references a payload by it's hash
 #[derive(Debug, Clone)]
 pub struct Transaction {
+    /// Hash of the compact JWS representation
     pub id: Hash,
+    /// The compact JWS representation
     pub data: Vec<u8>,
     pub prevs: Vec<Hash>,
     pub payload: Hash,
@@ -74,6 +191,15 @@ pub struct Transaction {
     pub key_id: String,
     pub sign_at: NaiveDateTime,
     pub sign_algo: SignatureAlgorithm,
+    /// Encrypted addresses of the participants, only present for private transactions
+    pub pal: Vec<String>,
+    /// Lamport clock as claimed by the `lc` header, which is missing in transactions created by older nodes
+    pub lamport_clock: Option<u32>,
+    /// Header parameters which aren't understood by this node (e.g. introduced by a future protocol version), these
+    /// are preserved in the JWS itself but exposed here as well
+    pub extra_headers: Map<String, Value>,
+    /// Only available when the transaction was verified
+    pub verification: Option<Verification>,
 }
 
 impl Transaction {
@@ -81,6 +207,11 @@ impl Transaction {
     pub fn is_root(&self) -> bool {
         self.prevs.is_empty()
     }
+
+    /// Private transactions are addressed to participants, their payload is only exchanged between participants
+    pub fn is_private(&self) -> bool {
+        !self.pal.is_empty()
+    }
 }
 
 impl Default for Transaction {
@@ -96,7 +227,122 @@ impl Default for Transaction {
             key_id: "".to_string(),
             sign_at: NaiveDateTime::from_timestamp(0, 0),
             sign_algo: Default::default(),
+            pal: vec![],
+            lamport_clock: None,
+            extra_headers: Map::new(),
+            verification: None,
+        }
+    }
+}
+
+/// Builds and signs new transactions in the compact JWS format as described in
+/// RFC004: <https://nuts-foundation.gitbook.io/drafts/rfc/rfc004-verifiable-transactional-graph>
+pub struct TransactionBuilder {
+    payload: Hash,
+    payload_type: String,
+    prevs: Vec<Hash>,
+    sign_at: Option<NaiveDateTime>,
+    embed_key: bool,
+    lamport_clock: Option<u32>,
+}
+
+impl TransactionBuilder {
+    pub fn new(payload_type: impl Into<String>, payload: impl AsRef<[u8]>) -> Result<Self> {
+        Ok(Self {
+            payload: Hash::new(payload)?,
+            payload_type: payload_type.into(),
+            prevs: vec![],
+            sign_at: None,
+            embed_key: false,
+            lamport_clock: None,
+        })
+    }
+
+    /// Sets the previous transactions, a transaction without previous transactions is a root transaction
+    pub fn prevs(mut self, prevs: Vec<Hash>) -> Self {
+        self.prevs = prevs;
+        self
+    }
+
+    /// Sets the previous transactions to the current heads of the graph, so that the transaction is appended to
+    /// the DAG (or becomes the root transaction of an empty graph), and the Lamport clock accordingly
+    pub fn append_to(self, graph: &Graph) -> Self {
+        let heads = graph.heads();
+        let lamport_clock = graph.next_clock(&heads);
+
+        self.prevs(heads).lamport_clock(lamport_clock)
+    }
+
+    /// Sets the Lamport clock, which must be one higher than the highest clock of the previous transactions
+    pub fn lamport_clock(mut self, lamport_clock: u32) -> Self {
+        self.lamport_clock = Some(lamport_clock);
+        self
+    }
+
+    /// Sets the signing time, defaults to the current time
+    pub fn sign_at(mut self, sign_at: NaiveDateTime) -> Self {
+        self.sign_at = Some(sign_at);
+        self
+    }
+
+    /// Embeds the public key in the transaction instead of referencing it by it's key ID, which is
+    /// required when a key is used for the first time (e.g. in the root transaction)
+    pub fn embed_key(mut self, embed_key: bool) -> Self {
+        self.embed_key = embed_key;
+        self
+    }
+
+    /// Signs the transaction using ES256 and returns the parsed result
+    pub fn sign(self, key_id: &str, key: &SigningKey) -> Result<Transaction> {
+        let sign_at = self
+            .sign_at
+            .unwrap_or_else(|| Utc::now().naive_utc())
+            .timestamp();
+        let mut critical = vec!["sigt".to_string(), "ver".to_string(), "prevs".to_string()];
+
+        if self.lamport_clock.is_some() {
+            critical.push("lc".to_string());
         }
+
+        let header = Header {
+            registered: RegisteredHeader {
+                algorithm: SignatureAlgorithm::ES256,
+                content_type: Some(self.payload_type),
+                web_key: if self.embed_key {
+                    Some(public_jwk(key_id, key))
+                } else {
+                    None
+                },
+                key_id: if self.embed_key {
+                    None
+                } else {
+                    Some(key_id.to_string())
+                },
+                critical: Some(critical),
+                ..Default::default()
+            },
+            private: TransactionHeader {
+                version: 1,
+                sign_time: sign_at,
+                previous: self.prevs.iter().map(|id| id.to_string()).collect(),
+                participants: vec![],
+                lamport_clock: self.lamport_clock,
+            },
+        };
+        let payload = self.payload.to_string().into_bytes();
+        let signing_input = format!(
+            "{}.{}",
+            header.to_base64()?.str(),
+            payload.to_base64()?.str()
+        );
+        let signature: Signature = key.sign(signing_input.as_bytes());
+        let raw = format!(
+            "{}.{}",
+            signing_input,
+            signature.to_bytes().to_vec().to_base64()?.str()
+        );
+
+        Transaction::parse_unsafe(raw)
     }
 }
 
@@ -108,10 +354,103 @@ struct TransactionHeader {
     pub sign_time: i64,
     #[serde(rename = "prevs")]
     pub previous: Vec<String>,
+    #[serde(rename = "pal", default, skip_serializing_if = "Vec::is_empty")]
+    pub participants: Vec<String>,
+    #[serde(rename = "lc", default, skip_serializing_if = "Option::is_none")]
+    pub lamport_clock: Option<u32>,
 }
 
 impl CompactJson for TransactionHeader {}
 
+/// Header parameters which are allowed in strict mode, duplicate or unknown parameters are rejected
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+#[allow(dead_code)]
+struct StrictHeader {
+    alg: String,
+    cty: String,
+    #[serde(default)]
+    typ: Option<String>,
+    #[serde(default)]
+    kid: Option<String>,
+    #[serde(default)]
+    jwk: Option<serde_json::Value>,
+    #[serde(default)]
+    crit: Option<Vec<String>>,
+    #[serde(default)]
+    ver: Option<usize>,
+    #[serde(default)]
+    sigt: Option<i64>,
+    #[serde(default)]
+    prevs: Option<Vec<String>>,
+    #[serde(default)]
+    pal: Option<Vec<String>>,
+    #[serde(default)]
+    lc: Option<u32>,
+}
+
+/// Header parameters which are registered by RFC 7515 or RFC004 and therefore parsed into the transaction
+const KNOWN_PARAMETERS: [&str; 15] = [
+    "alg", "jku", "jwk", "kid", "x5u", "x5c", "x5t", "x5t#S256", "typ", "cty", "crit", "ver",
+    "sigt", "prevs", "pal",
+];
+
+/// Extension parameters which this implementation understands and can therefore be listed as critical
+const CRITICAL_PARAMETERS: [&str; 4] = ["sigt", "ver", "prevs", "lc"];
+
+/// Validates the protected header of a transaction more strictly than is needed to parse it: the header must be
+/// canonically base64url encoded, can't contain duplicate or unknown parameters and every critical parameter must
+/// be understood and present (see RFC 7515 section 4.1.11)
+pub fn validate_header(raw: &str) -> Result<()> {
+    let encoded = raw.split('.').next().unwrap_or_default();
+    let decoded = base64::decode_config(encoded, base64::URL_SAFE_NO_PAD)
+        .map_err(|e| ParseError::invalid(format!("header isn't base64url encoded: {}", e)))?;
+
+    // Padding and non-zero trailing bits are accepted by the decoder but aren't canonical
+    if base64::encode_config(&decoded, base64::URL_SAFE_NO_PAD) != encoded {
+        return Err(ParseError::invalid(
+            "header isn't canonically base64url encoded".to_string(),
+        ));
+    }
+
+    let header: StrictHeader = serde_json::from_slice(&decoded)
+        .map_err(|e| ParseError::invalid(format!("invalid header: {}", e)))?;
+
+    if let Some(crit) = header.crit {
+        if crit.is_empty() {
+            return Err(ParseError::invalid(
+                "crit header can't be empty".to_string(),
+            ));
+        }
+
+        for name in crit.iter() {
+            if !CRITICAL_PARAMETERS.contains(&name.as_str()) {
+                return Err(ParseError::invalid(format!(
+                    "unsupported critical header: {}",
+                    name
+                )));
+            }
+
+            let present = match name.as_str() {
+                "sigt" => header.sigt.is_some(),
+                "ver" => header.ver.is_some(),
+                "prevs" => header.prevs.is_some(),
+                "lc" => header.lc.is_some(),
+                _ => false,
+            };
+
+            if !present {
+                return Err(ParseError::invalid(format!(
+                    "critical header is missing: {}",
+                    name
+                )));
+            }
+        }
+    }
+
+    Ok(())
+}
+
 fn parse_key(header: &Header<TransactionHeader>) -> Result<(Option<Key>, String)> {
     Ok(match &header.registered.web_key {
         Some(key) => {
@@ -122,18 +461,14 @@ fn parse_key(header: &Header<TransactionHeader>) -> Result<(Option<Key>, String)
                 .clone()
                 .or_else(|| header.registered.key_id.clone())
                 .ok_or_else(|| {
-                    ParseError::NutsValidationError(
-                        "missing ID for transaction signing key".to_string(),
-                    )
+                    ParseError::invalid("missing ID for transaction signing key".to_string())
                 })?;
 
             (Some(key.clone()), key_id)
         }
         None => {
             let key_id = header.registered.key_id.clone().ok_or_else(|| {
-                ParseError::NutsValidationError(
-                    "unable to add transaction without key or key ID".to_string(),
-                )
+                ParseError::invalid("unable to add transaction without key or key ID".to_string())
             })?;
 
             (None, key_id)
@@ -141,12 +476,125 @@ fn parse_key(header: &Header<TransactionHeader>) -> Result<(Option<Key>, String)
     })
 }
 
+/// Verifies that the type of the key matches the signing algorithm as required by RFC004, e.g. ES256 can only be
+/// used with a P-256 key and RSA keys must be at least 2048 bits
+fn check_key(key: &Key, algorithm: SignatureAlgorithm) -> Result<()> {
+    if let AlgorithmParameters::RSA(params) = &key.algorithm {
+        let bits = rsa::key_bits(params);
+
+        if bits < rsa::MIN_KEY_BITS {
+            return Err(ParseError::NutsValidationError(
+                ValidationError::KeyTooSmall(bits),
+            ));
+        }
+    }
+
+    let allowed = match (&key.algorithm, algorithm) {
+        (AlgorithmParameters::EllipticCurve(params), SignatureAlgorithm::ES256) => {
+            params.curve == EllipticCurve::P256
+        }
+        (AlgorithmParameters::EllipticCurve(params), SignatureAlgorithm::ES384) => {
+            params.curve == EllipticCurve::P384
+        }
+        (AlgorithmParameters::EllipticCurve(params), SignatureAlgorithm::ES512) => {
+            params.curve == EllipticCurve::P521
+        }
+        (
+            AlgorithmParameters::RSA(_),
+            SignatureAlgorithm::PS256 | SignatureAlgorithm::PS384 | SignatureAlgorithm::PS512,
+        ) => true,
+        _ => false,
+    };
+
+    if !allowed {
+        return Err(ParseError::NutsValidationError(
+            ValidationError::KeyNotAllowed {
+                algorithm,
+                key_type: match &key.algorithm {
+                    AlgorithmParameters::EllipticCurve(params) => format!("{:?}", params.curve),
+                    AlgorithmParameters::RSA(_) => "RSA".to_string(),
+                    AlgorithmParameters::OctetKey(_) => "symmetric".to_string(),
+                    _ => "unsupported".to_string(),
+                },
+            },
+        ));
+    }
+
+    Ok(())
+}
+
+/// Returns the header parameters which aren't known to this implementation
+fn parse_extra_headers(raw: &str) -> Result<Map<String, Value>> {
+    let encoded = raw.split('.').next().unwrap_or_default();
+    let decoded = base64::decode_config(encoded, base64::URL_SAFE_NO_PAD)
+        .map_err(|e| ParseError::invalid(format!("header isn't base64url encoded: {}", e)))?;
+    let headers = serde_json::from_slice::<Map<String, Value>>(&decoded)
+        .map_err(|e| ParseError::invalid(format!("invalid header: {}", e)))?
+        .into_iter()
+        .filter(|(name, _)| {
+            !KNOWN_PARAMETERS.contains(&name.as_str())
+                && !CRITICAL_PARAMETERS.contains(&name.as_str())
+        })
+        .collect::<Map<_, _>>();
+
+    if !headers.is_empty() {
+        let size = serde_json::to_vec(&headers).map_err(|e| anyhow!(e))?.len();
+
+        if size > MAX_EXTRA_HEADERS_SIZE {
+            return Err(ParseError::NutsValidationError(
+                ValidationError::ExtraHeadersTooLarge(size),
+            ));
+        }
+    }
+
+    Ok(headers)
+}
+
+/// Parses a hex encoded SHA256 hash from the transaction, which must be in it's canonical (lowercase) form
+fn parse_hash(field: &str, source: &[u8]) -> Result<Hash> {
+    if source.is_empty() {
+        return Err(ParseError::invalid(format!("{} hash is empty", field)));
+    }
+
+    // The spec requires hex encoding but some implementations use base64url instead
+    if source.len() != 64 && Hash::parse_base64url(source).is_ok() {
+        return Err(ParseError::invalid(format!(
+            "{} hash must be hex encoded (got base64url)",
+            field
+        )));
+    }
+
+    if let Some(pos) = source.iter().position(|c| !c.is_ascii_hexdigit()) {
+        return Err(ParseError::invalid(format!(
+            "{} hash contains a non-hex character at position {}",
+            field, pos
+        )));
+    }
+
+    if source.iter().any(u8::is_ascii_uppercase) {
+        return Err(ParseError::invalid(format!(
+            "{} hash must be lowercase hex encoded",
+            field
+        )));
+    }
+
+    if source.len() != 64 {
+        return Err(ParseError::invalid(format!(
+            "{} hash has an invalid length (expected 64 hex characters, got {})",
+            field,
+            source.len()
+        )));
+    }
+
+    Ok(Hash::parse_hex(source)?)
+}
+
 fn parse_transaction(
     raw: &str,
     header: &Header<TransactionHeader>,
     payload: &[u8],
 ) -> Result<Transaction> {
-    let payload = Hash::parse_hex(payload)?;
+    let payload = parse_hash("payload", payload)?;
 
     // Validate supported algorithms in line with: https://nuts-foundation.gitbook.io/drafts/rfc/rfc004-verifiable-transactional-graph#3-1-jws-implementation
     if !matches!(
@@ -158,22 +606,40 @@ fn parse_transaction(
             | SignatureAlgorithm::PS384
             | SignatureAlgorithm::PS512
     ) {
-        return Err(ParseError::NutsValidationError(format!(
+        return Err(ParseError::invalid(format!(
             "unsupported algorithm: {:?}",
             header.registered.algorithm
         )));
     }
 
     let payload_type = header.registered.content_type.clone().ok_or_else(|| {
-        ParseError::NutsValidationError("transaction is missing the payload-type".to_string())
+        ParseError::invalid("transaction is missing the payload-type".to_string())
     })?;
+    if header.private.version != 1 {
+        return Err(ParseError::NutsValidationError(
+            ValidationError::UnsupportedVersion(header.private.version),
+        ));
+    }
+
     let sign_at = NaiveDateTime::from_timestamp(header.private.sign_time, 0);
     let (key, key_id) = parse_key(header)?;
 
-    let mut prevs = vec![];
+    if let Some(key) = &key {
+        check_key(key, header.registered.algorithm)?;
+    }
+
+    let mut prevs: Vec<Hash> = vec![];
 
     for hash in header.private.previous.iter() {
-        prevs.push(Hash::parse_hex(hash.as_bytes())?);
+        let hash = parse_hash("previous transaction", hash.as_bytes())?;
+
+        if prevs.contains(&hash) {
+            return Err(ParseError::NutsValidationError(
+                ValidationError::DuplicatePrevious(hash),
+            ));
+        }
+
+        prevs.push(hash);
     }
 
     let data = raw.as_bytes().to_vec();
@@ -190,10 +656,55 @@ fn parse_transaction(
         key_id,
         sign_at,
         sign_algo: header.registered.algorithm,
+        pal: header.private.participants.clone(),
+        lamport_clock: header.private.lamport_clock,
+        extra_headers: parse_extra_headers(raw)?,
+        verification: None,
     })
 }
 
 impl Transaction {
+    /// Verifies that the ID of the embedded key (if any) is the RFC7638 thumbprint of the key, or a DID URL with
+    /// the thumbprint as fragment (e.g. `did:nuts:123#<thumbprint>`)
+    pub fn check_key_id(&self) -> Result<()> {
+        let key = match &self.key {
+            Some(key) => key,
+            None => return Ok(()),
+        };
+        let thumbprint = key.thumbprint()?;
+        let fragment = match self.key_id.rsplit_once('#') {
+            Some((_, fragment)) => fragment,
+            None => self.key_id.as_str(),
+        };
+
+        if fragment != thumbprint {
+            return Err(ParseError::NutsValidationError(
+                ValidationError::KeyIdMismatch {
+                    key_id: self.key_id.clone(),
+                    thumbprint,
+                },
+            ));
+        }
+
+        Ok(())
+    }
+
+    /// Verifies that the transaction isn't signed further in the future than the allowed clock skew
+    pub fn check_sign_time(&self, max_skew: Duration) -> Result<()> {
+        let skew = ChronoDuration::from_std(max_skew).map_err(|e| anyhow!(e))?;
+
+        if self.sign_at > Utc::now().naive_utc() + skew {
+            return Err(ParseError::NutsValidationError(
+                ValidationError::FutureSignTime {
+                    sign_at: self.sign_at,
+                    max_skew,
+                },
+            ));
+        }
+
+        Ok(())
+    }
+
     /// Parses a transaction from the compact JWS representation without verifying the signature
     pub fn parse_unsafe(raw: impl AsRef<str>) -> Result<Transaction> {
         let compact: Compact<Vec<u8>, TransactionHeader> = Compact::new_encoded(raw.as_ref());
@@ -206,7 +717,7 @@ impl Transaction {
     }
 
     /// Parses and verifies a transaction from the compact JWS representation
-    pub fn parse(store: &KeyStore, raw: impl AsRef<str>) -> Result<Transaction> {
+    pub fn parse(store: &impl KeyStorage, raw: impl AsRef<str>) -> Result<Transaction> {
         let compact: Compact<Vec<u8>, TransactionHeader> = Compact::new_encoded(raw.as_ref());
         let header = compact.unverified_header()?;
         let (key, key_id) = parse_key(&header)?;
@@ -217,27 +728,103 @@ impl Transaction {
                 .get(&key_id)?
                 .ok_or_else(|| anyhow!("unable to find verification key: {}", key_id))?
         };
+
+        check_key(&key, header.registered.algorithm)?;
+        let mut tx = Self::verify(raw.as_ref(), compact, &header, &key)?;
+
+        if let Some(revoked_at) = store.revoked_at(&tx.key_id)? {
+            if revoked_at <= tx.sign_at.timestamp() {
+                return Err(ParseError::NutsValidationError(
+                    ValidationError::KeyRevoked {
+                        key_id: tx.key_id,
+                        revoked_at: NaiveDateTime::from_timestamp(revoked_at, 0),
+                    },
+                ));
+            }
+        }
+
+        tx.verification = Some(Verification {
+            key_thumbprint: key.thumbprint()?,
+            algorithm: format!("{:?}", header.registered.algorithm),
+            policy_version: VALIDATION_POLICY_VERSION,
+            verified_at: Utc::now().timestamp(),
+        });
+
+        Ok(tx)
+    }
+
+    #[tracing::instrument(
+        target = "nuts::network",
+        name = "verify_signature",
+        level = "debug",
+        skip_all,
+        fields(algorithm = ?header.registered.algorithm)
+    )]
+    fn verify(
+        raw: &str,
+        compact: Compact<Vec<u8>, TransactionHeader>,
+        header: &Header<TransactionHeader>,
+        key: &Key,
+    ) -> Result<Transaction> {
         let compact = compact.decode(
-            &match key.algorithm {
-                AlgorithmParameters::RSA(rsa) => rsa.jws_public_key_secret(),
-                AlgorithmParameters::OctetKey(oct) => Secret::Bytes(oct.value),
+            &match &key.algorithm {
+                // RSA-PSS signatures are verified up front as well, so that the minimum key size is enforced
+                AlgorithmParameters::RSA(params) => {
+                    let components = raw.split('.').collect::<Vec<_>>();
+                    let signature_payload = format!("{}.{}", components[0], components[1]);
+
+                    rsa::verify_pss(
+                        params,
+                        header.registered.algorithm,
+                        signature_payload.as_bytes(),
+                        &compact.signature()?,
+                    )?;
+
+                    return parse_transaction(
+                        raw,
+                        &compact.unverified_header()?,
+                        &compact.unverified_payload()?,
+                    );
+                }
+                AlgorithmParameters::OctetKey(oct) => Secret::Bytes(oct.value.clone()),
                 // It seems like `biscuit` doesn't support elliptic curve public key based verifications so instead
                 // we validate the signature up front and return the 'unverified' data if that succeeds
                 AlgorithmParameters::EllipticCurve(params) => {
-                    let point: EncodedPoint<NistP256> = EncodedPoint::from_affine_coordinates(
-                        params.x.as_slice().into(),
-                        params.y.as_slice().into(),
-                        false,
-                    );
-                    let ec_key = VerifyingKey::from_encoded_point(&point)?;
-                    let signature = Signature::try_from(compact.signature()?.as_slice())?;
-                    let components = raw.as_ref().split('.').collect::<Vec<_>>();
+                    let signature = compact.signature()?;
+                    let components = raw.split('.').collect::<Vec<_>>();
                     let signature_payload = format!("{}.{}", components[0], components[1]);
 
-                    ec_key.verify(signature_payload.as_bytes(), &signature)?;
+                    match (&params.curve, header.registered.algorithm) {
+                        (EllipticCurve::P256, SignatureAlgorithm::ES256) => {
+                            let point =
+                                [&[4][..], params.x.as_slice(), params.y.as_slice()].concat();
+                            let ec_key = VerifyingKey::from_sec1_bytes(&point)?;
+                            let signature = Signature::from_slice(&signature)?;
+
+                            ec_key.verify(signature_payload.as_bytes(), &signature)?;
+                        }
+                        (EllipticCurve::P384, SignatureAlgorithm::ES384) => curves::verify_p384(
+                            &params.x,
+                            &params.y,
+                            signature_payload.as_bytes(),
+                            &signature,
+                        )?,
+                        (EllipticCurve::P521, SignatureAlgorithm::ES512) => curves::verify_p521(
+                            &params.x,
+                            &params.y,
+                            signature_payload.as_bytes(),
+                            &signature,
+                        )?,
+                        (curve, algorithm) => {
+                            return Err(ParseError::invalid(format!(
+                                "algorithm {:?} can't be used with curve {:?}",
+                                algorithm, curve
+                            )))
+                        }
+                    }
 
                     return parse_transaction(
-                        raw.as_ref(),
+                        raw,
                         &compact.unverified_header()?,
                         &compact.unverified_payload()?,
                     );
@@ -252,6 +839,254 @@ impl Transaction {
             header.registered.algorithm,
         )?;
 
-        parse_transaction(raw.as_ref(), compact.header()?, compact.payload()?)
+        parse_transaction(raw, compact.header()?, compact.payload()?)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use p256::ecdsa::SigningKey;
+    use rand::rngs::OsRng;
+
+    use super::*;
+    use crate::pki::MemoryKeyStore;
+
+    /// Hex encoded SHA-256 hash of `payload`
+    const HASH: &str = "239f59ed55e737c77147cf55ad0c1b030b6d7ee748a7426952f9b852d5a935e5";
+    /// Root transaction signed using PS256 by the 2048 bits key of the RSA test vectors, which is embedded
+    const PS256_TRANSACTION: &str = "eyJhbGciOiJQUzI1NiIsImN0eSI6ImFwcGxpY2F0aW9uL2RpZCtqc29uIiwiandrIjp7Imt0eSI6IlJTQSIsImtpZCI6ImRpZDpudXRzOjEyMyNrZXktMSIsIm4iOiJ3Tm9WcUdKanlISlJJWkw0SGNOb1VjallsSXFqbERfZTY4cUZFVUs5MzNqWGRJT0dvVjlwWi0zNlJISzlQaWFEbnhtSWVyUm9WMTdZV1JMZ09RWEM4XzlldWFCU2Y1bFg2MVZ3cWxwN0haelR6M0ZObEFaYUwyQ1NfOXUyby02SktuZVhFRmkwMExUYUZyNElqRWtCRW02LVIzTUpSaEdmN3NTWF9hUzZEX0lkX2o2aVJDenUzejJxc3BZTjJqbzRmZWhjMDV5Mkp1czZPb0lZamo0ZkM2TkZjZjIxaVpTN2V0RkRsTUZOc2NKdzlSZmg1Y2EzSVVGUzRMX3hYODBpV2NKV090TTBjR3RlRGFvcndmQ1BuZjY0NERXajZoLXpNZ3FJVXRzOXVEVlJxQW0xMU5EUWdYYjVJbzYxY3dfY3prWFRiVDhlUVdjLUxkZHNCa25ialEiLCJlIjoiQVFBQiJ9LCJjcml0IjpbInNpZ3QiLCJ2ZXIiLCJwcmV2cyJdLCJzaWd0IjoxNjUwMDAwMDAwLCJ2ZXIiOjEsInByZXZzIjpbXX0.MjM5ZjU5ZWQ1NWU3MzdjNzcxNDdjZjU1YWQwYzFiMDMwYjZkN2VlNzQ4YTc0MjY5NTJmOWI4NTJkNWE5MzVlNQ.oFqSW6coiBog9vbhYCHc7M8h1KtaFm0mtnkPEVuBcmQGCSC9yLXzS75RGz2zJiJm-F62dVYO3zHt_cocY9FXoAuBOMDE_AKG5EL_bxR-rqxtSArCy5HseTgX61R2G5Y2vuzksTmAiQztFbJ_D2Ki4_6UVz708hwTforWt57OmPuPFdcPD9E7XLSLiTNFjnR5ikFiVwTNjmarR2M_9D59qCgT0kYE4zfAVwAo-qgeL7cZRuSIMD5BVTFniUl7SYhxEadRGp5IoFnaU9-4VUEN7Jee59z0O87aWqGHZW5oWeuMTkrUY15nXtR8o3TkEp47sV29qUBdpXr1-NBqGJ-r1A";
+    /// Same as above but signed by the 1024 bits key of the RSA test vectors
+    const SMALL_KEY_TRANSACTION: &str = "eyJhbGciOiJQUzI1NiIsImN0eSI6ImFwcGxpY2F0aW9uL2RpZCtqc29uIiwiandrIjp7Imt0eSI6IlJTQSIsImtpZCI6ImRpZDpudXRzOjEyMyNrZXktMSIsIm4iOiJ5Mm1IcWRwOGVxc1RER2hGLUVGOG5Ma004b1BENWpLdVMzTXlqQld5bFBYQ2JDNlZDaU1TNUJ0RGtTamFUQTljenlsVWF4OUFVZnVUWWRiSXVRcUExZUVyZlRFVG1iRnFlWENGOFBnVi1xM19LN2xURHN4UDhVbWlxRnV2Z2dzZlpia2IxekdFbEFPR19xWGNGVGZZRWxBOFJkTHlZWS1LRjhzczBfV1BnMGMiLCJlIjoiQVFBQiJ9LCJjcml0IjpbInNpZ3QiLCJ2ZXIiLCJwcmV2cyJdLCJzaWd0IjoxNjUwMDAwMDAwLCJ2ZXIiOjEsInByZXZzIjpbXX0.MjM5ZjU5ZWQ1NWU3MzdjNzcxNDdjZjU1YWQwYzFiMDMwYjZkN2VlNzQ4YTc0MjY5NTJmOWI4NTJkNWE5MzVlNQ.meMBN6eDlqWuFx2jF9JjqaSjyIUzlERw4VynV3F7cBsMc8klbwPztu74K9_n6QqF1RVzhV-_Jss2Rf8oDUcbdEoK0VWzbfjbHn-CaGvX93qhixTVpjaGyNUp0WaKgbEsri9S7qatYOm4cnIUWbLJJOrrwgc6fDmqLHZk90FlqAQ";
+    /// Same as above but the key is referred to by it's ID (`did:nuts:123#key-1`) instead of embedded
+    const SMALL_KEY_ID_TRANSACTION: &str = "eyJhbGciOiJQUzI1NiIsImN0eSI6ImFwcGxpY2F0aW9uL2RpZCtqc29uIiwia2lkIjoiZGlkOm51dHM6MTIzI2tleS0xIiwiY3JpdCI6WyJzaWd0IiwidmVyIiwicHJldnMiXSwic2lndCI6MTY1MDAwMDAwMCwidmVyIjoxLCJwcmV2cyI6W119.MjM5ZjU5ZWQ1NWU3MzdjNzcxNDdjZjU1YWQwYzFiMDMwYjZkN2VlNzQ4YTc0MjY5NTJmOWI4NTJkNWE5MzVlNQ.NgaH17IBOwgK5KRMs7N5TNbvat_GsMnwEEovNFV9j_a6JMG6ZHn01-dfXA9uCuq4W_NDnFLQZNDdYUJkBhDZQR6s1Qy7Q4E-a1vAO7og2DozhYd2ZW1zZUsZudBWJlyQpE3HUM37zFpTvFCbvOu0_1cG4zOLEypZkpgk4qWutbk";
+
+    fn invalid(result: Result<Hash>) -> String {
+        match result {
+            Err(ParseError::NutsValidationError(ValidationError::Invalid(message))) => message,
+            other => panic!("expected a validation error, got {:?}", other),
+        }
+    }
+
+    fn signed() -> String {
+        let tx = TransactionBuilder::new("application/did+json", "payload")
+            .unwrap()
+            .embed_key(true)
+            .sign("key-1", &SigningKey::random(&mut OsRng))
+            .unwrap();
+
+        String::from_utf8(tx.data).unwrap()
+    }
+
+    /// Replaces the payload of the JWS, which invalidates the signature
+    fn with_payload(raw: &str, payload: &[u8]) -> String {
+        let components = raw.split('.').collect::<Vec<_>>();
+
+        format!(
+            "{}.{}.{}",
+            components[0],
+            base64::encode_config(payload, base64::URL_SAFE_NO_PAD),
+            components[2]
+        )
+    }
+
+    /// Changes the header parameters of the JWS, which invalidates the signature
+    fn with_header(raw: &str, change: impl FnOnce(&mut Map<String, Value>)) -> String {
+        let components = raw.split('.').collect::<Vec<_>>();
+        let decoded = base64::decode_config(components[0], base64::URL_SAFE_NO_PAD).unwrap();
+        let mut header: Map<String, Value> = serde_json::from_slice(&decoded).unwrap();
+
+        change(&mut header);
+
+        format!(
+            "{}.{}.{}",
+            base64::encode_config(
+                serde_json::to_vec(&header).unwrap(),
+                base64::URL_SAFE_NO_PAD
+            ),
+            components[1],
+            components[2]
+        )
+    }
+
+    #[test]
+    fn parse_hash_of_payload() {
+        assert_eq!(
+            parse_hash("payload", HASH.as_bytes()).unwrap(),
+            Hash::new("payload").unwrap()
+        );
+    }
+
+    #[test]
+    fn parse_hash_empty() {
+        assert_eq!(invalid(parse_hash("payload", b"")), "payload hash is empty");
+    }
+
+    #[test]
+    fn parse_hash_base64url() {
+        let encoded = Hash::new("payload").unwrap().to_base64url();
+
+        assert_eq!(
+            invalid(parse_hash("payload", encoded.as_bytes())),
+            "payload hash must be hex encoded (got base64url)"
+        );
+    }
+
+    #[test]
+    fn parse_hash_non_hex_character() {
+        let mut hash = HASH.as_bytes().to_vec();
+
+        hash[10] = b'g';
+
+        assert_eq!(
+            invalid(parse_hash("previous transaction", &hash)),
+            "previous transaction hash contains a non-hex character at position 10"
+        );
+    }
+
+    #[test]
+    fn parse_hash_uppercase() {
+        assert_eq!(
+            invalid(parse_hash("payload", HASH.to_uppercase().as_bytes())),
+            "payload hash must be lowercase hex encoded"
+        );
+    }
+
+    #[test]
+    fn parse_hash_wrong_length() {
+        assert_eq!(
+            invalid(parse_hash("payload", HASH[..62].as_bytes())),
+            "payload hash has an invalid length (expected 64 hex characters, got 62)"
+        );
+    }
+
+    #[test]
+    fn invalid_payload_hash_is_a_validation_error() {
+        let raw = with_payload(&signed(), b"not-a-hash");
+
+        assert!(matches!(
+            Transaction::parse_unsafe(raw),
+            Err(ParseError::NutsValidationError(ValidationError::Invalid(_)))
+        ));
+    }
+
+    #[test]
+    fn unsupported_version() {
+        let raw = with_header(&signed(), |header| {
+            header.insert("ver".to_string(), Value::from(2));
+        });
+
+        assert!(matches!(
+            Transaction::parse_unsafe(raw),
+            Err(ParseError::NutsValidationError(
+                ValidationError::UnsupportedVersion(2)
+            ))
+        ));
+    }
+
+    #[test]
+    fn duplicate_previous() {
+        let raw = with_header(&signed(), |header| {
+            header.insert("prevs".to_string(), Value::from(vec![HASH, HASH]));
+        });
+
+        match Transaction::parse_unsafe(raw) {
+            Err(ParseError::NutsValidationError(ValidationError::DuplicatePrevious(id))) => {
+                assert_eq!(id, Hash::new("payload").unwrap())
+            }
+            other => panic!("expected a duplicate previous transaction, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn key_not_allowed() {
+        let raw = with_header(&signed(), |header| {
+            header.insert("alg".to_string(), Value::from("ES384"));
+        });
+
+        match Transaction::parse_unsafe(raw) {
+            Err(ParseError::NutsValidationError(ValidationError::KeyNotAllowed {
+                algorithm,
+                key_type,
+            })) => {
+                assert_eq!(algorithm, SignatureAlgorithm::ES384);
+                assert_eq!(key_type, "P256");
+            }
+            other => panic!("expected a key which isn't allowed, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn future_sign_time() {
+        let sign = |ahead: ChronoDuration| {
+            TransactionBuilder::new("application/did+json", "payload")
+                .unwrap()
+                .sign_at(Utc::now().naive_utc() + ahead)
+                .sign("key-1", &SigningKey::random(&mut OsRng))
+                .unwrap()
+        };
+
+        assert!(sign(ChronoDuration::minutes(5))
+            .check_sign_time(DEFAULT_MAX_CLOCK_SKEW)
+            .is_ok());
+        assert!(matches!(
+            sign(ChronoDuration::minutes(15)).check_sign_time(DEFAULT_MAX_CLOCK_SKEW),
+            Err(ParseError::NutsValidationError(
+                ValidationError::FutureSignTime { .. }
+            ))
+        ));
+        assert!(sign(ChronoDuration::minutes(15))
+            .check_sign_time(Duration::from_secs(20 * 60))
+            .is_ok());
+    }
+
+    #[test]
+    fn ps256_transaction() {
+        let tx = Transaction::parse(&MemoryKeyStore::default(), PS256_TRANSACTION).unwrap();
+
+        assert_eq!(tx.sign_algo, SignatureAlgorithm::PS256);
+        assert_eq!(tx.key_id, "did:nuts:123#key-1");
+        assert_eq!(tx.payload, Hash::new("payload").unwrap());
+        assert!(tx.is_root());
+    }
+
+    #[test]
+    fn ps256_transaction_with_another_payload() {
+        let raw = with_payload(
+            PS256_TRANSACTION,
+            Hash::new("other").unwrap().to_string().as_bytes(),
+        );
+
+        assert!(Transaction::parse(&MemoryKeyStore::default(), raw).is_err());
+    }
+
+    #[test]
+    fn embedded_rsa_key_too_small() {
+        assert!(matches!(
+            Transaction::parse(&MemoryKeyStore::default(), SMALL_KEY_TRANSACTION),
+            Err(ParseError::NutsValidationError(
+                ValidationError::KeyTooSmall(1024)
+            ))
+        ));
+    }
+
+    #[test]
+    fn stored_rsa_key_too_small() {
+        let key: Key = serde_json::from_value(serde_json::json!({
+            "kty": "RSA",
+            "kid": "did:nuts:123#key-1",
+            "n": "y2mHqdp8eqsTDGhF-EF8nLkM8oPD5jKuS3MyjBWylPXCbC6VCiMS5BtDkSjaTA9czylUax9AUfuTYdbIuQqA1eErfTETmbFqeXCF8PgV-q3_K7lTDsxP8UmiqFuvggsfZbkb1zGElAOG_qXcFTfYElA8RdLyYY-KF8ss0_WPg0c",
+            "e": "AQAB",
+        }))
+        .unwrap();
+        let mut store = MemoryKeyStore::default();
+
+        store.add("did:nuts:123#key-1".to_string(), key).unwrap();
+
+        assert!(matches!(
+            Transaction::parse(&store, SMALL_KEY_ID_TRANSACTION),
+            Err(ParseError::NutsValidationError(
+                ValidationError::KeyTooSmall(1024)
+            ))
+        ));
     }
 }
@@ -0,0 +1,164 @@
+use std::sync::{mpsc, Arc, Mutex, Weak};
+use std::thread::{self, JoinHandle};
+
+use anyhow::{anyhow, Result};
+use tokio::sync::oneshot;
+
+use crate::backend::Tree;
+
+enum Write {
+    Insert(Arc<dyn Tree>, Vec<u8>, Vec<u8>),
+    Remove(Arc<dyn Tree>, Vec<u8>),
+    /// Completed once all writes queued before it are applied
+    Barrier(oneshot::Sender<()>),
+}
+
+/// Maximum number of writes which are queued, adding to a full queue waits until the disk catches up
+const QUEUE_CAPACITY: usize = 10_000;
+
+/// Write-ahead queue of database writes which are applied in order by a dedicated thread, so that the caller
+/// doesn't wait for the disk. Pending writes are applied before the writer is dropped
+pub struct Writer {
+    queue: Option<Arc<mpsc::SyncSender<Write>>>,
+    /// Error of the first write which failed, no more writes are queued afterwards
+    error: Arc<Mutex<Option<String>>>,
+    thread: Option<JoinHandle<()>>,
+}
+
+impl Writer {
+    pub fn spawn(name: &str) -> Result<Self> {
+        let (queue, writes) = mpsc::sync_channel(QUEUE_CAPACITY);
+        let error = Arc::new(Mutex::new(None));
+        let thread = {
+            let error = error.clone();
+
+            thread::Builder::new()
+                .name(name.to_string())
+                .spawn(move || apply(writes, &error))?
+        };
+
+        Ok(Self {
+            queue: Some(Arc::new(queue)),
+            error,
+            thread: Some(thread),
+        })
+    }
+
+    pub fn insert(
+        &mut self,
+        tree: &Arc<dyn Tree>,
+        key: impl AsRef<[u8]>,
+        value: Vec<u8>,
+    ) -> Result<()> {
+        self.send(Write::Insert(tree.clone(), key.as_ref().to_vec(), value))
+    }
+
+    pub fn remove(&mut self, tree: &Arc<dyn Tree>, key: impl AsRef<[u8]>) -> Result<()> {
+        self.send(Write::Remove(tree.clone(), key.as_ref().to_vec()))
+    }
+
+    fn send(&mut self, write: Write) -> Result<()> {
+        self.check()?;
+        self.queue
+            .as_ref()
+            .and_then(|queue| queue.send(write).ok())
+            .ok_or_else(|| anyhow!("database writer stopped"))?;
+
+        Ok(())
+    }
+
+    /// Returns the error of the first write which failed
+    fn check(&self) -> Result<()> {
+        check(&self.error)
+    }
+
+    /// Waits until all queued writes are applied without blocking the thread, which is needed before reading what
+    /// was written
+    pub async fn drain(&self) -> Result<()> {
+        let queue = self
+            .queue
+            .clone()
+            .ok_or_else(|| anyhow!("database writer stopped"))?;
+
+        drain(queue, &self.error).await
+    }
+
+    /// Returns a handle to wait for the queued writes from outside of the owner of the writer
+    pub fn pending(&self) -> PendingWrites {
+        PendingWrites {
+            queue: self.queue.as_ref().map(Arc::downgrade).unwrap_or_default(),
+            error: self.error.clone(),
+        }
+    }
+}
+
+/// Handle to wait until the writes queued by a writer are applied (e.g. before taking a backup), which doesn't keep
+/// the writer running
+#[derive(Clone)]
+pub struct PendingWrites {
+    queue: Weak<mpsc::SyncSender<Write>>,
+    error: Arc<Mutex<Option<String>>>,
+}
+
+impl PendingWrites {
+    /// Waits until all writes which were queued before calling this are applied
+    pub async fn drain(&self) -> Result<()> {
+        let queue = self
+            .queue
+            .upgrade()
+            .ok_or_else(|| anyhow!("database writer stopped"))?;
+
+        drain(queue, &self.error).await
+    }
+}
+
+fn check(error: &Mutex<Option<String>>) -> Result<()> {
+    match &*error.lock().unwrap() {
+        Some(e) => Err(anyhow!("failed to write to the database: {}", e)),
+        None => Ok(()),
+    }
+}
+
+async fn drain(queue: Arc<mpsc::SyncSender<Write>>, error: &Mutex<Option<String>>) -> Result<()> {
+    let (done, drained) = oneshot::channel();
+
+    queue
+        .send(Write::Barrier(done))
+        .map_err(|_| anyhow!("database writer stopped"))?;
+    // The queue is closed once the writer is dropped, which shouldn't wait for this handle
+    drop(queue);
+    drained
+        .await
+        .map_err(|_| anyhow!("database writer stopped"))?;
+
+    check(error)
+}
+
+impl Drop for Writer {
+    fn drop(&mut self) {
+        // Closing the queue stops the thread once it applied the remaining writes
+        self.queue.take();
+
+        if let Some(thread) = self.thread.take() {
+            let _ = thread.join();
+        }
+    }
+}
+
+fn apply(writes: mpsc::Receiver<Write>, error: &Mutex<Option<String>>) {
+    for write in writes {
+        let result = match write {
+            Write::Insert(tree, key, value) => tree.insert(&key, &value),
+            Write::Remove(tree, key) => tree.remove(&key).map(|_| ()),
+            Write::Barrier(done) => {
+                // Sending only fails when the caller stopped waiting
+                let _ = done.send(());
+                continue;
+            }
+        };
+        if let Err(e) = result {
+            tracing::error!(target: "nuts::network", "failed to write to the database: {}", e);
+            error.lock().unwrap().get_or_insert_with(|| e.to_string());
+        }
+    }
+}
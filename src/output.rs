@@ -0,0 +1,36 @@
+use std::str::FromStr;
+
+use anyhow::{anyhow, Error, Result};
+use serde::Serialize;
+
+/// Format in which commands print their results, JSON is meant for scripts and monitoring tooling
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Output {
+    Text,
+    Json,
+}
+
+impl Output {
+    pub fn is_json(self) -> bool {
+        self == Output::Json
+    }
+}
+
+impl FromStr for Output {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "text" => Ok(Output::Text),
+            "json" => Ok(Output::Json),
+            _ => Err(anyhow!("invalid output '{}' (expected text or json)", s)),
+        }
+    }
+}
+
+/// Prints the value as pretty-printed JSON
+pub fn print_json(value: &impl Serialize) -> Result<()> {
+    println!("{}", serde_json::to_string_pretty(value)?);
+
+    Ok(())
+}
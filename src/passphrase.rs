@@ -0,0 +1,110 @@
+use std::env;
+use std::io::{self, BufRead, Write};
+
+use anyhow::{anyhow, Result};
+use nuts_rs::pki::PrivateKeyStore;
+use sled::Db;
+
+/// Environment variable which contains the passphrase of the private keys, which is used instead of prompting for it
+const PASSPHRASE_ENV: &str = "NUTS_PASSPHRASE";
+
+/// Disables echoing the input of the terminal until it's dropped
+#[cfg(unix)]
+struct HideInput(Option<libc::termios>);
+
+#[cfg(unix)]
+impl HideInput {
+    fn new() -> Self {
+        let mut termios = std::mem::MaybeUninit::<libc::termios>::uninit();
+
+        // Stdin isn't a terminal when the passphrase is piped in
+        unsafe {
+            if libc::tcgetattr(libc::STDIN_FILENO, termios.as_mut_ptr()) != 0 {
+                return Self(None);
+            }
+
+            let original = termios.assume_init();
+            let mut hidden = original;
+
+            hidden.c_lflag &= !libc::ECHO;
+            libc::tcsetattr(libc::STDIN_FILENO, libc::TCSANOW, &hidden);
+
+            Self(Some(original))
+        }
+    }
+}
+
+#[cfg(unix)]
+impl Drop for HideInput {
+    fn drop(&mut self) {
+        if let Some(original) = &self.0 {
+            unsafe {
+                libc::tcsetattr(libc::STDIN_FILENO, libc::TCSANOW, original);
+            }
+        }
+    }
+}
+
+#[cfg(not(unix))]
+struct HideInput;
+
+#[cfg(not(unix))]
+impl HideInput {
+    fn new() -> Self {
+        Self
+    }
+}
+
+/// Reads the passphrase from the environment or prompts for it on the terminal without echoing the input
+pub fn read(prompt: &str) -> Result<String> {
+    if let Ok(passphrase) = env::var(PASSPHRASE_ENV) {
+        return Ok(passphrase);
+    }
+
+    eprint!("{}: ", prompt);
+    io::stderr().flush()?;
+
+    let mut passphrase = String::new();
+
+    {
+        let _hidden = HideInput::new();
+
+        io::stdin().lock().read_line(&mut passphrase)?;
+    }
+
+    eprintln!();
+
+    let passphrase = passphrase.trim_end_matches(&['\r', '\n'][..]).to_string();
+
+    if passphrase.is_empty() {
+        return Err(anyhow!("passphrase can't be empty"));
+    }
+
+    Ok(passphrase)
+}
+
+/// Reads a new passphrase which must be entered twice when prompted
+pub fn read_new() -> Result<String> {
+    if let Ok(passphrase) = env::var(PASSPHRASE_ENV) {
+        return Ok(passphrase);
+    }
+
+    let passphrase = read("New passphrase for the private keys")?;
+
+    if read("Repeat the passphrase")? != passphrase {
+        return Err(anyhow!("passphrases don't match"));
+    }
+
+    Ok(passphrase)
+}
+
+/// Opens the private keys and unlocks them when they're encrypted
+pub fn unlock(db: Db) -> Result<PrivateKeyStore> {
+    let mut store = PrivateKeyStore::open(db)?;
+
+    if store.is_encrypted()? {
+        store.unlock(&read("Passphrase of the private keys")?)?;
+    }
+
+    Ok(store)
+}
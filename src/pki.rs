@@ -1,72 +1,889 @@
+use std::collections::{BTreeMap, HashMap};
+use std::error::Error;
+use std::fmt::{Display, Formatter};
+use std::num::NonZeroU32;
+use std::string::FromUtf8Error;
+use std::sync::{Arc, Mutex};
+
 use anyhow::{anyhow, Result};
-use biscuit::jwk::JWKSet;
-use biscuit::{jwk::JWK, Empty};
+use argon2::{Algorithm, Argon2, Params, Version};
+use biscuit::jwk::{
+    AlgorithmParameters, CommonParameters, EllipticCurve, EllipticCurveKeyParameters,
+    EllipticCurveKeyType,
+};
+use biscuit::{jwk::JWK, CompactPart, Empty};
+use chrono::{DateTime, Utc};
+use ecdsa::signature::{Signer, Verifier};
+use p256::ecdsa::{Signature, SigningKey, VerifyingKey};
+use ring::aead::{Aad, LessSafeKey, Nonce, UnboundKey, AES_256_GCM, NONCE_LEN};
+use ring::pbkdf2;
+use ring::rand::{SecureRandom, SystemRandom};
 use rmp_serde::{decode, encode};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
 use sled::Db;
 
+use crate::backend::{self, Storage, Tree};
+
 pub type Key = JWK<Empty>;
 
+fn base64url(bytes: Vec<u8>) -> Result<String> {
+    Ok(bytes.to_base64()?.unwrap())
+}
+
+/// Computes the JWK thumbprint as described in RFC7638: <https://datatracker.ietf.org/doc/html/rfc7638>
+pub fn thumbprint(key: &Key) -> Result<String> {
+    // The required members MUST be in lexicographic order and without any whitespace
+    let members = match &key.algorithm {
+        AlgorithmParameters::EllipticCurve(params) => format!(
+            r#"{{"crv":"{}","kty":"EC","x":"{}","y":"{}"}}"#,
+            match params.curve {
+                EllipticCurve::P256 => "P-256",
+                EllipticCurve::P384 => "P-384",
+                EllipticCurve::P521 => "P-521",
+                EllipticCurve::Curve25519 => "Ed25519",
+                EllipticCurve::Curve448 => "Ed448",
+            },
+            base64url(params.x.clone())?,
+            base64url(params.y.clone())?,
+        ),
+        AlgorithmParameters::RSA(params) => format!(
+            r#"{{"e":"{}","kty":"RSA","n":"{}"}}"#,
+            base64url(params.e.to_bytes_be())?,
+            base64url(params.n.to_bytes_be())?,
+        ),
+        AlgorithmParameters::OctetKey(params) => format!(
+            r#"{{"k":"{}","kty":"oct"}}"#,
+            base64url(params.value.clone())?,
+        ),
+        _ => return Err(anyhow!("unsupported key type for thumbprint")),
+    };
+
+    base64url(Sha256::digest(members.as_bytes()).to_vec())
+}
+
+/// Extension methods of a public JWK
+pub trait KeyExt {
+    /// Computes the JWK thumbprint as described in RFC7638, see [`thumbprint`]
+    fn thumbprint(&self) -> Result<String>;
+}
+
+impl KeyExt for Key {
+    fn thumbprint(&self) -> Result<String> {
+        thumbprint(self)
+    }
+}
+
+/// Creates the public JWK of a P-256 signing key
+pub fn public_jwk(key_id: &str, key: &SigningKey) -> Key {
+    let point = key.verifying_key().to_encoded_point(false);
+
+    JWK {
+        common: CommonParameters {
+            key_id: Some(key_id.to_string()),
+            ..Default::default()
+        },
+        algorithm: AlgorithmParameters::EllipticCurve(EllipticCurveKeyParameters {
+            key_type: EllipticCurveKeyType::EC,
+            curve: EllipticCurve::P256,
+            // An uncompressed point always contains both coordinates
+            x: point.x().unwrap().to_vec(),
+            y: point.y().unwrap().to_vec(),
+            d: None,
+        }),
+        additional: Empty {},
+    }
+}
+
+/// Signs a message using ES256 and returns the fixed-size `r || s` signature
+pub fn sign_es256(key: &SigningKey, message: &[u8]) -> Vec<u8> {
+    let signature: Signature = key.sign(message);
+
+    signature.to_bytes().to_vec()
+}
+
+/// Verifies an ES256 signature using a P-256 public key
+pub fn verify_es256(key: &Key, message: &[u8], signature: &[u8]) -> Result<()> {
+    let params = match &key.algorithm {
+        AlgorithmParameters::EllipticCurve(params) if params.curve == EllipticCurve::P256 => params,
+        _ => return Err(anyhow!("expected a P-256 key")),
+    };
+    let point = [&[4][..], params.x.as_slice(), params.y.as_slice()].concat();
+
+    VerifyingKey::from_sec1_bytes(&point)?
+        .verify(message, &Signature::from_slice(signature)?)
+        .map_err(|_| anyhow!("invalid signature"))
+}
+
+/// Errors returned by the public and private key stores
+#[derive(Debug)]
+pub enum KeyStoreError {
+    /// A key with the same key ID already exists
+    DuplicateKey(String),
+    /// The private keys are encrypted and the store wasn't unlocked using the passphrase
+    Locked,
+    InvalidPassphrase,
+    /// The private keys aren't encrypted using a passphrase
+    NotEncrypted,
+    AlreadyEncrypted,
+    /// Reading, writing or decoding a stored key failed
+    Storage(anyhow::Error),
+}
+
+impl Display for KeyStoreError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            KeyStoreError::DuplicateKey(id) => write!(f, "key with ID '{}' already exists", id),
+            KeyStoreError::Locked => write!(
+                f,
+                "private keys are locked, unlock them using the passphrase"
+            ),
+            KeyStoreError::InvalidPassphrase => write!(f, "invalid passphrase"),
+            KeyStoreError::NotEncrypted => write!(f, "private keys aren't encrypted"),
+            KeyStoreError::AlreadyEncrypted => write!(f, "private keys are already encrypted"),
+            KeyStoreError::Storage(e) => write!(f, "key store error: {}", e),
+        }
+    }
+}
+
+impl Error for KeyStoreError {}
+
+impl From<anyhow::Error> for KeyStoreError {
+    fn from(e: anyhow::Error) -> Self {
+        KeyStoreError::Storage(e)
+    }
+}
+
+impl From<sled::Error> for KeyStoreError {
+    fn from(e: sled::Error) -> Self {
+        KeyStoreError::Storage(e.into())
+    }
+}
+
+impl From<encode::Error> for KeyStoreError {
+    fn from(e: encode::Error) -> Self {
+        KeyStoreError::Storage(e.into())
+    }
+}
+
+impl From<decode::Error> for KeyStoreError {
+    fn from(e: decode::Error) -> Self {
+        KeyStoreError::Storage(e.into())
+    }
+}
+
+impl From<FromUtf8Error> for KeyStoreError {
+    fn from(e: FromUtf8Error) -> Self {
+        KeyStoreError::Storage(e.into())
+    }
+}
+
+impl From<ecdsa::Error> for KeyStoreError {
+    fn from(e: ecdsa::Error) -> Self {
+        KeyStoreError::Storage(anyhow!("invalid private key: {}", e))
+    }
+}
+
+/// Storage backend of the public keys used to verify transactions, which allows keys to be kept somewhere else than
+/// the database of the node (e.g. in a HSM or remote vault)
+pub trait KeyStorage: Send + Sync {
+    /// Get a key by it's key ID
+    fn get(&self, id: &str) -> Result<Option<Key>, KeyStoreError>;
+
+    /// Whether a key with the given key ID exists
+    fn contains(&self, id: &str) -> Result<bool, KeyStoreError>;
+
+    /// Adds a key to the store (note that the key ID MUST not be empty)
+    fn add(&mut self, id: String, key: Key) -> Result<(), KeyStoreError>;
+
+    /// Returns all keys ordered by their key ID
+    fn list(&self) -> Result<Vec<Key>, KeyStoreError>;
+
+    /// Returns the moment (as unix timestamp) the key was revoked, transactions signed at or after it are rejected.
+    /// Every storage has to answer this as transactions signed by revoked keys would be accepted otherwise
+    fn revoked_at(&self, id: &str) -> Result<Option<i64>, KeyStoreError>;
+}
+
+/// Number of keys which are kept in memory by the [`KeyStore`] by default
+pub const DEFAULT_KEY_CACHE_CAPACITY: usize = 1024;
+
+/// Least recently used keys, the least recently used key is evicted when the cache is full
+struct KeyCache {
+    capacity: usize,
+    entries: HashMap<String, (Key, u64)>,
+    /// Key IDs by the moment they were last used
+    recency: BTreeMap<u64, String>,
+    clock: u64,
+}
+
+impl KeyCache {
+    fn new(capacity: usize) -> Self {
+        Self {
+            capacity,
+            entries: HashMap::new(),
+            recency: BTreeMap::new(),
+            clock: 0,
+        }
+    }
+
+    fn get(&mut self, id: &str) -> Option<Key> {
+        let (key, used) = self.entries.get_mut(id)?;
+
+        self.recency.remove(&*used);
+        self.clock += 1;
+        *used = self.clock;
+        self.recency.insert(self.clock, id.to_string());
+
+        Some(key.clone())
+    }
+
+    fn insert(&mut self, id: String, key: Key) {
+        if self.capacity == 0 {
+            return;
+        }
+
+        self.remove(&id);
+
+        if self.entries.len() >= self.capacity {
+            if let Some((_, oldest)) = self.recency.pop_first() {
+                self.entries.remove(&oldest);
+            }
+        }
+
+        self.clock += 1;
+        self.recency.insert(self.clock, id.clone());
+        self.entries.insert(id, (key, self.clock));
+    }
+
+    fn remove(&mut self, id: &str) {
+        if let Some((_, used)) = self.entries.remove(id) {
+            self.recency.remove(&used);
+        }
+    }
+}
+
+/// Public keys used to verify transactions, keys are added when they're embedded in a transaction or generated
+/// locally. Keys are read through a cache of the most recently used keys which is invalidated when a key is added or
+/// removed
 pub struct KeyStore {
-    db: Db,
-    jwk_set: JWKSet<Empty>,
+    tree: Arc<dyn Tree>,
+    trust: TrustPolicy,
+    cache: Mutex<KeyCache>,
 }
 
 impl KeyStore {
+    /// Opens the key store with a cache of [`DEFAULT_KEY_CACHE_CAPACITY`] keys
+    pub fn open(db: Db) -> Result<Self> {
+        Self::with_cache_capacity(db, DEFAULT_KEY_CACHE_CAPACITY)
+    }
+
+    /// Opens the key store with a cache of the given number of keys, zero disables the cache
+    pub fn with_cache_capacity(db: Db, capacity: usize) -> Result<Self> {
+        let storage = backend::open(&db)?;
+
+        Ok(Self {
+            tree: storage.open_tree("nuts/keys")?,
+            trust: TrustPolicy::with_storage(storage.as_ref())?,
+            cache: Mutex::new(KeyCache::new(capacity)),
+        })
+    }
+
+    /// Returns the number of keys
+    pub fn len(&self) -> Result<usize, KeyStoreError> {
+        Ok(self.tree.len()?)
+    }
+
+    pub fn is_empty(&self) -> Result<bool, KeyStoreError> {
+        Ok(self.tree.is_empty()?)
+    }
+
+    /// Iterates over the key IDs and keys ordered by their key ID, the keys are read from the database without
+    /// filling the cache
+    pub fn iter(&self) -> impl Iterator<Item = Result<(String, Key), KeyStoreError>> + '_ {
+        self.tree.iter().map(|record| {
+            let (id, value) = record?;
+
+            Ok((String::from_utf8(id)?, decode::from_read(value.as_ref())?))
+        })
+    }
+
+    /// Removes a key, returns `false` when there is no key with the given key ID. Revocations of the key are kept
+    /// so that it stays revoked when it's added again (e.g. as it's embedded in a transaction)
+    pub fn remove(&mut self, id: &str) -> Result<bool, KeyStoreError> {
+        tracing::debug!(target: "nuts::pki", "removing a key: {}", id);
+
+        let removed = self.tree.remove(id.as_bytes())?;
+
+        self.cache.lock().unwrap().remove(id);
+
+        Ok(removed)
+    }
+
+    /// Revokes the key at the given moment in the revocation list of the [`TrustPolicy`], a key which is already
+    /// revoked keeps the earliest moment
+    pub fn revoke(&self, id: &str, at: DateTime<Utc>) -> Result<(), KeyStoreError> {
+        tracing::debug!(target: "nuts::pki", "revoking a key: {}", id);
+
+        Ok(self.trust.revoke(id, at.timestamp())?)
+    }
+}
+
+impl KeyStorage for KeyStore {
+    fn get(&self, id: &str) -> Result<Option<Key>, KeyStoreError> {
+        if let Some(key) = self.cache.lock().unwrap().get(id) {
+            return Ok(Some(key));
+        }
+
+        match self.tree.get(id.as_bytes())? {
+            Some(value) => {
+                let key: Key = decode::from_read(value.as_ref())?;
+
+                self.cache
+                    .lock()
+                    .unwrap()
+                    .insert(id.to_string(), key.clone());
+
+                Ok(Some(key))
+            }
+            None => Ok(None),
+        }
+    }
+
+    fn contains(&self, id: &str) -> Result<bool, KeyStoreError> {
+        if self.cache.lock().unwrap().entries.contains_key(id) {
+            return Ok(true);
+        }
+
+        Ok(self.tree.contains_key(id.as_bytes())?)
+    }
+
+    fn add(&mut self, id: String, key: Key) -> Result<(), KeyStoreError> {
+        tracing::debug!(target: "nuts::pki", "adding a key: {}", id);
+
+        if self.tree.contains_key(id.as_bytes())? {
+            return Err(KeyStoreError::DuplicateKey(id));
+        }
+
+        self.tree.insert(id.as_bytes(), &encode::to_vec(&key)?)?;
+        self.cache.lock().unwrap().remove(&id);
+
+        Ok(())
+    }
+
+    fn list(&self) -> Result<Vec<Key>, KeyStoreError> {
+        self.iter().map(|record| Ok(record?.1)).collect()
+    }
+
+    fn revoked_at(&self, id: &str) -> Result<Option<i64>, KeyStoreError> {
+        Ok(self.trust.get(id)?.and_then(|record| record.revoked_at))
+    }
+}
+
+/// Keeps the public keys in memory only, which is useful for tests and short-lived tools
+#[derive(Default)]
+pub struct MemoryKeyStore {
+    keys: BTreeMap<String, Key>,
+    revocations: BTreeMap<String, i64>,
+}
+
+impl MemoryKeyStore {
+    /// Revokes the key at the given moment, an earlier revocation is kept
+    pub fn revoke(&mut self, id: &str, at: DateTime<Utc>) {
+        let at = at.timestamp();
+
+        self.revocations
+            .entry(id.to_string())
+            .and_modify(|revoked_at| *revoked_at = (*revoked_at).min(at))
+            .or_insert(at);
+    }
+}
+
+impl KeyStorage for MemoryKeyStore {
+    fn get(&self, id: &str) -> Result<Option<Key>, KeyStoreError> {
+        Ok(self.keys.get(id).cloned())
+    }
+
+    fn contains(&self, id: &str) -> Result<bool, KeyStoreError> {
+        Ok(self.keys.contains_key(id))
+    }
+
+    fn add(&mut self, id: String, key: Key) -> Result<(), KeyStoreError> {
+        if self.keys.contains_key(&id) {
+            return Err(KeyStoreError::DuplicateKey(id));
+        }
+
+        self.keys.insert(id, key);
+
+        Ok(())
+    }
+
+    fn list(&self) -> Result<Vec<Key>, KeyStoreError> {
+        Ok(self.keys.values().cloned().collect())
+    }
+
+    fn revoked_at(&self, id: &str) -> Result<Option<i64>, KeyStoreError> {
+        Ok(self.revocations.get(id).copied())
+    }
+}
+
+/// Argon2id parameters used to derive the key which encrypts the private keys from the passphrase, the memory (in
+/// KiB), iterations and parallelism recommended by OWASP
+const VAULT_MEMORY: u32 = 19 * 1024;
+const VAULT_ITERATIONS: u32 = 2;
+const VAULT_PARALLELISM: u32 = 1;
+
+/// Known plaintext which is encrypted when the vault is created, used to verify the passphrase
+const VAULT_CHECK: &[u8] = b"nuts-private-keys";
+
+/// Parameters of the vault which are needed to derive the key from the passphrase
+#[derive(Serialize, Deserialize)]
+struct VaultParams {
+    salt: Vec<u8>,
+    iterations: u32,
+    check: Vec<u8>,
+    /// Vaults created by older versions don't have these as they use PBKDF2 and don't bind the private keys to their
+    /// key ID, these are upgraded when they're unlocked
+    #[serde(default)]
+    argon2: Option<Argon2Params>,
+}
+
+#[derive(Serialize, Deserialize)]
+struct Argon2Params {
+    memory: u32,
+    parallelism: u32,
+}
+
+/// Key which encrypts the private keys using AES-256-GCM, the key ID is authenticated along with the private key so
+/// that encrypted keys can't be swapped
+struct VaultKey {
+    key: LessSafeKey,
+    /// Derived using PBKDF2 and without authenticating the key ID, as done by older versions
+    legacy: bool,
+}
+
+impl VaultKey {
+    fn derive(passphrase: &str, params: &VaultParams) -> Result<Self> {
+        let mut key = [0; 32];
+
+        match &params.argon2 {
+            Some(argon2) => {
+                let argon2_params = Params::new(
+                    argon2.memory,
+                    params.iterations,
+                    argon2.parallelism,
+                    Some(key.len()),
+                )
+                .map_err(|e| anyhow!("invalid vault parameters: {}", e))?;
+
+                Argon2::new(Algorithm::Argon2id, Version::V0x13, argon2_params)
+                    .hash_password_into(passphrase.as_bytes(), &params.salt, &mut key)
+                    .map_err(|e| anyhow!("unable to derive vault key: {}", e))?;
+            }
+            None => {
+                let iterations = NonZeroU32::new(params.iterations)
+                    .ok_or_else(|| anyhow!("invalid number of iterations"))?;
+
+                pbkdf2::derive(
+                    pbkdf2::PBKDF2_HMAC_SHA256,
+                    iterations,
+                    &params.salt,
+                    passphrase.as_bytes(),
+                    &mut key,
+                );
+            }
+        }
+
+        Ok(Self {
+            key: LessSafeKey::new(
+                UnboundKey::new(&AES_256_GCM, &key).map_err(|_| anyhow!("invalid vault key"))?,
+            ),
+            legacy: params.argon2.is_none(),
+        })
+    }
+
+    fn aad<'a>(&self, id: &'a [u8]) -> Aad<&'a [u8]> {
+        Aad::from(if self.legacy { &[] } else { id })
+    }
+
+    /// Encrypts the data which is stored under the given key ID, the random nonce is prepended to the ciphertext
+    fn seal(&self, id: &[u8], data: &[u8]) -> Result<Vec<u8>> {
+        let mut nonce = [0; NONCE_LEN];
+
+        SystemRandom::new()
+            .fill(&mut nonce)
+            .map_err(|_| anyhow!("unable to generate nonce"))?;
+
+        let mut sealed = data.to_vec();
+
+        self.key
+            .seal_in_place_append_tag(
+                Nonce::assume_unique_for_key(nonce),
+                self.aad(id),
+                &mut sealed,
+            )
+            .map_err(|_| anyhow!("unable to encrypt private key"))?;
+
+        Ok([nonce.as_ref(), &sealed].concat())
+    }
+
+    fn unseal(&self, id: &[u8], data: &[u8]) -> Result<Vec<u8>> {
+        if data.len() < NONCE_LEN {
+            return Err(anyhow!("encrypted private key is truncated"));
+        }
+
+        let (nonce, sealed) = data.split_at(NONCE_LEN);
+        let mut sealed = sealed.to_vec();
+        let plaintext = self
+            .key
+            .open_in_place(
+                Nonce::try_assume_unique_for_key(nonce).map_err(|_| anyhow!("invalid nonce"))?,
+                self.aad(id),
+                &mut sealed,
+            )
+            .map_err(|_| anyhow!("unable to decrypt private key"))?;
+
+        Ok(plaintext.to_vec())
+    }
+}
+
+/// Tree in which the private keys are stored
+const PRIVATE_KEYS_TREE: &str = "nuts/private-keys";
+
+/// Key of the parameters of the vault, which are stored next to the private keys so that both are updated at once
+/// when the keys are encrypted. Key IDs are never empty so it doesn't collide with a private key
+const VAULT_PARAMS: &[u8] = b"";
+
+/// Stores the private keys of the node which are never shared with peers, once the vault is created the keys are
+/// encrypted using a key derived from a passphrase and the store must be unlocked before keys can be used
+pub struct PrivateKeyStore {
+    tree: Arc<dyn Tree>,
+    key: Option<VaultKey>,
+}
+
+impl PrivateKeyStore {
     pub fn open(db: Db) -> Result<Self> {
-        let mut store = Self {
-            db,
-            jwk_set: JWKSet { keys: vec![] },
+        Ok(Self {
+            tree: backend::open(&db)?.open_tree(PRIVATE_KEYS_TREE)?,
+            key: None,
+        })
+    }
+
+    /// Moves the private keys into the storage backend and the parameters of the vault next to them, as older
+    /// versions always stored them in sled
+    pub(crate) fn move_to_backend(db: &Db) -> Result<()> {
+        let vault = db.open_tree("nuts/vault")?;
+
+        backend::move_tree(db, PRIVATE_KEYS_TREE)?;
+
+        if let Some(params) = vault.get("params")? {
+            let tree = backend::open(db)?.open_tree(PRIVATE_KEYS_TREE)?;
+
+            tree.insert(VAULT_PARAMS, &params)?;
+            tree.flush()?;
+        }
+
+        db.drop_tree("nuts/vault")?;
+
+        Ok(())
+    }
+
+    fn params(&self) -> Result<Option<VaultParams>, KeyStoreError> {
+        match self.tree.get(VAULT_PARAMS)? {
+            Some(value) => Ok(Some(decode::from_read(value.as_ref())?)),
+            None => Ok(None),
+        }
+    }
+
+    /// Whether the private keys are encrypted using a passphrase
+    pub fn is_encrypted(&self) -> Result<bool, KeyStoreError> {
+        Ok(self.params()?.is_some())
+    }
+
+    /// Whether the private keys are encrypted and the store wasn't unlocked yet
+    pub fn is_locked(&self) -> Result<bool, KeyStoreError> {
+        Ok(self.key.is_none() && self.is_encrypted()?)
+    }
+
+    /// Derives the key from the passphrase and verifies it against the vault, a vault created by an older version is
+    /// upgraded to Argon2id
+    pub fn unlock(&mut self, passphrase: &str) -> Result<(), KeyStoreError> {
+        let params = self.params()?.ok_or(KeyStoreError::NotEncrypted)?;
+        let key = VaultKey::derive(passphrase, &params)?;
+
+        match key.unseal(VAULT_PARAMS, &params.check) {
+            Ok(check) if check == VAULT_CHECK => {}
+            _ => return Err(KeyStoreError::InvalidPassphrase),
+        }
+
+        let key = if key.legacy {
+            let (key, upgraded) = self.seal_all(passphrase, Some(&key))?;
+
+            tracing::info!(target: "nuts::pki", "upgraded the vault of {} private keys to Argon2id", upgraded);
+
+            key
+        } else {
+            key
         };
 
-        let tree = store.db.open_tree("nuts/keys")?;
+        self.key = Some(key);
 
-        for record in tree.iter() {
-            let (_, value) = record?;
-            let key: JWK<Empty> = decode::from_slice(&value)?;
+        Ok(())
+    }
 
-            store.jwk_set.keys.push(key);
+    /// Creates the vault and encrypts all existing private keys using the passphrase, returns the number of keys
+    /// which were encrypted
+    pub fn encrypt(&mut self, passphrase: &str) -> Result<usize, KeyStoreError> {
+        if self.is_encrypted()? {
+            return Err(KeyStoreError::AlreadyEncrypted);
         }
 
-        Ok(store)
+        let (key, encrypted) = self.seal_all(passphrase, None)?;
+
+        self.key = Some(key);
+
+        Ok(encrypted)
     }
 
-    /// Get a key by it's key ID
-    pub fn get(&self, id: &str) -> Result<Option<Key>> {
-        let tree = self.db.open_tree("nuts/keys")?;
+    /// Encrypts all private keys using a new key derived from the passphrase, after decrypting them using the current
+    /// key of the vault (if any). Returns the new key and the number of keys which were encrypted
+    fn seal_all(
+        &self,
+        passphrase: &str,
+        current: Option<&VaultKey>,
+    ) -> Result<(VaultKey, usize), KeyStoreError> {
+        let mut salt = vec![0; 16];
+
+        SystemRandom::new()
+            .fill(&mut salt)
+            .map_err(|_| anyhow!("unable to generate salt"))?;
+
+        let mut params = VaultParams {
+            salt,
+            iterations: VAULT_ITERATIONS,
+            check: vec![],
+            argon2: Some(Argon2Params {
+                memory: VAULT_MEMORY,
+                parallelism: VAULT_PARALLELISM,
+            }),
+        };
+        let key = VaultKey::derive(passphrase, &params)?;
+
+        params.check = key.seal(VAULT_PARAMS, VAULT_CHECK)?;
+
+        let mut records = vec![];
+
+        for record in self.tree.iter() {
+            let (id, value) = record?;
+
+            if id == VAULT_PARAMS {
+                continue;
+            }
+
+            let value = match current {
+                Some(current) => current.unseal(&id, &value)?,
+                None => value,
+            };
+            let sealed = key.seal(&id, &value)?;
+
+            records.push((id, sealed));
+        }
+
+        let encrypted = records.len();
+
+        // The keys and vault are updated at once so that plaintext and encrypted keys are never mixed
+        records.push((VAULT_PARAMS.to_vec(), encode::to_vec(&params)?));
+
+        self.tree
+            .insert_all(records)
+            .map_err(|e| anyhow!("unable to encrypt private keys: {}", e))?;
+
+        Ok((key, encrypted))
+    }
+
+    /// Returns the key used to encrypt the private keys or nothing when they're stored in plaintext
+    fn vault_key(&self) -> Result<Option<&VaultKey>, KeyStoreError> {
+        match &self.key {
+            Some(key) => Ok(Some(key)),
+            None if self.is_encrypted()? => Err(KeyStoreError::Locked),
+            None => Ok(None),
+        }
+    }
 
-        if let Some(value) = tree.get(id)? {
-            return Ok(Some(decode::from_read(value.as_ref())?));
+    pub fn get(&self, id: &str) -> Result<Option<SigningKey>, KeyStoreError> {
+        if id.is_empty() {
+            return Ok(None);
         }
 
-        Ok(None)
+        match self.tree.get(id.as_bytes())? {
+            Some(value) => {
+                let bytes = match self.vault_key()? {
+                    Some(key) => key.unseal(id.as_bytes(), &value)?,
+                    None => value,
+                };
+
+                Ok(Some(SigningKey::from_slice(&bytes)?))
+            }
+            None => Ok(None),
+        }
     }
 
-    pub fn contains(&self, id: &str) -> Result<bool> {
-        let tree = self.db.open_tree("nuts/keys")?;
+    /// Returns the IDs of all private keys
+    pub fn ids(&self) -> Result<Vec<String>, KeyStoreError> {
+        let mut ids = vec![];
+
+        for record in self.tree.iter() {
+            let (id, _) = record?;
 
-        Ok(tree.contains_key(id)?)
+            if id != VAULT_PARAMS {
+                ids.push(String::from_utf8(id)?);
+            }
+        }
+
+        Ok(ids)
     }
 
-    /// Adds a key to the store (note that the key ID MUST not be empty)
-    pub fn add(&mut self, id: String, key: Key) -> Result<()> {
-        let tree = self.db.open_tree("nuts/keys")?;
+    /// Removes a private key, which doesn't require the store to be unlocked. Returns `false` when there is no
+    /// private key with the given key ID
+    pub fn remove(&self, id: &str) -> Result<bool, KeyStoreError> {
+        if id.is_empty() {
+            return Ok(false);
+        }
 
-        log::debug!(target: "nuts::pki", "adding a key: {}", id);
+        Ok(self.tree.remove(id.as_bytes())?)
+    }
+
+    /// Adds a private key to the store (note that the key ID MUST not be empty)
+    pub fn add(&self, id: &str, key: &SigningKey) -> Result<(), KeyStoreError> {
+        if id.is_empty() {
+            return Err(anyhow!("key ID of a private key can't be empty").into());
+        }
 
-        if tree.contains_key(&id)? {
-            return Err(anyhow!("key with ID '{}' already exists", id));
+        if self.tree.contains_key(id.as_bytes())? {
+            return Err(KeyStoreError::DuplicateKey(id.to_string()));
         }
 
-        tree.insert(id, encode::to_vec(&key)?)?;
+        let bytes = key.to_bytes();
 
-        self.jwk_set.keys.push(key);
+        match self.vault_key()? {
+            Some(key) => self
+                .tree
+                .insert(id.as_bytes(), &key.seal(id.as_bytes(), &bytes)?)?,
+            None => self.tree.insert(id.as_bytes(), &bytes)?,
+        };
 
         Ok(())
     }
 }
 
-impl AsRef<JWKSet<Empty>> for KeyStore {
-    fn as_ref(&self) -> &JWKSet<Empty> {
-        &self.jwk_set
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub enum Decision {
+    Trusted,
+    Distrusted,
+}
+
+/// Trust decision and revocation of a single key
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TrustRecord {
+    pub key_id: String,
+    pub decision: Option<Decision>,
+    /// Transactions signed at or after this moment are rejected
+    pub revoked_at: Option<i64>,
+}
+
+/// Tree in which the trust decisions and revocations are stored
+const TRUST_TREE: &str = "nuts/trust";
+
+/// Decisions of the operator on which keys are trusted, transactions signed by distrusted or revoked keys aren't
+/// admitted
+#[derive(Clone)]
+pub struct TrustPolicy {
+    tree: Arc<dyn Tree>,
+}
+
+impl TrustPolicy {
+    pub fn open(db: Db) -> Result<Self> {
+        Self::with_storage(backend::open(&db)?.as_ref())
+    }
+
+    fn with_storage(storage: &dyn Storage) -> Result<Self> {
+        Ok(Self {
+            tree: storage.open_tree(TRUST_TREE)?,
+        })
+    }
+
+    /// Moves the trust policy into the storage backend, as older versions always stored it in sled
+    pub(crate) fn move_to_backend(db: &Db) -> Result<()> {
+        backend::move_tree(db, TRUST_TREE)
+    }
+
+    pub fn get(&self, key_id: &str) -> Result<Option<TrustRecord>> {
+        match self.tree.get(key_id.as_bytes())? {
+            Some(value) => Ok(Some(decode::from_read(value.as_ref())?)),
+            None => Ok(None),
+        }
+    }
+
+    pub fn list(&self) -> Result<Vec<TrustRecord>> {
+        let mut records = vec![];
+
+        for record in self.tree.iter() {
+            let (_, value) = record?;
+
+            records.push(decode::from_read(value.as_ref())?);
+        }
+
+        Ok(records)
+    }
+
+    fn update(&self, key_id: &str, f: impl FnOnce(&mut TrustRecord)) -> Result<()> {
+        let mut record = self.get(key_id)?.unwrap_or_else(|| TrustRecord {
+            key_id: key_id.to_string(),
+            decision: None,
+            revoked_at: None,
+        });
+
+        f(&mut record);
+
+        self.tree
+            .insert(key_id.as_bytes(), &encode::to_vec(&record)?)?;
+
+        Ok(())
+    }
+
+    pub fn decide(&self, key_id: &str, decision: Decision) -> Result<()> {
+        self.update(key_id, |record| record.decision = Some(decision))
+    }
+
+    /// Revokes the key at the given moment, a key which is already revoked keeps the earliest moment
+    pub fn revoke(&self, key_id: &str, revoked_at: i64) -> Result<()> {
+        self.update(key_id, |record| {
+            record.revoked_at = Some(
+                record
+                    .revoked_at
+                    .map_or(revoked_at, |current| current.min(revoked_at)),
+            )
+        })
+    }
+
+    /// Returns an error when the key is distrusted or was revoked before the given signing time
+    pub fn check(&self, key_id: &str, sign_at: i64) -> Result<()> {
+        match self.get(key_id)? {
+            Some(record) if record.decision == Some(Decision::Distrusted) => {
+                Err(anyhow!("key '{}' is distrusted", key_id))
+            }
+            Some(TrustRecord {
+                revoked_at: Some(revoked_at),
+                ..
+            }) if revoked_at <= sign_at => Err(anyhow!(
+                "key '{}' was revoked before the transaction was signed",
+                key_id
+            )),
+            _ => Ok(()),
+        }
+    }
+
+    /// Revokes the key now
+    pub fn revoke_now(&self, key_id: &str) -> Result<()> {
+        self.revoke(key_id, Utc::now().timestamp())
     }
 }
@@ -0,0 +1,64 @@
+use std::str::FromStr;
+
+use anyhow::{anyhow, Error};
+
+/// Preset of settings which depend on the hardware the node is running on
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Profile {
+    Default,
+    RaspberryPi,
+    Server,
+}
+
+/// Settings which are tuned by a profile, each of them can be overridden individually
+#[derive(Debug, Clone, Copy)]
+pub struct Tuning {
+    pub admission_workers: usize,
+    pub channel_capacity: usize,
+    pub cache_capacity: u64,
+    pub sync_batch_size: usize,
+}
+
+impl Profile {
+    pub fn tuning(self) -> Tuning {
+        match self {
+            Profile::Default => Tuning {
+                admission_workers: 1,
+                channel_capacity: 10,
+                cache_capacity: 1024 * 1024 * 1024,
+                sync_batch_size: 1000,
+            },
+            // Keeps memory usage low and avoids large bursts of work on a few cores
+            Profile::RaspberryPi => Tuning {
+                admission_workers: 1,
+                channel_capacity: 4,
+                cache_capacity: 32 * 1024 * 1024,
+                sync_batch_size: 100,
+            },
+            Profile::Server => Tuning {
+                admission_workers: std::thread::available_parallelism()
+                    .map(|n| n.get())
+                    .unwrap_or(4),
+                channel_capacity: 100,
+                cache_capacity: 4 * 1024 * 1024 * 1024,
+                sync_batch_size: 5000,
+            },
+        }
+    }
+}
+
+impl FromStr for Profile {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "default" => Ok(Profile::Default),
+            "raspberry-pi" => Ok(Profile::RaspberryPi),
+            "server" => Ok(Profile::Server),
+            _ => Err(anyhow!(
+                "invalid profile '{}' (expected default, raspberry-pi or server)",
+                s
+            )),
+        }
+    }
+}
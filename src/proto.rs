@@ -1 +0,0 @@
-tonic::include_proto!("transport");
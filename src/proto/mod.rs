@@ -0,0 +1,13 @@
+#![allow(dead_code)]
+
+tonic::include_proto!("transport");
+
+/// Crate-native representation of the messages which is validated when converting from the generated types, so
+/// that handlers don't depend on the wire format
+pub mod model;
+
+/// Private version 2 of the protocol which exchanges gossip and state messages in conversations, it's served as
+/// `nutsrs.v2.Protocol` as it isn't compatible with version 2 of the nuts-node protocol
+pub mod v2 {
+    tonic::include_proto!("nutsrs.v2");
+}
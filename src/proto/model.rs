@@ -0,0 +1,577 @@
+use std::convert::{TryFrom, TryInto};
+
+use anyhow::{anyhow, Result};
+use bytes::Bytes;
+
+use crate::network::{Hash, PayloadFilter, Transaction};
+use crate::proto::{self, network_message};
+
+fn parse_hashes(field: &str, hashes: Vec<Vec<u8>>) -> Result<Vec<Hash>> {
+    hashes
+        .into_iter()
+        .map(|hash| Hash::parse(hash).map_err(|e| anyhow!("invalid {}: {}", field, e)))
+        .collect()
+}
+
+fn to_bytes(hashes: Vec<Hash>) -> Vec<Vec<u8>> {
+    hashes
+        .into_iter()
+        .map(|hash| hash.as_ref().to_vec())
+        .collect()
+}
+
+/// An empty string means the field wasn't set as proto3 doesn't distinguish between the two
+fn non_empty(value: String) -> Option<String> {
+    if value.is_empty() {
+        None
+    } else {
+        Some(value)
+    }
+}
+
+/// Transaction as it's exchanged between peers, the data is the compact JWS which is parsed when it's admitted
+#[derive(Debug, Clone)]
+pub struct EncodedTransaction {
+    pub hash: Hash,
+    pub data: Vec<u8>,
+}
+
+impl TryFrom<proto::Transaction> for EncodedTransaction {
+    type Error = anyhow::Error;
+
+    fn try_from(tx: proto::Transaction) -> Result<Self> {
+        if tx.data.is_empty() {
+            return Err(anyhow!("transaction data is empty"));
+        }
+
+        Ok(Self {
+            hash: Hash::parse(tx.hash).map_err(|e| anyhow!("invalid transaction hash: {}", e))?,
+            data: tx.data,
+        })
+    }
+}
+
+impl From<Transaction> for EncodedTransaction {
+    fn from(tx: Transaction) -> Self {
+        Self {
+            hash: tx.id,
+            data: tx.data,
+        }
+    }
+}
+
+impl From<EncodedTransaction> for proto::Transaction {
+    fn from(tx: EncodedTransaction) -> Self {
+        Self {
+            hash: tx.hash.as_ref().to_vec(),
+            data: tx.data,
+        }
+    }
+}
+
+/// Heads of the DAG of a peer, the historic hash is only set by peers which support blocks
+#[derive(Debug, Clone)]
+pub struct AdvertHashes {
+    pub current_block_date: u32,
+    pub blocks: Vec<Vec<Hash>>,
+    pub historic_hash: Option<Hash>,
+    pub groups: Vec<String>,
+    pub payloads: Option<PayloadFilter>,
+}
+
+impl TryFrom<proto::AdvertHashes> for AdvertHashes {
+    type Error = anyhow::Error;
+
+    fn try_from(advert: proto::AdvertHashes) -> Result<Self> {
+        Ok(Self {
+            current_block_date: advert.current_block_date,
+            blocks: advert
+                .blocks
+                .into_iter()
+                .map(|block| parse_hashes("head hash", block.hashes))
+                .collect::<Result<_>>()?,
+            historic_hash: if advert.historic_hash.is_empty() {
+                None
+            } else {
+                Some(Hash::parse(advert.historic_hash)?)
+            },
+            groups: advert.groups,
+            payloads: advert
+                .payloads
+                .map(|filter| PayloadFilter::parse(filter.bits, filter.hashes))
+                .transpose()?,
+        })
+    }
+}
+
+impl From<AdvertHashes> for proto::AdvertHashes {
+    fn from(advert: AdvertHashes) -> Self {
+        Self {
+            current_block_date: advert.current_block_date,
+            blocks: advert
+                .blocks
+                .into_iter()
+                .map(|hashes| proto::BlockHashes {
+                    hashes: to_bytes(hashes),
+                })
+                .collect(),
+            historic_hash: advert
+                .historic_hash
+                .map(|hash| hash.as_ref().to_vec())
+                .unwrap_or_default(),
+            groups: advert.groups,
+            payloads: advert.payloads.map(|filter| proto::PayloadFilter {
+                bits: filter.bits().to_vec(),
+                hashes: filter.hashes(),
+            }),
+        }
+    }
+}
+
+/// Restricts a transaction list query to the transactions matching all fields and their ancestors
+#[derive(Debug, Clone, Default)]
+pub struct SubDagFilter {
+    pub did: Option<String>,
+    pub payload_type: Option<String>,
+}
+
+#[derive(Debug, Clone)]
+pub struct TransactionListQuery {
+    pub block_date: u32,
+    pub filter: Option<SubDagFilter>,
+}
+
+impl From<proto::TransactionListQuery> for TransactionListQuery {
+    fn from(query: proto::TransactionListQuery) -> Self {
+        Self {
+            block_date: query.block_date,
+            filter: query.filter.map(|filter| SubDagFilter {
+                did: non_empty(filter.did),
+                payload_type: non_empty(filter.payload_type),
+            }),
+        }
+    }
+}
+
+impl From<TransactionListQuery> for proto::TransactionListQuery {
+    fn from(query: TransactionListQuery) -> Self {
+        Self {
+            block_date: query.block_date,
+            filter: query.filter.map(|filter| proto::SubDagFilter {
+                did: filter.did.unwrap_or_default(),
+                payload_type: filter.payload_type.unwrap_or_default(),
+            }),
+        }
+    }
+}
+
+/// Transactions of a block, compressed lists must be decompressed before they're converted
+#[derive(Debug, Clone)]
+pub struct TransactionList {
+    pub block_date: u32,
+    pub transactions: Vec<EncodedTransaction>,
+    /// Number of this page (starting at 1) or 0 when the list isn't paginated
+    pub message_number: u32,
+    pub total_messages: u32,
+}
+
+impl TryFrom<proto::TransactionList> for TransactionList {
+    type Error = anyhow::Error;
+
+    fn try_from(list: proto::TransactionList) -> Result<Self> {
+        if !list.compressed.is_empty() {
+            return Err(anyhow!("transaction list is still compressed"));
+        }
+
+        Ok(Self {
+            block_date: list.block_date,
+            transactions: list
+                .transactions
+                .into_iter()
+                .map(EncodedTransaction::try_from)
+                .collect::<Result<_>>()?,
+            message_number: list.message_number,
+            total_messages: list.total_messages,
+        })
+    }
+}
+
+impl From<TransactionList> for proto::TransactionList {
+    fn from(list: TransactionList) -> Self {
+        Self {
+            block_date: list.block_date,
+            transactions: list.transactions.into_iter().map(Into::into).collect(),
+            compressed: vec![],
+            message_number: list.message_number,
+            total_messages: list.total_messages,
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct TransactionPayloadQuery {
+    pub payload_hash: Hash,
+}
+
+impl TryFrom<proto::TransactionPayloadQuery> for TransactionPayloadQuery {
+    type Error = anyhow::Error;
+
+    fn try_from(query: proto::TransactionPayloadQuery) -> Result<Self> {
+        Ok(Self {
+            payload_hash: Hash::parse(query.payload_hash)
+                .map_err(|e| anyhow!("invalid payload hash: {}", e))?,
+        })
+    }
+}
+
+impl From<TransactionPayloadQuery> for proto::TransactionPayloadQuery {
+    fn from(query: TransactionPayloadQuery) -> Self {
+        Self {
+            payload_hash: query.payload_hash.as_ref().to_vec(),
+        }
+    }
+}
+
+/// Payload of a transaction, the data is empty when the peer doesn't have it or isn't allowed to share it
+#[derive(Debug, Clone)]
+pub struct TransactionPayload {
+    pub payload_hash: Hash,
+    pub data: Bytes,
+}
+
+impl TryFrom<proto::TransactionPayload> for TransactionPayload {
+    type Error = anyhow::Error;
+
+    fn try_from(payload: proto::TransactionPayload) -> Result<Self> {
+        Ok(Self {
+            payload_hash: Hash::parse(payload.payload_hash)
+                .map_err(|e| anyhow!("invalid payload hash: {}", e))?,
+            data: payload.data,
+        })
+    }
+}
+
+impl From<TransactionPayload> for proto::TransactionPayload {
+    fn from(payload: TransactionPayload) -> Self {
+        Self {
+            payload_hash: payload.payload_hash.as_ref().to_vec(),
+            data: payload.data,
+        }
+    }
+}
+
+/// State of a peer which it broadcasts to it's peers, all fields are optional
+#[derive(Debug, Clone, Default)]
+pub struct Diagnostics {
+    /// Seconds since the node started
+    pub uptime: u32,
+    pub peer_id: Option<String>,
+    pub peers: Vec<String>,
+    pub number_of_transactions: u32,
+    pub software_version: Option<String>,
+    pub software_id: Option<String>,
+}
+
+impl From<proto::Diagnostics> for Diagnostics {
+    fn from(diagnostics: proto::Diagnostics) -> Self {
+        Self {
+            uptime: diagnostics.uptime,
+            peer_id: non_empty(diagnostics.peer_id),
+            peers: diagnostics.peers,
+            number_of_transactions: diagnostics.number_of_transactions,
+            software_version: non_empty(diagnostics.software_version),
+            software_id: non_empty(diagnostics.software_id),
+        }
+    }
+}
+
+impl From<Diagnostics> for proto::Diagnostics {
+    fn from(diagnostics: Diagnostics) -> Self {
+        Self {
+            uptime: diagnostics.uptime,
+            peer_id: diagnostics.peer_id.unwrap_or_default(),
+            peers: diagnostics.peers,
+            number_of_transactions: diagnostics.number_of_transactions,
+            software_version: diagnostics.software_version.unwrap_or_default(),
+            software_id: diagnostics.software_id.unwrap_or_default(),
+        }
+    }
+}
+
+/// Message of version 1 of the protocol
+#[derive(Debug, Clone)]
+pub enum Message {
+    AdvertHashes(AdvertHashes),
+    TransactionListQuery(TransactionListQuery),
+    TransactionList(TransactionList),
+    TransactionPayloadQuery(TransactionPayloadQuery),
+    TransactionPayload(TransactionPayload),
+    Diagnostics(Diagnostics),
+}
+
+impl Message {
+    /// Name of the type of message as used in the logs
+    pub fn kind(&self) -> &'static str {
+        match self {
+            Message::AdvertHashes(_) => "advert_hashes",
+            Message::TransactionListQuery(_) => "transaction_list_query",
+            Message::TransactionList(_) => "transaction_list",
+            Message::TransactionPayloadQuery(_) => "transaction_payload_query",
+            Message::TransactionPayload(_) => "transaction_payload",
+            Message::Diagnostics(_) => "diagnostics",
+        }
+    }
+}
+
+impl TryFrom<network_message::Message> for Message {
+    type Error = anyhow::Error;
+
+    fn try_from(message: network_message::Message) -> Result<Self> {
+        Ok(match message {
+            network_message::Message::AdvertHashes(advert) => {
+                Message::AdvertHashes(advert.try_into()?)
+            }
+            network_message::Message::TransactionListQuery(query) => {
+                Message::TransactionListQuery(query.into())
+            }
+            network_message::Message::TransactionList(list) => {
+                Message::TransactionList(list.try_into()?)
+            }
+            network_message::Message::TransactionPayloadQuery(query) => {
+                Message::TransactionPayloadQuery(query.try_into()?)
+            }
+            network_message::Message::TransactionPayload(payload) => {
+                Message::TransactionPayload(payload.try_into()?)
+            }
+            network_message::Message::DiagnosticsBroadcast(diagnostics) => {
+                Message::Diagnostics(diagnostics.into())
+            }
+        })
+    }
+}
+
+impl From<Message> for proto::NetworkMessage {
+    fn from(message: Message) -> Self {
+        Self {
+            message: match message {
+                Message::AdvertHashes(advert) => {
+                    Some(network_message::Message::AdvertHashes(advert.into()))
+                }
+                Message::TransactionListQuery(query) => {
+                    Some(network_message::Message::TransactionListQuery(query.into()))
+                }
+                Message::TransactionList(list) => {
+                    Some(network_message::Message::TransactionList(list.into()))
+                }
+                Message::TransactionPayloadQuery(query) => Some(
+                    network_message::Message::TransactionPayloadQuery(query.into()),
+                ),
+                Message::TransactionPayload(payload) => {
+                    Some(network_message::Message::TransactionPayload(payload.into()))
+                }
+                Message::Diagnostics(diagnostics) => Some(
+                    network_message::Message::DiagnosticsBroadcast(diagnostics.into()),
+                ),
+            },
+        }
+    }
+}
+
+/// Messages of version 2 of the protocol
+pub mod v2 {
+    use std::convert::TryFrom;
+
+    use anyhow::{anyhow, Result};
+
+    use super::{parse_hashes, to_bytes};
+    use crate::network::Hash;
+    use crate::proto::v2::{self as proto, envelope};
+
+    fn parse_xor(xor: Vec<u8>) -> Result<Hash> {
+        Hash::parse(xor).map_err(|e| anyhow!("invalid XOR: {}", e))
+    }
+
+    fn conversation_id(id: Vec<u8>) -> Result<Vec<u8>> {
+        if id.is_empty() {
+            return Err(anyhow!("conversation ID is empty"));
+        }
+
+        Ok(id)
+    }
+
+    /// Transaction as it's exchanged between peers, the payload is left out when it's private or unknown
+    #[derive(Debug, Clone)]
+    pub struct Transaction {
+        pub hash: Hash,
+        pub data: Vec<u8>,
+        pub payload: Option<Bytes>,
+    }
+
+    impl TryFrom<proto::Transaction> for Transaction {
+        type Error = anyhow::Error;
+
+        fn try_from(tx: proto::Transaction) -> Result<Self> {
+            if tx.data.is_empty() {
+                return Err(anyhow!("transaction data is empty"));
+            }
+
+            Ok(Self {
+                hash: Hash::parse(tx.hash)
+                    .map_err(|e| anyhow!("invalid transaction hash: {}", e))?,
+                data: tx.data,
+                payload: if tx.payload.is_empty() {
+                    None
+                } else {
+                    Some(tx.payload)
+                },
+            })
+        }
+    }
+
+    impl From<Transaction> for proto::Transaction {
+        fn from(tx: Transaction) -> Self {
+            Self {
+                hash: tx.hash.as_ref().to_vec(),
+                data: tx.data,
+                payload: tx.payload.unwrap_or_default(),
+            }
+        }
+    }
+
+    #[derive(Debug, Clone)]
+    pub struct Gossip {
+        pub xor: Hash,
+        pub lc: u32,
+        pub transactions: Vec<Hash>,
+    }
+
+    #[derive(Debug, Clone)]
+    pub struct State {
+        pub conversation_id: Vec<u8>,
+        pub xor: Hash,
+        pub lc: u32,
+    }
+
+    #[derive(Debug, Clone)]
+    pub struct TransactionSet {
+        pub conversation_id: Vec<u8>,
+        pub xor: Hash,
+        pub lc: u32,
+        pub transactions: Vec<Hash>,
+    }
+
+    #[derive(Debug, Clone)]
+    pub struct TransactionListQuery {
+        pub conversation_id: Vec<u8>,
+        pub refs: Vec<Hash>,
+    }
+
+    #[derive(Debug, Clone)]
+    pub struct TransactionList {
+        pub conversation_id: Vec<u8>,
+        pub transactions: Vec<Transaction>,
+    }
+
+    /// Message of version 2 of the protocol
+    #[derive(Debug, Clone)]
+    pub enum Message {
+        Gossip(Gossip),
+        State(State),
+        TransactionSet(TransactionSet),
+        TransactionListQuery(TransactionListQuery),
+        TransactionList(TransactionList),
+    }
+
+    impl Message {
+        /// Name of the type of message as used in the logs
+        pub fn kind(&self) -> &'static str {
+            match self {
+                Message::Gossip(_) => "gossip",
+                Message::State(_) => "state",
+                Message::TransactionSet(_) => "transaction_set",
+                Message::TransactionListQuery(_) => "transaction_list_query",
+                Message::TransactionList(_) => "transaction_list",
+            }
+        }
+    }
+
+    impl TryFrom<envelope::Message> for Message {
+        type Error = anyhow::Error;
+
+        fn try_from(message: envelope::Message) -> Result<Self> {
+            Ok(match message {
+                envelope::Message::Gossip(gossip) => Message::Gossip(Gossip {
+                    xor: parse_xor(gossip.xor)?,
+                    lc: gossip.lc,
+                    transactions: parse_hashes("transaction hash", gossip.transactions)?,
+                }),
+                envelope::Message::State(state) => Message::State(State {
+                    conversation_id: conversation_id(state.conversation_id)?,
+                    xor: parse_xor(state.xor)?,
+                    lc: state.lc,
+                }),
+                envelope::Message::TransactionSet(set) => Message::TransactionSet(TransactionSet {
+                    conversation_id: conversation_id(set.conversation_id)?,
+                    xor: parse_xor(set.xor)?,
+                    lc: set.lc,
+                    transactions: parse_hashes("transaction hash", set.transactions)?,
+                }),
+                envelope::Message::TransactionListQuery(query) => {
+                    Message::TransactionListQuery(TransactionListQuery {
+                        conversation_id: conversation_id(query.conversation_id)?,
+                        refs: parse_hashes("transaction hash", query.refs)?,
+                    })
+                }
+                envelope::Message::TransactionList(list) => {
+                    Message::TransactionList(TransactionList {
+                        conversation_id: conversation_id(list.conversation_id)?,
+                        transactions: list
+                            .transactions
+                            .into_iter()
+                            .map(Transaction::try_from)
+                            .collect::<Result<_>>()?,
+                    })
+                }
+            })
+        }
+    }
+
+    impl From<Message> for proto::Envelope {
+        fn from(message: Message) -> Self {
+            Self {
+                message: Some(match message {
+                    Message::Gossip(gossip) => envelope::Message::Gossip(proto::Gossip {
+                        xor: gossip.xor.as_ref().to_vec(),
+                        lc: gossip.lc,
+                        transactions: to_bytes(gossip.transactions),
+                    }),
+                    Message::State(state) => envelope::Message::State(proto::State {
+                        conversation_id: state.conversation_id,
+                        xor: state.xor.as_ref().to_vec(),
+                        lc: state.lc,
+                    }),
+                    Message::TransactionSet(set) => {
+                        envelope::Message::TransactionSet(proto::TransactionSet {
+                            conversation_id: set.conversation_id,
+                            xor: set.xor.as_ref().to_vec(),
+                            lc: set.lc,
+                            transactions: to_bytes(set.transactions),
+                        })
+                    }
+                    Message::TransactionListQuery(query) => {
+                        envelope::Message::TransactionListQuery(proto::TransactionListQuery {
+                            conversation_id: query.conversation_id,
+                            refs: to_bytes(query.refs),
+                        })
+                    }
+                    Message::TransactionList(list) => {
+                        envelope::Message::TransactionList(proto::TransactionList {
+                            conversation_id: list.conversation_id,
+                            transactions: list.transactions.into_iter().map(Into::into).collect(),
+                        })
+                    }
+                }),
+            }
+        }
+    }
+}
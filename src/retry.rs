@@ -0,0 +1,128 @@
+use std::time::{Duration, Instant};
+
+use rand::Rng;
+
+use crate::metrics::Metrics;
+
+/// Exponential backoff policy which is shared by everything that retries an operation (e.g. reconnecting to peers
+/// or fetching payloads)
+#[derive(Debug, Clone)]
+pub struct RetryPolicy {
+    /// Delay before the first retry, which is doubled for every consecutive failed attempt
+    pub interval: Duration,
+    pub max_interval: Duration,
+    /// Fraction of the delay which is added at random so that retries don't happen in lockstep
+    pub jitter: f64,
+    /// Maximum number of consecutive failed attempts before giving up
+    pub max_attempts: Option<u32>,
+    /// Maximum time spent retrying before giving up
+    pub budget: Option<Duration>,
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        Self::exponential(Duration::from_secs(1))
+    }
+}
+
+impl RetryPolicy {
+    pub fn exponential(interval: Duration) -> Self {
+        Self {
+            interval,
+            max_interval: Duration::from_secs(60),
+            jitter: 0.25,
+            max_attempts: None,
+            budget: None,
+        }
+    }
+
+    pub fn max_interval(mut self, max_interval: Duration) -> Self {
+        self.max_interval = max_interval;
+        self
+    }
+
+    pub fn jitter(mut self, jitter: f64) -> Self {
+        self.jitter = jitter;
+        self
+    }
+
+    pub fn max_attempts(mut self, max_attempts: Option<u32>) -> Self {
+        self.max_attempts = max_attempts;
+        self
+    }
+
+    pub fn budget(mut self, budget: Option<Duration>) -> Self {
+        self.budget = budget;
+        self
+    }
+
+    /// Returns the delay before the given (zero-based) retry
+    pub fn delay(&self, attempt: u32) -> Duration {
+        let backoff = self
+            .interval
+            .saturating_mul(2u32.saturating_pow(attempt))
+            .min(self.max_interval);
+
+        if self.jitter > 0.0 {
+            backoff + backoff.mul_f64(rand::thread_rng().gen_range(0.0..self.jitter))
+        } else {
+            backoff
+        }
+    }
+
+    /// Starts a sequence of retries for the given subsystem, which is used to count the retries in the counters
+    pub fn backoff(&self, subsystem: &'static str, metrics: &Metrics) -> Backoff {
+        Backoff {
+            policy: self.clone(),
+            subsystem,
+            metrics: metrics.clone(),
+            attempt: 0,
+            started_at: Instant::now(),
+        }
+    }
+}
+
+/// State of a sequence of retries
+#[derive(Debug, Clone)]
+pub struct Backoff {
+    policy: RetryPolicy,
+    subsystem: &'static str,
+    metrics: Metrics,
+    attempt: u32,
+    started_at: Instant,
+}
+
+impl Backoff {
+    /// Number of failed attempts so far
+    pub fn attempts(&self) -> u32 {
+        self.attempt
+    }
+
+    /// Records a failed attempt and returns the delay before the next one or nothing when the maximum number of
+    /// attempts or the budget is exhausted
+    pub fn next_delay(&mut self) -> Option<Duration> {
+        if matches!(self.policy.max_attempts, Some(max_attempts) if self.attempt >= max_attempts) {
+            return None;
+        }
+
+        let delay = self.policy.delay(self.attempt);
+
+        if matches!(self.policy.budget, Some(budget) if self.started_at.elapsed() + delay > budget)
+        {
+            return None;
+        }
+
+        self.attempt += 1;
+
+        self.metrics
+            .increment(&format!("retries.{}", self.subsystem));
+
+        Some(delay)
+    }
+
+    /// Starts over after a successful attempt
+    pub fn reset(&mut self) {
+        self.attempt = 0;
+        self.started_at = Instant::now();
+    }
+}
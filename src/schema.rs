@@ -0,0 +1,117 @@
+use std::convert::TryInto;
+
+use anyhow::{anyhow, Result};
+use sled::Db;
+
+use crate::network::Graph;
+use crate::pki::{PrivateKeyStore, TrustPolicy};
+
+/// Tree in which the version of the database layout and other properties of the database are stored
+pub(crate) const META_TREE: &str = "nuts/meta";
+
+const VERSION_KEY: &str = "schema_version";
+
+/// Upgrades the database from the previous version to the version of the migration
+pub struct Migration {
+    pub version: u32,
+    pub description: &'static str,
+    apply: fn(&Db) -> Result<()>,
+}
+
+/// Registered migrations in the order in which they're applied, new migrations are added to the end
+const MIGRATIONS: &[Migration] = &[
+    Migration {
+        version: 1,
+        description: "index the payloads of the stored transactions",
+        apply: Graph::index_payloads,
+    },
+    Migration {
+        version: 2,
+        description: "move the trust policy into the storage backend",
+        apply: TrustPolicy::move_to_backend,
+    },
+    Migration {
+        version: 3,
+        description: "move the private keys into the storage backend",
+        apply: PrivateKeyStore::move_to_backend,
+    },
+];
+
+/// Version of the database layout which is used by this version of the node
+pub const SCHEMA_VERSION: u32 = MIGRATIONS.len() as u32;
+
+/// Returns the version of the database layout, databases which were written before the version was stored are
+/// version 0 unless they're empty
+pub fn version(db: &Db) -> Result<u32> {
+    match db.open_tree(META_TREE)?.get(VERSION_KEY)? {
+        Some(value) => Ok(u32::from_be_bytes(value.as_ref().try_into()?)),
+        None if is_empty(db)? => Ok(SCHEMA_VERSION),
+        None => Ok(0),
+    }
+}
+
+/// Whether nothing is stored in the database yet, apart from it's properties
+pub(crate) fn is_empty(db: &Db) -> Result<bool> {
+    for name in db.tree_names() {
+        if name != META_TREE.as_bytes() && !db.open_tree(name)?.is_empty() {
+            return Ok(false);
+        }
+    }
+
+    Ok(true)
+}
+
+fn set_version(db: &Db, version: u32) -> Result<()> {
+    db.open_tree(META_TREE)?
+        .insert(VERSION_KEY, &version.to_be_bytes())?;
+    db.flush()?;
+
+    Ok(())
+}
+
+/// Returns the migrations which still need to be applied to the database
+pub fn pending(db: &Db) -> Result<Vec<&'static Migration>> {
+    let version = version(db)?;
+
+    if version > SCHEMA_VERSION {
+        return Err(anyhow!(
+            "database schema version {} is newer than the supported version {}, upgrade the node to use it",
+            version,
+            SCHEMA_VERSION
+        ));
+    }
+
+    Ok(MIGRATIONS
+        .iter()
+        .filter(|migration| migration.version > version)
+        .collect())
+}
+
+/// Upgrades a database written by an older version of the node, which must be done before the database is used (the
+/// [`Server`](crate::network::Server) does this when it's created). The pending migrations are applied one by one and
+/// the version is stored after every migration so that an interrupted upgrade continues where it stopped. Returns the
+/// applied migrations
+pub fn migrate(db: &Db) -> Result<Vec<&'static Migration>> {
+    let pending = pending(db)?;
+
+    for migration in pending.iter() {
+        tracing::info!(target: "nuts::schema", "migrating database to version {}: {}", migration.version, migration.description);
+
+        (migration.apply)(db).map_err(|e| {
+            anyhow!(
+                "failed to migrate database to version {}: {}",
+                migration.version,
+                e
+            )
+        })?;
+
+        set_version(db, migration.version)?;
+    }
+
+    // New databases don't need any migration but the version is stored so that it's known once data is written
+    if db.open_tree(META_TREE)?.get(VERSION_KEY)?.is_none() {
+        set_version(db, SCHEMA_VERSION)?;
+    }
+
+    Ok(pending)
+}
@@ -0,0 +1,104 @@
+use std::future::Future;
+
+use anyhow::{anyhow, Result};
+use nuts_rs::network::{
+    Graph, Server, ServerOptions, TlsMaterial, TlsPolicy, Transaction, TransactionBuilder,
+};
+use nuts_rs::pki::KeyStore;
+use p256::ecdsa::SigningKey;
+use rand::rngs::OsRng;
+use sled::Db;
+
+const KEY_ID: &str = "did:nuts:self-test#key-1";
+
+/// Signs a throwaway transaction, verifies it and checks that it survives reopening the graph
+async fn check_transaction() -> Result<()> {
+    let db = sled::Config::new().temporary(true).open()?;
+    let key = SigningKey::random(&mut OsRng);
+    let mut graph = Graph::open(db.clone())?;
+    let tx = TransactionBuilder::new("application/did+json", b"self-test")?
+        .append_to(&graph)
+        .embed_key(true)
+        .sign(KEY_ID, &key)?;
+    let verified = Transaction::parse(&KeyStore::open(db.clone())?, String::from_utf8(tx.data)?)?;
+
+    graph.add(verified.clone())?;
+
+    if graph.heads() != vec![verified.id.clone()] {
+        return Err(anyhow!("transaction isn't the head of the graph"));
+    }
+
+    graph.flush().await?;
+
+    match Graph::open(db)?.get(&verified.id) {
+        Some(_) => Ok(()),
+        None => Err(anyhow!("transaction missing after reopening the graph")),
+    }
+}
+
+/// Writes, reads and removes a record in the database of the node
+fn check_db(db: &Db) -> Result<()> {
+    let tree = db.open_tree("nuts/self-test")?;
+
+    tree.insert("probe", "ok")?;
+    db.flush()?;
+
+    let value = tree.remove("probe")?;
+
+    db.drop_tree("nuts/self-test")?;
+
+    match value {
+        Some(value) if value.as_ref() == b"ok" => Ok(()),
+        _ => Err(anyhow!(
+            "record written to the database couldn't be read back"
+        )),
+    }
+}
+
+/// Opens and closes a TLS connection with a server listening on the loopback interface
+async fn check_tls(tls: &TlsMaterial, tls_policy: TlsPolicy) -> Result<()> {
+    let db = sled::Config::new().temporary(true).open()?;
+    let server = Server::new(
+        db,
+        tls,
+        ServerOptions {
+            tls_policy,
+            ..ServerOptions::default()
+        },
+    )?;
+    let listener = std::net::TcpListener::bind("127.0.0.1:0")?;
+    let port = listener.local_addr()?.port();
+
+    server.listen_on(listener)?;
+    server.probe(format!("https://localhost:{}", port)).await?;
+
+    Ok(())
+}
+
+async fn check(name: &str, result: impl Future<Output = Result<()>>) -> bool {
+    match result.await {
+        Ok(()) => {
+            println!("{}: ok", name);
+            true
+        }
+        Err(e) => {
+            println!("{}: failed ({})", name, e);
+            false
+        }
+    }
+}
+
+/// Performs an end-to-end check of the node and returns an error when any of the checks failed
+pub async fn run(db: &Db, tls: &TlsMaterial, tls_policy: TlsPolicy) -> Result<()> {
+    let results = [
+        check("transaction", check_transaction()).await,
+        check("database", async { check_db(db) }).await,
+        check("tls", check_tls(tls, tls_policy)).await,
+    ];
+
+    if results.iter().any(|ok| !ok) {
+        return Err(anyhow!("self-test failed"));
+    }
+
+    Ok(())
+}
@@ -0,0 +1,66 @@
+use anyhow::Result;
+
+/// Write end of the pipe which is used to wake up the runtime from within the signal handler
+#[cfg(unix)]
+static PIPE: std::sync::atomic::AtomicI32 = std::sync::atomic::AtomicI32::new(-1);
+
+#[cfg(unix)]
+extern "C" fn handle_signal(_: libc::c_int) {
+    let fd = PIPE.load(std::sync::atomic::Ordering::SeqCst);
+
+    // Only async-signal-safe functions may be called from here (see `signal-safety(7)`)
+    if fd >= 0 {
+        unsafe {
+            libc::write(fd, [1u8].as_ptr() as *const libc::c_void, 1);
+        }
+    }
+}
+
+/// Waits until the process receives SIGINT or SIGTERM, a second signal terminates the process immediately
+#[cfg(unix)]
+pub async fn signal() -> Result<()> {
+    use std::os::unix::io::IntoRawFd;
+    use std::os::unix::net::UnixStream;
+    use std::sync::atomic::Ordering;
+
+    let (rx, tx) = UnixStream::pair()?;
+
+    rx.set_nonblocking(true)?;
+    tx.set_nonblocking(true)?;
+    PIPE.store(tx.into_raw_fd(), Ordering::SeqCst);
+
+    unsafe {
+        libc::signal(
+            libc::SIGINT,
+            handle_signal as *const () as libc::sighandler_t,
+        );
+        libc::signal(
+            libc::SIGTERM,
+            handle_signal as *const () as libc::sighandler_t,
+        );
+    }
+
+    let rx = tokio::net::UnixStream::from_std(rx)?;
+
+    loop {
+        rx.readable().await?;
+
+        match rx.try_read(&mut [0; 1]) {
+            Ok(_) => break,
+            Err(e) if e.kind() == std::io::ErrorKind::WouldBlock => continue,
+            Err(e) => return Err(e.into()),
+        }
+    }
+
+    unsafe {
+        libc::signal(libc::SIGINT, libc::SIG_DFL);
+        libc::signal(libc::SIGTERM, libc::SIG_DFL);
+    }
+
+    Ok(())
+}
+
+#[cfg(not(unix))]
+pub async fn signal() -> Result<()> {
+    futures::future::pending().await
+}
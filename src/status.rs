@@ -0,0 +1,84 @@
+use std::net::SocketAddr;
+use std::path::{Path, PathBuf};
+
+use anyhow::{anyhow, Result};
+use hyper::body;
+use hyper::Client;
+use nuts_rs::network::Health;
+use tokio::fs;
+use tokio::sync::watch;
+
+/// Name of the file in the data directory to which a running node writes it's health
+const STATUS_FILE: &str = "status.json";
+
+pub fn path(data_dir: &Path) -> PathBuf {
+    data_dir.join(STATUS_FILE)
+}
+
+/// Writes the health of the node to the status file every time it's updated, the file is written to a temporary
+/// file first so that readers never see a partial write
+pub fn spawn_writer(path: PathBuf, mut health: watch::Receiver<Health>) {
+    tokio::spawn(async move {
+        while health.changed().await.is_ok() {
+            let result = async {
+                let tmp = path.with_extension("json.tmp");
+                let data = serde_json::to_vec_pretty(&*health.borrow())?;
+
+                fs::write(&tmp, data).await?;
+                fs::rename(&tmp, &path).await?;
+
+                Ok::<_, anyhow::Error>(())
+            };
+
+            if let Err(e) = result.await {
+                tracing::error!(target: "nuts::status", "failed to write status file '{}': {}", path.display(), e);
+            }
+        }
+    });
+}
+
+/// Waits until the node completed it's initial sync or stopped waiting for it, returns false when the node stopped
+pub async fn ready(health: &mut watch::Receiver<Health>) -> bool {
+    loop {
+        if health.borrow().sync.is_ready() {
+            return true;
+        }
+
+        if health.changed().await.is_err() {
+            return false;
+        }
+    }
+}
+
+/// Reads the status file written by a running node
+pub async fn read(path: &Path) -> Result<Health> {
+    let data = fs::read(path).await.map_err(|e| {
+        anyhow!(
+            "unable to read status file '{}' (is the node running?): {}",
+            path.display(),
+            e
+        )
+    })?;
+
+    Ok(serde_json::from_slice(&data)?)
+}
+
+/// Fetches the health from the admin API of a running node
+pub async fn fetch(addr: SocketAddr) -> Result<Health> {
+    let response = Client::new()
+        .get(format!("http://{}/health", addr).parse()?)
+        .await
+        .map_err(|e| anyhow!("unable to reach admin API on {}: {}", addr, e))?;
+    let data = body::to_bytes(response.into_body()).await?;
+
+    Ok(serde_json::from_slice(&data)?)
+}
+
+/// Fetches the health from the admin API when an address is given, otherwise reads the status file in the data
+/// directory
+pub async fn load(data_dir: &Path, admin_addr: Option<SocketAddr>) -> Result<Health> {
+    match admin_addr {
+        Some(addr) => fetch(addr).await,
+        None => read(&path(data_dir)).await,
+    }
+}
@@ -0,0 +1,49 @@
+use std::path::Path;
+use std::str::FromStr;
+
+use anyhow::{anyhow, Error, Result};
+use nuts_rs::backend;
+use sled::Db;
+
+/// Where the database is stored, the memory storage leaves no state behind which is useful for tests and throwaway
+/// nodes
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Storage {
+    Disk,
+    Memory,
+}
+
+impl Storage {
+    /// Opens the database, the data directory is only created (and used) when stored on disk
+    pub fn open(self, data_dir: &Path, cache_capacity: u64) -> Result<Db> {
+        let config = sled::Config::new().cache_capacity(cache_capacity);
+
+        Ok(match self {
+            Storage::Disk => {
+                std::fs::create_dir_all(data_dir)?;
+
+                let db = config.path(data_dir).open()?;
+
+                backend::attach(&db, data_dir)?;
+
+                db
+            }
+            Storage::Memory => config.temporary(true).open()?,
+        })
+    }
+}
+
+impl FromStr for Storage {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "disk" => Ok(Storage::Disk),
+            "memory" => Ok(Storage::Memory),
+            _ => Err(anyhow!(
+                "invalid storage '{}' (expected disk or memory)",
+                s
+            )),
+        }
+    }
+}
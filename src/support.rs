@@ -0,0 +1,183 @@
+use std::collections::BTreeMap;
+use std::path::Path;
+use std::time::Duration;
+
+use anyhow::{anyhow, Result};
+use chrono::Utc;
+use nuts_rs::network::{
+    AddressBook, Health, PeerIdentities, PeerIdentity, PeerRecord, Sample, Stats,
+    VALIDATION_POLICY_VERSION,
+};
+use nuts_rs::pki::{public_jwk, sign_es256, Key, PrivateKeyStore};
+use serde::Serialize;
+use serde_json::Value;
+use sled::Db;
+
+use crate::{config, status};
+
+/// Settings and log lines which contain any of these words are redacted
+const SECRETS: &[&str] = &["passphrase", "password", "secret", "token"];
+
+const REDACTED: &str = "[redacted]";
+
+/// Period of the statistics which are included
+const STATS_PERIOD: Duration = Duration::from_secs(24 * 60 * 60);
+
+fn is_secret(name: &str) -> bool {
+    let name = name.to_lowercase();
+
+    SECRETS.iter().any(|secret| name.contains(secret))
+}
+
+/// Removes the settings which might contain secrets
+fn strip_secrets(value: &mut Value) {
+    match value {
+        Value::Object(map) => {
+            map.retain(|key, _| !is_secret(key));
+            map.values_mut().for_each(strip_secrets);
+        }
+        Value::Array(values) => values.iter_mut().for_each(strip_secrets),
+        _ => {}
+    }
+}
+
+/// Replaces bearer tokens and the values of secrets (e.g. `token=abc` or `passphrase: abc`) in a log line
+fn redact(line: &str) -> String {
+    let mut words = vec![];
+    let mut redact_next = false;
+
+    for word in line.split(' ') {
+        if redact_next && !word.is_empty() {
+            words.push(REDACTED.to_string());
+            redact_next = false;
+            continue;
+        }
+
+        match word.split_once('=') {
+            Some((key, _)) if is_secret(key) => words.push(format!("{}={}", key, REDACTED)),
+            _ => {
+                redact_next = word.eq_ignore_ascii_case("bearer")
+                    || (word.ends_with(':') && is_secret(word));
+                words.push(word.to_string());
+            }
+        }
+    }
+
+    words.join(" ")
+}
+
+/// Versions of the node and the formats it uses, together with the number of records per database tree
+#[derive(Debug, Serialize)]
+struct Versions {
+    node: &'static str,
+    validation_policy: u32,
+    protocols: Vec<&'static str>,
+    trees: BTreeMap<String, usize>,
+}
+
+/// Diagnostics of the node which are shared with support
+#[derive(Debug, Serialize)]
+struct Report {
+    created_at: i64,
+    versions: Versions,
+    /// Health as last written by the running node
+    status: Option<Health>,
+    stats: Vec<Sample>,
+    peers: Vec<PeerRecord>,
+    identities: Vec<PeerIdentity>,
+    config: Option<Value>,
+    logs: Vec<String>,
+}
+
+/// Signed diagnostics report as it's written to a file
+#[derive(Debug, Serialize)]
+pub struct SupportBundle {
+    /// ID of the key which signed the report
+    signer: String,
+    /// Public key of the signer, which support verifies against the keys published by the node
+    key: Key,
+    /// Base64url encoded JSON report
+    payload: String,
+    /// Base64url encoded ES256 signature over the payload
+    signature: String,
+}
+
+impl SupportBundle {
+    /// Collects the diagnostics of the node and signs them using one of it's private keys, the status is read from
+    /// the data directory and only the last log lines of the log file are included
+    pub async fn create(
+        db: Db,
+        data_dir: Option<&Path>,
+        config: Option<&Path>,
+        log_file: Option<&Path>,
+        log_lines: usize,
+        private_keys: &PrivateKeyStore,
+        signer: &str,
+    ) -> Result<Self> {
+        let signing_key = private_keys
+            .get(signer)?
+            .ok_or_else(|| anyhow!("private key not found with ID: {}", signer))?;
+        let mut trees = BTreeMap::new();
+
+        for name in db.tree_names() {
+            trees.insert(
+                String::from_utf8_lossy(&name).to_string(),
+                db.open_tree(&name)?.len(),
+            );
+        }
+
+        let config = match config {
+            Some(path) => {
+                let mut value = config::read(path)?;
+
+                strip_secrets(&mut value);
+                Some(value)
+            }
+            None => None,
+        };
+        let logs = match log_file {
+            Some(path) => {
+                let source = std::fs::read_to_string(path)
+                    .map_err(|e| anyhow!("unable to read log file '{}': {}", path.display(), e))?;
+                let lines = source.lines().collect::<Vec<_>>();
+
+                lines[lines.len().saturating_sub(log_lines)..]
+                    .iter()
+                    .map(|line| redact(line))
+                    .collect()
+            }
+            None => vec![],
+        };
+        // The node isn't necessarily running or stored on disk, in which case there is no status
+        let status = match data_dir {
+            Some(data_dir) => status::read(&status::path(data_dir)).await.ok(),
+            None => None,
+        };
+        let report = Report {
+            created_at: Utc::now().timestamp(),
+            versions: Versions {
+                node: env!("CARGO_PKG_VERSION"),
+                validation_policy: VALIDATION_POLICY_VERSION,
+                protocols: vec!["1", "2"],
+                trees,
+            },
+            status,
+            stats: Stats::open(db.clone())?.history(STATS_PERIOD)?,
+            peers: AddressBook::open(db.clone())?.list()?,
+            identities: PeerIdentities::open(db)?.list()?,
+            config,
+            logs,
+        };
+        let payload = serde_json::to_vec(&report)?;
+
+        Ok(Self {
+            signer: signer.to_string(),
+            key: public_jwk(signer, &signing_key),
+            signature: base64::encode_config(
+                sign_es256(&signing_key, &payload),
+                base64::URL_SAFE_NO_PAD,
+            ),
+            payload: base64::encode_config(payload, base64::URL_SAFE_NO_PAD),
+        })
+    }
+}
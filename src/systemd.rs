@@ -0,0 +1,165 @@
+use std::env;
+use std::net::TcpListener;
+use std::process;
+use std::time::Duration;
+
+use anyhow::{anyhow, Result};
+use tokio::time;
+
+/// File descriptors passed by systemd using socket activation start at 3 (see `sd_listen_fds(3)`)
+#[cfg(unix)]
+const LISTEN_FDS_START: i32 = 3;
+
+/// Sends a state update to the service manager as described in `sd_notify(3)`, this is a no-op when the
+/// process isn't managed by systemd
+#[cfg(unix)]
+fn notify(state: &str) -> Result<()> {
+    use std::os::unix::ffi::OsStrExt;
+    use std::os::unix::net::UnixDatagram;
+
+    let path = match env::var_os("NOTIFY_SOCKET") {
+        Some(path) => path,
+        None => return Ok(()),
+    };
+    let socket = UnixDatagram::unbound()?;
+
+    // Sockets starting with '@' are in the abstract namespace
+    match path.as_bytes().strip_prefix(b"@") {
+        #[cfg(target_os = "linux")]
+        Some(name) => {
+            use std::os::linux::net::SocketAddrExt;
+            use std::os::unix::net::SocketAddr;
+
+            socket.send_to_addr(state.as_bytes(), &SocketAddr::from_abstract_name(name)?)?;
+        }
+        #[cfg(not(target_os = "linux"))]
+        Some(_) => return Err(anyhow!("abstract notify sockets are not supported")),
+        None => {
+            socket.send_to(state.as_bytes(), path)?;
+        }
+    }
+
+    Ok(())
+}
+
+#[cfg(not(unix))]
+fn notify(_state: &str) -> Result<()> {
+    Ok(())
+}
+
+fn notify_or_log(state: &str) {
+    if let Err(e) = notify(state) {
+        tracing::warn!(target: "nuts::systemd", "failed to notify service manager: {}", e);
+    }
+}
+
+pub fn notify_ready() {
+    notify_or_log("READY=1");
+}
+
+pub fn notify_stopping() {
+    notify_or_log("STOPPING=1");
+}
+
+/// Starts pinging the service manager when the watchdog is enabled, the pings are sent by a task on the async
+/// runtime so that the service manager restarts the node when the runtime stops making progress
+pub fn spawn_watchdog() -> Result<()> {
+    let usec = match env::var("WATCHDOG_USEC") {
+        Ok(usec) => usec.parse::<u64>()?,
+        Err(_) => return Ok(()),
+    };
+
+    if let Ok(pid) = env::var("WATCHDOG_PID") {
+        if pid.parse::<u32>()? != process::id() {
+            return Ok(());
+        }
+    }
+
+    // Ping twice per interval as recommended by `sd_watchdog_enabled(3)`
+    let interval = Duration::from_micros(usec) / 2;
+
+    tracing::debug!(target: "nuts::systemd", "watchdog enabled (interval: {}ms)", interval.as_millis());
+
+    tokio::spawn(async move {
+        let mut ticks = time::interval(interval);
+
+        loop {
+            ticks.tick().await;
+            notify_or_log("WATCHDOG=1");
+        }
+    });
+
+    Ok(())
+}
+
+/// Sockets passed by the service manager using socket activation, which are named using `FileDescriptorName=` in
+/// the socket unit (the name of the unit is used by default)
+#[derive(Default)]
+pub struct ListenFds {
+    sockets: Vec<(String, TcpListener)>,
+}
+
+impl ListenFds {
+    /// Takes the socket with the given name
+    pub fn take(&mut self, name: &str) -> Option<TcpListener> {
+        let idx = self.sockets.iter().position(|(n, _)| n == name)?;
+
+        Some(self.sockets.remove(idx).1)
+    }
+
+    /// Takes the remaining socket when exactly one is left, regardless of it's name
+    pub fn take_single(&mut self) -> Option<TcpListener> {
+        if self.sockets.len() == 1 {
+            self.sockets.pop().map(|(_, listener)| listener)
+        } else {
+            None
+        }
+    }
+
+    /// Returns the names of the sockets which weren't taken
+    pub fn names(&self) -> Vec<&str> {
+        self.sockets.iter().map(|(name, _)| name.as_str()).collect()
+    }
+}
+
+/// Returns the sockets passed by the service manager using socket activation, which can only be done once as the
+/// environment variables are removed
+#[cfg(unix)]
+pub fn listen_fds() -> Result<ListenFds> {
+    use std::os::unix::io::FromRawFd;
+
+    let pid = match env::var("LISTEN_PID") {
+        Ok(pid) => pid.parse::<u32>()?,
+        Err(_) => return Ok(ListenFds::default()),
+    };
+
+    if pid != process::id() {
+        return Ok(ListenFds::default());
+    }
+
+    let count = env::var("LISTEN_FDS")
+        .map_err(|_| anyhow!("missing LISTEN_FDS for socket activation"))?
+        .parse::<i32>()?;
+    let names = env::var("LISTEN_FDNAMES").unwrap_or_default();
+    let mut names = names.split(':');
+
+    // Make sure child processes don't inherit the sockets
+    env::remove_var("LISTEN_PID");
+    env::remove_var("LISTEN_FDS");
+    env::remove_var("LISTEN_FDNAMES");
+
+    Ok(ListenFds {
+        sockets: (LISTEN_FDS_START..LISTEN_FDS_START + count)
+            .map(|fd| {
+                let name = names.next().unwrap_or("unknown").to_string();
+
+                (name, unsafe { TcpListener::from_raw_fd(fd) })
+            })
+            .collect(),
+    })
+}
+
+#[cfg(not(unix))]
+pub fn listen_fds() -> Result<ListenFds> {
+    Ok(ListenFds::default())
+}
@@ -0,0 +1,124 @@
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime};
+
+use anyhow::{anyhow, Result};
+use nuts_rs::network::{TlsMaterial, TlsReloader};
+use tokio::fs;
+use tokio::time;
+
+use crate::config::TlsConfig;
+
+async fn read_file(path: &Path) -> Result<Vec<u8>> {
+    fs::read(path)
+        .await
+        .map_err(|e| anyhow!("unable to read '{}': {}", path.display(), e))
+}
+
+/// PEM encoded files which contain the CA certificates which are trusted and the identity of the node
+#[derive(Debug, Clone)]
+pub struct TlsFiles {
+    pub truststore: PathBuf,
+    pub certificate: PathBuf,
+    pub key: PathBuf,
+}
+
+impl TlsFiles {
+    /// Uses the given paths, falling back to the configuration file and the defaults in the tls directory
+    pub fn new(
+        truststore: Option<&PathBuf>,
+        certificate: Option<&PathBuf>,
+        key: Option<&PathBuf>,
+        config: &TlsConfig,
+    ) -> Self {
+        Self {
+            truststore: truststore
+                .or_else(|| config.truststore.as_ref())
+                .cloned()
+                .unwrap_or_else(|| "tls/truststore.pem".into()),
+            certificate: certificate
+                .or_else(|| config.certificate.as_ref())
+                .cloned()
+                .unwrap_or_else(|| "tls/localhost.pem".into()),
+            key: key
+                .or_else(|| config.key.as_ref())
+                .cloned()
+                .unwrap_or_else(|| "tls/localhost.key".into()),
+        }
+    }
+
+    fn paths(&self) -> [&PathBuf; 3] {
+        [&self.truststore, &self.certificate, &self.key]
+    }
+
+    /// Reads the CA certificates which are trusted and the certificate and private key of the node
+    pub async fn read(&self) -> Result<TlsMaterial> {
+        Ok(TlsMaterial {
+            truststore: read_file(&self.truststore).await?,
+            certificate: read_file(&self.certificate).await?,
+            key: read_file(&self.key).await?,
+        })
+    }
+
+    async fn modified(&self) -> Result<Vec<SystemTime>> {
+        let mut modified = vec![];
+
+        for path in &self.paths() {
+            modified.push(fs::metadata(path).await?.modified()?);
+        }
+
+        Ok(modified)
+    }
+}
+
+/// Polls the files for changes and replaces the TLS material of the node when they changed, until the node no
+/// longer exists. Files are only read once they didn't change for a whole interval so that a new certificate isn't
+/// combined with the key it replaces when they're written one after the other
+pub fn spawn_reload(files: TlsFiles, reloader: TlsReloader, interval: Duration) {
+    tokio::spawn(async move {
+        let mut loaded = files.modified().await.ok();
+        let mut pending = None;
+        let mut ticks = time::interval(interval);
+
+        // The first tick completes immediately
+        ticks.tick().await;
+
+        loop {
+            ticks.tick().await;
+
+            let modified = match files.modified().await {
+                Ok(modified) => Some(modified),
+                Err(e) => {
+                    tracing::warn!(target: "nuts::tls", "unable to check TLS files for changes: {}", e);
+                    continue;
+                }
+            };
+
+            if modified == loaded {
+                pending = None;
+                continue;
+            }
+
+            if modified != pending {
+                pending = modified;
+                continue;
+            }
+
+            tracing::info!(target: "nuts::tls", "TLS files changed, reloading certificates");
+
+            // Files which can't be used are only reported once, until they change again
+            loaded = pending.take();
+
+            match files
+                .read()
+                .await
+                .and_then(|material| reloader.reload(&material))
+            {
+                Ok(true) => {}
+                Ok(false) => break,
+                Err(e) => {
+                    tracing::error!(target: "nuts::tls", "unable to reload TLS files, the previous certificates are kept: {}", e);
+                }
+            }
+        }
+    });
+}
@@ -0,0 +1,84 @@
+use std::time::Duration;
+
+use anyhow::{anyhow, Result};
+use hyper::body::Bytes;
+use hyper::client::HttpConnector;
+use hyper::header::CONTENT_TYPE;
+use hyper::{Body, Client, Method, Request, StatusCode, Uri};
+use nuts_rs::metrics::Metrics;
+use nuts_rs::network::{Anomaly, AnomalyHandler};
+use nuts_rs::retry::RetryPolicy;
+use tokio::time;
+
+/// Posts the detected key usage anomalies as JSON to an HTTP endpoint, failures are retried in the background and
+/// never block the admission of transactions
+pub struct Webhook {
+    url: Uri,
+    client: Client<HttpConnector>,
+    retry: RetryPolicy,
+    metrics: Metrics,
+}
+
+impl Webhook {
+    pub fn new(url: Uri, metrics: Metrics) -> Self {
+        Self {
+            url,
+            client: Client::new(),
+            retry: RetryPolicy::exponential(Duration::from_secs(1)).max_attempts(Some(5)),
+            metrics,
+        }
+    }
+}
+
+async fn post(client: &Client<HttpConnector>, url: &Uri, body: Bytes) -> Result<StatusCode> {
+    let request = Request::builder()
+        .method(Method::POST)
+        .uri(url.clone())
+        .header(CONTENT_TYPE, "application/json")
+        .body(Body::from(body))?;
+
+    Ok(client.request(request).await?.status())
+}
+
+impl AnomalyHandler for Webhook {
+    fn handle(&self, anomaly: &Anomaly) {
+        let url = self.url.clone();
+        let client = self.client.clone();
+        let mut backoff = self.retry.backoff("webhook", &self.metrics);
+        let body = match serde_json::to_vec(anomaly) {
+            Ok(body) => Bytes::from(body),
+            Err(e) => {
+                tracing::error!(target: "nuts::audit", "failed to encode anomaly: {}", e);
+                return;
+            }
+        };
+
+        tokio::spawn(async move {
+            loop {
+                let e = match post(&client, &url, body.clone()).await {
+                    Ok(status) if status.is_success() => return,
+                    // Sending the same anomaly again won't change the outcome
+                    Ok(status)
+                        if status.is_client_error() && status != StatusCode::TOO_MANY_REQUESTS =>
+                    {
+                        tracing::error!(target: "nuts::audit", "webhook '{}' rejected anomaly: {}", url, status);
+                        return;
+                    }
+                    Ok(status) => anyhow!("unexpected status: {}", status),
+                    Err(e) => e,
+                };
+
+                match backoff.next_delay() {
+                    Some(delay) => {
+                        tracing::warn!(target: "nuts::audit", "failed to send anomaly to webhook '{}', retrying in {:?}: {}", url, delay, e);
+                        time::sleep(delay).await;
+                    }
+                    None => {
+                        tracing::error!(target: "nuts::audit", "failed to send anomaly to webhook '{}' after {} attempts: {}", url, backoff.attempts() + 1, e);
+                        return;
+                    }
+                }
+            }
+        });
+    }
+}